@@ -5,7 +5,7 @@ extern crate resp;
 
 use test::Bencher;
 use std::io::BufReader;
-use resp::{Value, Decoder};
+use resp::{Value, Decoder, FeedDecoder};
 
 fn prepare_values() -> Value {
     let a = vec![Value::Null,
@@ -51,3 +51,100 @@ fn decode_values(b: &mut Bencher) {
                assert!(decoder.decode().is_err());
            });
 }
+
+#[bench]
+fn feed_decode_values(b: &mut Bencher) {
+    let value = prepare_values();
+    let buf = value.encode();
+    b.iter(|| {
+               let mut decoder = FeedDecoder::new();
+               for chunk in buf.chunks(64) {
+                   decoder.feed(chunk).unwrap();
+               }
+               assert_eq!(decoder.read().unwrap(), value);
+               assert_eq!(decoder.buffer_len(), 0);
+           });
+}
+
+fn prepare_small_frames() -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    for i in 0..10_000 {
+        buf.extend_from_slice(b"+OK\r\n");
+        buf.extend_from_slice(&Value::Integer(i).encode());
+        buf.extend_from_slice(b"$5\r\nhello\r\n");
+    }
+    buf
+}
+
+#[bench]
+fn decode_small_frames(b: &mut Bencher) {
+    let buf = prepare_small_frames();
+    b.iter(|| {
+               let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+               assert_eq!(decoder.decode_all().unwrap().len(), 30_000);
+           });
+}
+
+#[bench]
+fn feed_decode_small_frames(b: &mut Bencher) {
+    let buf = prepare_small_frames();
+    b.iter(|| {
+               let mut decoder = FeedDecoder::new();
+               for chunk in buf.chunks(4096) {
+                   decoder.feed(chunk).unwrap();
+               }
+               assert_eq!(decoder.result_len(), 30_000);
+           });
+}
+
+// Short bulks, with and without the inline storage of `Value::SmallBulk`.
+// Last result (`cargo bench short_bulks`):
+// test decode_short_bulks            ... bench:     113,054 ns/iter (+/- 13,884)
+// test decode_short_bulks_small      ... bench:      70,473 ns/iter (+/- 19,128)
+// test feed_decode_short_bulks       ... bench:      81,803 ns/iter (+/- 25,583)
+// test feed_decode_short_bulks_small ... bench:      69,808 ns/iter (+/- 18,161)
+
+fn prepare_short_bulks() -> Vec<u8> {
+    let keys: Vec<Value> = (0..1000).map(|i| Value::Bulk(format!("user:{}:name", i))).collect();
+    Value::Array(keys).encode()
+}
+
+#[bench]
+fn decode_short_bulks(b: &mut Bencher) {
+    let buf = prepare_short_bulks();
+    b.iter(|| {
+               let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+               decoder.decode().unwrap()
+           });
+}
+
+#[bench]
+fn decode_short_bulks_small(b: &mut Bencher) {
+    let buf = prepare_short_bulks();
+    b.iter(|| {
+               let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+               decoder.set_small_bulks(true);
+               decoder.decode().unwrap()
+           });
+}
+
+#[bench]
+fn feed_decode_short_bulks(b: &mut Bencher) {
+    let buf = prepare_short_bulks();
+    b.iter(|| {
+               let mut decoder = FeedDecoder::new();
+               decoder.feed(&buf).unwrap();
+               decoder.read().unwrap()
+           });
+}
+
+#[bench]
+fn feed_decode_short_bulks_small(b: &mut Bencher) {
+    let buf = prepare_short_bulks();
+    b.iter(|| {
+               let mut decoder = FeedDecoder::new();
+               decoder.set_small_bulks(true);
+               decoder.feed(&buf).unwrap();
+               decoder.read().unwrap()
+           });
+}
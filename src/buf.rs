@@ -0,0 +1,310 @@
+//! Zero-copy decoding into `bytes::Bytes` buffers
+
+use std::str;
+use std::string::String;
+use std::collections::VecDeque;
+use std::io::{Result, Error, ErrorKind};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::Value;
+use super::de::Parser;
+use super::generic::{GenericValue, Input};
+use super::serialize::{Options, Version, encode_to_writer, encoded_len};
+
+/// A value decoded by a `BytesDecoder`, the payloads of its bulk strings and unknown values are
+/// slices of the fed buffer. Requires the `bytes` feature.
+pub type BytesValue = GenericValue<String, Bytes>;
+
+impl Input<String, Bytes> for Bytes {
+    fn str(&self, start: usize, end: usize) -> Result<String> {
+        str::from_utf8(&self[start..end])
+            .map(str::to_string)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    fn bytes(&self, start: usize, end: usize) -> Bytes {
+        self.slice(start..end)
+    }
+
+    fn owned(&self, value: Value) -> Result<BytesValue> {
+        Ok(GenericValue::from(value))
+    }
+}
+
+/// Appends the encoded RESP value to a `BytesMut`, e.g. in a tokio codec, the output can then
+/// be frozen into `Bytes` without a copy. Requires the `bytes` feature.
+/// # Examples
+/// ```
+/// # extern crate bytes;
+/// # extern crate resp;
+/// # use bytes::BytesMut;
+/// # use resp::{Value, encode_bytes};
+/// # fn main() {
+/// let mut buf = BytesMut::new();
+/// encode_bytes(&Value::String("OK".to_string()), &mut buf);
+/// encode_bytes(&Value::Integer(1), &mut buf);
+/// assert_eq!(&buf.freeze()[..], b"+OK\r\n:1\r\n");
+/// # }
+/// ```
+pub fn encode_bytes(value: &Value, buf: &mut BytesMut) {
+    buf.reserve(encoded_len(value));
+    // writing to a `BytesMut` never fails.
+    encode_to_writer(value, &mut buf.writer()).unwrap();
+}
+
+/// A push-based RESP Decoder like `FeedDecoder`, buffering the fed bytes in a shared `BytesMut`.
+/// The payloads of bulk strings and unknown values, at any depth, are carved out of it with zero
+/// copies. Requires the `bytes` feature.
+/// # Examples
+/// ```
+/// # extern crate bytes;
+/// # extern crate resp;
+/// # use bytes::Bytes;
+/// # use resp::{BytesDecoder, BytesValue};
+/// # fn main() {
+/// let mut decoder = BytesDecoder::new();
+/// decoder.feed(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nva").unwrap();
+/// assert_eq!(decoder.read(), None);
+/// decoder.feed(b"lue\r\n").unwrap();
+/// assert_eq!(decoder.read(),
+///            Some(BytesValue::Array(vec![BytesValue::BufBulk(Bytes::from("SET")),
+///                                        BytesValue::BufBulk(Bytes::from("key")),
+///                                        BytesValue::BufBulk(Bytes::from("value"))])));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BytesDecoder {
+    buf: BytesMut,
+    pos: usize,
+    parser: Parser,
+    results: VecDeque<BytesValue>,
+}
+
+impl BytesDecoder {
+    /// Creates a BytesDecoder instance, bulk strings are decoded to `BufBulk` slices.
+    pub fn new() -> Self {
+        BytesDecoder {
+            buf: BytesMut::new(),
+            pos: 0,
+            parser: Parser::with_spans(Options {
+                                           buf_bulk: true,
+                                           ..Options::default()
+                                       }),
+            results: VecDeque::new(),
+        }
+    }
+
+    /// Creates a BytesDecoder instance that decodes bulk strings to `Bulk` strings, like
+    /// `FeedDecoder::new`, their payloads are checked as UTF-8 and copied.
+    pub fn with_str_bulk() -> Self {
+        BytesDecoder {
+            buf: BytesMut::new(),
+            pos: 0,
+            parser: Parser::with_spans(Options::default()),
+            results: VecDeque::new(),
+        }
+    }
+
+    /// Sets whether values of an unknown type are decoded to `Unknown` slices instead of
+    /// failing, see `Decoder::set_passthrough_unknown`.
+    pub fn set_passthrough_unknown(&mut self, passthrough: bool) {
+        self.parser.options.passthrough_unknown = passthrough;
+    }
+
+    /// Sets whether the RESP3 attributes preceding a value are kept,
+    /// see `Decoder::set_keep_attributes`.
+    pub fn set_keep_attributes(&mut self, keep: bool) {
+        self.parser.options.keep_attributes = keep;
+    }
+
+    /// Sets whether a bulk string that is not valid UTF-8 is decoded to a `BufBulk` slice instead
+    /// of failing, with `BytesDecoder::with_str_bulk`, see `Decoder::set_buf_bulk_fallback`.
+    pub fn set_buf_bulk_fallback(&mut self, fallback: bool) {
+        self.parser.options.buf_bulk_fallback = fallback;
+    }
+
+    /// Sets whether lengths must be written the way Redis writes them,
+    /// see `Decoder::set_strict_lengths`.
+    pub fn set_strict_lengths(&mut self, strict: bool) {
+        self.parser.options.strict_lengths = strict;
+    }
+
+    /// Pins the protocol version, see `Decoder::set_version`.
+    pub fn set_version(&mut self, version: Version) {
+        self.parser.options.version = Some(version);
+    }
+
+    /// Sets the max length of a bulk string, see `Decoder::set_max_bulk_len`.
+    pub fn set_max_bulk_len(&mut self, max: usize) {
+        self.parser.options.max_bulk_len = Some(max);
+    }
+
+    /// Sets the max number of elements of an aggregate, see `Decoder::set_max_array_len`.
+    pub fn set_max_array_len(&mut self, max: usize) {
+        self.parser.options.max_array_len = Some(max);
+    }
+
+    /// Sets the max number of bytes of a top-level value, see `Decoder::set_max_frame_len`.
+    pub fn set_max_frame_len(&mut self, max: usize) {
+        self.parser.options.max_frame_len = Some(max);
+    }
+
+    /// Sets the max length of a line, see `Decoder::set_max_line_len`.
+    pub fn set_max_line_len(&mut self, max: usize) {
+        self.parser.options.max_line_len = Some(max);
+    }
+
+    /// Feeds bytes to the decoder, every value they complete is decoded,
+    /// see `FeedDecoder::feed`.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(bytes);
+        loop {
+            match self.parser.parse(&self.buf, &mut self.pos) {
+                Ok(Some(value)) => {
+                    // the spans are relative to the buffer, which starts at the value.
+                    let frame = self.buf.split_to(self.pos).freeze();
+                    self.pos = 0;
+                    let mut spans = self.parser.take_spans().into_iter();
+                    match GenericValue::from_parsed(value, &frame, &mut spans) {
+                        Ok(value) => self.results.push_back(value),
+                        Err(err) => {
+                            self.parser.reset();
+                            return Err(err);
+                        }
+                    }
+                }
+                Ok(None) => return Ok(()),
+                Err(err) => {
+                    self.buf.clear();
+                    self.pos = 0;
+                    self.parser.reset();
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Returns the next decoded value, `None` if no value is complete yet.
+    pub fn read(&mut self) -> Option<BytesValue> {
+        self.results.pop_front()
+    }
+}
+
+impl Default for BytesDecoder {
+    fn default() -> Self {
+        BytesDecoder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(bytes: &'static str) -> BytesValue {
+        BytesValue::BufBulk(Bytes::from(bytes))
+    }
+
+    #[test]
+    fn struct_bytes_decoder() {
+        let mut decoder = BytesDecoder::new();
+        decoder.feed(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n").unwrap();
+        decoder.feed(b"$?\r\n;2\r\nab\r\n;0\r\n:1\r\n+OK\r\n$-1\r\n").unwrap();
+        assert_eq!(decoder.read(),
+                   Some(BytesValue::Array(vec![bulk("GET"), bulk("key")])));
+        assert_eq!(decoder.read(), Some(bulk("ab")));
+        assert_eq!(decoder.read(), Some(BytesValue::Integer(1)));
+        assert_eq!(decoder.read(), Some(BytesValue::String("OK".to_string())));
+        assert_eq!(decoder.read(), Some(BytesValue::Null));
+        assert_eq!(decoder.read(), None);
+
+        // attributes are dropped unless kept, like with `FeedDecoder`.
+        decoder.feed(b"|1\r\n$1\r\na\r\n$1\r\nb\r\n").unwrap();
+        decoder.feed(b"*2\r\n%1\r\n$1\r\nc\r\n$1\r\nd\r\n$1\r\ne\r\n").unwrap();
+        assert_eq!(decoder.read(),
+                   Some(BytesValue::Array(vec![BytesValue::Map(vec![(bulk("c"), bulk("d"))]),
+                                               bulk("e")])));
+        decoder.set_keep_attributes(true);
+        decoder.feed(b"|1\r\n$1\r\na\r\n$1\r\nb\r\n~1\r\n$1\r\nc\r\n").unwrap();
+        assert_eq!(decoder.read(),
+                   Some(BytesValue::Attribute(vec![(bulk("a"), bulk("b"))],
+                                              Box::new(BytesValue::Set(vec![bulk("c")])))));
+
+        assert!(decoder.feed(b"*1\r\n$3\r\nfoo\r\r").is_err());
+        decoder.feed(b"$3\r\nbar\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(bulk("bar")));
+    }
+
+    #[test]
+    fn struct_bytes_decoder_options() {
+        let mut decoder = BytesDecoder::new();
+        assert!(decoder.feed(b"!3\r\nerr\r\n").is_err());
+        decoder.set_passthrough_unknown(true);
+        decoder.feed(b"!3\r\nerr\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(BytesValue::Unknown(Bytes::from("!3\r\nerr\r\n"))));
+
+        decoder.set_strict_lengths(true);
+        assert!(decoder.feed(b"$03\r\nfoo\r\n").is_err());
+        decoder.set_version(Version::Resp2);
+        assert!(decoder.feed(b"_\r\n").is_err());
+
+        let mut decoder = BytesDecoder::with_str_bulk();
+        decoder.feed(b"*1\r\n$3\r\nfoo\r\n").unwrap();
+        assert_eq!(decoder.read(),
+                   Some(BytesValue::Array(vec![BytesValue::Bulk("foo".to_string())])));
+        assert!(decoder.feed(b"$1\r\n\xff\r\n").is_err());
+        decoder.set_buf_bulk_fallback(true);
+        decoder.feed(b"$1\r\n\xff\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(BytesValue::BufBulk(Bytes::from(&b"\xff"[..]))));
+    }
+
+    #[test]
+    fn struct_bytes_decoder_zero_copy() {
+        let mut decoder = BytesDecoder::new();
+        decoder.feed(b"*2\r\n$3\r\nSET\r\n%1\r\n$5\r\nvalue\r\n:1\r\n").unwrap();
+        let values = match decoder.read() {
+            Some(BytesValue::Array(values)) => values,
+            value => panic!("unexpected value: {:?}", value),
+        };
+        match (&values[0], &values[1]) {
+            (BytesValue::BufBulk(command), BytesValue::Map(pairs)) => {
+                match pairs[0].0 {
+                    BytesValue::BufBulk(ref payload) => {
+                        assert_eq!(command.as_ptr().wrapping_add(13), payload.as_ptr());
+                    }
+                    ref value => panic!("unexpected value: {:?}", value),
+                }
+            }
+            values => panic!("unexpected values: {:?}", values),
+        }
+    }
+
+    #[test]
+    fn fn_encode_bytes() {
+        let value = Value::Array(vec![Value::Bulk("SET".to_string()),
+                                      Value::BufBulk(vec![0xff; 10_000]),
+                                      Value::Map(vec![(Value::Integer(1), Value::Null)])]);
+        let mut buf = BytesMut::from(&b"+OK\r\n"[..]);
+        encode_bytes(&value, &mut buf);
+        let mut expected = b"+OK\r\n".to_vec();
+        expected.extend_from_slice(&value.encode());
+        assert_eq!(&buf[..], &expected[..]);
+
+        // the encoded value decodes zero-copy.
+        let mut decoder = BytesDecoder::new();
+        decoder.feed(&buf.split_off(5)).unwrap();
+        assert_eq!(decoder.read().map(|value| value.to_value()),
+                   Some(Value::Array(vec![Value::BufBulk(b"SET".to_vec()),
+                                          Value::BufBulk(vec![0xff; 10_000]),
+                                          Value::Map(vec![(Value::Integer(1), Value::Null)])])));
+    }
+
+    #[test]
+    fn struct_bytes_decoder_max_frame_len() {
+        let mut decoder = BytesDecoder::new();
+        decoder.set_max_frame_len(16);
+        assert!(decoder.feed(b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n").is_err());
+        decoder.feed(b"$3\r\nfoo\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(bulk("foo")));
+    }
+}
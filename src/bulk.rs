@@ -0,0 +1,236 @@
+//! Lazily read bulk strings
+
+use std::cmp::min;
+use std::io::{self, Read, BufRead, Result, Error, ErrorKind};
+
+use super::serialize::{Decoder, FrameType, Line, parse_line, parse_chunk_header,
+                       check_streamed_len, is_crlf};
+
+/// A reader over the payload of a bulk string, see `Decoder::read_bulk`. It ends with the
+/// payload, once the trailing CRLF is checked.
+#[derive(Debug)]
+pub struct BulkReader<'a, R: 'a> {
+    decoder: &'a mut Decoder<R>,
+    /// the length of the payload, `None` for a streamed bulk string.
+    len: Option<usize>,
+    /// the number of bytes of the payload, or of the current chunk, not read yet.
+    remaining: usize,
+    /// whether the payload, or the current chunk, is followed by a CRLF not checked yet.
+    crlf: bool,
+    /// the number of bytes of the chunks read so far of a streamed bulk string.
+    total: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Reads the header of the next value, which must be a bulk string, and returns a reader
+    /// over its payload, `None` for a null bulk string. The payload is read as the reader is
+    /// read, streamed bulk strings chunk by chunk. The limits of the decoder apply, the bulk
+    /// hook doesn't. Other values fail with `InvalidInput` and are not consumed.
+    /// The payload must be read to its end, or skipped by `BulkReader::finish`, before the
+    /// next value is decoded.
+    /// # Examples
+    /// ```
+    /// # use std::io;
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"$5\r\nvalue\r\n$-1\r\n:1\r\n"[..]);
+    /// let mut output: Vec<u8> = Vec::new();
+    /// {
+    ///     let mut reader = decoder.read_bulk().unwrap().unwrap();
+    ///     assert_eq!(reader.len(), Some(5));
+    ///     io::copy(&mut reader, &mut output).unwrap();
+    /// }
+    /// assert_eq!(output, b"value");
+    /// assert!(decoder.read_bulk().unwrap().is_none());
+    /// assert!(decoder.read_bulk().is_err());
+    /// assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+    /// ```
+    pub fn read_bulk(&mut self) -> Result<Option<BulkReader<'_, R>>> {
+        let len = match self.resumable(Decoder::read_bulk_header)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        Ok(Some(BulkReader::new(self, len)))
+    }
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Reads the header of a bulk string for `read_bulk`, returns the length of its payload,
+    /// `Some(None)` for a streamed bulk string and `None` for a null one.
+    fn read_bulk_header(&mut self) -> Result<Option<Option<usize>>> {
+        let frame_type = self.peek_type()?;
+        if frame_type != FrameType::Bulk {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("expected a bulk string, found {:?}", frame_type)));
+        }
+        self.frame_len = 0;
+        let line = self.read_line()?;
+        let len = match parse_line(&line, &self.options)? {
+            Line::Bulk(int) => {
+                self.consume(int + 2)?;
+                Some(Some(int))
+            }
+            Line::StreamedBulk => Some(None),
+            _ => None,
+        };
+        self.line = line;
+        Ok(len)
+    }
+}
+
+impl<'a, R: BufRead> BulkReader<'a, R> {
+    /// Creates a reader over the payload of `len` bytes, whose bytes are consumed already, or
+    /// over the chunks of a streamed bulk string for `None`.
+    pub(crate) fn new(decoder: &'a mut Decoder<R>, len: Option<usize>) -> Self {
+        BulkReader {
+            decoder,
+            len,
+            remaining: len.unwrap_or(0),
+            crlf: len.is_some(),
+            total: 0,
+            done: false,
+        }
+    }
+
+    /// Returns the length of the payload, `None` for a streamed bulk string.
+    pub fn len(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// Returns `true` if the payload is empty, `false` for a streamed bulk string.
+    pub fn is_empty(&self) -> bool {
+        self.len == Some(0)
+    }
+
+    /// Skips the rest of the payload, still checking its framing, so the decoder can decode
+    /// the next value.
+    pub fn finish(mut self) -> Result<()> {
+        io::copy(&mut self, &mut io::sink()).map(|_| ())
+    }
+
+    /// Checks the CRLF after the payload or the current chunk, then reads the header of the
+    /// next chunk of a streamed bulk string. The reader is updated once they are read, so a
+    /// nonblocking decoder replays them all after a `WouldBlock`.
+    fn next_payload(&mut self) -> Result<()> {
+        let (crlf, streamed, total) = (self.crlf, self.len.is_none(), self.total);
+        let int = self.decoder.resumable(|decoder| {
+            if crlf {
+                let mut crlf = [0u8; 2];
+                decoder.reader.read_exact(&mut crlf)?;
+                if !is_crlf(crlf[0], crlf[1]) {
+                    return Err(Error::new(ErrorKind::InvalidInput,
+                                          format!("invalid CRLF: {:?}", crlf)));
+                }
+            }
+            if !streamed {
+                return Ok(0);
+            }
+            let line = decoder.read_line()?;
+            let int = parse_chunk_header(&line, &decoder.options)?;
+            decoder.line = line;
+            if int > 0 {
+                check_streamed_len(total, int, &decoder.options)?;
+                decoder.consume(int + 2)?;
+            }
+            Ok(int)
+        })?;
+        self.crlf = int > 0;
+        self.done = int == 0;
+        self.total += int;
+        self.remaining = int;
+        Ok(())
+    }
+}
+
+impl<'a, R: BufRead> Read for BulkReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        while self.remaining == 0 && !self.done {
+            self.next_payload()?;
+        }
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = {
+            let chunk = self.decoder.reader.fill_buf()?;
+            if chunk.is_empty() {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+            }
+            let n = min(min(buf.len(), chunk.len()), self.remaining);
+            buf[..n].copy_from_slice(&chunk[..n]);
+            n
+        };
+        self.decoder.reader.consume(n);
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, ErrorKind};
+    use super::super::{Decoder, Value};
+
+    #[test]
+    fn struct_bulk_reader() {
+        let payload: Vec<u8> = (0..100_000).map(|i| (i % 13) as u8).collect();
+        let mut buf = Value::BufBulk(payload.clone()).encode();
+        buf.extend_from_slice(b"$?\r\n;3\r\nstr\r\n;5\r\neamed\r\n;0\r\n");
+        buf.extend_from_slice(b"$0\r\n\r\n$3\r\nfoo\r\n:1\r\n");
+
+        let mut decoder = Decoder::from_chunks(buf.chunks(1000));
+        let mut output: Vec<u8> = Vec::new();
+        {
+            let mut reader = decoder.read_bulk().unwrap().unwrap();
+            assert_eq!(reader.len(), Some(100_000));
+            assert_eq!(io::copy(&mut reader, &mut output).unwrap(), 100_000);
+            assert_eq!(reader.read(&mut [0u8; 8]).unwrap(), 0);
+        }
+        assert_eq!(output, payload);
+        output.clear();
+        {
+            let mut reader = decoder.read_bulk().unwrap().unwrap();
+            assert_eq!(reader.len(), None);
+            reader.read_to_end(&mut output).unwrap();
+        }
+        assert_eq!(output, b"streamed");
+        output.clear();
+        {
+            let mut reader = decoder.read_bulk().unwrap().unwrap();
+            assert!(reader.is_empty());
+            reader.read_to_end(&mut output).unwrap();
+        }
+        assert!(output.is_empty());
+        // a reader partly read is finished to decode the next value.
+        {
+            let mut reader = decoder.read_bulk().unwrap().unwrap();
+            assert_eq!(reader.read(&mut [0u8; 1]).unwrap(), 1);
+            reader.finish().unwrap();
+        }
+        assert_eq!(decoder.read_bulk().unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+        assert_eq!(decoder.bytes_consumed(), buf.len() as u64);
+        assert_eq!(decoder.read_bulk().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn struct_bulk_reader_invalid() {
+        let invalid: &[&[u8]] = &[b"$3\r\nfoo\r\r",
+                                  b"$?\r\n;1\r\na\r\n.\r\n",
+                                  b"$?\r\n;1\r\nab\r\n"];
+        for case in invalid {
+            let mut decoder = Decoder::new(*case);
+            let reader = decoder.read_bulk().unwrap().unwrap();
+            assert_eq!(reader.finish().unwrap_err().kind(), ErrorKind::InvalidInput, "{:?}", case);
+        }
+        let mut decoder = Decoder::new(&b"$3\r\nfo"[..]);
+        let reader = decoder.read_bulk().unwrap().unwrap();
+        assert_eq!(reader.finish().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut decoder = Decoder::new(&b"$-2\r\n"[..]);
+        assert_eq!(decoder.read_bulk().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"$?\r\n;2\r\nab\r\n;2\r\ncd\r\n;0\r\n"[..]);
+        decoder.set_max_bulk_len(3);
+        let reader = decoder.read_bulk().unwrap().unwrap();
+        assert_eq!(reader.finish().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
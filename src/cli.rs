@@ -0,0 +1,205 @@
+//! RESP value formatting compatible with redis-cli
+
+use std::string::String;
+
+use super::Value;
+use super::serialize::format_double;
+
+impl Value {
+    /// Formats the value exactly like redis-cli prints a reply on a terminal, so tools can
+    /// produce the same output as redis-cli for scripts and golden files:
+    ///
+    /// - `Null` and `NullArray` -> `(nil)`
+    /// - `String` -> the text as is, `Error` -> `(error) ERR ...`
+    /// - `Integer` -> `(integer) 1`, `Double` -> `(double) 1.5`, `BigNumber` -> `(big number) 1`
+    /// - `Boolean` -> `(true)` or `(false)`
+    /// - `Bulk` and `BufBulk` -> quoted, escaping `\` and `"`, `\n`, `\r`, `\t`, `\a`, `\b`, and
+    ///   other bytes outside of printable ASCII as `\xHH`
+    /// - `Array` and `Push` elements are numbered `1)`, `Set` elements `1~` and `Map` entries
+    ///   `1# key => value`, nested values are indented after the numbers
+    /// - `Attribute` -> its value, the attributes are not printed
+    ///
+    /// Every line ends with `\n`, including the last one.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Array(vec![Value::Bulk("a".to_string()),
+    ///                                                 Value::Null]),
+    ///                               Value::Integer(1),
+    ///                               Value::Bulk("中".to_string())]);
+    /// assert_eq!(reply.to_string_redis_cli(),
+    ///            "1) 1) \"a\"\n   2) (nil)\n2) (integer) 1\n3) \"\\xe4\\xb8\\xad\"\n");
+    /// ```
+    pub fn to_string_redis_cli(&self) -> String {
+        let mut out = String::new();
+        write_reply(self, "", &mut out);
+        out
+    }
+}
+
+fn write_reply(value: &Value, prefix: &str, out: &mut String) {
+    match *value {
+        Value::Null | Value::NullArray => out.push_str("(nil)\n"),
+        Value::String(ref val) => {
+            out.push_str(val);
+            out.push('\n');
+        }
+        Value::Error(ref val) => out.push_str(&format!("(error) {}\n", val)),
+        Value::Integer(val) => out.push_str(&format!("(integer) {}\n", val)),
+        Value::Double(val) => out.push_str(&format!("(double) {}\n", format_double(val))),
+        Value::BigNumber(ref val) => out.push_str(&format!("(big number) {}\n", val)),
+        Value::Boolean(val) => out.push_str(if val { "(true)\n" } else { "(false)\n" }),
+        Value::Bulk(ref val) => write_repr(val.as_bytes(), out),
+        Value::BufBulk(ref val) => write_repr(val, out),
+        Value::SharedBulk(ref val) => write_repr(val, out),
+        Value::SmallBulk(ref val) => write_repr(val, out),
+        Value::Array(ref val) if val.is_empty() => out.push_str("(empty array)\n"),
+        Value::Map(ref val) if val.is_empty() => out.push_str("(empty hash)\n"),
+        Value::Set(ref val) if val.is_empty() => out.push_str("(empty set)\n"),
+        Value::Push(ref val) if val.is_empty() => out.push_str("(empty push)\n"),
+        Value::Array(ref val) | Value::Push(ref val) => write_array(val, ')', prefix, out),
+        Value::Set(ref val) => write_array(val, '~', prefix, out),
+        Value::Map(ref val) => write_map(val, prefix, out),
+        Value::Attribute(_, ref val) => write_reply(val, prefix, out),
+        Value::Unknown(ref val) => write_repr(val, out),
+        Value::Raw(ref val) => {
+            match value.decode_raw() {
+                Some(decoded) => write_reply(&decoded, prefix, out),
+                None => write_repr(val, out),
+            }
+        }
+    }
+}
+
+/// Writes the bytes quoted like `sdscatrepr` of redis-cli.
+fn write_repr(bytes: &[u8], out: &mut String) {
+    out.push('"');
+    for &byte in bytes {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push_str("\"\n");
+}
+
+/// Writes the number of an element, the first one follows the number of its parent, so it
+/// doesn't need the prefix.
+fn write_index(i: usize, len: usize, delimiter: char, prefix: &str, out: &mut String) {
+    if i > 0 {
+        out.push_str(prefix);
+    }
+    let width = len.to_string().len();
+    out.push_str(&format!("{:>width$}{} ", i + 1, delimiter, width = width));
+}
+
+/// Returns the prefix of the nested lines, aligned after the numbers of the elements.
+fn nested_prefix(prefix: &str, len: usize) -> String {
+    format!("{}{}", prefix, " ".repeat(len.to_string().len() + 2))
+}
+
+fn write_array(values: &[Value], delimiter: char, prefix: &str, out: &mut String) {
+    let nested = nested_prefix(prefix, values.len());
+    for (i, value) in values.iter().enumerate() {
+        write_index(i, values.len(), delimiter, prefix, out);
+        write_reply(value, &nested, out);
+    }
+}
+
+fn write_map(pairs: &[(Value, Value)], prefix: &str, out: &mut String) {
+    let nested = nested_prefix(prefix, pairs.len());
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        write_index(i, pairs.len(), '#', prefix, out);
+        write_reply(key, &nested, out);
+        // the value follows the last line of the key.
+        out.pop();
+        out.push_str(" => ");
+        write_reply(value, &nested, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fn_to_string_redis_cli() {
+        let cases = vec![(Value::Null, "(nil)\n"),
+                         (Value::NullArray, "(nil)\n"),
+                         (Value::String("OK".to_string()), "OK\n"),
+                         (Value::Error("ERR unknown".to_string()), "(error) ERR unknown\n"),
+                         (Value::Integer(-1), "(integer) -1\n"),
+                         (Value::Double(1.5), "(double) 1.5\n"),
+                         (Value::Double(f64::NEG_INFINITY), "(double) -inf\n"),
+                         (Value::BigNumber("12345678901234567890".to_string()),
+                          "(big number) 12345678901234567890\n"),
+                         (Value::Boolean(false), "(false)\n"),
+                         (Value::Bulk("".to_string()), "\"\"\n"),
+                         (Value::Bulk("a \"b\"\\\r\n\t\x07\x08\x00\x7f~".to_string()),
+                          "\"a \\\"b\\\"\\\\\\r\\n\\t\\a\\b\\x00\\x7f~\"\n"),
+                         (Value::BufBulk(vec![0xff, b'a']), "\"\\xffa\"\n"),
+                         (Value::Array(vec![]), "(empty array)\n"),
+                         (Value::Map(vec![]), "(empty hash)\n"),
+                         (Value::Set(vec![]), "(empty set)\n"),
+                         (Value::Push(vec![]), "(empty push)\n"),
+                         (Value::Set(vec![Value::Integer(1), Value::Integer(2)]),
+                          "1~ (integer) 1\n2~ (integer) 2\n"),
+                         (Value::Attribute(vec![(Value::Null, Value::Null)],
+                                           Box::new(Value::Boolean(true))),
+                          "(true)\n"),
+                         (Value::Raw(b"*1\r\n:1\r\n".to_vec()), "1) (integer) 1\n"),
+                         (Value::Raw(b":1".to_vec()), "\":1\"\n")];
+        for (value, expected) in cases {
+            assert_eq!(value.to_string_redis_cli(), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn fn_to_string_redis_cli_nested() {
+        let mut values: Vec<Value> = (1..10).map(Value::Integer).collect();
+        values.push(Value::Array(vec![Value::Bulk("a".to_string()),
+                                      Value::Array(vec![Value::Null, Value::Null])]));
+        assert_eq!(Value::Array(values).to_string_redis_cli(),
+                   [" 1) (integer) 1",
+                    " 2) (integer) 2",
+                    " 3) (integer) 3",
+                    " 4) (integer) 4",
+                    " 5) (integer) 5",
+                    " 6) (integer) 6",
+                    " 7) (integer) 7",
+                    " 8) (integer) 8",
+                    " 9) (integer) 9",
+                    "10) 1) \"a\"",
+                    "    2) 1) (nil)",
+                    "       2) (nil)",
+                    ""]
+                           .join("\n"));
+
+        let hello = Value::Map(vec![(Value::Bulk("server".to_string()),
+                                     Value::Bulk("redis".to_string())),
+                                    (Value::Bulk("proto".to_string()), Value::Integer(3)),
+                                    (Value::Bulk("modules".to_string()), Value::Array(vec![])),
+                                    (Value::Bulk("flags".to_string()),
+                                     Value::Set(vec![Value::String("a".to_string()),
+                                                     Value::String("b".to_string())])),
+                                    (Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                                     Value::Null)]);
+        assert_eq!(hello.to_string_redis_cli(),
+                   ["1# \"server\" => \"redis\"",
+                    "2# \"proto\" => (integer) 3",
+                    "3# \"modules\" => (empty array)",
+                    "4# \"flags\" => 1~ a",
+                    "   2~ b",
+                    "5# 1) (integer) 1",
+                    "   2) (integer) 2 => (nil)",
+                    ""]
+                           .join("\n"));
+    }
+}
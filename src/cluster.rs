@@ -0,0 +1,120 @@
+//! RESP cluster redirections
+
+use std::string::String;
+
+use super::Value;
+
+/// The number of hash slots of a cluster.
+const CLUSTER_SLOTS: u16 = 16384;
+
+/// The kind of a cluster redirection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RedirectKind {
+    /// `MOVED`, the slot is served by another node, the client should update its slot map.
+    Moved,
+    /// `ASK`, the slot is being migrated, the client should send `ASKING` and retry the one
+    /// command on the other node.
+    Ask,
+}
+
+/// Represents a cluster redirection error, e.g. `-MOVED 3999 127.0.0.1:6381`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Redirect {
+    /// `MOVED` or `ASK`.
+    pub kind: RedirectKind,
+    /// The hash slot of the key.
+    pub slot: u16,
+    /// The host of the node serving the slot, empty if the node doesn't know its endpoint, in
+    /// which case the host of the node sending the redirection is meant.
+    pub host: String,
+    /// The port of the node serving the slot.
+    pub port: u16,
+}
+
+impl Redirect {
+    /// Recognizes a `MOVED` or `ASK` error. Returns `None` for other values, or if the error is
+    /// malformed.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, Redirect, RedirectKind};
+    /// let error = Value::Error("MOVED 3999 127.0.0.1:6381".to_string());
+    /// let redirect = Redirect::from_resp(&error).unwrap();
+    /// assert_eq!(redirect.kind, RedirectKind::Moved);
+    /// assert_eq!(redirect.slot, 3999);
+    /// assert_eq!(redirect.addr(), "127.0.0.1:6381");
+    ///
+    /// assert_eq!(Redirect::from_resp(&Value::Error("ERR".to_string())), None);
+    /// ```
+    pub fn from_resp(value: &Value) -> Option<Redirect> {
+        match *value {
+            Value::Error(ref val) => Redirect::parse(val),
+            _ => None,
+        }
+    }
+
+    /// Parses the text of a `MOVED` or `ASK` error, see `Redirect::from_resp`.
+    pub fn parse(error: &str) -> Option<Redirect> {
+        let mut parts = error.split(' ');
+        let kind = match parts.next()? {
+            "MOVED" => RedirectKind::Moved,
+            "ASK" => RedirectKind::Ask,
+            _ => return None,
+        };
+        let slot = parts.next()?.parse::<u16>().ok().filter(|slot| *slot < CLUSTER_SLOTS)?;
+        // the host may be an IPv6 address, the port follows the last colon.
+        let (host, port) = parts.next()?.rsplit_once(':')?;
+        let port = port.parse::<u16>().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Redirect {
+                 kind,
+                 slot,
+                 host: host.to_string(),
+                 port,
+             })
+    }
+
+    /// Returns the `host:port` address of the node serving the slot.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn struct_redirect_from_resp() {
+        let redirect = Redirect::from_resp(&Value::Error("ASK 0 ::1:7000".to_string())).unwrap();
+        assert_eq!(redirect,
+                   Redirect {
+                       kind: RedirectKind::Ask,
+                       slot: 0,
+                       host: "::1".to_string(),
+                       port: 7000,
+                   });
+        assert_eq!(redirect.addr(), "::1:7000");
+
+        let redirect = Redirect::parse("MOVED 16383 :6380").unwrap();
+        assert_eq!(redirect.kind, RedirectKind::Moved);
+        assert_eq!(redirect.slot, 16383);
+        assert_eq!(redirect.host, "");
+
+        let invalid = ["MOVED",
+                       "MOVED 3999",
+                       "MOVED 16384 127.0.0.1:6381",
+                       "MOVED -1 127.0.0.1:6381",
+                       "MOVED 3999 127.0.0.1",
+                       "MOVED 3999 127.0.0.1:65536",
+                       "MOVED 3999 127.0.0.1:6381 extra",
+                       "moved 3999 127.0.0.1:6381",
+                       "ERR MOVED 3999 127.0.0.1:6381"];
+        for error in &invalid {
+            assert_eq!(Redirect::parse(error), None, "{}", error);
+        }
+        assert_eq!(Redirect::from_resp(&Value::String("MOVED 1 a:1".to_string())), None);
+        assert_eq!(Redirect::from_resp(&Value::Null), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! Building commands
+
+use std::vec::Vec;
+use std::string::String;
+
+use super::Value;
+use super::serialize::format_double;
+
+/// A command argument, converted to a bulk string by `cmd!`. Strings and bytes are taken as
+/// they are, numbers are written in decimal, the way Redis reads them.
+pub trait ToArg {
+    /// Converts to the argument value.
+    fn to_arg(&self) -> Value;
+}
+
+impl ToArg for str {
+    fn to_arg(&self) -> Value {
+        Value::Bulk(self.to_string())
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(&self) -> Value {
+        Value::Bulk(self.clone())
+    }
+}
+
+impl ToArg for [u8] {
+    fn to_arg(&self) -> Value {
+        Value::BufBulk(self.to_vec())
+    }
+}
+
+impl<const N: usize> ToArg for [u8; N] {
+    fn to_arg(&self) -> Value {
+        Value::BufBulk(self.to_vec())
+    }
+}
+
+impl ToArg for Vec<u8> {
+    fn to_arg(&self) -> Value {
+        Value::BufBulk(self.clone())
+    }
+}
+
+/// A bulk string is taken as it is, e.g. a bulk string of a reply. Other values are converted
+/// to a bulk string: the text of simple strings, errors and big numbers, integers and doubles in
+/// decimal, booleans as `1` or `0`, and the RESP encoding of any other value.
+impl ToArg for Value {
+    fn to_arg(&self) -> Value {
+        match *self {
+            Value::Bulk(_) | Value::BufBulk(_) | Value::SharedBulk(_) | Value::SmallBulk(_) => {
+                self.clone()
+            }
+            Value::String(ref val) | Value::Error(ref val) | Value::BigNumber(ref val) => {
+                Value::Bulk(val.clone())
+            }
+            Value::Integer(val) => val.to_arg(),
+            Value::Double(val) => val.to_arg(),
+            Value::Boolean(val) => (val as u8).to_arg(),
+            Value::Unknown(ref val) | Value::Raw(ref val) => Value::BufBulk(val.clone()),
+            _ => Value::BufBulk(self.encode()),
+        }
+    }
+}
+
+impl<T: ToArg + ?Sized> ToArg for &T {
+    fn to_arg(&self) -> Value {
+        (**self).to_arg()
+    }
+}
+
+macro_rules! impl_to_arg_for_number {
+    ($($ty:ty),*) => {
+        $(
+            impl ToArg for $ty {
+                fn to_arg(&self) -> Value {
+                    Value::Bulk(self.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_to_arg_for_number!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+impl ToArg for f64 {
+    fn to_arg(&self) -> Value {
+        Value::Bulk(format_double(*self))
+    }
+}
+
+impl ToArg for f32 {
+    fn to_arg(&self) -> Value {
+        Value::Bulk(format_double(*self as f64))
+    }
+}
+
+/// Builds a command, an array of bulk strings, from arguments of mixed types, see `ToArg`.
+/// The arguments are borrowed. `.encode()` encodes the command to send it.
+/// # Examples
+/// ```
+/// # #[macro_use] extern crate resp;
+/// # use resp::{Value, encode_slice};
+/// # fn main() {
+/// let key = "counter".to_string();
+/// let command = cmd!("INCRBY", key, 10);
+/// assert_eq!(command,
+///            Value::Array(vec![Value::Bulk("INCRBY".to_string()),
+///                              Value::Bulk("counter".to_string()),
+///                              Value::Bulk("10".to_string())]));
+/// assert_eq!(command.encode(), encode_slice(&["INCRBY", "counter", "10"]));
+///
+/// let value: &[u8] = &[0xff, 0];
+/// assert_eq!(cmd!("SET", b"key", value, "EX", 1.5).encode(),
+///            b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$2\r\n\xff\x00\r\n$2\r\nEX\r\n$3\r\n1.5\r\n");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! cmd {
+    ($($arg:expr),* $(,)*) => {
+        $crate::Value::Array(vec![$($crate::ToArg::to_arg(&$arg)),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::encode_slice;
+
+    #[test]
+    fn trait_to_arg() {
+        let bulk = |val: &str| Value::Bulk(val.to_string());
+        assert_eq!("GET".to_arg(), bulk("GET"));
+        assert_eq!("GET".to_string().to_arg(), bulk("GET"));
+        assert_eq!((&&"GET").to_arg(), bulk("GET"));
+        assert_eq!(b"GET".to_arg(), Value::BufBulk(b"GET".to_vec()));
+        assert_eq!(b"GET"[..].to_arg(), Value::BufBulk(b"GET".to_vec()));
+        assert_eq!(vec![0xffu8].to_arg(), Value::BufBulk(vec![0xff]));
+        assert_eq!((-1i8).to_arg(), bulk("-1"));
+        assert_eq!(u64::MAX.to_arg(), bulk("18446744073709551615"));
+        assert_eq!(0.5f32.to_arg(), bulk("0.5"));
+        assert_eq!((1.0f64 / 0.0).to_arg(), bulk("inf"));
+        assert_eq!(Value::BufBulk(vec![0xff]).to_arg(), Value::BufBulk(vec![0xff]));
+        assert_eq!(Value::Integer(1).to_arg(), bulk("1"));
+        assert_eq!(Value::String("OK".to_string()).to_arg(), bulk("OK"));
+        assert_eq!(Value::Double(1.5).to_arg(), bulk("1.5"));
+        assert_eq!(Value::Boolean(true).to_arg(), bulk("1"));
+        assert_eq!(Value::Array(vec![Value::Integer(1)]).to_arg(),
+                   Value::BufBulk(b"*1\r\n:1\r\n".to_vec()));
+    }
+
+    #[test]
+    fn macro_cmd() {
+        assert_eq!(cmd!(), Value::Array(vec![]));
+        assert_eq!(cmd!("PING",), Value::Array(vec![Value::Bulk("PING".to_string())]));
+
+        // the arguments are borrowed.
+        let key = "a".to_string();
+        let values = [b"x".to_vec(), b"y".to_vec()];
+        let command = cmd!("RPUSH", key, values[0], values[1]);
+        assert_eq!(command,
+                   Value::Array(vec![Value::Bulk("RPUSH".to_string()),
+                                     Value::Bulk("a".to_string()),
+                                     Value::BufBulk(b"x".to_vec()),
+                                     Value::BufBulk(b"y".to_vec())]));
+        assert_eq!(key, "a");
+        assert_eq!(command.encode(), encode_slice(&[&b"RPUSH"[..], b"a", b"x", b"y"]));
+
+        // every argument is a bulk string, whatever its value.
+        assert_eq!(cmd!("INCRBY", "a", Value::Integer(1)).encode(),
+                   encode_slice(&["INCRBY", "a", "1"]));
+    }
+}
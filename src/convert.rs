@@ -0,0 +1,322 @@
+//! RESP value conversion
+
+use std::vec::Vec;
+use std::string::String;
+use std::convert::TryFrom;
+use std::io::{Result, Error, ErrorKind};
+
+use super::Value;
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
+
+/// Converts a RESP value into a Rust type. `String`, `Vec<u8>`, `bool`, `Vec<Value>` and the
+/// integers implement `TryFrom<Value>` the same way.
+/// # Examples
+/// ```
+/// # use self::resp::{Value};
+/// # use std::convert::{TryFrom, TryInto};
+/// let reply = Value::Integer(42);
+/// let n: i64 = reply.try_into().unwrap();
+/// assert_eq!(n, 42);
+/// let err = String::try_from(Value::Null).unwrap_err();
+/// assert_eq!(err.to_string(), "expected string, found null");
+/// ```
+pub trait FromResp: Sized {
+    /// Converts the value, fails with `InvalidData` if the value has an unexpected type.
+    fn from_resp(value: Value) -> Result<Self>;
+}
+
+impl FromResp for Value {
+    fn from_resp(value: Value) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl FromResp for String {
+    fn from_resp(value: Value) -> Result<Self> {
+        match value {
+            Value::String(val) | Value::Bulk(val) => Ok(val),
+            Value::BufBulk(val) => {
+                String::from_utf8(val).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+            Value::SharedBulk(val) => {
+                ::std::str::from_utf8(&val)
+                    .map(str::to_string)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+            Value::SmallBulk(val) => {
+                ::std::str::from_utf8(&val)
+                    .map(str::to_string)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+            value => Err(unexpected("string", &value)),
+        }
+    }
+}
+
+impl FromResp for Vec<u8> {
+    fn from_resp(value: Value) -> Result<Self> {
+        match value {
+            Value::String(val) | Value::Bulk(val) => Ok(val.into_bytes()),
+            Value::BufBulk(val) => Ok(val),
+            Value::SharedBulk(val) => Ok(val.to_vec()),
+            Value::SmallBulk(val) => Ok(val.to_vec()),
+            value => Err(unexpected("bulk", &value)),
+        }
+    }
+}
+
+impl FromResp for bool {
+    fn from_resp(value: Value) -> Result<Self> {
+        match value {
+            Value::Integer(0) => Ok(false),
+            Value::Integer(1) => Ok(true),
+            Value::Boolean(val) => Ok(val),
+            value => Err(unexpected("boolean or integer 0 or 1", &value)),
+        }
+    }
+}
+
+impl<T: FromResp> FromResp for Option<T> {
+    fn from_resp(value: Value) -> Result<Self> {
+        if value.is_null() {
+            return Ok(None);
+        }
+        T::from_resp(value).map(Some)
+    }
+}
+
+impl<T: FromResp> FromResp for Vec<T> {
+    fn from_resp(value: Value) -> Result<Self> {
+        match value {
+            Value::Array(values) => {
+                let mut res: Vec<T> = Vec::with_capacity(values.len());
+                for (i, value) in values.into_iter().enumerate() {
+                    res.push(T::from_resp(value)
+                                 .map_err(|err| Error::new(err.kind(), format!("[{}]: {}", i, err)))?);
+                }
+                Ok(res)
+            }
+            value => Err(unexpected("array", &value)),
+        }
+    }
+}
+
+macro_rules! impl_from_resp_for_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl FromResp for $ty {
+                fn from_resp(value: Value) -> Result<Self> {
+                    let int = match value {
+                        Value::Integer(val) => {
+                            return <$ty>::try_from(val).map_err(|err| {
+                                Error::new(ErrorKind::InvalidData,
+                                           format!("invalid integer {}: {}", val, err))
+                            });
+                        }
+                        // Some replies, like the SCAN cursor, carry integers as strings.
+                        Value::String(val) | Value::Bulk(val) | Value::BigNumber(val) => val,
+                        Value::BufBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+                        Value::SharedBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+                        Value::SmallBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+                        value => return Err(unexpected("integer", &value)),
+                    };
+                    int.parse::<$ty>().map_err(|err| {
+                        Error::new(ErrorKind::InvalidData,
+                                   format!("invalid integer {:?}: {}", int, err))
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_from_resp_for_integer!(i64, u64, i32, u32, isize, usize);
+
+/// Converts a big number, an integer or a string of digits, requires the `bignum` feature.
+#[cfg(feature = "bignum")]
+impl FromResp for BigInt {
+    fn from_resp(value: Value) -> Result<Self> {
+        let int = match value {
+            Value::Integer(val) => return Ok(BigInt::from(val)),
+            Value::String(val) | Value::Bulk(val) | Value::BigNumber(val) => val,
+            Value::BufBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+            Value::SharedBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+            Value::SmallBulk(val) => String::from_utf8_lossy(&val).into_owned(),
+            value => return Err(unexpected("big number", &value)),
+        };
+        int.parse::<BigInt>().map_err(|err| {
+            Error::new(ErrorKind::InvalidData,
+                       format!("invalid big number {:?}: {}", int, err))
+        })
+    }
+}
+
+macro_rules! impl_try_from_value {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<Value> for $ty {
+                type Error = Error;
+
+                /// Converts the value with `FromResp`.
+                fn try_from(value: Value) -> Result<Self> {
+                    <$ty as FromResp>::from_resp(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_value!(String, Vec<u8>, bool, Vec<Value>, i64, u64, i32, u32, isize, usize);
+#[cfg(feature = "bignum")]
+impl_try_from_value!(BigInt);
+
+fn unexpected(expected: &str, value: &Value) -> Error {
+    Error::new(ErrorKind::InvalidData,
+               format!("expected {}, found {}", expected, value.type_name()))
+}
+
+/// Unwraps an array with the given number of elements, used by `expect_array!`.
+#[doc(hidden)]
+pub fn expect_array(value: Value, names: &[&str]) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(values) => {
+            if values.len() != names.len() {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("expected array of {} elements ({}), found array \
+                                               of {} elements",
+                                              names.len(),
+                                              names.join(", "),
+                                              values.len())));
+            }
+            Ok(values)
+        }
+        value => Err(unexpected("array", &value)),
+    }
+}
+
+/// Converts a named array element, used by `expect_array!`.
+#[doc(hidden)]
+pub fn from_element<T: FromResp>(value: Value, name: &str) -> Result<T> {
+    T::from_resp(value).map_err(|err| Error::new(err.kind(), format!("{}: {}", name, err)))
+}
+
+/// Destructures an array value into a tuple of typed elements, evaluates to `io::Result`.
+/// Each element is converted with `FromResp`, a failed conversion reports the element name.
+/// # Examples
+/// ```
+/// # #[macro_use] extern crate resp;
+/// # use resp::Value;
+/// # fn main() {
+/// let reply = Value::Array(vec![Value::Bulk("17".to_string()),
+///                               Value::Array(vec![Value::Bulk("key".to_string())])]);
+/// let (cursor, keys) = expect_array!(reply => (cursor: u64, keys: Vec<String>)).unwrap();
+/// assert_eq!(cursor, 17);
+/// assert_eq!(keys, vec!["key".to_string()]);
+///
+/// let reply = Value::Array(vec![Value::Null, Value::Array(vec![])]);
+/// let err = expect_array!(reply => (cursor: u64, keys: Vec<String>)).unwrap_err();
+/// assert_eq!(err.to_string(), "cursor: expected integer, found null");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! expect_array {
+    ($value:expr => ($($name:ident : $ty:ty),+ $(,)*)) => {
+        $crate::__private::expect_array($value, &[$(stringify!($name)),+]).and_then(|values| {
+            let mut values = values.into_iter();
+            Ok(($($crate::__private::from_element::<$ty>(values.next().unwrap(),
+                                                         stringify!($name))?,)+))
+        })
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trait_from_resp() {
+        assert_eq!(String::from_resp(Value::Bulk("foo".to_string())).unwrap(), "foo");
+        assert_eq!(String::from_resp(Value::BufBulk(vec![79, 75])).unwrap(), "OK");
+        assert!(String::from_resp(Value::BufBulk(vec![255])).is_err());
+        assert_eq!(Vec::<u8>::from_resp(Value::String("OK".to_string())).unwrap(),
+                   vec![79, 75]);
+        assert_eq!(i64::from_resp(Value::Integer(-1)).unwrap(), -1);
+        assert_eq!(u64::from_resp(Value::Bulk("42".to_string())).unwrap(), 42);
+        assert!(u64::from_resp(Value::Integer(-1)).is_err());
+        assert_eq!(u64::from_resp(Value::BigNumber("18446744073709551615".to_string())).unwrap(),
+                   u64::MAX);
+        assert!(i64::from_resp(Value::BigNumber("9223372036854775808".to_string())).is_err());
+        assert_eq!(u32::from_resp(Value::Integer(7)).unwrap(), 7);
+        assert_eq!(u64::from_resp(Value::Integer(-1)).unwrap_err().to_string(),
+                   "invalid integer -1: out of range integral type conversion attempted");
+        assert!(i32::from_resp(Value::Integer(i64::MAX)).is_err());
+        assert!(bool::from_resp(Value::Integer(1)).unwrap());
+        assert!(bool::from_resp(Value::Integer(2)).is_err());
+        assert!(!bool::from_resp(Value::Boolean(false)).unwrap());
+        assert_eq!(Option::<i64>::from_resp(Value::Null).unwrap(), None);
+        assert_eq!(Option::<i64>::from_resp(Value::Integer(1)).unwrap(), Some(1));
+
+        let value = Value::Array(vec![Value::Integer(1), Value::Bulk("x".to_string())]);
+        let err = Vec::<i64>::from_resp(value).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "[1]: invalid integer \"x\": invalid digit found in string");
+
+        let err = i64::from_resp(Value::Error("ERR".to_string())).unwrap_err();
+        assert_eq!(err.to_string(), "expected integer, found error");
+    }
+
+    #[test]
+    fn trait_try_from() {
+        assert_eq!(String::try_from(Value::String("OK".to_string())).unwrap(), "OK");
+        assert_eq!(Vec::<u8>::try_from(Value::BufBulk(vec![255])).unwrap(), vec![255]);
+        assert!(bool::try_from(Value::Boolean(true)).unwrap());
+        assert_eq!(i64::try_from(Value::Integer(-1)).unwrap(), -1);
+        assert_eq!(usize::try_from(Value::Bulk("3".to_string())).unwrap(), 3);
+        assert_eq!(Vec::<Value>::try_from(Value::Array(vec![Value::Null])).unwrap(),
+                   vec![Value::Null]);
+
+        let err = u32::try_from(Value::Integer(-1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = Vec::<Value>::try_from(Value::Map(vec![])).unwrap_err();
+        assert_eq!(err.to_string(), "expected array, found map");
+    }
+
+    #[test]
+    #[cfg(feature = "bignum")]
+    fn trait_from_resp_big_int() {
+        use super::super::Decoder;
+        use std::io::BufReader;
+
+        let digits = "-3492890328409238509324850943850943825024385";
+        let big: BigInt = digits.parse().unwrap();
+        let buf = Value::from(big.clone()).encode();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(BigInt::from_resp(decoder.decode().unwrap()).unwrap(), big);
+        assert_eq!(BigInt::from_resp(Value::Bulk(digits.to_string())).unwrap(), big);
+        assert_eq!(BigInt::from_resp(Value::Integer(-7)).unwrap(), BigInt::from(-7));
+        assert!(BigInt::from_resp(Value::BigNumber("12a".to_string())).is_err());
+        assert!(BigInt::from_resp(Value::Null).is_err());
+        assert_eq!(BigInt::try_from(Value::Integer(-7)).unwrap(), BigInt::from(-7));
+    }
+
+    #[test]
+    fn macro_expect_array() {
+        let reply = Value::Array(vec![Value::Integer(1), Value::Null, Value::Integer(3)]);
+        let (a, b, c) = expect_array!(reply.clone() => (a: i64, b: Option<String>, c: Value))
+            .unwrap();
+        assert_eq!(a, 1);
+        assert_eq!(b, None);
+        assert_eq!(c, Value::Integer(3));
+
+        let err = expect_array!(reply.clone() => (a: i64, b: String)).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "expected array of 2 elements (a, b), found array of 3 elements");
+
+        let err = expect_array!(reply => (a: i64, b: String, c: i64)).unwrap_err();
+        assert_eq!(err.to_string(), "b: expected string, found null");
+
+        let err = expect_array!(Value::NullArray => (a: i64)).unwrap_err();
+        assert_eq!(err.to_string(), "expected array, found null array");
+    }
+}
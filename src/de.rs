@@ -0,0 +1,1457 @@
+//! RESP push-based decoding
+
+use std::vec::Vec;
+use std::str;
+use std::string::String;
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write, Result, Error, ErrorKind};
+
+use memchr::memchr;
+
+use super::Value;
+use super::error::incomplete;
+use super::serialize::{CRLF_BYTES, STREAMED_END_BYTES, Decoder, FrameType, Options, Version,
+                       Line, Leaf, Aggregate, encode,
+                       encode_len_header, is_crlf, check_line, parse_line, parse_frame_line,
+                       parse_chunk_header, check_streamed_len, check_max_frame_len,
+                       check_max_line_len, bulk_value, bulk_slice_value, sequence_prefix};
+
+/// A resumable RESP parser, aggregates are tracked on an explicit stack so a value can be parsed
+/// across several calls. Lines and bulk strings are only consumed when they are complete.
+#[derive(Debug)]
+pub(crate) struct Parser {
+    pub(crate) options: Options,
+    /// partial aggregates, with their declared lengths, `None` for streamed aggregates.
+    stack: Vec<(Aggregate, Option<usize>, Vec<Value>)>,
+    /// the number of bytes consumed of the value being parsed.
+    frame_len: usize,
+    /// when recording, the positions of the strings and bytes of the values parsed, in order:
+    /// the text of simple strings, errors and big numbers, the payload of bulk strings, `None`
+    /// for streamed bulk strings, and the whole of unknown values. Bulk strings are then left
+    /// empty.
+    spans: Option<Vec<Option<(usize, usize)>>>,
+}
+
+impl Parser {
+    pub(crate) fn new(options: Options) -> Self {
+        Parser {
+            options,
+            stack: Vec::new(),
+            frame_len: 0,
+            spans: None,
+        }
+    }
+
+    /// Creates a parser that records the positions of strings and bytes, the payloads of bulk
+    /// strings are not copied, see `Parser::take_spans`.
+    pub(crate) fn with_spans(options: Options) -> Self {
+        Parser {
+            spans: Some(Vec::new()),
+            ..Parser::new(options)
+        }
+    }
+
+    /// Returns the recorded positions, relative to the buffer being parsed.
+    pub(crate) fn take_spans(&mut self) -> Vec<Option<(usize, usize)>> {
+        self.spans.as_mut().map(|spans| spans.split_off(0)).unwrap_or_default()
+    }
+
+    /// Appends the encoded headers and elements of the partial aggregates, feeding them to a new
+    /// parser restores this parser's state.
+    fn encode_partial(&self, buf: &mut Vec<u8>) {
+        for &(aggregate, len, ref array) in &self.stack {
+            match len {
+                Some(len) => encode_len_header(aggregate.prefix(), len, buf),
+                None => {
+                    buf.push(aggregate.prefix());
+                    buf.extend_from_slice(b"?\r\n");
+                }
+            }
+            for value in array {
+                encode_replay(value, buf);
+            }
+        }
+    }
+
+    /// Returns the state of the parser, `buf` holds the bytes not parsed yet.
+    fn state(&self, buf: &[u8]) -> DecoderState {
+        let aggregates = self.stack
+            .iter()
+            .map(|&(aggregate, len, ref array)| {
+                (FrameType::from_prefix(aggregate.prefix()),
+                 len.map(|len| aggregate.values_len(len) - array.len()))
+            })
+            .collect();
+        DecoderState {
+            aggregates,
+            awaiting: self.awaiting(buf),
+        }
+    }
+
+    /// Returns what completes the next leaf, `buf` holds its bytes parsed so far.
+    fn awaiting(&self, buf: &[u8]) -> Awaiting {
+        let mut start = 0;
+        let mut streamed = false;
+        loop {
+            let end = match memchr(b'\n', &buf[start..]) {
+                Some(i) => start + i + 1,
+                None if start == buf.len() && !streamed => return Awaiting::Value,
+                None => return Awaiting::Line(buf.len() - start),
+            };
+            let len = if streamed {
+                parse_chunk_header(&buf[start..end], &self.options).ok()
+            } else {
+                match parse_line(&buf[start..end], &self.options) {
+                    Ok(Line::Bulk(len)) | Ok(Line::Unknown(Some(len))) => Some(len),
+                    Ok(Line::StreamedBulk) => {
+                        streamed = true;
+                        start = end;
+                        continue;
+                    }
+                    _ => None,
+                }
+            };
+            match len {
+                Some(len) if buf.len() < end + len + 2 => {
+                    return Awaiting::Payload(end + len + 2 - buf.len())
+                }
+                Some(len) if streamed && len > 0 => start = end + len + 2,
+                // a complete leaf, it was parsed unless it is invalid.
+                _ => return Awaiting::Value,
+            }
+        }
+    }
+
+    /// Drops the partial aggregates.
+    pub(crate) fn reset(&mut self) {
+        self.stack.clear();
+        self.frame_len = 0;
+        if let Some(ref mut spans) = self.spans {
+            spans.clear();
+        }
+    }
+
+    /// Drops the spans of attributes that are dropped when they are built from `values`.
+    fn drop_attribute_spans(&mut self, aggregate: Aggregate, values: &[Value]) {
+        if aggregate != Aggregate::Attribute || self.options.keep_attributes {
+            return;
+        }
+        if let (Some(ref mut spans), Some((value, attributes))) = (self.spans.as_mut(),
+                                                                 values.split_last()) {
+            let end = spans.len() - span_count(value);
+            let start = end - attributes.iter().map(span_count).sum::<usize>();
+            spans.drain(start..end);
+        }
+    }
+
+    /// Counts `len` more bytes consumed of the value being parsed.
+    fn consume(&mut self, len: usize) -> Result<()> {
+        self.frame_len += len;
+        check_max_frame_len(self.frame_len, &self.options)
+    }
+
+    /// Parses from `buf[*pos..]` and advances `pos` over the consumed bytes.
+    /// Returns `None` if more bytes are needed to complete the value.
+    pub(crate) fn parse(&mut self, buf: &[u8], pos: &mut usize) -> Result<Option<Value>> {
+        loop {
+            let start = *pos;
+            let streamed = matches!(self.stack.last(), Some(&(_, None, _)));
+            let mut value = if streamed && buf[*pos..].starts_with(STREAMED_END_BYTES) {
+                *pos += STREAMED_END_BYTES.len();
+                let (aggregate, _, array) = self.stack.pop().unwrap();
+                self.drop_attribute_spans(aggregate, &array);
+                aggregate.build_streamed(array, &self.options)?
+            } else {
+                match self.parse_leaf(buf, pos)? {
+                    None => {
+                        // the buffered bytes of an incomplete line or payload count too.
+                        check_max_frame_len(self.frame_len + buf.len() - start, &self.options)?;
+                        return Ok(None);
+                    }
+                    Some(Leaf::Value(value)) => value,
+                    Some(Leaf::Aggregate(aggregate, len)) => {
+                        aggregate.check_len(len, 0, &self.options)?;
+                        let values_len = len.map(|len| aggregate.values_len(len));
+                        if values_len == Some(0) {
+                            aggregate.build(Vec::new(), &self.options)
+                        } else {
+                            self.consume(*pos - start)?;
+                            let capacity = min(values_len.unwrap_or(0), 1024);
+                            self.stack.push((aggregate, len, Vec::with_capacity(capacity)));
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.consume(*pos - start)?;
+
+            loop {
+                match self.stack.last_mut() {
+                    None => {
+                        self.frame_len = 0;
+                        return Ok(Some(value));
+                    }
+                    Some(&mut (aggregate, len, ref mut array)) => {
+                        array.push(value);
+                        match len {
+                            Some(len) if array.len() >= aggregate.values_len(len) => {}
+                            Some(_) => break,
+                            None => {
+                                aggregate.check_len(None, array.len(), &self.options)?;
+                                break;
+                            }
+                        }
+                    }
+                }
+                let (aggregate, _, array) = self.stack.pop().unwrap();
+                self.drop_attribute_spans(aggregate, &array);
+                value = aggregate.build(array, &self.options);
+            }
+        }
+    }
+
+    fn parse_leaf(&mut self, buf: &[u8], pos: &mut usize) -> Result<Option<Leaf>> {
+        let start = *pos;
+        let end = match line_end(buf, start, &self.options)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let line = &buf[start..end];
+
+        let (leaf, next) = match parse_line(line, &self.options)? {
+            Line::Value(value) => {
+                if let Some(ref mut spans) = self.spans {
+                    if span_count(&value) > 0 {
+                        spans.push(Some((start + 1, end - 2)));
+                    }
+                }
+                (Leaf::Value(value), end)
+            }
+            Line::Aggregate(aggregate, len) => (Leaf::Aggregate(aggregate, Some(len)), end),
+            Line::StreamedAggregate(aggregate) => (Leaf::Aggregate(aggregate, None), end),
+            Line::Bulk(len) => {
+                check_max_frame_len(self.frame_len + end - start + len + 2, &self.options)?;
+                let payload = match payload(buf, end, len)? {
+                    Some(payload) => payload,
+                    None => return Ok(None),
+                };
+                let value = match self.spans {
+                    Some(ref mut spans) => {
+                        spans.push(Some((end, end + len)));
+                        bulk_span_value(payload, &self.options)?
+                    }
+                    None => bulk_slice_value(payload, &self.options)?,
+                };
+                (Leaf::Value(value), end + len + 2)
+            }
+            Line::StreamedBulk => {
+                // the chunks are only consumed once the terminating chunk is buffered.
+                let mut bulk: Vec<u8> = Vec::new();
+                let mut next = end;
+                loop {
+                    let chunk = match line_end(buf, next, &self.options)? {
+                        Some(chunk) => chunk,
+                        None => return Ok(None),
+                    };
+                    let len = parse_chunk_header(&buf[next..chunk], &self.options)?;
+                    if len == 0 {
+                        next = chunk;
+                        break;
+                    }
+                    check_streamed_len(bulk.len(), len, &self.options)?;
+                    match payload(buf, chunk, len)? {
+                        Some(payload) => bulk.extend_from_slice(payload),
+                        None => return Ok(None),
+                    }
+                    next = chunk + len + 2;
+                }
+                if let Some(ref mut spans) = self.spans {
+                    spans.push(None);
+                }
+                (Leaf::Value(bulk_value(bulk, &self.options)?), next)
+            }
+            Line::Unknown(None) => (Leaf::Value(Value::Unknown(line.to_vec())), end),
+            Line::Unknown(Some(len)) => {
+                if payload(buf, end, len)?.is_none() {
+                    return Ok(None);
+                }
+                (Leaf::Value(Value::Unknown(buf[start..end + len + 2].to_vec())), end + len + 2)
+            }
+        };
+        if let (Some(ref mut spans), Leaf::Value(Value::Unknown(_))) = (self.spans.as_mut(),
+                                                                       &leaf) {
+            spans.push(Some((start, next)));
+        }
+        *pos = next;
+        Ok(Some(leaf))
+    }
+}
+
+/// Returns the number of spans a parser records for a value, see `Parser::with_spans`.
+fn span_count(value: &Value) -> usize {
+    match *value {
+        Value::String(_) | Value::Error(_) | Value::BigNumber(_) | Value::Bulk(_) |
+        Value::BufBulk(_) | Value::Unknown(_) => 1,
+        Value::Array(ref values) | Value::Set(ref values) | Value::Push(ref values) => {
+            values.iter().map(span_count).sum()
+        }
+        Value::Map(ref pairs) => pairs_span_count(pairs),
+        Value::Attribute(ref attributes, ref value) => {
+            pairs_span_count(attributes) + span_count(value)
+        }
+        _ => 0,
+    }
+}
+
+fn pairs_span_count(pairs: &[(Value, Value)]) -> usize {
+    pairs.iter().map(|(key, value)| span_count(key) + span_count(value)).sum()
+}
+
+/// Creates the empty value of a bulk payload recorded as a span, checked like
+/// `bulk_slice_value` checks it.
+fn bulk_span_value(payload: &[u8], options: &Options) -> Result<Value> {
+    if options.buf_bulk {
+        return Ok(Value::BufBulk(Vec::new()));
+    }
+    match str::from_utf8(payload) {
+        Ok(_) => Ok(Value::Bulk(String::new())),
+        Err(_) if options.buf_bulk_fallback => Ok(Value::BufBulk(Vec::new())),
+        Err(err) => Err(Error::new(ErrorKind::InvalidData, err)),
+    }
+}
+
+/// Returns the end of the line at `buf[start..]`, checking its CRLF and its length.
+/// Returns `None` if the line is not complete.
+fn line_end(buf: &[u8], start: usize, options: &Options) -> Result<Option<usize>> {
+    match memchr(b'\n', &buf[start..]) {
+        Some(i) => {
+            check_line(&buf[start..start + i + 1])?;
+            check_max_line_len(&buf[start..start + i + 1], options)?;
+            Ok(Some(start + i + 1))
+        }
+        None => {
+            check_max_line_len(&buf[start..], options)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the payload of `len` bytes at `buf[start..]`, checking its trailing CRLF.
+/// Returns `None` if the payload is not complete.
+fn payload(buf: &[u8], start: usize, len: usize) -> Result<Option<&[u8]>> {
+    if buf.len() < start + len + 2 {
+        return Ok(None);
+    }
+    if !is_crlf(buf[start + len], buf[start + len + 1]) {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid CRLF: {:?}", &buf[start..start + len + 2])));
+    }
+    Ok(Some(&buf[start..start + len]))
+}
+
+/// Decodes a value from the start of a buffer, returns the value and the number of bytes it
+/// spans. Fails with `UnexpectedEof` if the buffer holds an incomplete value.
+/// # Examples
+/// ```
+/// # use self::resp::{decode, Value};
+/// let buf = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n+OK\r\n";
+/// let (value, len) = decode(buf).unwrap();
+/// assert_eq!(value,
+///            Value::Array(vec![Value::Bulk("ECHO".to_string()), Value::Bulk("hey".to_string())]));
+/// assert_eq!(decode(&buf[len..]).unwrap(), (Value::String("OK".to_string()), 5));
+/// assert!(decode(&buf[..len - 1]).is_err());
+/// ```
+pub fn decode(buf: &[u8]) -> Result<(Value, usize)> {
+    let mut pos = 0;
+    match Parser::new(Options::default()).parse(buf, &mut pos)? {
+        Some(value) => Ok((value, pos)),
+        None => Err(incomplete()),
+    }
+}
+
+/// Scans a buffer for a complete value at its start, returns the number of bytes it spans,
+/// `None` if the buffer holds an incomplete value. No value is decoded: the framing of the
+/// value is checked, not the content of its lines and bulk strings. Fails with a `Protocol`
+/// error if the framing is invalid, see `ErrorClass`.
+/// # Examples
+/// ```
+/// # use self::resp::check_complete;
+/// let buf = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n+OK\r\n";
+/// assert_eq!(check_complete(buf).unwrap(), Some(23));
+/// assert_eq!(check_complete(&buf[23..]).unwrap(), Some(5));
+/// assert_eq!(check_complete(&buf[..22]).unwrap(), None);
+/// assert!(check_complete(b"$3\r\nhey\n\n").is_err());
+/// ```
+pub fn check_complete(buf: &[u8]) -> Result<Option<usize>> {
+    let options = Options::default();
+    let mut pos = 0;
+    // partial aggregates, with their declared lengths and the number of values scanned.
+    let mut stack: Vec<(Aggregate, Option<usize>, usize)> = Vec::new();
+    loop {
+        let end = match line_end(buf, pos, &options)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let line = &buf[pos..end];
+        pos = end;
+        let streamed = matches!(stack.last(), Some(&(_, None, _)));
+        let header = if streamed && line == STREAMED_END_BYTES {
+            let (aggregate, _, values) = stack.pop().unwrap();
+            aggregate.check_streamed(values)?;
+            None
+        } else {
+            match parse_frame_line(line, &options)? {
+                None | Some(Line::Value(_)) | Some(Line::Unknown(None)) => None,
+                Some(Line::Bulk(len)) | Some(Line::Unknown(Some(len))) => {
+                    if payload(buf, pos, len)?.is_none() {
+                        return Ok(None);
+                    }
+                    pos += len + 2;
+                    None
+                }
+                Some(Line::StreamedBulk) => {
+                    let mut total = 0;
+                    loop {
+                        let chunk = match line_end(buf, pos, &options)? {
+                            Some(chunk) => chunk,
+                            None => return Ok(None),
+                        };
+                        let len = parse_chunk_header(&buf[pos..chunk], &options)?;
+                        pos = chunk;
+                        if len == 0 {
+                            break;
+                        }
+                        check_streamed_len(total, len, &options)?;
+                        if payload(buf, pos, len)?.is_none() {
+                            return Ok(None);
+                        }
+                        pos += len + 2;
+                        total += len;
+                    }
+                    None
+                }
+                Some(Line::Aggregate(aggregate, len)) => Some((aggregate, Some(len))),
+                Some(Line::StreamedAggregate(aggregate)) => Some((aggregate, None)),
+            }
+        };
+        if let Some((aggregate, len)) = header {
+            if len.map(|len| aggregate.values_len(len)) != Some(0) {
+                stack.push((aggregate, len, 0));
+                continue;
+            }
+        }
+
+        loop {
+            match stack.last_mut() {
+                None => return Ok(Some(pos)),
+                Some(&mut (aggregate, len, ref mut values)) => {
+                    *values += 1;
+                    match len {
+                        Some(len) if *values >= aggregate.values_len(len) => {}
+                        _ => break,
+                    }
+                }
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// The state of a decoder in the middle of a value, see `FeedDecoder::state`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DecoderState {
+    /// The partial aggregates, outermost first, with the number of values they still expect,
+    /// `None` for streamed aggregates. The keys and the values of maps are counted apart.
+    pub aggregates: Vec<(FrameType, Option<usize>)>,
+    /// What the decoder waits for to complete the next value or element.
+    pub awaiting: Awaiting,
+}
+
+impl DecoderState {
+    /// Returns the state of a decoder between two values.
+    pub(crate) fn idle() -> Self {
+        DecoderState {
+            aggregates: Vec::new(),
+            awaiting: Awaiting::Value,
+        }
+    }
+
+    /// Returns `true` if the decoder is between two values.
+    pub fn is_idle(&self) -> bool {
+        self.aggregates.is_empty() && self.awaiting == Awaiting::Value
+    }
+}
+
+/// What a decoder waits for, see `DecoderState`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Awaiting {
+    /// The first byte of a value.
+    Value,
+    /// The end of a line, with the number of bytes buffered of it. The line may be a chunk
+    /// header of a streamed bulk string.
+    Line(usize),
+    /// The payload of a bulk string or of a chunk, with the number of bytes still missing,
+    /// the trailing CRLF included.
+    Payload(usize),
+}
+
+/// A push-based RESP Decoder, it doesn't own a reader: bytes are fed to it as they arrive
+/// and the decoded values are read from it.
+/// # Examples
+/// ```
+/// # use self::resp::{FeedDecoder, Value};
+/// let mut decoder = FeedDecoder::new();
+/// decoder.feed(b"+OK\r\n:1").unwrap();
+/// assert_eq!(decoder.read(), Some(Value::String("OK".to_string())));
+/// assert_eq!(decoder.read(), None);
+/// decoder.feed(b"\r\n").unwrap();
+/// assert_eq!(decoder.read(), Some(Value::Integer(1)));
+/// ```
+#[derive(Debug)]
+pub struct FeedDecoder {
+    buf: Vec<u8>,
+    pos: usize,
+    parser: Parser,
+    results: VecDeque<Value>,
+}
+
+impl FeedDecoder {
+    /// Creates a FeedDecoder instance.
+    pub fn new() -> Self {
+        FeedDecoder {
+            buf: Vec::new(),
+            pos: 0,
+            parser: Parser::new(Options::default()),
+            results: VecDeque::new(),
+        }
+    }
+
+    /// Creates a FeedDecoder instance that decodes bulk value to buffer bulk.
+    pub fn with_buf_bulk() -> Self {
+        FeedDecoder {
+            buf: Vec::new(),
+            pos: 0,
+            parser: Parser::new(Options {
+                                    buf_bulk: true,
+                                    ..Options::default()
+                                }),
+            results: VecDeque::new(),
+        }
+    }
+
+    /// Sets whether values of an unknown type are decoded to `Value::Unknown` instead of failing,
+    /// see `Decoder::set_passthrough_unknown`.
+    pub fn set_passthrough_unknown(&mut self, passthrough: bool) {
+        self.parser.options.passthrough_unknown = passthrough;
+    }
+
+    /// Sets whether the RESP3 attributes preceding a value are kept,
+    /// see `Decoder::set_keep_attributes`.
+    pub fn set_keep_attributes(&mut self, keep: bool) {
+        self.parser.options.keep_attributes = keep;
+    }
+
+    /// Sets whether a bulk string that is not valid UTF-8 is decoded to `Value::BufBulk` instead
+    /// of failing, see `Decoder::set_buf_bulk_fallback`.
+    pub fn set_buf_bulk_fallback(&mut self, fallback: bool) {
+        self.parser.options.buf_bulk_fallback = fallback;
+    }
+
+    /// Sets whether short bulk strings are decoded to `Value::SmallBulk`,
+    /// see `Decoder::set_small_bulks`.
+    pub fn set_small_bulks(&mut self, small: bool) {
+        self.parser.options.small_bulks = small;
+    }
+
+    /// Sets whether lengths must be written the way Redis writes them,
+    /// see `Decoder::set_strict_lengths`.
+    pub fn set_strict_lengths(&mut self, strict: bool) {
+        self.parser.options.strict_lengths = strict;
+    }
+
+    /// Pins the protocol version, see `Decoder::set_version`.
+    pub fn set_version(&mut self, version: Version) {
+        self.parser.options.version = Some(version);
+    }
+
+    /// Sets the max length of a bulk string, see `Decoder::set_max_bulk_len`.
+    pub fn set_max_bulk_len(&mut self, max: usize) {
+        self.parser.options.max_bulk_len = Some(max);
+    }
+
+    /// Sets the max number of elements of an aggregate, see `Decoder::set_max_array_len`.
+    pub fn set_max_array_len(&mut self, max: usize) {
+        self.parser.options.max_array_len = Some(max);
+    }
+
+    /// Sets the max number of bytes of a top-level value, see `Decoder::set_max_frame_len`.
+    /// The buffered bytes of an incomplete value count too.
+    pub fn set_max_frame_len(&mut self, max: usize) {
+        self.parser.options.max_frame_len = Some(max);
+    }
+
+    /// Sets the max length of a line, see `Decoder::set_max_line_len`.
+    /// The buffered bytes of an incomplete line count too.
+    pub fn set_max_line_len(&mut self, max: usize) {
+        self.parser.options.max_line_len = Some(max);
+    }
+
+    /// Feeds bytes to the decoder, every value they complete is decoded.
+    /// The bytes of an incomplete value are kept buffered, so it only fails if the bytes are
+    /// invalid, see `ErrorClass`. The buffered bytes and the partial value are then dropped.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buf.extend_from_slice(bytes);
+        loop {
+            match self.parser.parse(&self.buf, &mut self.pos) {
+                Ok(Some(value)) => self.results.push_back(value),
+                Ok(None) => break,
+                Err(err) => {
+                    self.buf.clear();
+                    self.pos = 0;
+                    self.parser.reset();
+                    return Err(err);
+                }
+            }
+        }
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        Ok(())
+    }
+
+    /// Returns the next decoded value, `None` if no value is complete yet.
+    pub fn read(&mut self) -> Option<Value> {
+        self.results.pop_front()
+    }
+
+    /// Returns the number of buffered bytes, they belong to a value that is not complete yet.
+    /// Lines of a partial aggregate that were already parsed are not counted.
+    /// # Examples
+    /// ```
+    /// # use self::resp::FeedDecoder;
+    /// let mut decoder = FeedDecoder::new();
+    /// decoder.feed(b"*2\r\n:1\r\n$3\r\nfo").unwrap();
+    /// assert_eq!(decoder.buffer_len(), 6);
+    /// assert_eq!(decoder.result_len(), 0);
+    /// decoder.feed(b"o\r\n+OK\r\n").unwrap();
+    /// assert_eq!(decoder.buffer_len(), 0);
+    /// assert_eq!(decoder.result_len(), 2);
+    /// ```
+    pub fn buffer_len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Returns the number of decoded values not read yet.
+    pub fn result_len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns the state of the value being decoded, to see what the decoder waits for.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Awaiting, FeedDecoder, FrameType};
+    /// let mut decoder = FeedDecoder::new();
+    /// assert!(decoder.state().is_idle());
+    /// decoder.feed(b"*3\r\n:1\r\n$5\r\nva").unwrap();
+    /// let state = decoder.state();
+    /// assert_eq!(state.aggregates, vec![(FrameType::Array, Some(2))]);
+    /// assert_eq!(state.awaiting, Awaiting::Payload(5));
+    /// ```
+    pub fn state(&self) -> DecoderState {
+        self.parser.state(&self.buf[self.pos..])
+    }
+
+    /// Serializes the state of the decoder: its options, the decoded values not read yet,
+    /// the partial value and the buffered bytes. The snapshot is itself a RESP value, it can be
+    /// sent to another process and restored there with `FeedDecoder::restore`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{FeedDecoder, Value};
+    /// let mut decoder = FeedDecoder::new();
+    /// decoder.feed(b"*2\r\n$3\r\nfoo\r\n$3\r\nba").unwrap();
+    /// let snapshot = decoder.snapshot();
+    ///
+    /// let mut decoder = FeedDecoder::restore(&snapshot).unwrap();
+    /// decoder.feed(b"r\r\n").unwrap();
+    /// assert_eq!(decoder.read().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("foo".to_string()),
+    ///                              Value::Bulk("bar".to_string())]));
+    /// ```
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut replay: Vec<u8> = Vec::new();
+        for value in &self.results {
+            encode_replay(value, &mut replay);
+        }
+        self.parser.encode_partial(&mut replay);
+        replay.extend_from_slice(&self.buf[self.pos..]);
+        encode(&Value::Array(vec![Value::Integer(SNAPSHOT_VERSION),
+                                  Value::Integer(self.parser.options.to_flags()),
+                                  Value::Array(self.parser.options.to_limits()),
+                                  Value::BufBulk(replay)]))
+    }
+
+    /// Creates a FeedDecoder instance from a snapshot taken by `FeedDecoder::snapshot`.
+    pub fn restore(snapshot: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let value = Parser::new(Options {
+                                    buf_bulk: true,
+                                    ..Options::default()
+                                })
+            .parse(snapshot, &mut pos)?;
+        let array = match value {
+            Some(Value::Array(array)) => array,
+            _ => return Err(invalid_snapshot()),
+        };
+        // version 1 snapshots have no limits.
+        let valid = match array.first() {
+            Some(&Value::Integer(1)) => array.len() == 3,
+            Some(&Value::Integer(SNAPSHOT_VERSION)) => array.len() == 4,
+            _ => false,
+        };
+        if pos != snapshot.len() || !valid {
+            return Err(invalid_snapshot());
+        }
+        let mut options = match array[1] {
+            Value::Integer(flags) => Options::from_flags(flags).ok_or_else(invalid_snapshot)?,
+            _ => return Err(invalid_snapshot()),
+        };
+        if array.len() == 4 {
+            match array[2] {
+                Value::Array(ref limits) => options.set_limits(limits)?,
+                _ => return Err(invalid_snapshot()),
+            }
+        }
+        let replay = match array[array.len() - 1] {
+            Value::BufBulk(ref replay) => replay,
+            _ => return Err(invalid_snapshot()),
+        };
+
+        let mut decoder = FeedDecoder::new();
+        decoder.parser.options = options;
+        decoder.feed(replay)?;
+        Ok(decoder)
+    }
+}
+
+const SNAPSHOT_VERSION: i64 = 2;
+const FLAG_BUF_BULK: i64 = 1;
+const FLAG_PASSTHROUGH_UNKNOWN: i64 = 2;
+const FLAG_KEEP_ATTRIBUTES: i64 = 4;
+const FLAG_RESP2: i64 = 8;
+const FLAG_RESP3: i64 = 16;
+const FLAG_BUF_BULK_FALLBACK: i64 = 32;
+const FLAG_STRICT_LENGTHS: i64 = 64;
+const FLAG_SMALL_BULKS: i64 = 128;
+const FLAGS: i64 = FLAG_BUF_BULK | FLAG_PASSTHROUGH_UNKNOWN | FLAG_KEEP_ATTRIBUTES | FLAG_RESP2 |
+                   FLAG_RESP3 | FLAG_BUF_BULK_FALLBACK | FLAG_STRICT_LENGTHS | FLAG_SMALL_BULKS;
+
+impl Options {
+    fn to_flags(&self) -> i64 {
+        let mut flags = 0;
+        if self.buf_bulk {
+            flags |= FLAG_BUF_BULK;
+        }
+        if self.passthrough_unknown {
+            flags |= FLAG_PASSTHROUGH_UNKNOWN;
+        }
+        if self.keep_attributes {
+            flags |= FLAG_KEEP_ATTRIBUTES;
+        }
+        if self.buf_bulk_fallback {
+            flags |= FLAG_BUF_BULK_FALLBACK;
+        }
+        if self.strict_lengths {
+            flags |= FLAG_STRICT_LENGTHS;
+        }
+        if self.small_bulks {
+            flags |= FLAG_SMALL_BULKS;
+        }
+        match self.version {
+            Some(Version::Resp2) => flags |= FLAG_RESP2,
+            Some(Version::Resp3) => flags |= FLAG_RESP3,
+            None => {}
+        }
+        flags
+    }
+
+    fn from_flags(flags: i64) -> Option<Self> {
+        if flags & !FLAGS != 0 || flags & (FLAG_RESP2 | FLAG_RESP3) == FLAG_RESP2 | FLAG_RESP3 {
+            return None;
+        }
+        let version = if flags & FLAG_RESP2 != 0 {
+            Some(Version::Resp2)
+        } else if flags & FLAG_RESP3 != 0 {
+            Some(Version::Resp3)
+        } else {
+            None
+        };
+        Some(Options {
+                 buf_bulk: flags & FLAG_BUF_BULK != 0,
+                 passthrough_unknown: flags & FLAG_PASSTHROUGH_UNKNOWN != 0,
+                 keep_attributes: flags & FLAG_KEEP_ATTRIBUTES != 0,
+                 buf_bulk_fallback: flags & FLAG_BUF_BULK_FALLBACK != 0,
+                 strict_lengths: flags & FLAG_STRICT_LENGTHS != 0,
+                 small_bulks: flags & FLAG_SMALL_BULKS != 0,
+                 version,
+                 ..Options::default()
+             })
+    }
+
+    /// Returns the limits of the options, `Null` for no limit.
+    fn to_limits(&self) -> Vec<Value> {
+        let limit = |max: Option<usize>| max.map_or(Value::Null, |max| Value::Integer(max as i64));
+        vec![limit(self.max_bulk_len),
+             limit(self.max_array_len),
+             limit(self.max_frame_len),
+             limit(self.max_line_len)]
+    }
+
+    /// Sets the limits of the options, the missing trailing limits are unset.
+    fn set_limits(&mut self, limits: &[Value]) -> Result<()> {
+        if limits.len() > 4 {
+            return Err(invalid_snapshot());
+        }
+        let limit = |index: usize| match limits.get(index) {
+            None | Some(&Value::Null) => Ok(None),
+            Some(&Value::Integer(max)) if max >= 0 => Ok(Some(max as usize)),
+            _ => Err(invalid_snapshot()),
+        };
+        self.max_bulk_len = limit(0)?;
+        self.max_array_len = limit(1)?;
+        self.max_frame_len = limit(2)?;
+        self.max_line_len = limit(3)?;
+        Ok(())
+    }
+}
+
+/// Appends a decoded value for a snapshot. Unlike `encode`, the lines are written as they are:
+/// a decoded line holds no LF, so it is parsed back to the same value, a CR included.
+fn encode_replay(value: &Value, buf: &mut Vec<u8>) {
+    let line = |prefix: u8, val: &str, buf: &mut Vec<u8>| {
+        buf.push(prefix);
+        buf.extend_from_slice(val.as_bytes());
+        buf.extend_from_slice(CRLF_BYTES);
+    };
+    match *value {
+        Value::String(ref val) => line(b'+', val, buf),
+        Value::Error(ref val) => line(b'-', val, buf),
+        Value::BigNumber(ref val) => line(b'(', val, buf),
+        Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+            encode_len_header(sequence_prefix(value), val.len(), buf);
+            for item in val {
+                encode_replay(item, buf);
+            }
+        }
+        Value::Map(ref val) => {
+            encode_len_header(b'%', val.len(), buf);
+            for (key, value) in val {
+                encode_replay(key, buf);
+                encode_replay(value, buf);
+            }
+        }
+        Value::Attribute(ref attributes, ref val) => {
+            encode_len_header(b'|', attributes.len(), buf);
+            for (key, value) in attributes {
+                encode_replay(key, buf);
+                encode_replay(value, buf);
+            }
+            encode_replay(val, buf);
+        }
+        _ => buf.extend_from_slice(&encode(value)),
+    }
+}
+
+fn invalid_snapshot() -> Error {
+    Error::new(ErrorKind::InvalidData, "invalid snapshot")
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Returns the state of the value being decoded where the decoder stopped: the value
+    /// interrupted by `WouldBlock`, see `Decoder::set_nonblocking`, by a read timeout or by
+    /// another error, whether the decoder is blocking or not. The state is tracked as the value
+    /// is decoded, it is idle once a value is complete.
+    /// # Examples
+    /// ```
+    /// # use std::io::{self, BufReader, ErrorKind, Read};
+    /// # use self::resp::{Awaiting, Decoder, FrameType};
+    /// // a reader that would block after every read.
+    /// struct Socket(Vec<u8>);
+    /// impl Read for Socket {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         if self.0.is_empty() {
+    ///             return Err(io::Error::new(ErrorKind::WouldBlock, "would block"));
+    ///         }
+    ///         let n = buf.len().min(self.0.len());
+    ///         buf[..n].copy_from_slice(&self.0[..n]);
+    ///         self.0.drain(..n);
+    ///         Ok(n)
+    ///     }
+    /// }
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(Socket(b"*2\r\n$3\r\nfoo\r\n:".to_vec())));
+    /// decoder.set_nonblocking(true);
+    /// assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::WouldBlock);
+    /// let state = decoder.state();
+    /// assert_eq!(state.aggregates, vec![(FrameType::Array, Some(1))]);
+    /// assert_eq!(state.awaiting, Awaiting::Line(1));
+    /// ```
+    pub fn state(&self) -> DecoderState {
+        self.state.clone()
+    }
+}
+
+impl Default for FeedDecoder {
+    fn default() -> Self {
+        FeedDecoder::new()
+    }
+}
+
+impl Write for FeedDecoder {
+    /// Feeds all the bytes to the decoder, see `FeedDecoder::feed`.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.feed(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::{encode_slice, ErrorClass};
+
+    fn values() -> Vec<Value> {
+        vec![Value::String("".to_string()),
+             Value::String("OK".to_string()),
+             Value::Error("Error message".to_string()),
+             Value::Integer(-1),
+             Value::Integer(1456061893587000000),
+             Value::Boolean(true),
+             Value::Double(-1.25),
+             Value::Double(f64::INFINITY),
+             Value::BigNumber("-12345678901234567890".to_string()),
+             Value::Null,
+             Value::Bulk("".to_string()),
+             Value::Bulk("你好！\n 换行".to_string()),
+             Value::NullArray,
+             Value::Array(vec![]),
+             Value::Array(vec![Value::Array(vec![Value::Integer(1), Value::Array(vec![])]),
+                               Value::Array(vec![Value::String("Foo".to_string()),
+                                                 Value::Error("Bar".to_string())])]),
+             Value::Array(vec![Value::Bulk("foo".to_string()),
+                               Value::Null,
+                               Value::Bulk("bar".to_string())]),
+             Value::Map(vec![]),
+             Value::Map(vec![(Value::Bulk("foo".to_string()),
+                              Value::Map(vec![(Value::Integer(1), Value::Array(vec![]))])),
+                             (Value::Array(vec![Value::Null]), Value::Map(vec![]))]),
+             Value::Set(vec![Value::Set(vec![]), Value::Bulk("foo".to_string())]),
+             Value::Push(vec![Value::Bulk("message".to_string()), Value::Push(vec![])])]
+    }
+
+    #[test]
+    fn fn_decode() {
+        let values = values();
+        let mut all: Vec<u8> = Vec::new();
+        for value in &values {
+            all.extend_from_slice(&value.encode());
+        }
+        let mut pos = 0;
+        for value in &values {
+            let (res, len) = decode(&all[pos..]).unwrap();
+            assert_eq!(res, *value);
+            assert_eq!(len, value.encode().len());
+            pos += len;
+        }
+        assert_eq!(pos, all.len());
+
+        let buf = Value::Array(values).encode();
+        for len in 0..buf.len() {
+            assert_eq!(decode(&buf[..len]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+        assert_eq!(decode(b"?\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_feed_decoder() {
+        let values = values();
+        let mut all: Vec<u8> = Vec::new();
+        for value in &values {
+            all.extend_from_slice(&value.encode());
+        }
+
+        // Feed all at once
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(&all).unwrap();
+        for value in &values {
+            assert_eq!(decoder.read().unwrap(), *value);
+        }
+        assert_eq!(decoder.read(), None);
+
+        // Feed byte by byte
+        let mut decoder = FeedDecoder::new();
+        let mut res: Vec<Value> = Vec::new();
+        for byte in &all {
+            decoder.feed(&[*byte]).unwrap();
+            while let Some(value) = decoder.read() {
+                res.push(value);
+            }
+        }
+        assert_eq!(res, values);
+
+        let mut decoder = FeedDecoder::with_buf_bulk();
+        decoder.feed(&encode_slice(&["SET", "a"])).unwrap();
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::BufBulk(b"SET".to_vec()),
+                                     Value::BufBulk(b"a".to_vec())]));
+    }
+
+    #[test]
+    fn fn_check_complete() {
+        let mut buf: Vec<u8> = Vec::new();
+        for value in values() {
+            let encoded = value.encode();
+            assert_eq!(check_complete(&encoded).unwrap(), Some(encoded.len()), "{:?}", value);
+            for i in 0..encoded.len() {
+                assert_eq!(check_complete(&encoded[..i]).unwrap(), None, "{:?}", value);
+            }
+            buf.extend_from_slice(&encoded);
+        }
+        buf.extend_from_slice(b"|1\r\n+a\r\n:1\r\n*?\r\n$?\r\n;1\r\na\r\n;0\r\n%?\r\n.\r\n.\r\n");
+        let mut pos = 0;
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(&buf).unwrap();
+        while let Some(len) = check_complete(&buf[pos..]).unwrap() {
+            assert_eq!(decode(&buf[pos..]).unwrap(), (decoder.read().unwrap(), len));
+            pos += len;
+        }
+        assert_eq!(pos, buf.len());
+        assert_eq!(decoder.read(), None);
+
+        // the content of the lines is not checked.
+        assert_eq!(check_complete(b":a\r\n").unwrap(), Some(4));
+        let invalid: &[&[u8]] = &[b"+OK\n",
+                                  b"$3\r\nfoo\n\n",
+                                  b"*a\r\n",
+                                  b"!3\r\n",
+                                  b".\r\n",
+                                  b"%?\r\n+a\r\n.\r\n",
+                                  b"$?\r\n;-1\r\n"];
+        for case in invalid {
+            assert_eq!(ErrorClass::of(&check_complete(case).unwrap_err()),
+                       ErrorClass::Protocol,
+                       "{:?}",
+                       case);
+        }
+    }
+
+    #[test]
+    fn struct_feed_decoder_state() {
+        let state = |aggregates: Vec<(FrameType, Option<usize>)>, awaiting| {
+            DecoderState {
+                aggregates,
+                awaiting,
+            }
+        };
+        let cases: Vec<(&[u8], DecoderState)> =
+            vec![(b"", state(vec![], Awaiting::Value)),
+                 (b"+O", state(vec![], Awaiting::Line(2))),
+                 (b"$5\r\nhel", state(vec![], Awaiting::Payload(4))),
+                 (b"$5\r\nhello\r", state(vec![], Awaiting::Payload(1))),
+                 (b"*2\r\n", state(vec![(FrameType::Array, Some(2))], Awaiting::Value)),
+                 (b"%2\r\n+a\r\n:1\r\n+b\r\n*?\r\n:1\r\n$?\r\n",
+                  state(vec![(FrameType::Map, Some(1)), (FrameType::Array, None)],
+                        Awaiting::Line(0))),
+                 (b"$?\r\n;2\r\nab\r\n;3\r\nc",
+                  state(vec![], Awaiting::Payload(4))),
+                 (b"$?\r\n;2\r\nab\r\n;", state(vec![], Awaiting::Line(1)))];
+        for (buf, expected) in &cases {
+            let mut decoder = FeedDecoder::new();
+            decoder.feed(buf).unwrap();
+            assert_eq!(&decoder.state(), expected, "{:?}", buf);
+        }
+
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(b"*1\r\n:1\r\n").unwrap();
+        assert!(decoder.state().is_idle());
+        assert!(!state(vec![], Awaiting::Line(0)).is_idle());
+    }
+
+    #[test]
+    fn struct_decoder_state() {
+        // a reader that would block at the end of every chunk.
+        struct Socket(Vec<&'static [u8]>, bool);
+        impl io::Read for Socket {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+                self.1 = !self.1;
+                if self.1 || self.0.is_empty() {
+                    return Err(Error::new(ErrorKind::WouldBlock, "would block"));
+                }
+                let chunk = self.0.remove(0);
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+        }
+
+        let socket = Socket(vec![b"*2\r\n$3", b"\r\nfo", b"o\r\n:1\r\n"], false);
+        let mut decoder = Decoder::new(io::BufReader::new(socket));
+        decoder.set_nonblocking(true);
+        assert!(decoder.state().is_idle());
+        let mut states: Vec<DecoderState> = Vec::new();
+        let value = loop {
+            match decoder.decode() {
+                Ok(value) => break value,
+                Err(_) => states.push(decoder.state()),
+            }
+        };
+        assert_eq!(value,
+                   Value::Array(vec![Value::Bulk("foo".to_string()), Value::Integer(1)]));
+        assert_eq!(states.len(), 3);
+        assert!(states[0].is_idle());
+        assert_eq!(states[1].aggregates, vec![(FrameType::Array, Some(2))]);
+        assert_eq!(states[1].awaiting, Awaiting::Line(2));
+        assert_eq!(states[2].awaiting, Awaiting::Payload(3));
+        assert!(decoder.state().is_idle());
+
+        // a socket with a read timeout fails with `WouldBlock` too, the state is tracked without
+        // the nonblocking mode.
+        let socket = Socket(vec![b"%2\r\n+a\r\n*3\r\n:1\r\n$5\r\nhe"], true);
+        let mut decoder = Decoder::new(io::BufReader::new(socket));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::WouldBlock);
+        let state = decoder.state();
+        assert_eq!(state.aggregates,
+                   vec![(FrameType::Map, Some(3)), (FrameType::Array, Some(2))]);
+        assert_eq!(state.awaiting, Awaiting::Payload(5));
+
+        // an invalid line, the state is where the decoder stopped.
+        let mut decoder = Decoder::new(&b"*2\r\n:1\r\n:a\r\n"[..]);
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.state().aggregates, vec![(FrameType::Array, Some(1))]);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(decoder.state().is_idle());
+    }
+
+    #[test]
+    fn struct_feed_decoder_len() {
+        let mut decoder = FeedDecoder::new();
+        assert_eq!(decoder.buffer_len(), 0);
+        assert_eq!(decoder.result_len(), 0);
+        decoder.feed(b"+OK\r\n:1\r\n*3\r\n$1\r\na\r\n:").unwrap();
+        assert_eq!(decoder.buffer_len(), 1);
+        assert_eq!(decoder.result_len(), 2);
+        assert!(decoder.read().is_some());
+        assert_eq!(decoder.result_len(), 1);
+        decoder.feed(b"2\r").unwrap();
+        assert_eq!(decoder.buffer_len(), 3);
+        decoder.feed(b"\n_\r\n").unwrap();
+        assert_eq!(decoder.buffer_len(), 0);
+        assert_eq!(decoder.result_len(), 2);
+
+        assert!(decoder.feed(b"?\r\n").is_err());
+        assert_eq!(decoder.buffer_len(), 0);
+        assert_eq!(decoder.result_len(), 2);
+    }
+
+    #[test]
+    fn struct_feed_decoder_with_invalid_data() {
+        let mut decoder = FeedDecoder::new();
+        assert_eq!(decoder.feed(b"&-1\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert!(decoder.feed(b"$3\r\nfoobar\r\n").is_err());
+        assert!(decoder.feed(b"$-2\r\n").is_err());
+        assert!(decoder.feed(b"+OK\n").is_err());
+        assert!(decoder.feed(b"*1\r\n:x\r\n").is_err());
+
+        // the decoder starts over after an error.
+        decoder.feed(b"*2\r\n:1\r\n").unwrap();
+        assert!(decoder.feed(b"\n").is_err());
+        decoder.feed(b":1\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Integer(1)));
+        assert_eq!(decoder.read(), None);
+    }
+
+    #[test]
+    fn struct_feed_decoder_snapshot() {
+        let values = values();
+        let mut all: Vec<u8> = Vec::new();
+        for value in &values {
+            all.extend_from_slice(&value.encode());
+        }
+
+        // Snapshot and restore after every byte
+        let mut decoder = FeedDecoder::new();
+        let mut res: Vec<Value> = Vec::new();
+        for (i, byte) in all.iter().enumerate() {
+            decoder.feed(&[*byte]).unwrap();
+            if i % 2 == 0 {
+                if let Some(value) = decoder.read() {
+                    res.push(value);
+                }
+            }
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        while let Some(value) = decoder.read() {
+            res.push(value);
+        }
+        assert_eq!(res, values);
+
+        // the lines are replayed as they were decoded, with their CR
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(b"+a\rb\r\n*2\r\n-c\rd\r\n%1\r\n+e\r\r\n(1").unwrap();
+        let mut decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::String("a\rb".to_string())));
+        assert_eq!(decoder.read(),
+                   Some(Value::Array(vec![Value::Error("c\rd".to_string()),
+                                          Value::Map(vec![(Value::String("e\r".to_string()),
+                                                           Value::BigNumber("1".to_string()))])])));
+
+        let mut decoder = FeedDecoder::with_buf_bulk();
+        decoder.feed(b"*2\r\n$1\r\na\r\n$1").unwrap();
+        let mut decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"\r\nb\r\n").unwrap();
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::BufBulk(b"a".to_vec()),
+                                     Value::BufBulk(b"b".to_vec())]));
+
+        assert!(FeedDecoder::restore(b"").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:2\r\n:0\r\n$0\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:1\r\n:0\r\n$3\r\n&\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:1\r\n:0\r\n$0\r\n\r\n+").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:1\r\n:256\r\n$0\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:1\r\n:24\r\n$0\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*3\r\n:1\r\n:0\r\n$0\r\n\r\n").is_ok());
+        assert!(FeedDecoder::restore(b"*3\r\n:2\r\n:0\r\n$0\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*4\r\n:2\r\n:0\r\n*0\r\n$0\r\n\r\n").is_ok());
+        assert!(FeedDecoder::restore(b"*4\r\n:2\r\n:0\r\n*4\r\n_\r\n_\r\n_\r\n_\r\n$0\r\n\r\n")
+                    .is_ok());
+        assert!(FeedDecoder::restore(b"*4\r\n:2\r\n:0\r\n*5\r\n_\r\n_\r\n_\r\n_\r\n_\r\n$0\r\n\r\n")
+                    .is_err());
+        assert!(FeedDecoder::restore(b"*4\r\n:2\r\n:0\r\n*1\r\n:-1\r\n$0\r\n\r\n").is_err());
+        assert!(FeedDecoder::restore(b"*4\r\n:2\r\n:0\r\n*1\r\n_\r\n$0\r\n\r\n").is_ok());
+    }
+
+    #[test]
+    fn struct_feed_decoder_passthrough_unknown() {
+        let buf = b"*2\r\n=8\r\ntxt:Some\r\n@t\r\n";
+        let mut decoder = FeedDecoder::new();
+        decoder.set_passthrough_unknown(true);
+        for byte in buf.iter() {
+            decoder.feed(&[*byte]).unwrap();
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        let value = decoder.read().unwrap();
+        assert_eq!(value,
+                   Value::Array(vec![Value::Unknown(b"=8\r\ntxt:Some\r\n".to_vec()),
+                                     Value::Unknown(b"@t\r\n".to_vec())]));
+        assert_eq!(value.encode(), buf.to_vec());
+
+        let mut decoder = FeedDecoder::new();
+        assert!(decoder.feed(b"@t\r\n").is_err());
+    }
+
+    #[test]
+    fn struct_feed_decoder_buf_bulk_fallback() {
+        let buf = b"*3\r\n$2\r\nok\r\n$1\r\n\xff\r\n$?\r\n;1\r\n\xc3\r\n;1\r\n\xa9\r\n;0\r\n";
+        let mut decoder = FeedDecoder::new();
+        decoder.set_buf_bulk_fallback(true);
+        for byte in buf.iter() {
+            decoder.feed(&[*byte]).unwrap();
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::Bulk("ok".to_string()),
+                                     Value::BufBulk(vec![0xff]),
+                                     Value::Bulk("\u{e9}".to_string())]));
+
+        let mut decoder = FeedDecoder::new();
+        assert_eq!(decoder.feed(buf).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn struct_feed_decoder_strict_lengths() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_strict_lengths(true);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"*1\r\n$3\r\nfoo\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Array(vec![Value::Bulk("foo".to_string())])));
+        assert_eq!(decoder.feed(b"*01\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(decoder.feed(b"$?\r\n;+1\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_feed_decoder_streamed_bulk() {
+        let buf = b"*2\r\n$?\r\n;4\r\nHell\r\n;1\r\no\r\n;0\r\n$?\r\n;0\r\n";
+        let mut decoder = FeedDecoder::new();
+        for byte in buf.iter() {
+            decoder.feed(&[*byte]).unwrap();
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::Bulk("Hello".to_string()),
+                                     Value::Bulk("".to_string())]));
+
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(b"$?\r\n;4\r\nHell\r\n").unwrap();
+        assert_eq!(decoder.read(), None);
+        assert_eq!(decoder.feed(b"+o\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(decoder.feed(b"$?\r\n;2\r\nabc\r\n").is_err());
+    }
+
+    #[test]
+    fn struct_feed_decoder_streamed_aggregate() {
+        let buf = b"*?\r\n:1\r\n%?\r\n+a\r\n~?\r\n.\r\n.\r\n*0\r\n.\r\n";
+        let mut decoder = FeedDecoder::new();
+        for byte in buf.iter() {
+            decoder.feed(&[*byte]).unwrap();
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::Integer(1),
+                                     Value::Map(vec![(Value::String("a".to_string()),
+                                                      Value::Set(vec![]))]),
+                                     Value::Array(vec![])]));
+
+        let mut decoder = FeedDecoder::new();
+        assert_eq!(decoder.feed(b"%?\r\n+a\r\n.\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert!(decoder.feed(b".\r\n").is_err());
+        assert!(decoder.feed(b"*?\r\n.\n").is_err());
+    }
+
+    #[test]
+    fn struct_feed_decoder_attribute() {
+        let buf = b"|1\r\n+ttl\r\n:3600\r\n*2\r\n|0\r\n:1\r\n:2\r\n";
+        let mut decoder = FeedDecoder::new();
+        decoder.feed(buf).unwrap();
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+
+        let value = Value::Attribute(vec![(Value::String("ttl".to_string()), Value::Integer(3600))],
+                                     Box::new(Value::Array(vec![Value::Attribute(vec![],
+                                                                Box::new(Value::Integer(1))),
+                                                                Value::Integer(2)])));
+        let mut decoder = FeedDecoder::new();
+        decoder.set_keep_attributes(true);
+        for byte in buf.iter() {
+            decoder.feed(&[*byte]).unwrap();
+            decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        }
+        assert_eq!(decoder.read().unwrap(), value);
+        assert_eq!(value.encode(), buf.to_vec());
+    }
+
+    #[test]
+    fn struct_feed_decoder_version() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_version(Version::Resp2);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"*1\r\n").unwrap();
+        assert_eq!(decoder.feed(b"#t\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let mut decoder = FeedDecoder::new();
+        decoder.set_version(Version::Resp3);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"*1\r\n#t\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Array(vec![Value::Boolean(true)])));
+    }
+
+    #[test]
+    fn struct_feed_decoder_max_bulk_len() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_max_bulk_len(5);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"$5\r\nhello\r\n$?\r\n;3\r\nhel\r\n;2\r\nlo\r\n;0\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Bulk("hello".to_string())));
+        assert_eq!(decoder.read(), Some(Value::Bulk("hello".to_string())));
+        // the length is checked before the payload arrives.
+        assert_eq!(decoder.feed(b"$6\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+        decoder.feed(b"$?\r\n;3\r\nhel\r\n").unwrap();
+        assert_eq!(decoder.feed(b";3\r\nlo!\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+
+        decoder.set_passthrough_unknown(true);
+        decoder.feed(b"!5\r\nERROR\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Unknown(b"!5\r\nERROR\r\n".to_vec())));
+        assert_eq!(decoder.feed(b"!20\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_feed_decoder_max_array_len() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_max_array_len(2);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"*2\r\n:1\r\n~?\r\n:1\r\n:2\r\n.\r\n").unwrap();
+        assert_eq!(decoder.read(),
+                   Some(Value::Array(vec![Value::Integer(1),
+                                          Value::Set(vec![Value::Integer(1),
+                                                          Value::Integer(2)])])));
+        assert_eq!(decoder.feed(b"*3\r\n").unwrap_err().kind(), ErrorKind::InvalidInput);
+        decoder.feed(b"*?\r\n:1\r\n:2\r\n").unwrap();
+        assert_eq!(decoder.feed(b":3\r\n").unwrap_err().to_string(),
+                   "array length 3 exceeds max array length 2");
+    }
+
+    #[test]
+    fn struct_feed_decoder_max_frame_len() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_max_frame_len(22);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*2\r\n").unwrap();
+        decoder.feed(b"*1\r\n:1\r\n*?\r\n:2\r\n").unwrap();
+        assert!(decoder.read().is_some());
+        assert_eq!(decoder.feed(b".\r\n").unwrap_err().to_string(),
+                   "frame length 23 exceeds max frame length 22");
+
+        // a bulk string fails before its payload arrives.
+        assert_eq!(decoder.feed(b"*2\r\n:1\r\n$1000000\r\n").unwrap_err().to_string(),
+                   "frame length 1000020 exceeds max frame length 22");
+        // and a line before its end arrives.
+        assert_eq!(decoder.feed(b"+OKOKOKOKOKOKOKOKOKOKOK").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        decoder.feed(b"+OK\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::String("OK".to_string())));
+    }
+
+    #[test]
+    fn struct_feed_decoder_max_line_len() {
+        let mut decoder = FeedDecoder::new();
+        decoder.set_max_line_len(5);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"+ABCD\r\n*1\r\n$5\r\nABCDE\r\n+ABC\r").unwrap();
+        decoder.feed(b"\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::String("ABCD".to_string())));
+        assert_eq!(decoder.read(), Some(Value::Array(vec![Value::Bulk("ABCDE".to_string())])));
+        assert_eq!(decoder.read(), Some(Value::String("ABC".to_string())));
+
+        // a line fails before its end arrives.
+        assert_eq!(decoder.feed(b"*1\r\n-ERROR").unwrap_err().to_string(),
+                   "line length 6 exceeds max line length 5");
+        assert_eq!(decoder.feed(b"$?\r\n;00003\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        decoder.feed(b":1\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn trait_write() {
+        let buf = encode_slice(&["SET", "a", "1"]);
+        let mut decoder = FeedDecoder::new();
+        assert_eq!(io::copy(&mut buf.as_slice(), &mut decoder).unwrap(),
+                   buf.len() as u64);
+        assert_eq!(decoder.read().unwrap(),
+                   Value::Array(vec![Value::Bulk("SET".to_string()),
+                                     Value::Bulk("a".to_string()),
+                                     Value::Bulk("1".to_string())]));
+
+        write!(decoder, ":{}\r\n", 42).unwrap();
+        assert_eq!(decoder.read(), Some(Value::Integer(42)));
+    }
+}
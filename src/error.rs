@@ -0,0 +1,116 @@
+//! RESP decoding error classes
+
+use std::error;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+/// The classes of the errors returned by the decoders. The errors are `io::Error`s, their kind
+/// tells whether more bytes may complete the value or the data is invalid:
+///
+/// - `UnexpectedEof` -> `Incomplete` if a buffer ends in the middle of a value, as `decode`
+///   returns, `Closed` if a reader does, as `Decoder` returns
+/// - `WouldBlock` and `Interrupted` -> `NotReady`
+/// - `InvalidInput` and `InvalidData` -> `Protocol`
+/// - other kinds -> `Io`, returned by the underlying reader
+///
+/// `FeedDecoder::feed` never fails with `Incomplete`, it keeps the bytes of an incomplete value
+/// buffered until more bytes are fed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorClass {
+    /// The buffer ended in the middle of a value, more bytes are needed.
+    Incomplete,
+    /// The reader isn't ready, e.g. a non-blocking socket, the call can be retried later, see
+    /// `Decoder::set_nonblocking`.
+    NotReady,
+    /// The reader was closed, before or in the middle of a value, the connection is over.
+    Closed,
+    /// The input is invalid, the connection should be dropped.
+    Protocol,
+    /// The underlying reader failed.
+    Io,
+}
+
+impl ErrorClass {
+    /// Returns the class of a decoding error.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{decode, Decoder, ErrorClass};
+    /// assert_eq!(ErrorClass::of(&decode(b"$5\r\nhel").unwrap_err()), ErrorClass::Incomplete);
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(&b"$5\r\nhel"[..]));
+    /// assert_eq!(ErrorClass::of(&decoder.decode().unwrap_err()), ErrorClass::Closed);
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(&b"$x\r\n"[..]));
+    /// assert_eq!(ErrorClass::of(&decoder.decode().unwrap_err()), ErrorClass::Protocol);
+    /// ```
+    pub fn of(err: &Error) -> ErrorClass {
+        match err.kind() {
+            ErrorKind::UnexpectedEof if is_incomplete(err) => ErrorClass::Incomplete,
+            ErrorKind::UnexpectedEof => ErrorClass::Closed,
+            ErrorKind::WouldBlock | ErrorKind::Interrupted => ErrorClass::NotReady,
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => ErrorClass::Protocol,
+            _ => ErrorClass::Io,
+        }
+    }
+}
+
+/// The error of a buffer that ends in the middle of a value, see `ErrorClass::Incomplete`.
+#[derive(Debug)]
+struct IncompleteValue;
+
+impl fmt::Display for IncompleteValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("incomplete value")
+    }
+}
+
+impl error::Error for IncompleteValue {}
+
+/// Returns the error of a buffer that ends in the middle of a value.
+pub(crate) fn incomplete() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, IncompleteValue)
+}
+
+fn is_incomplete(err: &Error) -> bool {
+    matches!(err.get_ref(), Some(inner) if inner.is::<IncompleteValue>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use super::*;
+    use super::super::{decode, Decoder, FeedDecoder};
+
+    #[test]
+    fn enum_error_class_of() {
+        let cases: &[(&[u8], ErrorClass)] = &[(b"", ErrorClass::Closed),
+                                              (b"*2\r\n:1\r\n", ErrorClass::Closed),
+                                              (b"+OK", ErrorClass::Closed),
+                                              (b"+OK\n", ErrorClass::Protocol),
+                                              (b"?\r\n", ErrorClass::Protocol),
+                                              (b"$1\r\n\xff\r\n", ErrorClass::Protocol),
+                                              (b"#x\r\n", ErrorClass::Protocol)];
+        for &(buf, class) in cases {
+            let mut decoder = Decoder::new(BufReader::new(buf));
+            assert_eq!(ErrorClass::of(&decoder.decode().unwrap_err()), class, "{:?}", buf);
+
+            let mut decoder = FeedDecoder::new();
+            match decoder.feed(buf) {
+                Ok(()) => assert_eq!(class, ErrorClass::Closed),
+                Err(err) => assert_eq!(ErrorClass::of(&err), ErrorClass::Protocol),
+            }
+
+            let class = if class == ErrorClass::Closed { ErrorClass::Incomplete } else { class };
+            assert_eq!(ErrorClass::of(&decode(buf).unwrap_err()), class, "{:?}", buf);
+        }
+
+        let err = Error::new(ErrorKind::ConnectionReset, "reset");
+        assert_eq!(ErrorClass::of(&err), ErrorClass::Io);
+        let err = Error::new(ErrorKind::UnexpectedEof, "unexpected EOF");
+        assert_eq!(ErrorClass::of(&err), ErrorClass::Closed);
+        for kind in &[ErrorKind::WouldBlock, ErrorKind::Interrupted] {
+            assert_eq!(ErrorClass::of(&Error::new(*kind, "not ready")), ErrorClass::NotReady);
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! RESP value generic over its storage types
+
+use std::vec::{self, Vec};
+use std::string::String;
+use std::str;
+use std::io::{Result, Error, ErrorKind};
+
+use super::Value;
+use super::de::Parser;
+use super::error::incomplete;
+use super::serialize::{NULL_BYTES, NULL_ARRAY_BYTES, Encode, Node, Options, encode_node,
+                       node_len};
+
+/// An entry of a map or of attributes.
+type Pair<S, B> = (GenericValue<S, B>, GenericValue<S, B>);
+
+/// A RESP value like `Value`, with the strings stored as `S` and the bytes as `B`, so a framework
+/// can back them with e.g. `Arc<str>`, `bytes::Bytes` or strings allocated in an arena.
+/// `GenericValue<String, Vec<u8>>` holds the same data as a `Value`, a `SharedBulk` or a
+/// `SmallBulk` is converted to a `BufBulk`.
+///
+/// The strings are encoded through `AsRef<str>` and the bytes through `AsRef<[u8]>`, with the
+/// encoder of `Value`, a value encodes to the same bytes as the `Value` it converts to.
+/// `GenericValue::decode` builds the strings and bytes from the slices of the decoded input.
+/// # Examples
+/// ```
+/// # use self::resp::{GenericValue, Value};
+/// use std::sync::Arc;
+///
+/// let value: GenericValue<Arc<str>, Arc<[u8]>> =
+///     GenericValue::Array(vec![GenericValue::Bulk(Arc::from("GET")),
+///                              GenericValue::BufBulk(Arc::from(&b"key"[..]))]);
+/// let cloned = value.clone();
+/// assert_eq!(cloned.encode(), b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n");
+///
+/// let value: GenericValue<Arc<str>, Arc<[u8]>> =
+///     GenericValue::from(Value::String("OK".to_string()));
+/// assert_eq!(value.to_value(), Value::String("OK".to_string()));
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub enum GenericValue<S = String, B = Vec<u8>> {
+    /// Null bulk reply, `$-1\r\n`, see `Value::Null`.
+    Null,
+    /// Null array reply, `*-1\r\n`, see `Value::NullArray`.
+    NullArray,
+    /// A Simple String, see `Value::String`.
+    String(S),
+    /// An Error, see `Value::Error`.
+    Error(S),
+    /// An Integer, see `Value::Integer`.
+    Integer(i64),
+    /// A RESP3 Boolean, see `Value::Boolean`.
+    Boolean(bool),
+    /// A RESP3 Double, see `Value::Double`.
+    Double(f64),
+    /// A RESP3 Big Number, see `Value::BigNumber`.
+    BigNumber(S),
+    /// A UTF-8 Bulk String, see `Value::Bulk`.
+    Bulk(S),
+    /// A binary Bulk String, see `Value::BufBulk`.
+    BufBulk(B),
+    /// An Array, see `Value::Array`.
+    Array(Vec<GenericValue<S, B>>),
+    /// A RESP3 Map, see `Value::Map`.
+    Map(Vec<Pair<S, B>>),
+    /// A RESP3 Set, see `Value::Set`.
+    Set(Vec<GenericValue<S, B>>),
+    /// A RESP3 Push, see `Value::Push`.
+    Push(Vec<GenericValue<S, B>>),
+    /// RESP3 Attributes and the value they describe, see `Value::Attribute`.
+    Attribute(Vec<Pair<S, B>>, Box<GenericValue<S, B>>),
+    /// The raw bytes of a value of an unknown type, see `Value::Unknown`.
+    Unknown(B),
+    /// Already encoded RESP bytes, see `Value::Raw`.
+    Raw(B),
+}
+
+impl<S: AsRef<str>, B: AsRef<[u8]>> GenericValue<S, B> {
+    /// Encodes the value, like `Value::encode`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    /// Appends the encoded value to a buffer, like `encode_into`.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        encode_node(self, buf);
+    }
+
+    /// Returns the number of bytes of the encoded value, like `Value::encoded_len`.
+    pub fn encoded_len(&self) -> usize {
+        node_len(self)
+    }
+
+    /// Copies the value into a `Value`.
+    pub fn to_value(&self) -> Value {
+        let pairs = |pairs: &[Pair<S, B>]| {
+            pairs.iter().map(|(key, value)| (key.to_value(), value.to_value())).collect()
+        };
+        let values = |values: &[GenericValue<S, B>]| {
+            values.iter().map(GenericValue::to_value).collect()
+        };
+        match *self {
+            GenericValue::Null => Value::Null,
+            GenericValue::NullArray => Value::NullArray,
+            GenericValue::String(ref val) => Value::String(val.as_ref().to_string()),
+            GenericValue::Error(ref val) => Value::Error(val.as_ref().to_string()),
+            GenericValue::Integer(val) => Value::Integer(val),
+            GenericValue::Boolean(val) => Value::Boolean(val),
+            GenericValue::Double(val) => Value::Double(val),
+            GenericValue::BigNumber(ref val) => Value::BigNumber(val.as_ref().to_string()),
+            GenericValue::Bulk(ref val) => Value::Bulk(val.as_ref().to_string()),
+            GenericValue::BufBulk(ref val) => Value::BufBulk(val.as_ref().to_vec()),
+            GenericValue::Array(ref val) => Value::Array(values(val)),
+            GenericValue::Map(ref val) => Value::Map(pairs(val)),
+            GenericValue::Set(ref val) => Value::Set(values(val)),
+            GenericValue::Push(ref val) => Value::Push(values(val)),
+            GenericValue::Attribute(ref attributes, ref val) => {
+                Value::Attribute(pairs(attributes), Box::new(val.to_value()))
+            }
+            GenericValue::Unknown(ref val) => Value::Unknown(val.as_ref().to_vec()),
+            GenericValue::Raw(ref val) => Value::Raw(val.as_ref().to_vec()),
+        }
+    }
+}
+
+impl<'a, S: From<&'a str>, B: From<&'a [u8]>> GenericValue<S, B> {
+    /// Decodes a value from the start of a buffer, like `decode`, returns the value and the
+    /// number of bytes it spans. The strings and bytes of the value are built from the slices
+    /// of the buffer they are read from, borrowed as they are with `&str` and `&[u8]` storage.
+    /// Fails with `UnexpectedEof` if the buffer holds an incomplete value, and with
+    /// `InvalidData` on a streamed bulk string, whose payload is not a slice of the buffer.
+    /// # Examples
+    /// ```
+    /// # use self::resp::GenericValue;
+    /// use std::sync::Arc;
+    ///
+    /// let buf = b"*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n+OK\r\n";
+    /// let (value, len) = GenericValue::<&str, &[u8]>::decode(buf).unwrap();
+    /// assert_eq!(value,
+    ///            GenericValue::Array(vec![GenericValue::Bulk("ECHO"),
+    ///                                     GenericValue::Bulk("hey")]));
+    ///
+    /// let (value, _) = GenericValue::<Arc<str>, Arc<[u8]>>::decode(&buf[len..]).unwrap();
+    /// assert_eq!(value, GenericValue::String(Arc::from("OK")));
+    /// ```
+    pub fn decode(buf: &'a [u8]) -> Result<(Self, usize)> {
+        let mut parser = Parser::with_spans(Options::default());
+        let mut pos = 0;
+        match parser.parse(buf, &mut pos)? {
+            Some(value) => {
+                let mut spans = parser.take_spans().into_iter();
+                Ok((GenericValue::from_parsed(value, &buf, &mut spans)?, pos))
+            }
+            None => Err(incomplete()),
+        }
+    }
+}
+
+impl<S, B> GenericValue<S, B> {
+    /// Builds the value from a value parsed with spans, see `Parser::with_spans`, its strings
+    /// and bytes are carved out of the parsed input.
+    pub(crate) fn from_parsed<I: Input<S, B>>(value: Value,
+                                              input: &I,
+                                              spans: &mut vec::IntoIter<Option<(usize, usize)>>)
+                                              -> Result<Self> {
+        let pairs = |pairs: Vec<(Value, Value)>, spans: &mut vec::IntoIter<_>| {
+            pairs.into_iter()
+                .map(|(key, value)| {
+                    let key = GenericValue::from_parsed(key, input, spans)?;
+                    Ok((key, GenericValue::from_parsed(value, input, spans)?))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let values = |values: Vec<Value>, spans: &mut vec::IntoIter<_>| {
+            values.into_iter()
+                .map(|value| GenericValue::from_parsed(value, input, spans))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(match value {
+            Value::Null => GenericValue::Null,
+            Value::NullArray => GenericValue::NullArray,
+            Value::Integer(val) => GenericValue::Integer(val),
+            Value::Boolean(val) => GenericValue::Boolean(val),
+            Value::Double(val) => GenericValue::Double(val),
+            Value::Array(val) => GenericValue::Array(values(val, spans)?),
+            Value::Map(val) => GenericValue::Map(pairs(val, spans)?),
+            Value::Set(val) => GenericValue::Set(values(val, spans)?),
+            Value::Push(val) => GenericValue::Push(values(val, spans)?),
+            Value::Attribute(attributes, val) => {
+                let attributes = pairs(attributes, spans)?;
+                let val = GenericValue::from_parsed(*val, input, spans)?;
+                GenericValue::Attribute(attributes, Box::new(val))
+            }
+            value => {
+                let (start, end) = match spans.next() {
+                    Some(Some(span)) => span,
+                    _ => return input.owned(value),
+                };
+                match value {
+                    Value::String(_) => GenericValue::String(input.str(start, end)?),
+                    Value::Error(_) => GenericValue::Error(input.str(start, end)?),
+                    Value::BigNumber(_) => GenericValue::BigNumber(input.str(start, end)?),
+                    Value::Bulk(_) => GenericValue::Bulk(input.str(start, end)?),
+                    Value::Unknown(_) => GenericValue::Unknown(input.bytes(start, end)),
+                    _ => GenericValue::BufBulk(input.bytes(start, end)),
+                }
+            }
+        })
+    }
+}
+
+/// The input values are parsed from, the strings and bytes of a `GenericValue` are carved out of
+/// it, see `GenericValue::from_parsed`.
+pub(crate) trait Input<S, B> {
+    /// Returns the string at `start..end`.
+    fn str(&self, start: usize, end: usize) -> Result<S>;
+
+    /// Returns the bytes at `start..end`.
+    fn bytes(&self, start: usize, end: usize) -> B;
+
+    /// Converts a leaf parsed without a span, a streamed bulk string joined by the parser.
+    fn owned(&self, value: Value) -> Result<GenericValue<S, B>>;
+}
+
+impl<'a, S: From<&'a str>, B: From<&'a [u8]>> Input<S, B> for &'a [u8] {
+    fn str(&self, start: usize, end: usize) -> Result<S> {
+        let buf: &'a [u8] = self;
+        str::from_utf8(&buf[start..end])
+            .map(S::from)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    fn bytes(&self, start: usize, end: usize) -> B {
+        let buf: &'a [u8] = self;
+        B::from(&buf[start..end])
+    }
+
+    fn owned(&self, _: Value) -> Result<GenericValue<S, B>> {
+        Err(Error::new(ErrorKind::InvalidData,
+                       "a streamed bulk string is not a slice of the input"))
+    }
+}
+
+impl<S: AsRef<str>, B: AsRef<[u8]>> Encode for GenericValue<S, B> {
+    fn node<'a>(&'a self) -> Node<'a, Self> {
+        match *self {
+            GenericValue::Null => Node::Bytes(NULL_BYTES),
+            GenericValue::NullArray => Node::Bytes(NULL_ARRAY_BYTES),
+            GenericValue::String(ref val) => Node::Line(b'+', val.as_ref()),
+            GenericValue::Error(ref val) => Node::Line(b'-', val.as_ref()),
+            GenericValue::Integer(val) => Node::Integer(val),
+            GenericValue::Boolean(true) => Node::Bytes(b"#t\r\n"),
+            GenericValue::Boolean(false) => Node::Bytes(b"#f\r\n"),
+            GenericValue::Double(val) => Node::Double(val),
+            GenericValue::BigNumber(ref val) => Node::Line(b'(', val.as_ref()),
+            GenericValue::Bulk(ref val) => Node::Bulk(val.as_ref().as_bytes()),
+            GenericValue::BufBulk(ref val) => Node::Bulk(val.as_ref()),
+            GenericValue::Array(ref val) => Node::Values(b'*', val),
+            GenericValue::Set(ref val) => Node::Values(b'~', val),
+            GenericValue::Push(ref val) => Node::Values(b'>', val),
+            GenericValue::Map(ref val) => Node::Map(val),
+            GenericValue::Attribute(ref attributes, ref val) => Node::Attribute(attributes, val),
+            GenericValue::Unknown(ref val) | GenericValue::Raw(ref val) => {
+                Node::Bytes(val.as_ref())
+            }
+        }
+    }
+}
+
+impl<S: From<String>, B: From<Vec<u8>>> From<Value> for GenericValue<S, B> {
+    /// Moves the strings and bytes of a `Value` into the storage types.
+    fn from(value: Value) -> Self {
+        let pairs = |pairs: Vec<(Value, Value)>| {
+            pairs.into_iter()
+                .map(|(key, value)| (GenericValue::from(key), GenericValue::from(value)))
+                .collect()
+        };
+        let values = |values: Vec<Value>| values.into_iter().map(GenericValue::from).collect();
+        match value {
+            Value::Null => GenericValue::Null,
+            Value::NullArray => GenericValue::NullArray,
+            Value::String(val) => GenericValue::String(S::from(val)),
+            Value::Error(val) => GenericValue::Error(S::from(val)),
+            Value::Integer(val) => GenericValue::Integer(val),
+            Value::Boolean(val) => GenericValue::Boolean(val),
+            Value::Double(val) => GenericValue::Double(val),
+            Value::BigNumber(val) => GenericValue::BigNumber(S::from(val)),
+            Value::Bulk(val) => GenericValue::Bulk(S::from(val)),
+            Value::BufBulk(val) => GenericValue::BufBulk(B::from(val)),
+            Value::SharedBulk(val) => GenericValue::BufBulk(B::from(val.to_vec())),
+            Value::SmallBulk(val) => GenericValue::BufBulk(B::from(val.to_vec())),
+            Value::Array(val) => GenericValue::Array(values(val)),
+            Value::Map(val) => GenericValue::Map(pairs(val)),
+            Value::Set(val) => GenericValue::Set(values(val)),
+            Value::Push(val) => GenericValue::Push(values(val)),
+            Value::Attribute(attributes, val) => {
+                GenericValue::Attribute(pairs(attributes), Box::new(GenericValue::from(*val)))
+            }
+            Value::Unknown(val) => GenericValue::Unknown(B::from(val)),
+            Value::Raw(val) => GenericValue::Raw(B::from(val)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn values() -> Vec<Value> {
+        vec![Value::Null,
+             Value::NullArray,
+             Value::String("OK\r\n".to_string()),
+             Value::Error("ERR".to_string()),
+             Value::Integer(-12),
+             Value::Boolean(true),
+             Value::Double(1.5),
+             Value::BigNumber("12345678901234567890".to_string()),
+             Value::Bulk("中文".to_string()),
+             Value::BufBulk(vec![255, 0]),
+             Value::Array(vec![Value::Integer(1), Value::Array(vec![])]),
+             Value::Map(vec![(Value::Bulk("a".to_string()), Value::Set(vec![Value::Null]))]),
+             Value::Push(vec![Value::Bulk("message".to_string())]),
+             Value::Attribute(vec![(Value::Bulk("ttl".to_string()), Value::Integer(1))],
+                              Box::new(Value::Bulk("v".to_string()))),
+             Value::Unknown(b"!1\r\n".to_vec()),
+             Value::Raw(b":1\r\n".to_vec())]
+    }
+
+    #[test]
+    fn enum_generic_value() {
+        for value in values() {
+            let generic: GenericValue = GenericValue::from(value.clone());
+            assert_eq!(generic.encode(), value.encode());
+            assert_eq!(generic.encoded_len(), value.encoded_len());
+            assert_eq!(generic.to_value(), value);
+
+            let shared: GenericValue<Arc<str>, Arc<[u8]>> = GenericValue::from(value.clone());
+            assert_eq!(shared.encode(), value.encode());
+            assert_eq!(shared.to_value(), value);
+        }
+
+        let shared: GenericValue = GenericValue::from(Value::SharedBulk(Arc::from(&b"a"[..])));
+        assert_eq!(shared, GenericValue::BufBulk(b"a".to_vec()));
+
+        // borrowed storage, e.g. strings allocated in an arena.
+        let args = ["SET", "key"];
+        let value: GenericValue<&str, &[u8]> =
+            GenericValue::Array(args.iter().map(|arg| GenericValue::Bulk(*arg)).collect());
+        assert_eq!(value.encode(), b"*2\r\n$3\r\nSET\r\n$3\r\nkey\r\n");
+    }
+
+    #[test]
+    fn fn_generic_value_decode() {
+        use super::super::decode;
+
+        for value in values() {
+            let buf = value.encode();
+            let expected = match decode(&buf) {
+                Ok((expected, _)) => expected,
+                Err(err) => {
+                    let generic = GenericValue::<&str, &[u8]>::decode(&buf);
+                    assert_eq!(generic.unwrap_err().kind(), err.kind());
+                    continue;
+                }
+            };
+            let (generic, len) = GenericValue::<&str, &[u8]>::decode(&buf).unwrap();
+            assert_eq!(len, buf.len());
+            assert_eq!(generic.to_value(), expected);
+
+            let (shared, _) = GenericValue::<Arc<str>, Arc<[u8]>>::decode(&buf).unwrap();
+            assert_eq!(shared.to_value(), generic.to_value());
+        }
+
+        // the strings and bytes are borrowed from the input.
+        let buf = b"*3\r\n+OK\r\n$3\r\nfoo\r\n|1\r\n+ttl\r\n:1\r\n(12\r\n";
+        let (value, _) = GenericValue::<&str, &[u8]>::decode(buf).unwrap();
+        assert_eq!(value,
+                   GenericValue::Array(vec![GenericValue::String("OK"),
+                                            GenericValue::Bulk("foo"),
+                                            GenericValue::BigNumber("12")]));
+        if let GenericValue::Array(ref values) = value {
+            if let GenericValue::Bulk(bulk) = values[1] {
+                assert_eq!(bulk.as_ptr(), buf[13..].as_ptr());
+            }
+        }
+
+        let err = GenericValue::<&str, &[u8]>::decode(b"$?\r\n;3\r\nfoo\r\n;0\r\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = GenericValue::<&str, &[u8]>::decode(b"*2\r\n$3\r\nfoo\r\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn enum_generic_value_bytes() {
+        use bytes::Bytes;
+
+        for value in values() {
+            let generic: GenericValue<String, Bytes> = GenericValue::from(value.clone());
+            assert_eq!(generic.encode(), value.encode());
+            assert_eq!(generic.to_value(), value);
+        }
+    }
+}
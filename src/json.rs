@@ -0,0 +1,287 @@
+//! RESP value conversion from and into `serde_json::Value`, requires the `json` feature.
+
+use std::vec::Vec;
+use std::string::String;
+
+use serde_json::{self, Map, Number};
+
+use super::Value;
+use super::value::write_base64;
+use super::serialize::format_double;
+
+impl Value {
+    /// Converts the value into a `serde_json::Value` with the rules of `to_json_string`. A
+    /// `BigNumber` which doesn't fit in an `i64` or `u64` becomes a string of its digits, as
+    /// `serde_json` numbers can't hold it losslessly. Objects keep their keys in the order of
+    /// `serde_json::Map`, sorted unless its `preserve_order` feature is enabled. Requires the
+    /// `json` feature.
+    /// # Examples
+    /// ```
+    /// # extern crate resp;
+    /// # #[macro_use] extern crate serde_json;
+    /// # use resp::Value;
+    /// # fn main() {
+    /// let value = Value::Array(vec![Value::Bulk("a".to_string()),
+    ///                               Value::BufBulk(vec![255]),
+    ///                               Value::Error("ERR".to_string())]);
+    /// assert_eq!(value.to_json_value(), json!(["a", {"base64": "/w=="}, {"error": "ERR"}]));
+    /// # }
+    /// ```
+    pub fn to_json_value(&self) -> serde_json::Value {
+        match *self {
+            Value::Null | Value::NullArray => serde_json::Value::Null,
+            Value::String(ref val) | Value::Bulk(ref val) => {
+                serde_json::Value::String(val.clone())
+            }
+            Value::Error(ref val) => object("error", serde_json::Value::String(val.clone())),
+            Value::Integer(val) => serde_json::Value::from(val),
+            Value::BigNumber(ref val) => {
+                if let Ok(int) = val.parse::<i64>() {
+                    serde_json::Value::from(int)
+                } else if let Ok(int) = val.parse::<u64>() {
+                    serde_json::Value::from(int)
+                } else {
+                    serde_json::Value::String(val.clone())
+                }
+            }
+            Value::Boolean(val) => serde_json::Value::Bool(val),
+            Value::Double(val) => {
+                match Number::from_f64(val) {
+                    Some(number) => serde_json::Value::Number(number),
+                    None => serde_json::Value::String(format_double(val)),
+                }
+            }
+            Value::BufBulk(ref val) => json_bytes(val),
+            Value::SharedBulk(ref val) => json_bytes(val),
+            Value::SmallBulk(ref val) => json_bytes(val),
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                serde_json::Value::Array(val.iter().map(Value::to_json_value).collect())
+            }
+            Value::Map(ref val) => json_map(val),
+            Value::Attribute(ref attributes, ref val) => {
+                let mut map = Map::new();
+                map.insert("attributes".to_string(), json_map(attributes));
+                map.insert("value".to_string(), val.to_json_value());
+                serde_json::Value::Object(map)
+            }
+            Value::Unknown(ref val) => object("unknown", base64(val)),
+            Value::Raw(ref val) => {
+                match self.decode_raw() {
+                    Some(decoded) => decoded.to_json_value(),
+                    None => object("raw", base64(val)),
+                }
+            }
+        }
+    }
+}
+
+impl From<serde_json::Value> for Value {
+    /// Converts a `serde_json::Value`, the reverse of `Value::to_json_value` for the common
+    /// types. Requires the `json` feature.
+    ///
+    /// - `null` -> `Null`
+    /// - `true` and `false` -> `Boolean`
+    /// - an integer -> `Integer`, or `BigNumber` if it exceeds `i64`
+    /// - any other number -> `Double`
+    /// - a string -> `Bulk`
+    /// - an array -> `Array`
+    /// - an object with a single `error` string -> `Error`
+    /// - an object with a single `base64` string of valid standard base64 -> `BufBulk`
+    /// - any other object -> `Map` with `Bulk` keys
+    ///
+    /// The other forms of `to_json_value` are not recognized, e.g. a `Double` infinity comes back
+    /// as the `Bulk` string `"inf"`, and an `Attribute` as a `Map`.
+    /// # Examples
+    /// ```
+    /// # extern crate resp;
+    /// # #[macro_use] extern crate serde_json;
+    /// # use resp::Value;
+    /// # fn main() {
+    /// assert_eq!(Value::from(json!([1, {"base64": "/w=="}])),
+    ///            Value::Array(vec![Value::Integer(1), Value::BufBulk(vec![255])]));
+    /// # }
+    /// ```
+    fn from(json: serde_json::Value) -> Value {
+        match json {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(val) => Value::Boolean(val),
+            serde_json::Value::Number(val) => {
+                if let Some(int) = val.as_i64() {
+                    Value::Integer(int)
+                } else if let Some(int) = val.as_u64() {
+                    Value::BigNumber(int.to_string())
+                } else {
+                    Value::Double(val.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            serde_json::Value::String(val) => Value::Bulk(val),
+            serde_json::Value::Array(val) => {
+                Value::Array(val.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(map) => {
+                if map.len() == 1 {
+                    let (key, val) = map.iter().next().unwrap();
+                    match (key.as_str(), val) {
+                        ("error", serde_json::Value::String(val)) => {
+                            return Value::Error(val.clone());
+                        }
+                        ("base64", serde_json::Value::String(val)) => {
+                            if let Some(bytes) = decode_base64(val) {
+                                return Value::BufBulk(bytes);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Value::Map(map.into_iter()
+                               .map(|(key, value)| (Value::Bulk(key), Value::from(value)))
+                               .collect())
+            }
+        }
+    }
+}
+
+fn object(key: &str, value: serde_json::Value) -> serde_json::Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    serde_json::Value::Object(map)
+}
+
+fn json_bytes(bytes: &[u8]) -> serde_json::Value {
+    match ::std::str::from_utf8(bytes) {
+        Ok(val) => serde_json::Value::String(val.to_string()),
+        Err(_) => object("base64", base64(bytes)),
+    }
+}
+
+fn base64(bytes: &[u8]) -> serde_json::Value {
+    let mut string = String::new();
+    write_base64(bytes, &mut string);
+    serde_json::Value::String(string)
+}
+
+/// Converts the entries into an object, with the key rules of `to_json_string`.
+fn json_map(pairs: &[(Value, Value)]) -> serde_json::Value {
+    let mut map = Map::new();
+    for (key, value) in pairs {
+        let key = match key.as_str() {
+            Some(key) => key.to_string(),
+            None => key.to_json_string(),
+        };
+        map.insert(key, value.to_json_value());
+    }
+    serde_json::Value::Object(map)
+}
+
+/// Decodes standard base64 with padding, returns `None` if the text is not valid base64.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if text.len() % 4 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i + 1 == text.len() / 4;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let digit = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => return None,
+            };
+            n = n << 6 | digit as u32;
+        }
+        n <<= 6 * padding as u32;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        bytes.extend_from_slice(&decoded[..3 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fn_to_json_value() {
+        let values = vec![Value::Null,
+                          Value::String("OK".to_string()),
+                          Value::Error("ERR x".to_string()),
+                          Value::Integer(-123),
+                          Value::Bulk("中文\"\\\r\n".to_string()),
+                          Value::BufBulk(vec![79, 75]),
+                          Value::BufBulk(vec![255, 0, 1, 2]),
+                          Value::Unknown(b"#t\r\n".to_vec()),
+                          Value::Raw(b"*1\r\n:1\r\n".to_vec()),
+                          Value::Set(vec![Value::Boolean(true)]),
+                          Value::Array(vec![Value::Double(-0.5),
+                                            Value::Double(f64::INFINITY),
+                                            Value::Double(f64::NAN)]),
+                          Value::BigNumber("-12345".to_string()),
+                          Value::BigNumber("18446744073709551615".to_string()),
+                          Value::Map(vec![(Value::Integer(2), Value::Map(vec![])),
+                                          (Value::Bulk("a".to_string()), Value::Integer(1)),
+                                          (Value::BufBulk(vec![255]), Value::Null)]),
+                          Value::Attribute(vec![(Value::Bulk("ttl".to_string()),
+                                                 Value::Integer(1))],
+                                           Box::new(Value::Null))];
+        for value in &values {
+            assert_eq!(serde_json::to_string(&value.to_json_value()).unwrap(),
+                       value.to_json_string());
+        }
+
+        let big = Value::BigNumber("-3492890328409238509324850943850943825024385".to_string());
+        assert_eq!(big.to_json_value(),
+                   serde_json::Value::String("-3492890328409238509324850943850943825024385"
+                                                 .to_string()));
+    }
+
+    #[test]
+    fn fn_from_json_value() {
+        let values = vec![Value::Null,
+                          Value::Boolean(false),
+                          Value::Integer(i64::MIN),
+                          Value::BigNumber("18446744073709551615".to_string()),
+                          Value::Double(1.5),
+                          Value::Bulk("中文".to_string()),
+                          Value::Error("ERR".to_string()),
+                          Value::BufBulk(vec![255]),
+                          Value::BufBulk(vec![255, 254]),
+                          Value::BufBulk(vec![255, 254, 253, 0]),
+                          Value::Array(vec![Value::Null, Value::Integer(1)]),
+                          Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+                                          (Value::Bulk("b".to_string()), Value::Array(vec![]))])];
+        for value in values {
+            assert_eq!(Value::from(value.to_json_value()), value);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(r#"{"base64":"/w="}"#).unwrap();
+        assert_eq!(Value::from(json),
+                   Value::Map(vec![(Value::Bulk("base64".to_string()),
+                                    Value::Bulk("/w=".to_string()))]));
+        let json: serde_json::Value = serde_json::from_str(r#"{"error":1,"x":2}"#).unwrap();
+        assert_eq!(Value::from(json).pairs().len(), 2);
+        assert_eq!(Value::from(Value::Double(f64::NAN).to_json_value()),
+                   Value::Bulk("nan".to_string()));
+    }
+
+    #[test]
+    fn fn_decode_base64() {
+        for len in 0..10 {
+            let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            let mut text = String::new();
+            write_base64(&bytes, &mut text);
+            assert_eq!(decode_base64(&text), Some(bytes));
+        }
+        for text in &["/w=", "/w==/w==", "/===", "/w=x", "!w=="] {
+            assert_eq!(decode_base64(text), None, "{}", text);
+        }
+    }
+}
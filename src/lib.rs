@@ -7,8 +7,62 @@
 
 //! RESP(Redis Serialization Protocol) Serialization for Rust.
 
-pub use self::value::Value;
-pub use self::serialize::{encode, encode_slice, Decoder};
+extern crate memchr;
+#[cfg(feature = "bignum")]
+extern crate num_bigint;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+pub use self::value::{Value, Pairs, ValueIndex, OK_REPLY, PONG_REPLY, QUEUED_REPLY};
+pub use self::generic::GenericValue;
+pub use self::shape::Shape;
+pub use self::convert::FromResp;
+pub use self::cmd::ToArg;
+pub use self::lua::{LuaValue, LuaTable};
+pub use self::de::{check_complete, decode, Awaiting, DecoderState, FeedDecoder};
+pub use self::error::ErrorClass;
+pub use self::pubsub::{Invalidation, PubSubMessage};
+pub use self::cluster::{Redirect, RedirectKind};
+pub use self::walk::Visitor;
+pub use self::rdb::RdbReader;
+pub use self::bulk::BulkReader;
+pub use self::small::{SmallBulk, SMALL_BULK_CAPACITY};
+#[cfg(feature = "bytes")]
+pub use self::buf::{encode_bytes, BytesDecoder, BytesValue};
+pub use self::serialize::{encode, encode_bulk_from_reader, encode_bytes_slice,
+                          encode_canonical, encode_into, encode_slice, encode_to_fmt,
+                          encode_to_writer, encode_to_writer_with_hook, encode_vectored,
+                          encode_with_hook, relay_bulk, relay_frame, BulkHook, ChunkReader,
+                          Decoder, Encoder, FrameType, PipelineEncoder, StreamedBulkWriter,
+                          VectoredEncoding, Version};
+pub use self::serialize::{OK_BYTES, PONG_BYTES, QUEUED_BYTES};
+pub use self::serialize::{encode_len_header, is_crlf, strip_crlf, parse_string, parse_integer,
+                          parse_double, parse_big_number, parse_integer_line, parse_bulk_header};
 
 mod value;
+mod generic;
 mod serialize;
+mod de;
+mod shape;
+mod convert;
+mod cmd;
+mod lua;
+mod pubsub;
+mod cluster;
+mod walk;
+mod cli;
+mod error;
+mod rdb;
+mod bulk;
+#[cfg(feature = "bytes")]
+mod buf;
+#[cfg(feature = "json")]
+mod json;
+mod small;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use super::convert::{expect_array, from_element};
+}
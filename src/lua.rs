@@ -0,0 +1,313 @@
+//! RESP and Lua value conversion
+
+use std::vec::Vec;
+use std::string::String;
+
+use super::Value;
+
+/// Represents a Lua value, following the conversion rules of Redis scripting,
+/// see [Conversion between Lua and Redis data types](https://redis.io/docs/interact/programmability/lua-api/).
+///
+/// It doesn't depend on a Lua binding, mapping it to the binding's values (e.g. `mlua::Value`)
+/// is a direct match on the variants.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LuaValue {
+    /// Lua `nil`.
+    Nil,
+    /// Lua boolean.
+    Boolean(bool),
+    /// Lua number.
+    Number(f64),
+    /// Lua string, it is binary safe.
+    String(Vec<u8>),
+    /// Lua table.
+    Table(LuaTable),
+}
+
+/// Represents a Lua table with its array part and its named fields.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LuaTable {
+    /// The array part, index `1` of the table is the first element.
+    pub array: Vec<LuaValue>,
+    /// The named fields, like `ok` and `err`.
+    pub fields: Vec<(String, LuaValue)>,
+}
+
+impl LuaTable {
+    /// Returns the value of a named field.
+    pub fn get(&self, name: &str) -> Option<&LuaValue> {
+        self.fields.iter().find(|field| field.0 == name).map(|field| &field.1)
+    }
+}
+
+impl LuaValue {
+    /// Converts a RESP value to a Lua value, the same as `redis.call` does:
+    ///
+    /// - `Integer` -> number
+    /// - `Boolean` -> boolean
+    /// - `Double` -> table with a single `double` field, a number
+    /// - `BigNumber` -> table with a single `big_number` field, a string
+    /// - `Bulk` and `BufBulk` -> string
+    /// - `Array` and `Push` -> table (nested)
+    /// - `Map` -> table with a single `map` field, a table of the entries whose keys are the
+    ///   text of the RESP keys (a key which is not a string uses its JSON text)
+    /// - `Set` -> table with a single `set` field, a table whose keys are the text of the
+    ///   elements, each set to `true`
+    /// - `String` -> table with a single `ok` field
+    /// - `Error` -> table with a single `err` field
+    /// - `Null` and `NullArray` -> `false`
+    /// - `Unknown` -> string of the raw bytes
+    /// - `Raw` -> the decoded value, or a string of the bytes if they don't hold a single value
+    /// - `Attribute` -> the value, the attributes are dropped
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, LuaValue};
+    /// assert_eq!(LuaValue::from_resp(&Value::Null), LuaValue::Boolean(false));
+    /// assert_eq!(LuaValue::from_resp(&Value::Integer(1)), LuaValue::Number(1.0));
+    /// ```
+    pub fn from_resp(value: &Value) -> LuaValue {
+        match *value {
+            Value::Null | Value::NullArray => LuaValue::Boolean(false),
+            Value::String(ref val) => field_table("ok", val),
+            Value::Error(ref val) => field_table("err", val),
+            Value::Integer(val) => LuaValue::Number(val as f64),
+            Value::Boolean(val) => LuaValue::Boolean(val),
+            Value::BigNumber(ref val) => field_table("big_number", val),
+            Value::Attribute(_, ref val) => LuaValue::from_resp(val),
+            Value::Raw(ref val) => {
+                match value.decode_raw() {
+                    Some(decoded) => LuaValue::from_resp(&decoded),
+                    None => LuaValue::String(val.clone()),
+                }
+            }
+            Value::Double(val) => {
+                LuaValue::Table(LuaTable {
+                                    array: Vec::new(),
+                                    fields: vec![("double".to_string(), LuaValue::Number(val))],
+                                })
+            }
+            Value::Bulk(ref val) => LuaValue::String(val.as_bytes().to_vec()),
+            Value::BufBulk(ref val) | Value::Unknown(ref val) => LuaValue::String(val.clone()),
+            Value::SharedBulk(ref val) => LuaValue::String(val.to_vec()),
+            Value::SmallBulk(ref val) => LuaValue::String(val.to_vec()),
+            Value::Array(ref val) | Value::Push(ref val) => {
+                LuaValue::Table(LuaTable {
+                                    array: val.iter().map(LuaValue::from_resp).collect(),
+                                    fields: Vec::new(),
+                                })
+            }
+            Value::Map(ref val) => {
+                let map = LuaTable {
+                    array: Vec::new(),
+                    fields: val.iter()
+                        .map(|(key, value)| (key_name(key), LuaValue::from_resp(value)))
+                        .collect(),
+                };
+                LuaValue::Table(LuaTable {
+                                    array: Vec::new(),
+                                    fields: vec![("map".to_string(), LuaValue::Table(map))],
+                                })
+            }
+            Value::Set(ref val) => {
+                let set = LuaTable {
+                    array: Vec::new(),
+                    fields: val.iter()
+                        .map(|item| (key_name(item), LuaValue::Boolean(true)))
+                        .collect(),
+                };
+                LuaValue::Table(LuaTable {
+                                    array: Vec::new(),
+                                    fields: vec![("set".to_string(), LuaValue::Table(set))],
+                                })
+            }
+        }
+    }
+
+    /// Converts a Lua value to a RESP value, the same as a script reply is converted:
+    ///
+    /// - number -> `Integer`, truncated toward zero
+    /// - string -> `BufBulk`
+    /// - table with an `err` string field -> `Error`
+    /// - table with an `ok` string field -> `String`
+    /// - table with a `double` number field -> `Double`
+    /// - table with a `big_number` string field -> `BigNumber`
+    /// - table with a `map` table field -> `Map` of its named fields, keys become `BufBulk`
+    /// - table with a `set` table field -> `Set` of its field names as `BufBulk`
+    /// - other table -> `Array` of its array part, up to the first `nil`
+    /// - `false` and `nil` -> `Null`
+    /// - `true` -> `Integer(1)`
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, LuaValue};
+    /// assert_eq!(LuaValue::Number(3.99).to_resp(), Value::Integer(3));
+    /// assert_eq!(LuaValue::Boolean(true).to_resp(), Value::Integer(1));
+    /// ```
+    pub fn to_resp(&self) -> Value {
+        match *self {
+            LuaValue::Nil | LuaValue::Boolean(false) => Value::Null,
+            LuaValue::Boolean(true) => Value::Integer(1),
+            LuaValue::Number(val) => Value::Integer(val as i64),
+            LuaValue::String(ref val) => Value::BufBulk(val.clone()),
+            LuaValue::Table(ref table) => {
+                if let Some(LuaValue::String(val)) = table.get("err") {
+                    return Value::Error(String::from_utf8_lossy(val).into_owned());
+                }
+                if let Some(LuaValue::String(val)) = table.get("ok") {
+                    return Value::String(String::from_utf8_lossy(val).into_owned());
+                }
+                if let Some(LuaValue::String(val)) = table.get("big_number") {
+                    return Value::BigNumber(String::from_utf8_lossy(val).into_owned());
+                }
+                if let Some(LuaValue::Number(val)) = table.get("double") {
+                    return Value::Double(*val);
+                }
+                if let Some(LuaValue::Table(map)) = table.get("map") {
+                    return Value::Map(map.fields
+                                          .iter()
+                                          .map(|(key, value)| {
+                                                   (Value::BufBulk(key.as_bytes().to_vec()),
+                                                    value.to_resp())
+                                               })
+                                          .collect());
+                }
+                if let Some(LuaValue::Table(set)) = table.get("set") {
+                    return Value::Set(set.fields
+                                          .iter()
+                                          .map(|(key, _)| Value::BufBulk(key.as_bytes().to_vec()))
+                                          .collect());
+                }
+                Value::Array(table.array
+                                 .iter()
+                                 .take_while(|item| **item != LuaValue::Nil)
+                                 .map(LuaValue::to_resp)
+                                 .collect())
+            }
+        }
+    }
+}
+
+fn field_table(name: &str, val: &str) -> LuaValue {
+    LuaValue::Table(LuaTable {
+                        array: Vec::new(),
+                        fields: vec![(name.to_string(), LuaValue::String(val.as_bytes().to_vec()))],
+                    })
+}
+
+fn key_name(key: &Value) -> String {
+    match *key {
+        Value::String(ref key) | Value::Bulk(ref key) => key.clone(),
+        _ => {
+            match key.as_str() {
+                Some(key) => key.to_string(),
+                None => key.to_json_string(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enum_from_resp() {
+        assert_eq!(LuaValue::from_resp(&Value::Null), LuaValue::Boolean(false));
+        assert_eq!(LuaValue::from_resp(&Value::NullArray),
+                   LuaValue::Boolean(false));
+        assert_eq!(LuaValue::from_resp(&Value::Integer(-2)), LuaValue::Number(-2.0));
+        assert_eq!(LuaValue::from_resp(&Value::Bulk("foo".to_string())),
+                   LuaValue::String(b"foo".to_vec()));
+        assert_eq!(LuaValue::from_resp(&Value::BufBulk(vec![0, 255])),
+                   LuaValue::String(vec![0, 255]));
+
+        let ok = LuaValue::from_resp(&Value::String("OK".to_string()));
+        assert_eq!(ok, field_table("ok", "OK"));
+        let err = LuaValue::from_resp(&Value::Error("ERR x".to_string()));
+        assert_eq!(err, field_table("err", "ERR x"));
+
+        let array = LuaValue::from_resp(&Value::Array(vec![Value::Integer(1), Value::Null]));
+        assert_eq!(array,
+                   LuaValue::Table(LuaTable {
+                                       array: vec![LuaValue::Number(1.0),
+                                                   LuaValue::Boolean(false)],
+                                       fields: vec![],
+                                   }));
+
+        let map = LuaValue::from_resp(&Value::Map(vec![(Value::Bulk("a".to_string()),
+                                                        Value::Integer(1)),
+                                                       (Value::Integer(2), Value::Null)]));
+        let fields = vec![("a".to_string(), LuaValue::Number(1.0)),
+                          ("2".to_string(), LuaValue::Boolean(false))];
+        assert_eq!(map,
+                   LuaValue::Table(LuaTable {
+                                       array: vec![],
+                                       fields: vec![("map".to_string(),
+                                                     LuaValue::Table(LuaTable {
+                                                                         array: vec![],
+                                                                         fields,
+                                                                     }))],
+                                   }));
+        assert_eq!(map.to_resp(),
+                   Value::Map(vec![(Value::BufBulk(b"a".to_vec()), Value::Integer(1)),
+                                   (Value::BufBulk(b"2".to_vec()), Value::Null)]));
+
+        assert_eq!(LuaValue::from_resp(&Value::Boolean(true)), LuaValue::Boolean(true));
+        let double = LuaValue::from_resp(&Value::Double(1.5));
+        assert_eq!(double.to_resp(), Value::Double(1.5));
+        let big = Value::BigNumber("12345678901234567890".to_string());
+        assert_eq!(LuaValue::from_resp(&big), field_table("big_number", "12345678901234567890"));
+        assert_eq!(LuaValue::from_resp(&big).to_resp(), big);
+        let attribute = Value::Attribute(vec![(Value::Null, Value::Null)],
+                                         Box::new(Value::Integer(1)));
+        assert_eq!(LuaValue::from_resp(&attribute), LuaValue::Number(1.0));
+        let set = LuaValue::from_resp(&Value::Set(vec![Value::Bulk("a".to_string()),
+                                                       Value::Integer(2)]));
+        assert_eq!(set.to_resp(),
+                   Value::Set(vec![Value::BufBulk(b"a".to_vec()), Value::BufBulk(b"2".to_vec())]));
+    }
+
+    #[test]
+    fn enum_to_resp() {
+        assert_eq!(LuaValue::Nil.to_resp(), Value::Null);
+        assert_eq!(LuaValue::Boolean(false).to_resp(), Value::Null);
+        assert_eq!(LuaValue::Boolean(true).to_resp(), Value::Integer(1));
+        assert_eq!(LuaValue::Number(-3.7).to_resp(), Value::Integer(-3));
+        assert_eq!(LuaValue::String(vec![0, 255]).to_resp(),
+                   Value::BufBulk(vec![0, 255]));
+        assert_eq!(field_table("ok", "OK").to_resp(), Value::String("OK".to_string()));
+        assert_eq!(field_table("err", "ERR").to_resp(),
+                   Value::Error("ERR".to_string()));
+
+        // err wins over ok, and a non string err is ignored.
+        let table = LuaTable {
+            array: vec![LuaValue::Number(1.0)],
+            fields: vec![("ok".to_string(), LuaValue::String(b"OK".to_vec())),
+                         ("err".to_string(), LuaValue::String(b"ERR".to_vec()))],
+        };
+        assert_eq!(LuaValue::Table(table).to_resp(), Value::Error("ERR".to_string()));
+        let table = LuaTable {
+            array: vec![LuaValue::Number(1.0)],
+            fields: vec![("err".to_string(), LuaValue::Number(1.0))],
+        };
+        assert_eq!(LuaValue::Table(table).to_resp(),
+                   Value::Array(vec![Value::Integer(1)]));
+
+        // the array part stops at the first nil.
+        let table = LuaTable {
+            array: vec![LuaValue::Number(1.0),
+                        LuaValue::Boolean(false),
+                        LuaValue::Nil,
+                        LuaValue::Number(2.0)],
+            fields: vec![],
+        };
+        assert_eq!(LuaValue::Table(table).to_resp(),
+                   Value::Array(vec![Value::Integer(1), Value::Null]));
+
+        // round trip keeps the RESP value, except bulks become BufBulk.
+        let value = Value::Array(vec![Value::String("OK".to_string()),
+                                      Value::Error("ERR".to_string()),
+                                      Value::Integer(7),
+                                      Value::BufBulk(b"foo".to_vec())]);
+        assert_eq!(LuaValue::from_resp(&value).to_resp(), value);
+    }
+}
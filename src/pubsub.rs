@@ -0,0 +1,337 @@
+//! RESP pub/sub and client side caching messages
+
+use std::vec::Vec;
+use std::string::String;
+use std::io::{Result, Error, ErrorKind};
+
+use super::Value;
+
+/// The channel invalidation messages are published to when tracking is redirected (RESP2).
+const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+/// Represents a client side caching invalidation message, sent by Redis to a client with
+/// `CLIENT TRACKING` on.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Invalidation {
+    /// The keys were modified.
+    Keys(Vec<Vec<u8>>),
+    /// All the keys were flushed, e.g. by `FLUSHALL`.
+    All,
+}
+
+impl Invalidation {
+    /// Recognizes an invalidation message: a RESP3 push `["invalidate", keys]`, or a RESP2
+    /// pub/sub message `["message", "__redis__:invalidate", keys]` when tracking is redirected.
+    /// A null key list invalidates all the keys. Returns `None` for other values.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, Invalidation};
+    /// let push = Value::Push(vec![Value::Bulk("invalidate".to_string()),
+    ///                             Value::Array(vec![Value::Bulk("a".to_string())])]);
+    /// assert_eq!(Invalidation::from_resp(&push),
+    ///            Some(Invalidation::Keys(vec![b"a".to_vec()])));
+    ///
+    /// let push = Value::Push(vec![Value::Bulk("invalidate".to_string()), Value::Null]);
+    /// assert_eq!(Invalidation::from_resp(&push), Some(Invalidation::All));
+    /// assert_eq!(Invalidation::from_resp(&Value::Integer(1)), None);
+    /// ```
+    pub fn from_resp(value: &Value) -> Option<Invalidation> {
+        let keys = match *value {
+            Value::Push(ref val) if val.len() == 2 && bytes(&val[0]) == Some(b"invalidate") => {
+                &val[1]
+            }
+            Value::Array(ref val) | Value::Push(ref val)
+                if val.len() == 3 && bytes(&val[0]) == Some(b"message") &&
+                   bytes(&val[1]) == Some(INVALIDATE_CHANNEL) => &val[2],
+            _ => return None,
+        };
+        match *keys {
+            Value::Null | Value::NullArray => Some(Invalidation::All),
+            Value::Array(ref keys) => {
+                keys.iter()
+                    .map(|key| bytes(key).map(|key| key.to_vec()))
+                    .collect::<Option<Vec<Vec<u8>>>>()
+                    .map(Invalidation::Keys)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the invalidated keys, empty if all the keys are invalidated.
+    pub fn keys(&self) -> &[Vec<u8>] {
+        match *self {
+            Invalidation::Keys(ref keys) => keys,
+            Invalidation::All => &[],
+        }
+    }
+
+    /// Returns the invalidated keys as strings, fails with `InvalidData` if a key is not UTF-8.
+    /// # Examples
+    /// ```
+    /// # use self::resp::Invalidation;
+    /// let invalidation = Invalidation::Keys(vec![b"a".to_vec(), b"b".to_vec()]);
+    /// assert_eq!(invalidation.string_keys().unwrap(), vec!["a", "b"]);
+    /// assert!(Invalidation::Keys(vec![vec![255]]).string_keys().is_err());
+    /// ```
+    pub fn string_keys(&self) -> Result<Vec<String>> {
+        self.keys()
+            .iter()
+            .map(|key| {
+                     String::from_utf8(key.clone())
+                         .map_err(|err| Error::new(ErrorKind::InvalidData, err))
+                 })
+            .collect()
+    }
+}
+
+/// Represents a pub/sub message, received by a client in subscribed state (RESP2) or as a push
+/// (RESP3).
+#[derive(Clone, PartialEq, Debug)]
+pub enum PubSubMessage {
+    /// Confirms a `SUBSCRIBE` or `SSUBSCRIBE` to the channel, with the subscription count.
+    Subscribe {
+        /// The channel subscribed to.
+        channel: Vec<u8>,
+        /// The number of subscriptions of the client.
+        count: i64,
+    },
+    /// Confirms a `PSUBSCRIBE` to the pattern, with the subscription count.
+    PSubscribe {
+        /// The pattern subscribed to.
+        pattern: Vec<u8>,
+        /// The number of subscriptions of the client.
+        count: i64,
+    },
+    /// Confirms an `UNSUBSCRIBE` or `SUNSUBSCRIBE` from the channel, which is `None` when the
+    /// client had no subscriptions.
+    Unsubscribe {
+        /// The channel unsubscribed from.
+        channel: Option<Vec<u8>>,
+        /// The number of subscriptions left.
+        count: i64,
+    },
+    /// Confirms a `PUNSUBSCRIBE` from the pattern, which is `None` when the client had no
+    /// subscriptions.
+    PUnsubscribe {
+        /// The pattern unsubscribed from.
+        pattern: Option<Vec<u8>>,
+        /// The number of subscriptions left.
+        count: i64,
+    },
+    /// A message published to a subscribed channel.
+    Message {
+        /// The channel the message was published to.
+        channel: Vec<u8>,
+        /// The message.
+        payload: Value,
+    },
+    /// A message published to a channel matching a subscribed pattern.
+    PMessage {
+        /// The pattern matching the channel.
+        pattern: Vec<u8>,
+        /// The channel the message was published to.
+        channel: Vec<u8>,
+        /// The message.
+        payload: Value,
+    },
+}
+
+impl PubSubMessage {
+    /// Classifies a pub/sub message: a RESP2 array or a RESP3 push whose first element is the
+    /// message kind. Returns `None` for other values, e.g. a regular reply.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, PubSubMessage};
+    /// let message = Value::Push(vec![Value::Bulk("message".to_string()),
+    ///                                Value::Bulk("news".to_string()),
+    ///                                Value::Bulk("hello".to_string())]);
+    /// assert_eq!(PubSubMessage::from_resp(&message),
+    ///            Some(PubSubMessage::Message {
+    ///                     channel: b"news".to_vec(),
+    ///                     payload: Value::Bulk("hello".to_string()),
+    ///                 }));
+    ///
+    /// let message = Value::Array(vec![Value::Bulk("subscribe".to_string()),
+    ///                                 Value::Bulk("news".to_string()),
+    ///                                 Value::Integer(1)]);
+    /// assert_eq!(PubSubMessage::from_resp(&message),
+    ///            Some(PubSubMessage::Subscribe { channel: b"news".to_vec(), count: 1 }));
+    /// assert_eq!(PubSubMessage::from_resp(&Value::String("OK".to_string())), None);
+    /// ```
+    pub fn from_resp(value: &Value) -> Option<PubSubMessage> {
+        let val = match *value {
+            Value::Array(ref val) | Value::Push(ref val) if !val.is_empty() => val,
+            _ => return None,
+        };
+        match (bytes(&val[0])?, val.len()) {
+            (b"subscribe", 3) | (b"ssubscribe", 3) => {
+                Some(PubSubMessage::Subscribe {
+                         channel: bytes(&val[1])?.to_vec(),
+                         count: integer(&val[2])?,
+                     })
+            }
+            (b"psubscribe", 3) => {
+                Some(PubSubMessage::PSubscribe {
+                         pattern: bytes(&val[1])?.to_vec(),
+                         count: integer(&val[2])?,
+                     })
+            }
+            (b"unsubscribe", 3) | (b"sunsubscribe", 3) => {
+                Some(PubSubMessage::Unsubscribe {
+                         channel: nullable_bytes(&val[1])?,
+                         count: integer(&val[2])?,
+                     })
+            }
+            (b"punsubscribe", 3) => {
+                Some(PubSubMessage::PUnsubscribe {
+                         pattern: nullable_bytes(&val[1])?,
+                         count: integer(&val[2])?,
+                     })
+            }
+            (b"message", 3) | (b"smessage", 3) => {
+                Some(PubSubMessage::Message {
+                         channel: bytes(&val[1])?.to_vec(),
+                         payload: val[2].clone(),
+                     })
+            }
+            (b"pmessage", 4) => {
+                Some(PubSubMessage::PMessage {
+                         pattern: bytes(&val[1])?.to_vec(),
+                         channel: bytes(&val[2])?.to_vec(),
+                         payload: val[3].clone(),
+                     })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the bytes of a string value, `Some(None)` for a null.
+fn nullable_bytes(value: &Value) -> Option<Option<Vec<u8>>> {
+    match *value {
+        Value::Null | Value::NullArray => Some(None),
+        _ => bytes(value).map(|val| Some(val.to_vec())),
+    }
+}
+
+/// Returns the integer of an integer value.
+fn integer(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Integer(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// Returns the bytes of a string value.
+fn bytes(value: &Value) -> Option<&[u8]> {
+    match *value {
+        Value::String(ref val) | Value::Bulk(ref val) => Some(val.as_bytes()),
+        Value::BufBulk(ref val) => Some(val),
+        Value::SharedBulk(ref val) => Some(val),
+        Value::SmallBulk(ref val) => Some(val),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enum_invalidation_from_resp() {
+        let push = Value::Push(vec![Value::BufBulk(b"invalidate".to_vec()),
+                                    Value::Array(vec![Value::BufBulk(b"a".to_vec()),
+                                                      Value::Bulk("b".to_string())])]);
+        let invalidation = Invalidation::from_resp(&push).unwrap();
+        assert_eq!(invalidation,
+                   Invalidation::Keys(vec![b"a".to_vec(), b"b".to_vec()]));
+        assert_eq!(invalidation.keys(), &[b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(invalidation.string_keys().unwrap(), vec!["a", "b"]);
+
+        let message = Value::Array(vec![Value::Bulk("message".to_string()),
+                                        Value::Bulk("__redis__:invalidate".to_string()),
+                                        Value::Array(vec![Value::Bulk("a".to_string())])]);
+        assert_eq!(Invalidation::from_resp(&message),
+                   Some(Invalidation::Keys(vec![b"a".to_vec()])));
+
+        let message = Value::Array(vec![Value::Bulk("message".to_string()),
+                                        Value::Bulk("__redis__:invalidate".to_string()),
+                                        Value::NullArray]);
+        assert_eq!(Invalidation::from_resp(&message), Some(Invalidation::All));
+        assert!(Invalidation::All.keys().is_empty());
+
+        // other messages and malformed invalidations are not recognized.
+        let message = Value::Array(vec![Value::Bulk("message".to_string()),
+                                        Value::Bulk("news".to_string()),
+                                        Value::Array(vec![Value::Bulk("a".to_string())])]);
+        assert_eq!(Invalidation::from_resp(&message), None);
+        let push = Value::Array(vec![Value::Bulk("invalidate".to_string()), Value::Null]);
+        assert_eq!(Invalidation::from_resp(&push), None);
+        let push = Value::Push(vec![Value::Bulk("invalidate".to_string()),
+                                    Value::Array(vec![Value::Integer(1)])]);
+        assert_eq!(Invalidation::from_resp(&push), None);
+        let push = Value::Push(vec![Value::Bulk("invalidate".to_string()),
+                                    Value::Bulk("a".to_string())]);
+        assert_eq!(Invalidation::from_resp(&push), None);
+    }
+
+    fn bulk(val: &str) -> Value {
+        Value::Bulk(val.to_string())
+    }
+
+    #[test]
+    fn enum_pubsub_message_from_resp() {
+        let message = Value::Push(vec![bulk("ssubscribe"), bulk("news"), Value::Integer(2)]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::Subscribe {
+                            channel: b"news".to_vec(),
+                            count: 2,
+                        }));
+        let message = Value::Array(vec![bulk("psubscribe"), bulk("n*"), Value::Integer(3)]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::PSubscribe {
+                            pattern: b"n*".to_vec(),
+                            count: 3,
+                        }));
+        let message = Value::Array(vec![bulk("unsubscribe"), bulk("news"), Value::Integer(1)]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::Unsubscribe {
+                            channel: Some(b"news".to_vec()),
+                            count: 1,
+                        }));
+        let message = Value::Push(vec![bulk("punsubscribe"), Value::Null, Value::Integer(0)]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::PUnsubscribe {
+                            pattern: None,
+                            count: 0,
+                        }));
+        let message = Value::Array(vec![Value::BufBulk(b"pmessage".to_vec()),
+                                        bulk("n*"),
+                                        bulk("news"),
+                                        Value::BufBulk(vec![0, 255])]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::PMessage {
+                            pattern: b"n*".to_vec(),
+                            channel: b"news".to_vec(),
+                            payload: Value::BufBulk(vec![0, 255]),
+                        }));
+        let message = Value::Push(vec![bulk("smessage"), bulk("news"), bulk("hello")]);
+        assert_eq!(PubSubMessage::from_resp(&message),
+                   Some(PubSubMessage::Message {
+                            channel: b"news".to_vec(),
+                            payload: bulk("hello"),
+                        }));
+
+        // regular replies and malformed messages are not recognized.
+        assert_eq!(PubSubMessage::from_resp(&Value::Array(vec![])), None);
+        assert_eq!(PubSubMessage::from_resp(&Value::Integer(1)), None);
+        let message = Value::Array(vec![bulk("message"), bulk("news")]);
+        assert_eq!(PubSubMessage::from_resp(&message), None);
+        let message = Value::Array(vec![bulk("subscribe"), bulk("news"), bulk("1")]);
+        assert_eq!(PubSubMessage::from_resp(&message), None);
+        let message = Value::Array(vec![bulk("subscribe"), Value::Null, Value::Integer(0)]);
+        assert_eq!(PubSubMessage::from_resp(&message), None);
+        let message = Value::Array(vec![bulk("pmessage"), bulk("n*"), bulk("news")]);
+        assert_eq!(PubSubMessage::from_resp(&message), None);
+    }
+}
@@ -0,0 +1,228 @@
+//! Replication payloads
+
+use std::cmp::min;
+use std::io::{Read, BufRead, Result, Error, ErrorKind};
+
+use super::serialize::{Decoder, strip_crlf, parse_integer};
+
+/// The length of the delimiter of an EOF-delimited payload.
+const DELIMITER_LEN: usize = 40;
+
+/// A reader over the RDB payload sent by a master after `+FULLRESYNC`, see `Decoder::read_rdb`.
+/// It ends with the payload.
+#[derive(Debug)]
+pub struct RdbReader<'a, R: 'a> {
+    decoder: &'a mut Decoder<R>,
+    /// the number of payload bytes not read yet, `None` for an EOF-delimited payload.
+    remaining: Option<u64>,
+    /// the delimiter of an EOF-delimited payload.
+    delimiter: Vec<u8>,
+    /// the bytes of an EOF-delimited payload read but not returned yet, the last ones may start
+    /// the delimiter.
+    pending: Vec<u8>,
+    /// whether the delimiter was read.
+    delimited: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Reads the header of an RDB payload and returns a reader over the payload. The header is
+    /// either `$<len>\r\n`, the payload is then `len` bytes, or `$EOF:<40 bytes>\r\n` as sent by
+    /// a diskless master, the payload then ends with the same 40 bytes delimiter.
+    /// Unlike a bulk string, the payload has no trailing CRLF and isn't limited to 512MB.
+    /// Once the reader is done, the decoder decodes the replication stream.
+    /// # Examples
+    /// ```
+    /// # use std::io;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"+FULLRESYNC 8371b4f 0\r\n$9\r\nREDIS0011*1\r\n$4\r\nPING\r\n";
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("FULLRESYNC 8371b4f 0".to_string()));
+    ///
+    /// let mut rdb: Vec<u8> = Vec::new();
+    /// io::copy(&mut decoder.read_rdb().unwrap(), &mut rdb).unwrap();
+    /// assert_eq!(rdb, b"REDIS0011");
+    /// assert_eq!(decoder.decode().unwrap(), Value::Array(vec![Value::Bulk("PING".to_string())]));
+    ///
+    /// let delimiter = "0123456789".repeat(4);
+    /// let buf = format!("$EOF:{}\r\nREDIS0011{}+OK\r\n", delimiter, delimiter);
+    /// let mut decoder = Decoder::new(buf.as_bytes());
+    /// let mut rdb: Vec<u8> = Vec::new();
+    /// io::copy(&mut decoder.read_rdb().unwrap(), &mut rdb).unwrap();
+    /// assert_eq!(rdb, b"REDIS0011");
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+    /// ```
+    pub fn read_rdb(&mut self) -> Result<RdbReader<'_, R>> {
+        let (remaining, delimiter) = self.resumable(Decoder::read_rdb_header)?;
+        Ok(RdbReader {
+               decoder: self,
+               remaining,
+               delimiter,
+               pending: Vec::new(),
+               delimited: false,
+           })
+    }
+
+    /// Reads the header of an RDB payload for `read_rdb`, returns the length of the payload, or
+    /// the delimiter of an EOF-delimited one.
+    fn read_rdb_header(&mut self) -> Result<(Option<u64>, Vec<u8>)> {
+        self.frame_len = 0;
+        let line = self.read_line()?;
+        let bytes = strip_crlf(&line)?;
+        let invalid = || {
+            Error::new(ErrorKind::InvalidInput,
+                       format!("invalid RDB header: {:?}", String::from_utf8_lossy(&line)))
+        };
+        if bytes[0] != b'$' {
+            return Err(invalid());
+        }
+        let header = match bytes[1..].strip_prefix(b"EOF:") {
+            Some(delimiter) if delimiter.len() == DELIMITER_LEN => (None, delimiter.to_vec()),
+            Some(_) => return Err(invalid()),
+            None => {
+                match parse_integer(&bytes[1..])? {
+                    len if len >= 0 => (Some(len as u64), Vec::new()),
+                    _ => return Err(invalid()),
+                }
+            }
+        };
+        Ok(header)
+    }
+}
+
+impl<'a, R: BufRead> RdbReader<'a, R> {
+    /// Returns the number of payload bytes not read yet, `None` for an EOF-delimited payload.
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+
+    /// Reads an EOF-delimited payload, the bytes that may start the delimiter are held back.
+    fn read_delimited(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let held = if self.delimited { 0 } else { DELIMITER_LEN - 1 };
+            if self.pending.len() > held {
+                let n = min(buf.len(), self.pending.len() - held);
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                return Ok(n);
+            }
+            if self.delimited {
+                return Ok(0);
+            }
+            let consumed = {
+                let chunk = self.decoder.reader.fill_buf()?;
+                if chunk.is_empty() {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+                }
+                let len = self.pending.len();
+                self.pending.extend_from_slice(chunk);
+                // only the bytes up to the delimiter are consumed.
+                let delimiter = &self.delimiter[..];
+                match self.pending.windows(DELIMITER_LEN).position(|w| w == delimiter) {
+                    Some(i) => {
+                        self.pending.truncate(i);
+                        self.delimited = true;
+                        i + DELIMITER_LEN - len
+                    }
+                    None => chunk.len(),
+                }
+            };
+            self.decoder.reader.consume(consumed);
+        }
+    }
+}
+
+impl<'a, R: BufRead> Read for RdbReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let remaining = match self.remaining {
+            Some(remaining) => remaining,
+            None => return self.read_delimited(buf),
+        };
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = if (buf.len() as u64) < remaining {
+            buf.len()
+        } else {
+            remaining as usize
+        };
+        let n = self.decoder.reader.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+        }
+        self.remaining = Some(remaining - n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, ErrorKind};
+    use super::super::{Decoder, Value};
+
+    #[test]
+    fn struct_rdb_reader() {
+        let rdb: Vec<u8> = (0..100_000).map(|i| (i % 13) as u8).collect();
+        let mut buf = b"$100000\r\n".to_vec();
+        buf.extend_from_slice(&rdb);
+        buf.extend_from_slice(b":1\r\n");
+
+        let mut decoder = Decoder::from_chunks(buf.chunks(1000));
+        let mut output: Vec<u8> = Vec::new();
+        {
+            let mut reader = decoder.read_rdb().unwrap();
+            assert_eq!(reader.remaining(), Some(100_000));
+            assert_eq!(io::copy(&mut reader, &mut output).unwrap(), 100_000);
+            assert_eq!(reader.remaining(), Some(0));
+        }
+        assert_eq!(output, rdb);
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+
+        let mut decoder = Decoder::new(&b"$0\r\n"[..]);
+        let mut output: Vec<u8> = Vec::new();
+        decoder.read_rdb().unwrap().read_to_end(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        let mut decoder = Decoder::new(&b"$5\r\nREDI"[..]);
+        let mut output: Vec<u8> = Vec::new();
+        let err = decoder.read_rdb().unwrap().read_to_end(&mut output).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        for header in &[&b"$-1\r\n"[..], b"*1\r\n", b"$a\r\n", b"$EOF:0123\r\n"] {
+            let mut decoder = Decoder::new(*header);
+            assert!(decoder.read_rdb().is_err());
+        }
+    }
+
+    #[test]
+    fn struct_rdb_reader_delimited() {
+        let delimiter: Vec<u8> = (0..40).map(|i| b'a' + (i % 26) as u8).collect();
+        // the payload holds a prefix of the delimiter.
+        let mut rdb: Vec<u8> = (0..100_000).map(|i| (i % 13) as u8).collect();
+        rdb.extend_from_slice(&delimiter[..39]);
+        rdb.extend_from_slice(b"REDIS");
+        let mut buf = b"$EOF:".to_vec();
+        buf.extend_from_slice(&delimiter);
+        buf.extend_from_slice(b"\r\n");
+        buf.extend_from_slice(&rdb);
+        buf.extend_from_slice(&delimiter);
+        buf.extend_from_slice(b":1\r\n");
+
+        for size in &[1, 7, 40, 1000, buf.len()] {
+            let mut decoder = Decoder::from_chunks(buf.chunks(*size));
+            let mut output: Vec<u8> = Vec::new();
+            {
+                let mut reader = decoder.read_rdb().unwrap();
+                assert_eq!(reader.remaining(), None);
+                assert_eq!(io::copy(&mut reader, &mut output).unwrap(), rdb.len() as u64);
+                assert_eq!(reader.read(&mut [0u8; 8]).unwrap(), 0);
+            }
+            assert_eq!(output, rdb);
+            assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+        }
+
+        let mut decoder = Decoder::new(&buf[..buf.len() - 10]);
+        let mut output: Vec<u8> = Vec::new();
+        let err = decoder.read_rdb().unwrap().read_to_end(&mut output).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -2,252 +2,4927 @@
 
 use std::vec::Vec;
 use std::string::String;
-use std::io::{Read, BufRead, BufReader, Result, Error, ErrorKind};
+use std::fmt;
+use std::cmp::min;
+use std::mem;
+use std::str;
+use std::collections::BTreeMap;
+use std::io::{self, Read, BufRead, Write, IoSlice, Result, Error, ErrorKind};
+
+use memchr::memchr;
 
 use super::Value;
+use super::bulk::BulkReader;
+use super::de::{Awaiting, DecoderState};
+use super::small::{SmallBulk, SMALL_BULK_CAPACITY};
+
+/// up to 512 MB in length
+pub(crate) const RESP_MAX_SIZE: i64 = 512 * 1024 * 1024;
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const CRLF_BYTES: &'static [u8] = b"\r\n";
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const NULL_BYTES: &'static [u8] = b"$-1\r\n";
+#[allow(clippy::redundant_static_lifetimes)]
+pub(crate) const NULL_ARRAY_BYTES: &'static [u8] = b"*-1\r\n";
+const RESP3_NULL_BYTES: &[u8] = b"_\r\n";
+/// The encoded `+OK` reply, see `Value::okay`, hot paths can write it as is.
+pub const OK_BYTES: &[u8] = b"+OK\r\n";
+/// The encoded `+PONG` reply, see `Value::pong`.
+pub const PONG_BYTES: &[u8] = b"+PONG\r\n";
+/// The encoded `+QUEUED` reply, see `Value::queued`.
+pub const QUEUED_BYTES: &[u8] = b"+QUEUED\r\n";
+/// the type prefixes added by RESP3
+const RESP3_PREFIXES: &[u8] = b"_,#(=!%~>|";
+/// the type prefixes of the lines holding a length
+const LENGTH_PREFIXES: &[u8] = b"$*%~>|;";
+/// the type prefixes of the values that are a single line
+const SIMPLE_PREFIXES: &[u8] = b"+-:_#,(";
+/// the end of a streamed aggregate
+pub(crate) const STREAMED_END_BYTES: &[u8] = b".\r\n";
+/// size of the fixed buffer used to relay bulk payloads
+const RELAY_BUF_SIZE: usize = 8 * 1024;
+/// the min length of the bulk payloads referenced by a vectored encoding, smaller ones are copied
+const VECTORED_MIN_LEN: usize = 4 * 1024;
+
+/// Encodes RESP value to RESP binary buffer.
+///
+/// CR and LF in Simple Strings, Errors and Big Numbers are encoded as spaces, so a value
+/// always encodes to a single frame.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode};
+/// let val = Value::String("OK".to_string());
+/// assert_eq!(encode(&val), vec![43, 79, 75, 13, 10]);
+/// ```
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut res: Vec<u8> = Vec::with_capacity(encoded_len(value));
+    buf_encode(value, &mut res);
+    res
+}
+
+/// Returns the number of bytes of the encoded value, see `Value::encoded_len`.
+pub(crate) fn encoded_len(value: &Value) -> usize {
+    node_len(value)
+}
+
+/// A borrowed view of a node of a value tree, as it is encoded. `Value` and `GenericValue`
+/// share their encoding through it, see `encode_node` and `node_len`.
+pub(crate) enum Node<'a, T: 'a> {
+    /// bytes written as they are, e.g. a null or an already encoded value.
+    Bytes(&'a [u8]),
+    /// a simple line after its type prefix, CR and LF in the line are encoded as spaces.
+    Line(u8, &'a str),
+    Integer(i64),
+    Double(f64),
+    Bulk(&'a [u8]),
+    /// an array, a set or a push, after its type prefix.
+    Values(u8, &'a [T]),
+    Map(&'a [(T, T)]),
+    Attribute(&'a [(T, T)], &'a T),
+}
+
+/// A value encoded through `Node`.
+pub(crate) trait Encode: Sized {
+    fn node<'a>(&'a self) -> Node<'a, Self>;
+}
+
+impl Encode for Value {
+    #[inline]
+    fn node<'a>(&'a self) -> Node<'a, Value> {
+        match *self {
+            Value::Null => Node::Bytes(NULL_BYTES),
+            Value::NullArray => Node::Bytes(NULL_ARRAY_BYTES),
+            Value::String(ref val) => Node::Line(b'+', val),
+            Value::Error(ref val) => Node::Line(b'-', val),
+            Value::Integer(val) => Node::Integer(val),
+            Value::Boolean(val) => Node::Bytes(if val { b"#t\r\n" } else { b"#f\r\n" }),
+            Value::Double(val) => Node::Double(val),
+            Value::BigNumber(ref val) => Node::Line(b'(', val),
+            Value::Bulk(ref val) => Node::Bulk(val.as_bytes()),
+            Value::BufBulk(ref val) => Node::Bulk(val),
+            Value::SharedBulk(ref val) => Node::Bulk(val),
+            Value::SmallBulk(ref val) => Node::Bulk(val),
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                Node::Values(sequence_prefix(self), val)
+            }
+            Value::Map(ref val) => Node::Map(val),
+            Value::Attribute(ref attributes, ref val) => Node::Attribute(attributes, val),
+            Value::Unknown(ref val) | Value::Raw(ref val) => Node::Bytes(val),
+        }
+    }
+}
+
+/// Appends the encoded value to a buffer.
+pub(crate) fn encode_node<T: Encode>(value: &T, buf: &mut Vec<u8>) {
+    match value.node() {
+        Node::Bytes(val) => buf.extend_from_slice(val),
+        Node::Line(prefix, val) => {
+            buf.push(prefix);
+            extend_line(buf, val);
+            buf.extend_from_slice(CRLF_BYTES);
+        }
+        Node::Integer(val) => {
+            buf.push(b':');
+            if val < 0 {
+                buf.push(b'-');
+            }
+            extend_decimal(buf, val.unsigned_abs());
+            buf.extend_from_slice(CRLF_BYTES);
+        }
+        Node::Double(val) => {
+            buf.push(b',');
+            buf.extend_from_slice(format_double(val).as_bytes());
+            buf.extend_from_slice(CRLF_BYTES);
+        }
+        Node::Bulk(val) => {
+            encode_len_header(b'$', val.len(), buf);
+            buf.extend_from_slice(val);
+            buf.extend_from_slice(CRLF_BYTES);
+        }
+        Node::Values(prefix, val) => {
+            encode_len_header(prefix, val.len(), buf);
+            for item in val {
+                encode_node(item, buf);
+            }
+        }
+        Node::Map(val) => encode_pairs(b'%', val, buf),
+        Node::Attribute(attributes, val) => {
+            encode_pairs(b'|', attributes, buf);
+            encode_node(val, buf);
+        }
+    }
+}
+
+fn encode_pairs<T: Encode>(prefix: u8, pairs: &[(T, T)], buf: &mut Vec<u8>) {
+    encode_len_header(prefix, pairs.len(), buf);
+    for (key, value) in pairs {
+        encode_node(key, buf);
+        encode_node(value, buf);
+    }
+}
+
+/// Returns the number of bytes of the encoded value.
+pub(crate) fn node_len<T: Encode>(value: &T) -> usize {
+    let header_len = |len: usize| 1 + decimal_len(len as u64) + 2;
+    match value.node() {
+        Node::Bytes(val) => val.len(),
+        Node::Line(_, val) => 1 + val.len() + 2,
+        Node::Integer(val) => 1 + (val < 0) as usize + decimal_len(val.unsigned_abs()) + 2,
+        Node::Double(val) => 1 + format_double(val).len() + 2,
+        Node::Bulk(val) => header_len(val.len()) + val.len() + 2,
+        Node::Values(_, val) => header_len(val.len()) + val.iter().map(node_len).sum::<usize>(),
+        Node::Map(val) => header_len(val.len()) + pairs_len(val),
+        Node::Attribute(attributes, val) => {
+            header_len(attributes.len()) + pairs_len(attributes) + node_len(val)
+        }
+    }
+}
+
+fn pairs_len<T: Encode>(pairs: &[(T, T)]) -> usize {
+    pairs.iter().map(|(key, value)| node_len(key) + node_len(value)).sum()
+}
+
+/// Appends the decimal digits of `n`, without formatting them to a string first.
+fn extend_decimal(buf: &mut Vec<u8>, mut n: u64) {
+    let start = buf.len();
+    loop {
+        buf.push(b'0' + (n % 10) as u8);
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    buf[start..].reverse();
+}
+
+/// Returns the number of decimal digits of `n`.
+fn decimal_len(mut n: u64) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+/// Appends the encoded RESP value to a buffer, so pipelined values can be encoded into one
+/// reusable buffer.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode_into};
+/// let mut buf: Vec<u8> = Vec::new();
+/// encode_into(&Value::String("OK".to_string()), &mut buf);
+/// encode_into(&Value::Integer(1), &mut buf);
+/// assert_eq!(buf, b"+OK\r\n:1\r\n");
+/// ```
+pub fn encode_into(value: &Value, buf: &mut Vec<u8>) {
+    buf_encode(value, buf);
+}
+
+/// Encodes RESP value straight to a writer, e.g. a `BufWriter` over a `TcpStream`, returns the
+/// number of bytes written. The payloads of bulk strings are written as they are, without
+/// being copied into an intermediate buffer first.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode_to_writer};
+/// let value = Value::Array(vec![Value::Bulk("GET".to_string()), Value::Integer(1)]);
+/// let mut output: Vec<u8> = Vec::new();
+/// assert_eq!(encode_to_writer(&value, &mut output).unwrap(), 17);
+/// assert_eq!(output, value.encode());
+/// ```
+pub fn encode_to_writer<W: Write>(value: &Value, writer: &mut W) -> Result<usize> {
+    let mut buf: Vec<u8> = Vec::new();
+    write_encoded(value, writer, &mut buf)
+}
+
+/// Encodes RESP value straight to a `fmt::Write`, e.g. a `String` or a `fmt::Formatter`,
+/// returns the number of bytes written. Fails with `InvalidData` if a binary payload, or the
+/// bytes of an `Unknown` or `Raw` value, are not valid UTF-8, the writer then holds a partial
+/// encoding.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode_to_fmt};
+/// let value = Value::Array(vec![Value::Bulk("GET".to_string()), Value::Integer(1)]);
+/// let mut output = String::new();
+/// assert_eq!(encode_to_fmt(&value, &mut output).unwrap(), 17);
+/// assert_eq!(output, "*2\r\n$3\r\nGET\r\n:1\r\n");
+/// assert!(encode_to_fmt(&Value::BufBulk(vec![255]), &mut output).is_err());
+/// ```
+pub fn encode_to_fmt<W: fmt::Write>(value: &Value, writer: &mut W) -> Result<usize> {
+    let mut writer = FmtWriter { inner: writer, len: 0 };
+    writer.write_value(value)?;
+    Ok(writer.len)
+}
+
+/// Writes encoded values to a `fmt::Write` and counts the bytes written. Text is written as it
+/// is, only binary payloads and the bytes of `Unknown` and `Raw` values are checked to be UTF-8.
+struct FmtWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    len: usize,
+}
+
+impl<'a, W: fmt::Write> FmtWriter<'a, W> {
+    fn write_value(&mut self, value: &Value) -> Result<()> {
+        match *value {
+            Value::Null => self.write_text("$-1\r\n"),
+            Value::NullArray => self.write_text("*-1\r\n"),
+            Value::String(ref val) => self.write_line("+", val),
+            Value::Error(ref val) => self.write_line("-", val),
+            Value::Integer(val) => self.write_fmt_text(format_args!(":{}\r\n", val)),
+            Value::Boolean(val) => self.write_text(if val { "#t\r\n" } else { "#f\r\n" }),
+            Value::Double(val) => self.write_fmt_text(format_args!(",{}\r\n", format_double(val))),
+            Value::BigNumber(ref val) => self.write_line("(", val),
+            Value::Bulk(ref val) => self.write_bulk(val),
+            Value::BufBulk(ref val) => self.write_bulk(utf8(val)?),
+            Value::SharedBulk(ref val) => self.write_bulk(utf8(val)?),
+            Value::SmallBulk(ref val) => self.write_bulk(utf8(val)?),
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                self.write_header(sequence_prefix(value), val.len())?;
+                val.iter().try_for_each(|item| self.write_value(item))
+            }
+            Value::Map(ref val) => self.write_pairs(b'%', val),
+            Value::Attribute(ref attributes, ref val) => {
+                self.write_pairs(b'|', attributes)?;
+                self.write_value(val)
+            }
+            Value::Unknown(ref val) | Value::Raw(ref val) => self.write_text(utf8(val)?),
+        }
+    }
+
+    fn write_pairs(&mut self, prefix: u8, pairs: &[(Value, Value)]) -> Result<()> {
+        self.write_header(prefix, pairs.len())?;
+        for (key, value) in pairs {
+            self.write_value(key)?;
+            self.write_value(value)?;
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self, prefix: u8, len: usize) -> Result<()> {
+        self.write_fmt_text(format_args!("{}{}\r\n", prefix as char, len))
+    }
+
+    fn write_bulk(&mut self, val: &str) -> Result<()> {
+        self.write_header(b'$', val.len())?;
+        self.write_text(val)?;
+        self.write_text("\r\n")
+    }
+
+    /// Writes a single line value, CR and LF are replaced with spaces as `extend_line` does.
+    fn write_line(&mut self, prefix: &str, val: &str) -> Result<()> {
+        self.write_text(prefix)?;
+        for (i, piece) in val.split(['\r', '\n']).enumerate() {
+            if i > 0 {
+                self.write_text(" ")?;
+            }
+            self.write_text(piece)?;
+        }
+        self.write_text("\r\n")
+    }
+
+    fn write_text(&mut self, text: &str) -> Result<()> {
+        fmt::Write::write_str(self, text).map_err(|_| Error::other("formatter error"))
+    }
+
+    fn write_fmt_text(&mut self, args: fmt::Arguments) -> Result<()> {
+        fmt::Write::write_fmt(self, args).map_err(|_| Error::other("formatter error"))
+    }
+}
+
+impl<'a, W: fmt::Write> fmt::Write for FmtWriter<'a, W> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.inner.write_str(text)?;
+        self.len += text.len();
+        Ok(())
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    str::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Writes the encoded value, `buf` is a scratch buffer for the lines.
+fn write_encoded<W: Write>(value: &Value, writer: &mut W, buf: &mut Vec<u8>) -> Result<usize> {
+    buf.clear();
+    let payload = match *value {
+        Value::Bulk(ref val) => val.as_bytes(),
+        Value::BufBulk(ref val) => val.as_slice(),
+        Value::SharedBulk(ref val) => val,
+        Value::SmallBulk(ref val) => val.as_bytes(),
+        Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+            encode_len_header(sequence_prefix(value), val.len(), buf);
+            writer.write_all(buf)?;
+            let mut len = buf.len();
+            for item in val {
+                len += write_encoded(item, writer, buf)?;
+            }
+            return Ok(len);
+        }
+        Value::Map(ref val) => return write_encoded_pairs(b'%', val, writer, buf),
+        Value::Attribute(ref attributes, ref val) => {
+            let len = write_encoded_pairs(b'|', attributes, writer, buf)?;
+            return Ok(len + write_encoded(val, writer, buf)?);
+        }
+        Value::Raw(ref val) => {
+            writer.write_all(val)?;
+            return Ok(val.len());
+        }
+        _ => {
+            buf_encode(value, buf);
+            writer.write_all(buf)?;
+            return Ok(buf.len());
+        }
+    };
+    encode_len_header(b'$', payload.len(), buf);
+    writer.write_all(buf)?;
+    writer.write_all(payload)?;
+    writer.write_all(CRLF_BYTES)?;
+    Ok(buf.len() + payload.len() + 2)
+}
+
+fn write_encoded_pairs<W: Write>(prefix: u8,
+                                 pairs: &[(Value, Value)],
+                                 writer: &mut W,
+                                 buf: &mut Vec<u8>)
+                                 -> Result<usize> {
+    buf.clear();
+    encode_len_header(prefix, pairs.len(), buf);
+    writer.write_all(buf)?;
+    let mut len = buf.len();
+    for (key, value) in pairs {
+        len += write_encoded(key, writer, buf)?;
+        len += write_encoded(value, writer, buf)?;
+    }
+    Ok(len)
+}
+
+/// Encodes a bulk string of `len` bytes read from a reader, e.g. a file, straight to a writer,
+/// so a large payload doesn't have to be loaded into a `Value`. Returns the number of bytes
+/// written. Fails with `InvalidInput` if `len` exceeds the protocol max of 512MB, and with
+/// `UnexpectedEof` if the reader ends before `len` bytes, the writer then holds a partial
+/// bulk string.
+/// # Examples
+/// ```
+/// # use self::resp::encode_bulk_from_reader;
+/// let mut output: Vec<u8> = Vec::new();
+/// let len = encode_bulk_from_reader(&mut &b"Hello world"[..], 5, &mut output).unwrap();
+/// assert_eq!(len, 11);
+/// assert_eq!(output, b"$5\r\nHello\r\n");
+/// ```
+pub fn encode_bulk_from_reader<R: Read, W: Write>(reader: &mut R,
+                                                  len: usize,
+                                                  writer: &mut W)
+                                                  -> Result<usize> {
+    if len as u64 >= RESP_MAX_SIZE as u64 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid bulk length: {}", len)));
+    }
+    let mut header: Vec<u8> = Vec::new();
+    encode_len_header(b'$', len, &mut header);
+    writer.write_all(&header)?;
+    if io::copy(&mut reader.take(len as u64), writer)? < len as u64 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+    }
+    writer.write_all(CRLF_BYTES)?;
+    Ok(header.len() + len + 2)
+}
+
+/// Encodes RESP value for vectored writes: the bulk payloads from 4KB are referenced, not
+/// copied, the rest of the value is encoded into a buffer. The encoding is written with
+/// `write_vectored`, so large payloads go straight from the value to the writer.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode_vectored};
+/// let value = Value::Array(vec![Value::Bulk("SET".to_string()),
+///                               Value::BufBulk(vec![0; 1024 * 1024])]);
+/// let encoding = encode_vectored(&value);
+/// assert_eq!(encoding.io_slices().len(), 3);
+/// assert_eq!(encoding.len(), value.encoded_len());
+///
+/// let mut output: Vec<u8> = Vec::new();
+/// assert_eq!(encoding.write_to(&mut output).unwrap(), encoding.len());
+/// assert_eq!(output, value.encode());
+/// ```
+pub fn encode_vectored(value: &Value) -> VectoredEncoding<'_> {
+    let mut encoding = VectoredEncoding {
+        buf: Vec::new(),
+        parts: Vec::new(),
+        flushed: 0,
+    };
+    encoding.encode(value);
+    encoding.flush();
+    encoding
+}
+
+/// A RESP value encoded for vectored writes, see `encode_vectored`.
+#[derive(Debug)]
+pub struct VectoredEncoding<'a> {
+    /// the encoded bytes, except the referenced payloads.
+    buf: Vec<u8>,
+    /// the parts of the encoding in order.
+    parts: Vec<VectoredPart<'a>>,
+    /// the bytes of `buf` already in `parts`.
+    flushed: usize,
+}
+
+#[derive(Debug)]
+enum VectoredPart<'a> {
+    /// a range of the buffer.
+    Buf(usize, usize),
+    Payload(&'a [u8]),
+}
+
+impl<'a> VectoredEncoding<'a> {
+    fn encode(&mut self, value: &'a Value) {
+        let payload = match *value {
+            Value::Bulk(ref val) if val.len() >= VECTORED_MIN_LEN => val.as_bytes(),
+            Value::BufBulk(ref val) if val.len() >= VECTORED_MIN_LEN => val.as_slice(),
+            Value::SharedBulk(ref val) if val.len() >= VECTORED_MIN_LEN => val,
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                encode_len_header(sequence_prefix(value), val.len(), &mut self.buf);
+                for item in val {
+                    self.encode(item);
+                }
+                return;
+            }
+            Value::Map(ref val) => {
+                self.encode_pairs(b'%', val);
+                return;
+            }
+            Value::Attribute(ref attributes, ref val) => {
+                self.encode_pairs(b'|', attributes);
+                self.encode(val);
+                return;
+            }
+            Value::Raw(ref val) if val.len() >= VECTORED_MIN_LEN => {
+                self.flush();
+                self.parts.push(VectoredPart::Payload(val));
+                return;
+            }
+            _ => {
+                buf_encode(value, &mut self.buf);
+                return;
+            }
+        };
+        encode_len_header(b'$', payload.len(), &mut self.buf);
+        self.flush();
+        self.parts.push(VectoredPart::Payload(payload));
+        self.buf.extend_from_slice(CRLF_BYTES);
+    }
+
+    fn encode_pairs(&mut self, prefix: u8, pairs: &'a [(Value, Value)]) {
+        encode_len_header(prefix, pairs.len(), &mut self.buf);
+        for (key, value) in pairs {
+            self.encode(key);
+            self.encode(value);
+        }
+    }
+
+    /// Adds the bytes of the buffer not in the parts yet.
+    fn flush(&mut self) {
+        if self.flushed < self.buf.len() {
+            self.parts.push(VectoredPart::Buf(self.flushed, self.buf.len()));
+            self.flushed = self.buf.len();
+        }
+    }
+
+    /// Returns the slices of the encoding, in order.
+    pub fn io_slices(&self) -> Vec<IoSlice<'_>> {
+        self.bytes().map(IoSlice::new).collect()
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = &[u8]> {
+        self.parts.iter().map(move |part| match *part {
+                                  VectoredPart::Buf(start, end) => &self.buf[start..end],
+                                  VectoredPart::Payload(payload) => payload,
+                              })
+    }
+
+    /// Returns the number of bytes of the encoding.
+    pub fn len(&self) -> usize {
+        self.parts
+            .iter()
+            .map(|part| match *part {
+                     VectoredPart::Buf(start, end) => end - start,
+                     VectoredPart::Payload(payload) => payload.len(),
+                 })
+            .sum()
+    }
+
+    /// Returns `true` if the encoding has no bytes, which no value encodes to.
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Writes the whole encoding with `write_vectored`, returns the number of bytes written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        // `IoSlice::advance_slices` needs Rust 1.81, so the unwritten bytes are tracked here.
+        let mut parts: Vec<&[u8]> = self.bytes().collect();
+        let mut first = 0;
+        let mut written = 0;
+        while first < parts.len() {
+            let slices: Vec<IoSlice> = parts[first..].iter().map(|p| IoSlice::new(p)).collect();
+            match writer.write_vectored(&slices) {
+                Ok(0) => return Err(Error::new(ErrorKind::WriteZero, "failed to write the value")),
+                Ok(mut n) => {
+                    written += n;
+                    while first < parts.len() && n >= parts[first].len() {
+                        n -= parts[first].len();
+                        first += 1;
+                    }
+                    if n > 0 {
+                        parts[first] = &parts[first][n..];
+                    }
+                }
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Encodes a slice of string to RESP binary buffer.
+/// It is use to create a request command on redis client.
+/// Any arguments that are bytes work: a slice or a `Vec` of `&str`, `String` or `&[u8]`, or an
+/// iterator of them that knows its length.
+/// # Examples
+/// ```
+/// # use self::resp::encode_slice;
+/// let array = ["SET", "a", "1"];
+/// assert_eq!(encode_slice(&array),
+///            "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n".to_string().into_bytes());
+///
+/// let args = vec!["SET".to_string(), "a".to_string(), "1".to_string()];
+/// assert_eq!(encode_slice(args.iter().map(String::as_str)), encode_slice(&array));
+/// ```
+pub fn encode_slice<I>(args: I) -> Vec<u8>
+    where I: IntoIterator,
+          I::IntoIter: ExactSizeIterator,
+          I::Item: AsRef<[u8]>
+{
+    let mut res: Vec<u8> = Vec::new();
+    buf_encode_slice(args, &mut res);
+    res
+}
+
+fn buf_encode_slice<I>(args: I, buf: &mut Vec<u8>)
+    where I: IntoIterator,
+          I::IntoIter: ExactSizeIterator,
+          I::Item: AsRef<[u8]>
+{
+    let args = args.into_iter();
+    encode_len_header(b'*', args.len(), buf);
+    for arg in args {
+        let arg = arg.as_ref();
+        encode_len_header(b'$', arg.len(), buf);
+        buf.extend_from_slice(arg);
+        buf.extend_from_slice(CRLF_BYTES);
+    }
+}
+
+/// Encodes a slice of binary-safe arguments to RESP binary buffer, like `encode_slice` for keys
+/// and values that are not UTF-8.
+/// # Examples
+/// ```
+/// # use self::resp::encode_bytes_slice;
+/// let array: [&[u8]; 3] = [b"SET", b"a", &[0xff, 0]];
+/// assert_eq!(encode_bytes_slice(&array),
+///            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$2\r\n\xff\x00\r\n".to_vec());
+/// ```
+pub fn encode_bytes_slice(slice: &[&[u8]]) -> Vec<u8> {
+    encode_slice(slice)
+}
+
+/// Encodes RESP value to a canonical RESP binary buffer, equal values in content always have
+/// the same canonical encoding, so it can be used as a cache key or compared across processes.
+///
+/// The canonical form may differ from the natural encoding of `encode`:
+///
+/// - `Bulk` and `BufBulk` are both encoded as a bulk string.
+/// - Aggregates are encoded with their elements in canonical form.
+/// - `Map` and `Attribute` entries are sorted by the canonical encoding of their keys (then of
+///   their values).
+/// - `Set` elements are sorted by their canonical encoding.
+/// - `Double` zero is encoded as `0`, whatever its sign.
+///
+/// Values of different types are never unified, `Null` and `NullArray` stay distinct, and so do
+/// `String` and `Bulk`.
+/// # Examples
+/// ```
+/// # use self::resp::{Value, encode_canonical};
+/// assert_eq!(encode_canonical(&Value::Bulk("OK".to_string())),
+///            encode_canonical(&Value::BufBulk(vec![79, 75])));
+/// ```
+pub fn encode_canonical(value: &Value) -> Vec<u8> {
+    let mut res: Vec<u8> = Vec::new();
+    buf_encode_canonical(value, &mut res);
+    res
+}
+
+fn buf_encode_canonical(value: &Value, buf: &mut Vec<u8>) {
+    match *value {
+        Value::Array(ref val) | Value::Push(ref val) => {
+            encode_len_header(sequence_prefix(value), val.len(), buf);
+            for item in val {
+                buf_encode_canonical(item, buf);
+            }
+        }
+        Value::Map(ref val) => buf_encode_canonical_map(b'%', val, buf),
+        Value::Attribute(ref attributes, ref val) => {
+            buf_encode_canonical_map(b'|', attributes, buf);
+            buf_encode_canonical(val, buf);
+        }
+        Value::Set(ref val) => {
+            let mut items: Vec<Vec<u8>> = val.iter().map(encode_canonical).collect();
+            items.sort();
+            encode_len_header(b'~', items.len(), buf);
+            for item in items {
+                buf.extend_from_slice(&item);
+            }
+        }
+        // matches -0.0 too.
+        Value::Double(0.0) => buf_encode(&Value::Double(0.0), buf),
+        _ => buf_encode(value, buf),
+    }
+}
+
+fn buf_encode_canonical_map(prefix: u8, map: &[(Value, Value)], buf: &mut Vec<u8>) {
+    let mut entries: Vec<(Vec<u8>, Vec<u8>)> = map.iter()
+        .map(|(key, value)| (encode_canonical(key), encode_canonical(value)))
+        .collect();
+    entries.sort();
+    encode_len_header(prefix, entries.len(), buf);
+    for (key, value) in entries {
+        buf.extend_from_slice(&key);
+        buf.extend_from_slice(&value);
+    }
+}
+
+/// A hook that transforms bulk payloads, e.g. to compress or encrypt them.
+/// Both sides of the connection must use the same hook.
+pub trait BulkHook {
+    /// Returns the length of the transformed payload of a payload of `len` bytes, if the hook
+    /// knows it up front, e.g. an encryption with a fixed overhead. The transformed payload is
+    /// then written after its bulk header as the hook writes it, otherwise it is buffered to
+    /// learn its length. By default `None`.
+    fn encoded_len(&self, len: usize) -> Option<usize> {
+        let _ = len;
+        None
+    }
+
+    /// Transforms a payload being encoded, `len` is the length of the payload and `input`
+    /// streams exactly `len` bytes. The transformed payload is written to `output`, it must be
+    /// `encoded_len` bytes long if that is known.
+    fn encode(&mut self, len: usize, input: &mut dyn Read, output: &mut dyn Write) -> Result<()>;
+
+    /// Transforms a payload being decoded, `len` is the length declared in the bulk header,
+    /// `None` for a streamed bulk string, and `input` streams the payload, a streamed one chunk
+    /// by chunk. The hook must read the payload to its end, bytes left unread fail with
+    /// `InvalidData`. The restored payload is written to `output`, whose writes fail with
+    /// `InvalidInput` past the bulk and frame length limits of the decoder.
+    fn decode(&mut self,
+              len: Option<usize>,
+              input: &mut dyn Read,
+              output: &mut dyn Write)
+              -> Result<()>;
+}
+
+struct Hook(Box<dyn BulkHook + Send>);
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("BulkHook")
+    }
+}
+
+/// Encodes RESP value to RESP binary buffer, bulk payloads are transformed by the hook.
+/// Bulk strings keep their `$<len>` framing, see `encode_to_writer_with_hook`.
+/// # Examples
+/// ```
+/// # use std::io::{Read, Write, BufReader, Result};
+/// # use self::resp::{Value, Decoder, BulkHook, encode_with_hook};
+/// struct Reverse;
+///
+/// impl BulkHook for Reverse {
+///     fn encode(&mut self, _: usize, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+///         let mut payload = Vec::new();
+///         input.read_to_end(&mut payload)?;
+///         payload.reverse();
+///         output.write_all(&payload)
+///     }
+///
+///     fn decode(&mut self, _: Option<usize>, input: &mut dyn Read, output: &mut dyn Write)
+///               -> Result<()> {
+///         let mut payload = Vec::new();
+///         input.read_to_end(&mut payload)?;
+///         payload.reverse();
+///         output.write_all(&payload)
+///     }
+/// }
+///
+/// let buf = encode_with_hook(&Value::Bulk("Hello".to_string()), &mut Reverse).unwrap();
+/// assert_eq!(buf, b"$5\r\nolleH\r\n");
+///
+/// let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+/// decoder.set_bulk_hook(Box::new(Reverse));
+/// assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello".to_string()));
+/// ```
+pub fn encode_with_hook(value: &Value, hook: &mut dyn BulkHook) -> Result<Vec<u8>> {
+    let mut res: Vec<u8> = Vec::new();
+    write_with_hook(value, &mut res, hook)?;
+    Ok(res)
+}
+
+/// Encodes RESP value straight to a writer, bulk payloads are transformed by the hook, returns
+/// the number of bytes written. The output is the same as `encode_with_hook`: bulk strings keep
+/// their `$<len>` framing, so a RESP2 peer reads them. When the hook knows the length of a
+/// transformed payload up front, see `BulkHook::encoded_len`, the payload is written as the
+/// hook writes it, it is buffered otherwise.
+/// # Examples
+/// ```
+/// # use std::io::{self, Read, Write, BufReader, Result};
+/// # use self::resp::{Value, Decoder, BulkHook, encode_to_writer_with_hook};
+/// struct Upper;
+///
+/// impl BulkHook for Upper {
+///     fn encoded_len(&self, len: usize) -> Option<usize> {
+///         Some(len)
+///     }
+///
+///     fn encode(&mut self, _: usize, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+///         let mut buf = [0u8; 4];
+///         loop {
+///             let n = input.read(&mut buf)?;
+///             if n == 0 {
+///                 return Ok(());
+///             }
+///             buf[..n].make_ascii_uppercase();
+///             output.write_all(&buf[..n])?;
+///         }
+///     }
+///
+///     fn decode(&mut self, _: Option<usize>, input: &mut dyn Read, output: &mut dyn Write)
+///               -> Result<()> {
+///         io::copy(input, output).map(|_| ())
+///     }
+/// }
+///
+/// let mut output: Vec<u8> = Vec::new();
+/// let value = Value::Bulk("hello".to_string());
+/// assert_eq!(encode_to_writer_with_hook(&value, &mut output, &mut Upper).unwrap(), 11);
+/// assert_eq!(output, b"$5\r\nHELLO\r\n");
+///
+/// let mut decoder = Decoder::new(BufReader::new(output.as_slice()));
+/// decoder.set_bulk_hook(Box::new(Upper));
+/// assert_eq!(decoder.decode().unwrap(), Value::Bulk("HELLO".to_string()));
+/// ```
+pub fn encode_to_writer_with_hook<W: Write>(value: &Value, writer: &mut W,
+                                            hook: &mut dyn BulkHook) -> Result<usize> {
+    let mut counter = CountingWriter { writer, count: 0 };
+    write_with_hook(value, &mut counter, hook)?;
+    Ok(counter.count)
+}
+
+fn write_with_hook<W: Write>(value: &Value, writer: &mut W, hook: &mut dyn BulkHook)
+                             -> Result<()> {
+    let mut header: Vec<u8> = Vec::new();
+    let payload = match *value {
+        Value::Bulk(ref val) => val.as_bytes(),
+        Value::BufBulk(ref val) => val.as_slice(),
+        Value::SharedBulk(ref val) => val,
+        Value::SmallBulk(ref val) => val.as_bytes(),
+        Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+            encode_len_header(sequence_prefix(value), val.len(), &mut header);
+            writer.write_all(&header)?;
+            for item in val {
+                write_with_hook(item, writer, hook)?;
+            }
+            return Ok(());
+        }
+        Value::Map(ref val) => {
+            encode_len_header(b'%', val.len(), &mut header);
+            writer.write_all(&header)?;
+            for (key, value) in val {
+                write_with_hook(key, writer, hook)?;
+                write_with_hook(value, writer, hook)?;
+            }
+            return Ok(());
+        }
+        Value::Attribute(ref attributes, ref val) => {
+            encode_len_header(b'|', attributes.len(), &mut header);
+            writer.write_all(&header)?;
+            for (key, value) in attributes {
+                write_with_hook(key, writer, hook)?;
+                write_with_hook(value, writer, hook)?;
+            }
+            return write_with_hook(val, writer, hook);
+        }
+        _ => return encode_to_writer(value, writer).map(|_| ()),
+    };
+
+    match hook.encoded_len(payload.len()) {
+        Some(len) => {
+            encode_len_header(b'$', len, &mut header);
+            writer.write_all(&header)?;
+            let mut output = ExactWriter {
+                writer: &mut *writer,
+                remaining: len,
+            };
+            hook.encode(payload.len(), &mut &payload[..], &mut output)?;
+            if output.remaining > 0 {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      format!("bulk hook wrote {} bytes short of {}",
+                                              output.remaining,
+                                              len)));
+            }
+        }
+        None => {
+            let mut output: Vec<u8> = Vec::new();
+            hook.encode(payload.len(), &mut &payload[..], &mut output)?;
+            encode_len_header(b'$', output.len(), &mut header);
+            writer.write_all(&header)?;
+            writer.write_all(&output)?;
+        }
+    }
+    writer.write_all(CRLF_BYTES)
+}
+
+/// A writer that takes exactly the length declared by `BulkHook::encoded_len`.
+struct ExactWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    remaining: usize,
+}
+
+impl<'a, W: Write> Write for ExactWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() > self.remaining {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  "bulk hook wrote more than its encoded length"));
+        }
+        let n = self.writer.write(buf)?;
+        self.remaining -= n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The output of `BulkHook::decode`, the restored payload is held to the bulk and frame length
+/// limits of the decoder, so a small transformed payload can't expand without bound.
+struct HookOutput {
+    buf: Vec<u8>,
+    options: Options,
+}
+
+impl Write for HookOutput {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        check_streamed_len(self.buf.len(), buf.len(), &self.options)?;
+        check_max_frame_len(self.buf.len() + buf.len(), &self.options)?;
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct CountingWriter<'a, W: 'a> {
+    writer: &'a mut W,
+    count: usize,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.count += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes a RESP3 streamed bulk string, `$?\r\n` followed by chunks, for a payload whose
+/// length is not known up front. Every non-empty write is sent as a chunk, `finish` ends the
+/// string.
+/// # Examples
+/// ```
+/// # use std::io::{BufReader, Write};
+/// # use self::resp::{Decoder, StreamedBulkWriter, Value};
+/// let mut writer = StreamedBulkWriter::new(Vec::new()).unwrap();
+/// writer.write_all(b"Hello ").unwrap();
+/// writer.write_all(b"world").unwrap();
+/// let buf = writer.finish().unwrap();
+/// assert_eq!(buf, b"$?\r\n;6\r\nHello \r\n;5\r\nworld\r\n;0\r\n");
+///
+/// let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+/// assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello world".to_string()));
+/// ```
+#[derive(Debug)]
+pub struct StreamedBulkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> StreamedBulkWriter<W> {
+    /// Creates a StreamedBulkWriter instance, the streamed bulk header is written at once.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"$?\r\n")?;
+        Ok(StreamedBulkWriter { writer })
+    }
+
+    /// Writes the terminating chunk and returns the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.write_all(b";0\r\n")?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for StreamedBulkWriter<W> {
+    /// Writes all the bytes as one chunk, an empty write writes nothing.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut header: Vec<u8> = Vec::new();
+        encode_len_header(b';', buf.len(), &mut header);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(CRLF_BYTES)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Accumulates pipelined commands and values into one buffer, so a batch is sent with a single
+/// write. The buffer keeps its capacity across flushes, a batch no larger than the previous ones
+/// doesn't allocate.
+/// # Examples
+/// ```
+/// # use self::resp::{PipelineEncoder, Value};
+/// let mut pipeline = PipelineEncoder::new();
+/// pipeline.push_command(&["SET", "a", "1"]);
+/// pipeline.push_command(&["GET", "a"]);
+/// pipeline.push(&Value::Array(vec![Value::Bulk("PING".to_string())]));
+/// assert_eq!(pipeline.len(), 3);
+///
+/// let mut output: Vec<u8> = Vec::new();
+/// pipeline.flush_to(&mut output).unwrap();
+/// assert!(pipeline.is_empty());
+/// assert_eq!(output,
+///            &b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$3\r\nGET\r\n$1\r\na\r\n\
+///               *1\r\n$4\r\nPING\r\n"[..]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PipelineEncoder {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl PipelineEncoder {
+    /// Creates a PipelineEncoder instance.
+    pub fn new() -> Self {
+        PipelineEncoder::default()
+    }
+
+    /// Creates a PipelineEncoder instance whose buffer holds `capacity` bytes up front.
+    pub fn with_capacity(capacity: usize) -> Self {
+        PipelineEncoder {
+            buf: Vec::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    /// Appends an encoded value.
+    pub fn push(&mut self, value: &Value) {
+        buf_encode(value, &mut self.buf);
+        self.len += 1;
+    }
+
+    /// Appends a command encoded from its arguments, see `encode_slice`.
+    pub fn push_command<I>(&mut self, args: I)
+        where I: IntoIterator,
+              I::IntoIter: ExactSizeIterator,
+              I::Item: AsRef<[u8]>
+    {
+        buf_encode_slice(args, &mut self.buf);
+        self.len += 1;
+    }
+
+    /// Returns the number of commands and values appended since the last flush.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if nothing was appended since the last flush.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the encoded batch.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Writes the batch to the writer with a single `write_all` and clears it, returns the
+    /// number of bytes written. The batch is kept if the write fails.
+    pub fn flush_to<W: Write>(&mut self, writer: &mut W) -> Result<usize> {
+        writer.write_all(&self.buf)?;
+        let len = self.buf.len();
+        self.clear();
+        Ok(len)
+    }
+
+    /// Drops the batch, keeping the capacity of the buffer.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.len = 0;
+    }
+}
+
+/// The RESP protocol versions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Version {
+    /// RESP2, the protocol of a connection before `HELLO 3`.
+    #[default]
+    Resp2,
+    /// RESP3, the protocol of a connection after `HELLO 3`.
+    Resp3,
+}
+
+/// A RESP Encoder for a given protocol version.
+/// # Examples
+/// ```
+/// # use self::resp::{Encoder, Version, Value};
+/// let value = Value::Array(vec![Value::Null, Value::NullArray]);
+/// assert_eq!(Encoder::new(Version::Resp2).encode(&value), b"*2\r\n$-1\r\n*-1\r\n");
+/// assert_eq!(Encoder::new(Version::Resp3).encode(&value), b"*2\r\n_\r\n_\r\n");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Encoder {
+    version: Version,
+    double_precision: Option<usize>,
+}
+
+impl Encoder {
+    /// Creates an Encoder instance for the protocol version.
+    pub fn new(version: Version) -> Self {
+        Encoder {
+            version,
+            double_precision: None,
+        }
+    }
+
+    /// Returns the protocol version.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Sets the number of decimals of the encoded doubles, for clients that expect a fixed
+    /// precision. `None`, the default, encodes the shortest representation that parses back to
+    /// the same value. `inf`, `-inf` and `nan` are encoded as is.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Encoder, Version, Value};
+    /// let mut encoder = Encoder::new(Version::Resp3);
+    /// assert_eq!(encoder.encode(&Value::Double(0.1)), b",0.1\r\n");
+    /// assert_eq!(encoder.encode(&Value::Double(1e300)), b",1e300\r\n");
+    ///
+    /// encoder.set_double_precision(Some(3));
+    /// assert_eq!(encoder.encode(&Value::Double(0.1)), b",0.100\r\n");
+    /// assert_eq!(encoder.encode(&Value::Double(-1.0 / 0.0)), b",-inf\r\n");
+    /// ```
+    pub fn set_double_precision(&mut self, precision: Option<usize>) {
+        self.double_precision = precision;
+    }
+
+    /// Returns the number of decimals of the encoded doubles, `None` for the shortest.
+    pub fn double_precision(&self) -> Option<usize> {
+        self.double_precision
+    }
+
+    fn format_double(&self, val: f64) -> String {
+        match self.double_precision {
+            Some(precision) => format_double_fixed(val, precision),
+            None => format_double(val),
+        }
+    }
+
+    /// Encodes the value for the protocol version.
+    ///
+    /// With `Version::Resp3`, `Null` and `NullArray` are encoded as the RESP3 null `_\r\n` and
+    /// other values as with `encode`.
+    ///
+    /// With `Version::Resp2`, the RESP3 only values are downgraded as Redis does for RESP2
+    /// clients:
+    ///
+    /// - `Map` -> `Array` of the keys and values, `[k1, v1, k2, v2, ...]`
+    /// - `Set` and `Push` -> `Array`
+    /// - `Boolean` -> `Integer`, `1` or `0`
+    /// - `Double` and `BigNumber` -> bulk string of the number
+    /// - `Attribute` -> the value, the attributes are dropped
+    ///
+    /// `Null` is encoded as `$-1\r\n` and `Unknown` and `Raw` values are written verbatim.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Encoder, Version, Value};
+    /// let value = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Double(1.5)),
+    ///                             (Value::Bulk("b".to_string()), Value::Boolean(true))]);
+    /// assert_eq!(Encoder::new(Version::Resp2).encode(&value),
+    ///            b"*4\r\n$1\r\na\r\n$3\r\n1.5\r\n$1\r\nb\r\n:1\r\n");
+    /// assert_eq!(Encoder::new(Version::Resp3).encode(&value), value.encode());
+    /// ```
+    pub fn encode(&self, value: &Value) -> Vec<u8> {
+        let mut res: Vec<u8> = Vec::new();
+        self.buf_encode(value, &mut res);
+        res
+    }
+
+    fn buf_encode(&self, value: &Value, buf: &mut Vec<u8>) {
+        match *value {
+            Value::Null | Value::NullArray if self.version == Version::Resp3 => {
+                buf.extend_from_slice(RESP3_NULL_BYTES);
+            }
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                let prefix = match self.version {
+                    Version::Resp2 => b'*',
+                    Version::Resp3 => sequence_prefix(value),
+                };
+                encode_len_header(prefix, val.len(), buf);
+                for item in val {
+                    self.buf_encode(item, buf);
+                }
+            }
+            Value::Map(ref val) => {
+                match self.version {
+                    Version::Resp2 => encode_len_header(b'*', val.len() * 2, buf),
+                    Version::Resp3 => encode_len_header(b'%', val.len(), buf),
+                }
+                for (key, value) in val {
+                    self.buf_encode(key, buf);
+                    self.buf_encode(value, buf);
+                }
+            }
+            Value::Attribute(_, ref val) if self.version == Version::Resp2 => {
+                self.buf_encode(val, buf);
+            }
+            Value::Attribute(ref attributes, ref val) => {
+                encode_len_header(b'|', attributes.len(), buf);
+                for (key, value) in attributes {
+                    self.buf_encode(key, buf);
+                    self.buf_encode(value, buf);
+                }
+                self.buf_encode(val, buf);
+            }
+            Value::Boolean(val) if self.version == Version::Resp2 => {
+                buf_encode(&Value::Integer(val as i64), buf);
+            }
+            Value::Double(val) if self.version == Version::Resp2 => {
+                buf_encode(&Value::Bulk(self.format_double(val)), buf);
+            }
+            Value::Double(val) => {
+                buf.push(b',');
+                buf.extend_from_slice(self.format_double(val).as_bytes());
+                buf.extend_from_slice(CRLF_BYTES);
+            }
+            Value::BigNumber(ref val) if self.version == Version::Resp2 => {
+                encode_len_header(b'$', val.len(), buf);
+                buf.extend_from_slice(val.as_bytes());
+                buf.extend_from_slice(CRLF_BYTES);
+            }
+            _ => buf_encode(value, buf),
+        }
+    }
+}
+
+#[inline]
+fn buf_encode(value: &Value, buf: &mut Vec<u8>) {
+    encode_node(value, buf);
+}
+
+/// The decoding options shared by the decoders.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options {
+    pub(crate) buf_bulk: bool,
+    /// whether a bulk string that is not UTF-8 is decoded to `Value::BufBulk` instead of failing.
+    pub(crate) buf_bulk_fallback: bool,
+    /// whether short bulk strings are decoded to `Value::SmallBulk`.
+    pub(crate) small_bulks: bool,
+    /// whether lines may end with a bare LF, only read by `Decoder`.
+    pub(crate) lenient_lf: bool,
+    /// whether lengths must be plain decimals, without sign, leading zeros or whitespace.
+    pub(crate) strict_lengths: bool,
+    /// whether top-level lines not starting with `*` are inline commands, only read by `Decoder`.
+    pub(crate) inline_commands: bool,
+    pub(crate) passthrough_unknown: bool,
+    pub(crate) keep_attributes: bool,
+    /// the pinned protocol version, `None` accepts every version.
+    pub(crate) version: Option<Version>,
+    /// the max length of a bulk string, `None` for the protocol max of 512MB.
+    pub(crate) max_bulk_len: Option<usize>,
+    /// the max number of elements of an aggregate, `None` for the protocol max.
+    pub(crate) max_array_len: Option<usize>,
+    /// the max number of bytes of a top-level value, `None` for no max.
+    pub(crate) max_frame_len: Option<usize>,
+    /// the max length of a line, its CRLF excluded, `None` for no max.
+    pub(crate) max_line_len: Option<usize>,
+}
+
+/// The first line of a value.
+pub(crate) enum Line {
+    /// A value complete in its first line.
+    Value(Value),
+    /// A bulk string header with the payload length.
+    Bulk(usize),
+    /// A streamed bulk string header, the payload follows in chunks.
+    StreamedBulk,
+    /// An aggregate header with the elements count.
+    Aggregate(Aggregate, usize),
+    /// A streamed aggregate header, the elements follow up to the end line `.`.
+    StreamedAggregate(Aggregate),
+    /// An unknown type in passthrough mode, with the payload length if the line declares one.
+    Unknown(Option<usize>),
+}
+
+/// A decoded value, or the header of an aggregate whose elements follow.
+pub(crate) enum Leaf {
+    Value(Value),
+    /// An aggregate with its elements count, `None` for a streamed aggregate.
+    Aggregate(Aggregate, Option<usize>),
+}
+
+/// Returns the type prefix of an `Array`, a `Set` or a `Push`.
+pub(crate) fn sequence_prefix(value: &Value) -> u8 {
+    match *value {
+        Value::Set(_) => b'~',
+        Value::Push(_) => b'>',
+        _ => b'*',
+    }
+}
+
+/// The aggregate types.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Aggregate {
+    Array,
+    Map,
+    Set,
+    Push,
+    /// Attributes, followed by the value they are attached to.
+    Attribute,
+}
+
+impl Aggregate {
+    pub(crate) fn prefix(self) -> u8 {
+        match self {
+            Aggregate::Array => b'*',
+            Aggregate::Map => b'%',
+            Aggregate::Set => b'~',
+            Aggregate::Push => b'>',
+            Aggregate::Attribute => b'|',
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Aggregate::Array => "array",
+            Aggregate::Map => "map",
+            Aggregate::Set => "set",
+            Aggregate::Push => "push",
+            Aggregate::Attribute => "attribute",
+        }
+    }
+
+    /// Returns the number of values of an aggregate with `len` elements.
+    pub(crate) fn values_len(self, len: usize) -> usize {
+        match self {
+            Aggregate::Map => len * 2,
+            Aggregate::Attribute => len * 2 + 1,
+            _ => len,
+        }
+    }
+
+    /// Creates the aggregate value from its values.
+    pub(crate) fn build(self, mut values: Vec<Value>, options: &Options) -> Value {
+        match self {
+            Aggregate::Array => Value::Array(values),
+            Aggregate::Map => Value::Map(pairs(values)),
+            Aggregate::Set => Value::Set(values),
+            Aggregate::Push => Value::Push(values),
+            Aggregate::Attribute => {
+                let value = values.pop().unwrap();
+                if !options.keep_attributes {
+                    return value;
+                }
+                Value::Attribute(pairs(values), Box::new(value))
+            }
+        }
+    }
+}
+
+impl Aggregate {
+    /// Checks the number of elements of an aggregate with `len` elements, or `None` and
+    /// `values` values for a streamed aggregate, against the `max_array_len` option.
+    pub(crate) fn check_len(self,
+                            len: Option<usize>,
+                            values: usize,
+                            options: &Options)
+                            -> Result<()> {
+        let len = match len {
+            Some(len) => len,
+            None if self == Aggregate::Map => values.div_ceil(2),
+            None => values,
+        };
+        match options.max_array_len {
+            Some(max) if len > max => {
+                Err(Error::new(ErrorKind::InvalidInput,
+                               format!("{} length {} exceeds max array length {}",
+                                       self.name(),
+                                       len,
+                                       max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates the value of a streamed aggregate from its values.
+    pub(crate) fn build_streamed(self, values: Vec<Value>, options: &Options) -> Result<Value> {
+        self.check_streamed(values.len())?;
+        Ok(self.build(values, options))
+    }
+
+    /// Checks the number of values of a streamed aggregate once it ends.
+    pub(crate) fn check_streamed(self, values: usize) -> Result<()> {
+        if self == Aggregate::Map && values % 2 != 0 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("invalid streamed map: {} values", values)));
+        }
+        Ok(())
+    }
+}
+
+fn pairs(values: Vec<Value>) -> Vec<(Value, Value)> {
+    let mut map: Vec<(Value, Value)> = Vec::with_capacity(values.len() / 2);
+    let mut values = values.into_iter();
+    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+        map.push((key, value));
+    }
+    map
+}
+
+/// Checks a line read up to `\n` is a valid CRLF terminated line.
+pub(crate) fn check_line(line: &[u8]) -> Result<()> {
+    let len = line.len();
+    if len < 3 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("too short: {}", len)));
+    }
+    if !is_crlf(line[len - 2], line[len - 1]) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", line)));
+    }
+    Ok(())
+}
+
+/// Parses a line checked by `check_line`.
+pub(crate) fn parse_line(line: &[u8], options: &Options) -> Result<Line> {
+    let bytes = &line[1..line.len() - 2];
+    if options.version == Some(Version::Resp2) {
+        let streamed = bytes == b"?" && (line[0] == b'$' || line[0] == b'*');
+        if streamed || RESP3_PREFIXES.contains(&line[0]) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("RESP3 type in RESP2: {:?}",
+                                          String::from_utf8_lossy(&line[..line.len() - 2]))));
+        }
+    }
+    if options.strict_lengths && LENGTH_PREFIXES.contains(&line[0]) && bytes != b"?" {
+        check_length_literal(bytes)?;
+    }
+    match line[0] {
+        // Value::String
+        b'+' => parse_string(bytes).map(|val| Line::Value(Value::String(val))),
+        // Value::Error
+        b'-' => parse_string(bytes).map(|val| Line::Value(Value::Error(val))),
+        // Value::Integer
+        b':' => parse_integer(bytes).map(|val| Line::Value(Value::Integer(val))),
+        // Value::Null, RESP3 null
+        b'_' => {
+            if !bytes.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid null: {:?}",
+                                              String::from_utf8_lossy(bytes))));
+            }
+            Ok(Line::Value(Value::Null))
+        }
+        // Value::Boolean
+        b'#' => {
+            match bytes {
+                b"t" => Ok(Line::Value(Value::Boolean(true))),
+                b"f" => Ok(Line::Value(Value::Boolean(false))),
+                _ => {
+                    Err(Error::new(ErrorKind::InvalidInput,
+                                   format!("invalid boolean: {:?}",
+                                           String::from_utf8_lossy(bytes))))
+                }
+            }
+        }
+        // Value::Double
+        b',' => parse_double(bytes).map(|val| Line::Value(Value::Double(val))),
+        // Value::BigNumber
+        b'(' => parse_big_number(bytes).map(|val| Line::Value(Value::BigNumber(val))),
+        // Value::Bulk
+        b'$' => {
+            if bytes == b"?" {
+                return Ok(Line::StreamedBulk);
+            }
+            let int = parse_bulk_len(bytes)?;
+            if int == -1 {
+                // Null bulk
+                return Ok(Line::Value(Value::Null));
+            }
+            check_max_bulk_len(int as usize, options)?;
+            Ok(Line::Bulk(int as usize))
+        }
+        // Value::Array
+        b'*' => {
+            if bytes == b"?" {
+                return Ok(Line::StreamedAggregate(Aggregate::Array));
+            }
+            let int = parse_integer(bytes)?;
+            if int == -1 {
+                // Null array
+                return Ok(Line::Value(Value::NullArray));
+            }
+            if !(-1..RESP_MAX_SIZE).contains(&int) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid array length: {}", int)));
+            }
+            Ok(Line::Aggregate(Aggregate::Array, int as usize))
+        }
+        // Value::Map
+        b'%' => {
+            if bytes == b"?" {
+                return Ok(Line::StreamedAggregate(Aggregate::Map));
+            }
+            let int = parse_integer(bytes)?;
+            if !(0..RESP_MAX_SIZE).contains(&int) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid map length: {}", int)));
+            }
+            Ok(Line::Aggregate(Aggregate::Map, int as usize))
+        }
+        // Value::Set
+        b'~' => {
+            if bytes == b"?" {
+                return Ok(Line::StreamedAggregate(Aggregate::Set));
+            }
+            let int = parse_integer(bytes)?;
+            if !(0..RESP_MAX_SIZE).contains(&int) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid set length: {}", int)));
+            }
+            Ok(Line::Aggregate(Aggregate::Set, int as usize))
+        }
+        // Value::Push
+        b'>' => {
+            let int = parse_integer(bytes)?;
+            if !(0..RESP_MAX_SIZE).contains(&int) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid push length: {}", int)));
+            }
+            Ok(Line::Aggregate(Aggregate::Push, int as usize))
+        }
+        // Value::Attribute
+        b'|' => {
+            let int = parse_integer(bytes)?;
+            if !(0..RESP_MAX_SIZE).contains(&int) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("invalid attribute length: {}", int)));
+            }
+            Ok(Line::Aggregate(Aggregate::Attribute, int as usize))
+        }
+        _ if options.passthrough_unknown => {
+            match parse_integer(bytes) {
+                Ok(int) if (0..RESP_MAX_SIZE).contains(&int) => {
+                    check_max_bulk_len(int as usize, options)?;
+                    Ok(Line::Unknown(Some(int as usize)))
+                }
+                _ => Ok(Line::Unknown(None)),
+            }
+        }
+        prefix => {
+            Err(Error::new(ErrorKind::InvalidInput,
+                           format!("invalid RESP type: {:?}", prefix)))
+        }
+    }
+}
+
+/// Parses the framing of a line checked by `check_line`: `None` for a value that is a single
+/// line, whose content is not parsed.
+pub(crate) fn parse_frame_line(line: &[u8], options: &Options) -> Result<Option<Line>> {
+    let resp3 = RESP3_PREFIXES.contains(&line[0]);
+    if SIMPLE_PREFIXES.contains(&line[0]) && !(resp3 && options.version == Some(Version::Resp2)) {
+        return Ok(None);
+    }
+    parse_line(line, options).map(Some)
+}
+
+/// Checks a length is written the way Redis writes it: `0`, `-1` or digits without a leading
+/// zero. Fails with `InvalidInput`.
+fn check_length_literal(bytes: &[u8]) -> Result<()> {
+    let digits = bytes.strip_prefix(b"-").unwrap_or(bytes);
+    let valid = match digits.first() {
+        Some(&b'0') => bytes.len() == 1,
+        Some(_) => digits.iter().all(u8::is_ascii_digit),
+        None => false,
+    };
+    if !valid {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid length literal: {:?}",
+                                      String::from_utf8_lossy(bytes))));
+    }
+    Ok(())
+}
+
+/// Splits an inline command into its arguments, with the quoting rules of Redis: arguments are
+/// separated by whitespace, double quoted arguments may hold escapes like `\n` or `\x41`,
+/// single quoted arguments may hold `\'`. A closing quote must be followed by whitespace.
+fn split_inline(line: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let unbalanced = || Error::new(ErrorKind::InvalidInput, "unbalanced quotes in inline command");
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut args: Vec<Vec<u8>> = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+        let mut arg: Vec<u8> = Vec::new();
+        let mut quote: Option<u8> = None;
+        while i < line.len() {
+            let c = line[i];
+            let next = line.get(i + 1).cloned();
+            match quote {
+                Some(b'"') if c == b'\\' && next == Some(b'x') => {
+                    match (line.get(i + 2).cloned().and_then(hex),
+                           line.get(i + 3).cloned().and_then(hex)) {
+                        (Some(high), Some(low)) => {
+                            arg.push(high << 4 | low);
+                            i += 2;
+                        }
+                        _ => arg.push(b'x'),
+                    }
+                    i += 1;
+                }
+                Some(b'"') if c == b'\\' && next.is_some() => {
+                    arg.push(match next.unwrap() {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 8,
+                        b'a' => 7,
+                        escaped => escaped,
+                    });
+                    i += 1;
+                }
+                Some(b'\'') if c == b'\\' && next == Some(b'\'') => {
+                    arg.push(b'\'');
+                    i += 1;
+                }
+                Some(q) if c == q => {
+                    if next.is_some_and(|b| !b.is_ascii_whitespace()) {
+                        return Err(unbalanced());
+                    }
+                    quote = None;
+                    i += 1;
+                    break;
+                }
+                Some(_) => arg.push(c),
+                None if c.is_ascii_whitespace() => break,
+                None if c == b'"' || c == b'\'' => quote = Some(c),
+                None => arg.push(c),
+            }
+            i += 1;
+        }
+        if quote.is_some() {
+            return Err(unbalanced());
+        }
+        args.push(arg);
+    }
+}
+
+/// Creates the value of a bulk payload borrowed from a buffer, a short one isn't copied to the
+/// heap if small bulks are enabled.
+pub(crate) fn bulk_slice_value(payload: &[u8], options: &Options) -> Result<Value> {
+    if options.small_bulks {
+        if let Some(bulk) = SmallBulk::new(payload) {
+            // the UTF-8 check of `Value::Bulk` still applies.
+            if !options.buf_bulk && !options.buf_bulk_fallback {
+                ::std::str::from_utf8(payload)
+                    .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            }
+            return Ok(Value::SmallBulk(bulk));
+        }
+    }
+    bulk_value(payload.to_vec(), options)
+}
+
+/// Creates the value of a bulk payload.
+pub(crate) fn bulk_value(buf: Vec<u8>, options: &Options) -> Result<Value> {
+    if options.buf_bulk {
+        return Ok(Value::BufBulk(buf));
+    }
+    match String::from_utf8(buf) {
+        Ok(string) => Ok(Value::Bulk(string)),
+        Err(err) if options.buf_bulk_fallback => Ok(Value::BufBulk(err.into_bytes())),
+        Err(err) => Err(Error::new(ErrorKind::InvalidData, err)),
+    }
+}
+
+/// The type of a frame, given by its first byte, see `Decoder::peek_type`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameType {
+    /// A simple string, `+`
+    String,
+    /// An error, `-`
+    Error,
+    /// An integer, `:`
+    Integer,
+    /// A RESP3 null, `_`
+    Null,
+    /// A RESP3 boolean, `#`
+    Boolean,
+    /// A RESP3 double, `,`
+    Double,
+    /// A RESP3 big number, `(`
+    BigNumber,
+    /// A bulk string or a null bulk, `$`
+    Bulk,
+    /// An array or a null array, `*`
+    Array,
+    /// A RESP3 map, `%`
+    Map,
+    /// A RESP3 set, `~`
+    Set,
+    /// A RESP3 push, `>`
+    Push,
+    /// RESP3 attributes followed by a value, `|`
+    Attribute,
+    /// A type unknown to this crate, or an inline command, with its first byte
+    Unknown(u8),
+}
+
+impl FrameType {
+    /// Returns the type of a frame starting with `prefix`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::FrameType;
+    /// assert_eq!(FrameType::from_prefix(b'$'), FrameType::Bulk);
+    /// assert_eq!(FrameType::from_prefix(b'='), FrameType::Unknown(b'='));
+    /// ```
+    pub fn from_prefix(prefix: u8) -> Self {
+        match prefix {
+            b'+' => FrameType::String,
+            b'-' => FrameType::Error,
+            b':' => FrameType::Integer,
+            b'_' => FrameType::Null,
+            b'#' => FrameType::Boolean,
+            b',' => FrameType::Double,
+            b'(' => FrameType::BigNumber,
+            b'$' => FrameType::Bulk,
+            b'*' => FrameType::Array,
+            b'%' => FrameType::Map,
+            b'~' => FrameType::Set,
+            b'>' => FrameType::Push,
+            b'|' => FrameType::Attribute,
+            prefix => FrameType::Unknown(prefix),
+        }
+    }
+
+    /// Returns the first byte of a frame of this type.
+    pub fn prefix(self) -> u8 {
+        match self {
+            FrameType::String => b'+',
+            FrameType::Error => b'-',
+            FrameType::Integer => b':',
+            FrameType::Null => b'_',
+            FrameType::Boolean => b'#',
+            FrameType::Double => b',',
+            FrameType::BigNumber => b'(',
+            FrameType::Bulk => b'$',
+            FrameType::Array => b'*',
+            FrameType::Map => b'%',
+            FrameType::Set => b'~',
+            FrameType::Push => b'>',
+            FrameType::Attribute => b'|',
+            FrameType::Unknown(prefix) => prefix,
+        }
+    }
+}
+
+/// A streaming RESP Decoder.
+#[derive(Debug)]
+pub struct Decoder<R> {
+    pub(crate) options: Options,
+    hook: Option<Hook>,
+    pub(crate) reader: Replay<R>,
+    /// the number of bytes read of the value being decoded.
+    pub(crate) frame_len: usize,
+    /// the buffer of the lines read, reused across lines.
+    pub(crate) line: Vec<u8>,
+    bytes_consumed: u64,
+    frames_decoded: u64,
+    /// the number of values decoded by the prefix of their first line.
+    type_counts: BTreeMap<u8, u64>,
+    /// the partial aggregates of the value being decoded and what the decoder waits for,
+    /// see `Decoder::state`.
+    pub(crate) state: DecoderState,
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Creates a Decoder instance with given buffered reader for decoding the RESP buffers.
+    /// A slice is a buffered reader, an unbuffered reader like a `TcpStream` should be wrapped
+    /// in a `BufReader`.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    ///
+    /// let value = Value::Bulk("Hello".to_string());
+    /// let buf = value.encode();
+    /// let mut decoder = Decoder::new(buf.as_slice());
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello".to_string()));
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello".to_string()));
+    /// ```
+    pub fn new(reader: R) -> Self {
+        Decoder {
+            options: Options::default(),
+            hook: None,
+            reader: Replay::new(reader),
+            frame_len: 0,
+            line: Vec::new(),
+            bytes_consumed: 0,
+            frames_decoded: 0,
+            type_counts: BTreeMap::new(),
+            state: DecoderState::idle(),
+        }
+    }
+
+    /// Creates a Decoder instance with given buffered reader for decoding the RESP buffers.
+    /// The instance will decode bulk value to buffer bulk.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    ///
+    /// let value = Value::Bulk("Hello".to_string());
+    /// let buf = value.encode();
+    /// let mut decoder = Decoder::with_buf_bulk(buf.as_slice());
+    /// // Always decode "$" buffers to Value::BufBulk even if feed Value::Bulk buffers
+    /// assert_eq!(decoder.decode().unwrap(), Value::BufBulk("Hello".to_string().into_bytes()));
+    /// ```
+    pub fn with_buf_bulk(reader: R) -> Self {
+        Decoder {
+            options: Options {
+                buf_bulk: true,
+                ..Options::default()
+            },
+            hook: None,
+            reader: Replay::new(reader),
+            frame_len: 0,
+            line: Vec::new(),
+            bytes_consumed: 0,
+            frames_decoded: 0,
+            type_counts: BTreeMap::new(),
+            state: DecoderState::idle(),
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader.inner
+    }
+
+    /// Gets a mutable reference to the underlying reader, e.g. to set options of a socket.
+    /// Reading from it directly may leave the decoder in the middle of a value.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader.inner
+    }
+
+    /// Unwraps the decoder, returning the underlying reader. The bytes buffered by the reader
+    /// but not decoded yet are kept in it, not the bytes of a value interrupted by `WouldBlock`,
+    /// see `Decoder::set_nonblocking`.
+    /// # Examples
+    /// ```
+    /// # use std::io::{BufRead, BufReader};
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"+OK\r\nraw bytes";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+    /// let mut reader = decoder.into_inner();
+    /// assert_eq!(reader.fill_buf().unwrap(), b"raw bytes");
+    /// ```
+    pub fn into_inner(self) -> R {
+        self.reader.inner
+    }
+
+    /// Sets whether the decoder reads from a non-blocking reader. When the reader fails with
+    /// `WouldBlock` in the middle of a value, `Decoder::decode` returns the error and keeps the
+    /// bytes read of the value, the next call decodes the value from them. `skip_value`,
+    /// `decode_all`, `read_bulk` and `read_rdb` resume the same way, as do the reads of a
+    /// `BulkReader`. `decode_bulk_to`, `relay_frame` and `relay_bulk` write the value as they
+    /// read it, so they can't resume and fail with `InvalidInput` in this mode. By default the
+    /// bytes are not kept, as keeping them copies every value read.
+    /// # Examples
+    /// ```
+    /// # use std::io::{self, BufReader, ErrorKind, Read};
+    /// # use self::resp::{Decoder, Value};
+    /// // a reader that would block after every 3 bytes.
+    /// struct Socket(Vec<u8>, usize);
+    /// impl Read for Socket {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         self.1 += 1;
+    ///         if self.1 % 2 == 0 {
+    ///             return Err(io::Error::new(ErrorKind::WouldBlock, "would block"));
+    ///         }
+    ///         let n = buf.len().min(self.0.len()).min(3);
+    ///         buf[..n].copy_from_slice(&self.0[..n]);
+    ///         self.0.drain(..n);
+    ///         Ok(n)
+    ///     }
+    /// }
+    ///
+    /// let socket = Socket(b"*2\r\n$3\r\nfoo\r\n:1\r\n".to_vec(), 0);
+    /// let mut decoder = Decoder::new(BufReader::new(socket));
+    /// decoder.set_nonblocking(true);
+    /// let value = loop {
+    ///     match decoder.decode() {
+    ///         Ok(value) => break value,
+    ///         Err(ref err) if err.kind() == ErrorKind::WouldBlock => continue,
+    ///         Err(err) => panic!("{}", err),
+    ///     }
+    /// };
+    /// assert_eq!(value, Value::Array(vec![Value::Bulk("foo".to_string()), Value::Integer(1)]));
+    /// ```
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.reader.nonblocking = nonblocking;
+        self.reader.journal.clear();
+    }
+
+    /// Runs an entry point of the decoder. In the nonblocking mode the bytes it consumes are
+    /// recorded, when the reader fails with `WouldBlock` they are replayed by the next call and
+    /// the counts of the decoder are restored.
+    pub(crate) fn resumable<T, F>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        if !self.reader.nonblocking || self.reader.recording {
+            // an entry point run by another one is replayed with it.
+            return f(self);
+        }
+        let (frame_len, bytes_consumed) = (self.frame_len, self.bytes_consumed);
+        self.reader.journal.clear();
+        self.reader.recording = true;
+        let res = f(self);
+        self.reader.recording = false;
+        match res {
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
+                // the value is read again by the next call.
+                self.frame_len = frame_len;
+                self.bytes_consumed = bytes_consumed;
+                self.reader.rewind();
+                Err(Error::new(ErrorKind::WouldBlock, err.to_string()))
+            }
+            res => {
+                self.reader.journal.clear();
+                res
+            }
+        }
+    }
+
+    /// Fails with `InvalidInput` in the nonblocking mode, for the entry points that write the
+    /// value as they read it and so can't replay it.
+    pub(crate) fn check_blocking(&self, name: &str) -> Result<()> {
+        if self.reader.nonblocking {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("{} can't resume a value in the nonblocking mode",
+                                          name)));
+        }
+        Ok(())
+    }
+
+    /// Sets a hook that transforms bulk payloads before they are decoded, see `encode_with_hook`.
+    pub fn set_bulk_hook(&mut self, hook: Box<dyn BulkHook + Send>) {
+        self.hook = Some(Hook(hook));
+    }
+
+    /// Sets whether values of an unknown type are decoded to `Value::Unknown` instead of failing.
+    /// An unknown value is its first line, plus, if the line holds a non-negative integer,
+    /// a payload of that many bytes and CRLF, as bulk strings have. It is re-encoded
+    /// byte-identically, so a proxy can relay types newer than this crate.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"=8\r\ntxt:Some\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_passthrough_unknown(true);
+    /// let value = decoder.decode().unwrap();
+    /// assert_eq!(value, Value::Unknown(buf.to_vec()));
+    /// assert_eq!(value.encode(), buf);
+    /// ```
+    pub fn set_passthrough_unknown(&mut self, passthrough: bool) {
+        self.options.passthrough_unknown = passthrough;
+    }
+
+    /// Sets whether the RESP3 attributes preceding a value are kept, the value is then decoded
+    /// to `Value::Attribute`. By default attributes are discarded.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"|1\r\n+ttl\r\n:3600\r\n+OK\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_keep_attributes(true);
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Attribute(vec![(Value::String("ttl".to_string()), Value::Integer(3600))],
+    ///                             Box::new(Value::String("OK".to_string()))));
+    /// ```
+    pub fn set_keep_attributes(&mut self, keep: bool) {
+        self.options.keep_attributes = keep;
+    }
+
+    /// Sets whether a bulk string that is not valid UTF-8 is decoded to `Value::BufBulk` instead
+    /// of failing with `InvalidData`, the valid ones are still decoded to `Value::Bulk`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"*2\r\n$3\r\nfoo\r\n$2\r\n\xff\xfe\r\n";
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// assert!(decoder.decode().is_err());
+    ///
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// decoder.set_buf_bulk_fallback(true);
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("foo".to_string()),
+    ///                              Value::BufBulk(vec![0xff, 0xfe])]));
+    /// ```
+    pub fn set_buf_bulk_fallback(&mut self, fallback: bool) {
+        self.options.buf_bulk_fallback = fallback;
+    }
+
+    /// Sets whether bulk strings of at most `SMALL_BULK_CAPACITY` bytes are decoded to
+    /// `Value::SmallBulk`, stored inline without a heap allocation. It saves allocator work when
+    /// most bulks are short, like keys and field names. Not valid UTF-8 ones still fail unless
+    /// the decoder falls back to `BufBulk`, see `Decoder::set_buf_bulk_fallback`. By default
+    /// bulks are decoded to `Value::Bulk`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, SmallBulk, Value};
+    /// let buf = b"*2\r\n$3\r\nkey\r\n$40\r\n0123456789012345678901234567890123456789\r\n";
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// decoder.set_small_bulks(true);
+    /// let value = decoder.decode().unwrap();
+    /// assert_eq!(value[0], Value::SmallBulk(SmallBulk::new(b"key").unwrap()));
+    /// assert_eq!(value[1].as_str(), Some("0123456789012345678901234567890123456789"));
+    /// ```
+    pub fn set_small_bulks(&mut self, small: bool) {
+        self.options.small_bulks = small;
+    }
+
+    /// Sets whether lines may end with a bare LF instead of CRLF, as sent by some sloppy clients
+    /// and telnet sessions. Bulk payloads still need their trailing CRLF. By default lines must
+    /// end with CRLF.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"*2\n$4\r\nPING\r\n:1\n";
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// assert!(decoder.decode().is_err());
+    ///
+    /// let mut decoder = Decoder::new(&buf[..]);
+    /// decoder.set_lenient_line_endings(true);
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("PING".to_string()), Value::Integer(1)]));
+    /// ```
+    pub fn set_lenient_line_endings(&mut self, lenient: bool) {
+        self.options.lenient_lf = lenient;
+    }
+
+    /// Sets whether lengths must be written the way Redis writes them, rejecting a sign,
+    /// leading zeros or whitespace with `InvalidInput`, e.g. for the conformance testing of
+    /// clients. By default any integer is accepted.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"$003\r\nfoo\r\n"[..]);
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("foo".to_string()));
+    ///
+    /// let mut decoder = Decoder::new(&b"$003\r\nfoo\r\n"[..]);
+    /// decoder.set_strict_lengths(true);
+    /// assert!(decoder.decode().is_err());
+    /// ```
+    pub fn set_strict_lengths(&mut self, strict: bool) {
+        self.options.strict_lengths = strict;
+    }
+
+    /// Sets whether inline commands are accepted, as a Redis server does for telnet clients.
+    /// A top-level line that doesn't start with `*` is then split into arguments with the
+    /// quoting rules of Redis, and decoded to an array of bulk strings. Empty lines are
+    /// skipped, the line may end with a bare LF. By default inline commands are not accepted.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"SET foo \"hello world\"\r\n\r\n*1\r\n$4\r\nPING\r\n"[..]);
+    /// decoder.set_inline_commands(true);
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("SET".to_string()),
+    ///                              Value::Bulk("foo".to_string()),
+    ///                              Value::Bulk("hello world".to_string())]));
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("PING".to_string())]));
+    /// ```
+    pub fn set_inline_commands(&mut self, inline: bool) {
+        self.options.inline_commands = inline;
+    }
+
+    /// Pins the protocol version. With `Version::Resp2` the RESP3 types, streamed strings and
+    /// aggregates included, fail with `InvalidInput` even in passthrough mode. With
+    /// `Version::Resp3`, as by default, every type is accepted.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value, Version};
+    /// let buf = b"%1\r\n+a\r\n:1\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_version(Version::Resp2);
+    /// assert_eq!(decoder.decode().unwrap_err().to_string(), "RESP3 type in RESP2: \"%1\"");
+    ///
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_version(Version::Resp3);
+    /// assert!(decoder.decode().is_ok());
+    /// ```
+    pub fn set_version(&mut self, version: Version) {
+        self.options.version = Some(version);
+    }
+
+    /// Sets the max length of a bulk string, as the `proto-max-bulk-len` of Redis. A longer bulk
+    /// string fails with `InvalidInput` before its payload is read. The total length of a
+    /// streamed bulk string is checked as its chunks arrive, and so is the payload of a value of
+    /// an unknown type, see `Decoder::set_passthrough_unknown`. By default the max is the
+    /// protocol max of 512MB.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"$3\r\nfoo\r\n$6\r\nfoobar\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_max_bulk_len(5);
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("foo".to_string()));
+    /// assert_eq!(decoder.decode().unwrap_err().to_string(),
+    ///            "bulk length 6 exceeds max bulk length 5");
+    /// ```
+    pub fn set_max_bulk_len(&mut self, max: usize) {
+        self.options.max_bulk_len = Some(max);
+    }
+
+    /// Sets the max number of elements of an aggregate, as Redis limits the length of a
+    /// multibulk request. An aggregate with more elements fails with `InvalidInput` as soon as
+    /// its header, or for a streamed aggregate its extra element, is read. A map counts its
+    /// entries. By default the max is the protocol max.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"*1000000\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_max_array_len(1024);
+    /// assert_eq!(decoder.decode().unwrap_err().to_string(),
+    ///            "array length 1000000 exceeds max array length 1024");
+    /// ```
+    pub fn set_max_array_len(&mut self, max: usize) {
+        self.options.max_array_len = Some(max);
+    }
+
+    /// Sets the max number of bytes of a top-level value, its lines and payloads, nested values
+    /// included. A value that would read more fails with `InvalidInput`, a bulk string before
+    /// its payload is read. By default there is no max.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"+OK\r\n*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_max_frame_len(20);
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+    /// assert_eq!(decoder.decode().unwrap_err().to_string(),
+    ///            "frame length 22 exceeds max frame length 20");
+    /// ```
+    pub fn set_max_frame_len(&mut self, max: usize) {
+        self.options.max_frame_len = Some(max);
+    }
+
+    /// Sets the max length of a line, its CRLF excluded: simple strings, errors, the other
+    /// single line values, the headers and the inline commands. A longer line fails with
+    /// `InvalidInput` as soon as its bytes are read, so a peer can't make the decoder buffer
+    /// an endless line. Redis caps inline commands at 64KB. By default there is no max.
+    /// # Examples
+    /// ```
+    /// # use std::io::BufReader;
+    /// # use self::resp::{Decoder, Value};
+    /// let buf = b"+OK\r\n+ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    /// let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+    /// decoder.set_max_line_len(16);
+    /// assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+    /// assert_eq!(decoder.decode().unwrap_err().to_string(),
+    ///            "line length 27 exceeds max line length 16");
+    /// ```
+    pub fn set_max_line_len(&mut self, max: usize) {
+        self.options.max_line_len = Some(max);
+    }
+
+    /// It will read buffers from the inner BufReader, decode it to a Value.
+    /// If the reader is closed, before or in the middle of a value, it fails with
+    /// `UnexpectedEof`, other errors mean invalid data or an IO failure.
+    /// Aggregates are tracked on an explicit stack, deeply nested values don't grow the call
+    /// stack.
+    pub fn decode(&mut self) -> Result<Value> {
+        self.resumable(Decoder::decode_value)
+    }
+
+    fn decode_value(&mut self) -> Result<Value> {
+        loop {
+            self.frame_len = 0;
+            self.state = DecoderState::idle();
+            if self.options.inline_commands && self.reader.fill_buf()?.first() != Some(&b'*') {
+                match self.decode_inline()? {
+                    Some(value) => {
+                        self.count_frame(FrameType::Array);
+                        return Ok(value);
+                    }
+                    None => continue,
+                }
+            }
+            let line = self.read_line()?;
+            let frame_type = FrameType::from_prefix(line[0]);
+            let value = self.decode_from(line)?;
+            self.count_frame(frame_type);
+            return Ok(value);
+        }
+    }
+
+    fn count_frame(&mut self, frame_type: FrameType) {
+        self.frames_decoded += 1;
+        *self.type_counts.entry(frame_type.prefix()).or_insert(0) += 1;
+    }
+
+    /// Returns the number of bytes consumed from the reader by the decoder, whether the values
+    /// were decoded, skipped or relayed.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, FrameType};
+    /// let mut decoder = Decoder::new(&b"+OK\r\n*1\r\n:1\r\n+OK\r\n"[..]);
+    /// decoder.decode_all().unwrap();
+    /// assert_eq!(decoder.bytes_consumed(), 18);
+    /// assert_eq!(decoder.frames_decoded(), 3);
+    /// assert_eq!(decoder.type_count(FrameType::String), 2);
+    /// assert_eq!(decoder.type_count(FrameType::Array), 1);
+    /// assert_eq!(decoder.type_count(FrameType::Integer), 0);
+    /// ```
+    pub fn bytes_consumed(&self) -> u64 {
+        self.bytes_consumed
+    }
+
+    /// Returns the number of values decoded by `Decoder::decode`.
+    pub fn frames_decoded(&self) -> u64 {
+        self.frames_decoded
+    }
+
+    /// Returns the number of values of a type decoded by `Decoder::decode`, the elements of
+    /// aggregates are not counted. Inline commands count as arrays.
+    pub fn type_count(&self, frame_type: FrameType) -> u64 {
+        self.type_counts.get(&frame_type.prefix()).cloned().unwrap_or(0)
+    }
+
+    /// Decodes an inline command to an array of bulk strings, `None` for an empty line.
+    fn decode_inline(&mut self) -> Result<Option<Value>> {
+        let mut line = mem::take(&mut self.line);
+        line.clear();
+        self.read_until_lf(&mut line)?;
+        if line.last() != Some(&b'\n') {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+        }
+        self.consume(line.len())?;
+        let args = split_inline(&line)?;
+        self.line = line;
+        if args.is_empty() {
+            return Ok(None);
+        }
+        let args = args.into_iter()
+            .map(|arg| bulk_value(arg, &self.options))
+            .collect::<Result<Vec<Value>>>()?;
+        Ok(Some(Value::Array(args)))
+    }
+
+    /// Decodes a value whose first line has been read.
+    fn decode_from(&mut self, first: Vec<u8>) -> Result<Value> {
+        let mut first = Some(first);
+        let mut stack: Vec<(Aggregate, Option<usize>, Vec<Value>)> = Vec::new();
+        loop {
+            let line = match first.take() {
+                Some(line) => line,
+                None => self.read_line()?,
+            };
+            let streamed = matches!(stack.last(), Some(&(_, None, _)));
+            let leaf = if streamed && line == STREAMED_END_BYTES {
+                None
+            } else {
+                Some(self.decode_line(&line))
+            };
+            self.line = line;
+            let mut value = match leaf {
+                None => {
+                    let (aggregate, _, array) = stack.pop().unwrap();
+                    self.state.aggregates.pop();
+                    aggregate.build_streamed(array, &self.options)?
+                }
+                Some(leaf) => match leaf? {
+                    Leaf::Value(value) => value,
+                    Leaf::Aggregate(aggregate, len) => {
+                        aggregate.check_len(len, 0, &self.options)?;
+                        let values_len = len.map(|len| aggregate.values_len(len));
+                        if values_len == Some(0) {
+                            aggregate.build(Vec::new(), &self.options)
+                        } else {
+                            let capacity = min(values_len.unwrap_or(0), 1024);
+                            stack.push((aggregate, len, Vec::with_capacity(capacity)));
+                            self.enter_aggregate(aggregate, values_len);
+                            continue;
+                        }
+                    }
+                },
+            };
+
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(value),
+                    Some(&mut (aggregate, len, ref mut array)) => {
+                        array.push(value);
+                        count_element(&mut self.state);
+                        match len {
+                            Some(len) if array.len() >= aggregate.values_len(len) => {}
+                            Some(_) => break,
+                            None => {
+                                aggregate.check_len(None, array.len(), &self.options)?;
+                                break;
+                            }
+                        }
+                    }
+                }
+                let (aggregate, _, array) = stack.pop().unwrap();
+                self.state.aggregates.pop();
+                value = aggregate.build(array, &self.options);
+            }
+        }
+    }
+
+    /// Decodes the values up to the end of the reader, e.g. a batch of pipelined commands.
+    /// Fails with `UnexpectedEof` if the reader ends in the middle of a value. In the
+    /// nonblocking mode, a `WouldBlock` after some values returns them, the value it
+    /// interrupted is decoded by the next call.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"+OK\r\n:1\r\n"[..]);
+    /// assert_eq!(decoder.decode_all().unwrap(),
+    ///            vec![Value::String("OK".to_string()), Value::Integer(1)]);
+    /// assert_eq!(decoder.decode_all().unwrap(), vec![]);
+    /// ```
+    pub fn decode_all(&mut self) -> Result<Vec<Value>> {
+        let mut values: Vec<Value> = Vec::new();
+        loop {
+            let res = match self.reader.fill_buf() {
+                Ok(&[]) => return Ok(values),
+                Ok(_) => self.decode(),
+                Err(err) => Err(err),
+            };
+            match res {
+                Ok(value) => values.push(value),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock && !values.is_empty() => {
+                    return Ok(values);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns the type of the next value without consuming it, so the caller can choose to
+    /// decode, skip or relay it. Fails with `UnexpectedEof` if the reader is closed.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, FrameType, Value};
+    /// let mut decoder = Decoder::new(&b"$3\r\nfoo\r\n"[..]);
+    /// assert_eq!(decoder.peek_type().unwrap(), FrameType::Bulk);
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("foo".to_string()));
+    /// assert!(decoder.peek_type().is_err());
+    /// ```
+    pub fn peek_type(&mut self) -> Result<FrameType> {
+        match self.reader.fill_buf()?.first() {
+            Some(&prefix) => Ok(FrameType::from_prefix(prefix)),
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF")),
+        }
+    }
+
+    /// Consumes the next value and discards it, without decoding it: the payloads of bulk
+    /// strings are skipped, the elements of aggregates are only counted. The value is still
+    /// checked as `Decoder::decode` does.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"*2\r\n$3\r\nfoo\r\n%1\r\n+a\r\n:1\r\n$3\r\nbar\r\n"[..]);
+    /// decoder.skip_value().unwrap();
+    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("bar".to_string()));
+    /// ```
+    pub fn skip_value(&mut self) -> Result<()> {
+        self.resumable(Decoder::skip_frame)
+    }
+
+    fn skip_frame(&mut self) -> Result<()> {
+        if self.options.inline_commands && self.reader.fill_buf()?.first() != Some(&b'*') {
+            return self.decode_value().map(|_| ());
+        }
+        self.frame_len = 0;
+        self.state = DecoderState::idle();
+        let mut stack: Vec<(Aggregate, Option<usize>, usize)> = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            let streamed = matches!(stack.last(), Some(&(_, None, _)));
+            let header = if streamed && line == STREAMED_END_BYTES {
+                let (aggregate, _, values) = stack.pop().unwrap();
+                self.state.aggregates.pop();
+                aggregate.check_streamed(values)?;
+                None
+            } else {
+                self.skip_line(&line)?
+            };
+            self.line = line;
+            if let Some((aggregate, len)) = header {
+                aggregate.check_len(len, 0, &self.options)?;
+                let values_len = len.map(|len| aggregate.values_len(len));
+                if values_len != Some(0) {
+                    stack.push((aggregate, len, 0));
+                    self.enter_aggregate(aggregate, values_len);
+                    continue;
+                }
+            }
+
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(()),
+                    Some(&mut (aggregate, len, ref mut values)) => {
+                        *values += 1;
+                        count_element(&mut self.state);
+                        match len {
+                            Some(len) if *values >= aggregate.values_len(len) => {}
+                            Some(_) => break,
+                            None => {
+                                aggregate.check_len(None, *values, &self.options)?;
+                                break;
+                            }
+                        }
+                    }
+                }
+                stack.pop();
+                self.state.aggregates.pop();
+            }
+        }
+    }
+
+    /// Tracks an aggregate whose `values_len` values are being decoded, see `Decoder::state`.
+    fn enter_aggregate(&mut self, aggregate: Aggregate, values_len: Option<usize>) {
+        self.state.aggregates.push((FrameType::from_prefix(aggregate.prefix()), values_len));
+    }
+
+    /// Skips the rest of a value whose first line has been read, returns the header of an
+    /// aggregate, whose elements follow.
+    fn skip_line(&mut self, line: &[u8]) -> Result<Option<(Aggregate, Option<usize>)>> {
+        // simple strings and errors, the most common lines, are valid as they are.
+        if line[0] == b'+' || line[0] == b'-' {
+            return Ok(None);
+        }
+        match parse_line(line, &self.options)? {
+            Line::Value(_) | Line::Unknown(None) => {}
+            Line::Bulk(int) | Line::Unknown(Some(int)) => self.skip_payload(int)?,
+            Line::StreamedBulk => {
+                let mut total = 0;
+                loop {
+                    let line = self.read_line()?;
+                    let int = parse_chunk_header(&line, &self.options)?;
+                    self.line = line;
+                    if int == 0 {
+                        break;
+                    }
+                    check_streamed_len(total, int, &self.options)?;
+                    self.skip_payload(int)?;
+                    total += int;
+                }
+            }
+            Line::Aggregate(aggregate, int) => return Ok(Some((aggregate, Some(int)))),
+            Line::StreamedAggregate(aggregate) => return Ok(Some((aggregate, None))),
+        }
+        Ok(None)
+    }
+
+    /// Decodes the next value, which must be a bulk string, writing its payload to the writer
+    /// instead of buffering it, so a large payload can be spooled to a file. Returns the length
+    /// of the payload, `None` for a null bulk string. Streamed bulk strings are written chunk by
+    /// chunk. The limits of the decoder apply, the bulk hook doesn't.
+    /// Other values fail with `InvalidInput` and are not consumed.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let mut decoder = Decoder::new(&b"$5\r\nvalue\r\n$-1\r\n:1\r\n"[..]);
+    /// let mut file: Vec<u8> = Vec::new();
+    /// assert_eq!(decoder.decode_bulk_to(&mut file).unwrap(), Some(5));
+    /// assert_eq!(file, b"value");
+    /// assert_eq!(decoder.decode_bulk_to(&mut file).unwrap(), None);
+    /// assert!(decoder.decode_bulk_to(&mut file).is_err());
+    /// assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+    /// ```
+    pub fn decode_bulk_to<W: Write>(&mut self, writer: &mut W) -> Result<Option<usize>> {
+        self.check_blocking("decode_bulk_to")?;
+        let frame_type = self.peek_type()?;
+        if frame_type != FrameType::Bulk {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("expected a bulk string, found {:?}", frame_type)));
+        }
+        self.frame_len = 0;
+        let line = self.read_line()?;
+        let len = match parse_line(&line, &self.options)? {
+            Line::Bulk(int) => {
+                self.write_payload(int, writer)?;
+                Some(int)
+            }
+            Line::StreamedBulk => {
+                let mut total = 0;
+                loop {
+                    let chunk = self.read_line()?;
+                    let int = parse_chunk_header(&chunk, &self.options)?;
+                    self.line = chunk;
+                    if int == 0 {
+                        break;
+                    }
+                    check_streamed_len(total, int, &self.options)?;
+                    self.write_payload(int, writer)?;
+                    total += int;
+                }
+                Some(total)
+            }
+            _ => None,
+        };
+        self.line = line;
+        Ok(len)
+    }
+
+    /// Skips a payload of `int` bytes and its trailing CRLF.
+    fn skip_payload(&mut self, int: usize) -> Result<()> {
+        self.write_payload(int, &mut io::sink())
+    }
+
+    /// Writes a payload of `int` bytes to the writer as it is read, then checks its trailing
+    /// CRLF.
+    fn write_payload<W: Write>(&mut self, int: usize, writer: &mut W) -> Result<()> {
+        self.consume(int + 2)?;
+        let mut remaining = int;
+        while remaining > 0 {
+            self.state.awaiting = Awaiting::Payload(remaining + 2);
+            let n = {
+                let buf = self.reader.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+                }
+                let n = min(buf.len(), remaining);
+                writer.write_all(&buf[..n])?;
+                n
+            };
+            self.reader.consume(n);
+            remaining -= n;
+        }
+        self.state.awaiting = Awaiting::Payload(2);
+        let mut crlf = [0u8; 2];
+        self.reader.read_exact(&mut crlf)?;
+        if !is_crlf(crlf[0], crlf[1]) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", crlf)));
+        }
+        self.state.awaiting = Awaiting::Value;
+        Ok(())
+    }
+
+    /// Reads a CRLF terminated line, the line includes the CRLF.
+    /// The line is read into the line buffer, which should be put back once the line is used.
+    /// Fails with `UnexpectedEof` if the reader is closed before the end of the line.
+    pub(crate) fn read_line(&mut self) -> Result<Vec<u8>> {
+        let mut res = mem::take(&mut self.line);
+        res.clear();
+        let read = self.read_until_lf(&mut res);
+        if read.is_err() || res.last() != Some(&b'\n') {
+            self.state.awaiting = match res.len() {
+                0 => Awaiting::Value,
+                len => Awaiting::Line(len),
+            };
+            read?;
+            return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+        }
+        self.state.awaiting = Awaiting::Value;
+        self.consume(res.len())?;
+        let len = res.len();
+        if self.options.lenient_lf && (len < 2 || res[len - 2] != b'\r') {
+            res.insert(len - 1, b'\r');
+        }
+        check_line(&res)?;
+        Ok(res)
+    }
+
+    /// Reads up to and including the next `\n` into the buffer, or up to the end of the reader.
+    /// The buffered bytes are scanned with `memchr`.
+    fn read_until_lf(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        loop {
+            let (done, used) = {
+                let available = match self.reader.fill_buf() {
+                    Ok(available) => available,
+                    Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                match memchr(b'\n', available) {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..i + 1]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            self.reader.consume(used);
+            check_max_line_len(buf, &self.options)?;
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Counts `len` more bytes read of the value being decoded.
+    pub(crate) fn consume(&mut self, len: usize) -> Result<()> {
+        self.frame_len += len;
+        self.bytes_consumed += len as u64;
+        check_max_frame_len(self.frame_len, &self.options)
+    }
+
+    /// Decodes the rest of a value whose first line has been read, except the elements of an
+    /// aggregate.
+    fn decode_line(&mut self, res: &[u8]) -> Result<Leaf> {
+        let value = match parse_line(res, &self.options)? {
+            Line::Value(value) => value,
+            Line::Bulk(int) if self.hook.is_some() => {
+                self.consume(int + 2)?;
+                bulk_value(self.decode_with_hook(Some(int))?, &self.options)?
+            }
+            Line::Bulk(int) => {
+                if self.options.small_bulks && int <= SMALL_BULK_CAPACITY {
+                    let mut payload = [0u8; SMALL_BULK_CAPACITY + 2];
+                    self.read_small_payload(&mut payload[..int + 2])?;
+                    let value = bulk_slice_value(&payload[..int], &self.options)?;
+                    return Ok(Leaf::Value(value));
+                }
+                bulk_value(self.read_payload(int)?, &self.options)?
+            }
+            Line::StreamedBulk if self.hook.is_some() => {
+                bulk_value(self.decode_with_hook(None)?, &self.options)?
+            }
+            Line::StreamedBulk => {
+                let mut buf: Vec<u8> = Vec::new();
+                loop {
+                    let line = self.read_line()?;
+                    let int = parse_chunk_header(&line, &self.options)?;
+                    self.line = line;
+                    if int == 0 {
+                        break;
+                    }
+                    check_streamed_len(buf.len(), int, &self.options)?;
+                    buf.extend_from_slice(&self.read_payload(int)?);
+                }
+                bulk_value(buf, &self.options)?
+            }
+            Line::Aggregate(aggregate, int) => return Ok(Leaf::Aggregate(aggregate, Some(int))),
+            Line::StreamedAggregate(aggregate) => return Ok(Leaf::Aggregate(aggregate, None)),
+            Line::Unknown(int) => {
+                let mut raw = res.to_vec();
+                if let Some(int) = int {
+                    raw.extend_from_slice(&self.read_payload(int)?);
+                    raw.extend_from_slice(CRLF_BYTES);
+                }
+                Value::Unknown(raw)
+            }
+        };
+        Ok(Leaf::Value(value))
+    }
+
+    /// Restores a payload with the bulk hook, `len` is the length of the payload, whose bytes
+    /// are consumed already, `None` for a streamed bulk string. The hook reads the payload
+    /// through a `BulkReader`, so a streamed one isn't joined first.
+    fn decode_with_hook(&mut self, len: Option<usize>) -> Result<Vec<u8>> {
+        let mut hook = match self.hook.take() {
+            Some(hook) => hook,
+            None => return Err(Error::other("no bulk hook")),
+        };
+        let mut output = HookOutput {
+            buf: Vec::new(),
+            options: self.options.clone(),
+        };
+        let res = {
+            let mut input = BulkReader::new(self, len);
+            hook.0.decode(len, &mut input, &mut output).and_then(|_| {
+                match io::copy(&mut input, &mut io::sink())? {
+                    0 => Ok(()),
+                    left => {
+                        Err(Error::new(ErrorKind::InvalidData,
+                                       format!("bulk hook left {} bytes of the payload unread",
+                                               left)))
+                    }
+                }
+            })
+        };
+        self.hook = Some(hook);
+        res.map(|_| output.buf)
+    }
+
+    /// Reads a short payload and its trailing CRLF into `buf` without allocating.
+    fn read_small_payload(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.consume(buf.len())?;
+        self.state.awaiting = Awaiting::Payload(buf.len());
+        self.reader.read_exact(buf).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"),
+            _ => err,
+        })?;
+        self.state.awaiting = Awaiting::Value;
+        let int = buf.len() - 2;
+        if !is_crlf(buf[int], buf[int + 1]) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", buf)));
+        }
+        Ok(())
+    }
+
+    /// Reads a payload of `int` bytes and its trailing CRLF, returns the payload.
+    /// The payload is read into spare capacity, the buffer isn't zeroed first.
+    fn read_payload(&mut self, int: usize) -> Result<Vec<u8>> {
+        self.consume(int + 2)?;
+        let mut buf: Vec<u8> = Vec::with_capacity(int + 2);
+        let read = (&mut self.reader).take(int as u64 + 2).read_to_end(&mut buf);
+        if read.is_err() || buf.len() < int + 2 {
+            // the bytes read before an error are kept in the buffer.
+            self.state.awaiting = Awaiting::Payload(int + 2 - buf.len());
+            read?;
+            return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
+        }
+        self.state.awaiting = Awaiting::Value;
+        if !is_crlf(buf[int], buf[int + 1]) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", buf)));
+        }
+        buf.truncate(int);
+        Ok(buf)
+    }
+}
+
+/// Counts a value decoded, an element of the innermost aggregate of the state.
+fn count_element(state: &mut DecoderState) {
+    if let Some(&mut (_, Some(ref mut remaining))) = state.aggregates.last_mut() {
+        *remaining -= 1;
+    }
+}
+
+/// The reader of a decoder, it can record the bytes consumed and replay them,
+/// see `Decoder::set_nonblocking`.
+#[derive(Debug)]
+pub(crate) struct Replay<R> {
+    inner: R,
+    /// whether the decoder reads from a non-blocking reader.
+    nonblocking: bool,
+    /// whether the consumed bytes are recorded to the journal, only while an entry point of a
+    /// nonblocking decoder runs.
+    recording: bool,
+    journal: Vec<u8>,
+    /// the bytes to read before the inner reader, from `pos`.
+    replay: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Replay<R> {
+    fn new(inner: R) -> Self {
+        Replay {
+            inner,
+            nonblocking: false,
+            recording: false,
+            journal: Vec::new(),
+            replay: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Replays the recorded bytes before the bytes not read yet.
+    fn rewind(&mut self) {
+        let mut replay = mem::take(&mut self.journal);
+        replay.extend_from_slice(&self.replay[self.pos..]);
+        self.replay = replay;
+        self.pos = 0;
+    }
+}
+
+impl<R: BufRead> Read for Replay<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.recording && self.pos == self.replay.len() {
+            return self.inner.read(buf);
+        }
+        let n = {
+            let data = self.fill_buf()?;
+            let n = min(buf.len(), data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Replay<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos < self.replay.len() {
+            return Ok(&self.replay[self.pos..]);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.replay.len() {
+            if self.recording {
+                self.journal.extend_from_slice(&self.replay[self.pos..self.pos + amt]);
+            }
+            self.pos += amt;
+            if self.pos == self.replay.len() {
+                self.replay.clear();
+                self.pos = 0;
+            }
+            return;
+        }
+        if self.recording {
+            // the bytes consumed are still buffered, `fill_buf` returns them without reading.
+            if let Ok(buf) = self.inner.fill_buf() {
+                self.journal.extend_from_slice(&buf[..amt]);
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// A reader over an iterator of byte chunks, see `Decoder::from_chunks`.
+#[derive(Debug)]
+pub struct ChunkReader<I: Iterator> {
+    chunks: I,
+    chunk: Option<I::Item>,
+    offset: usize,
+}
+
+impl<I> Read for ChunkReader<I>
+    where I: Iterator,
+          I::Item: AsRef<[u8]>
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = {
+            let chunk = self.fill_buf()?;
+            let n = min(buf.len(), chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<I> BufRead for ChunkReader<I>
+    where I: Iterator,
+          I::Item: AsRef<[u8]>
+{
+    /// Returns the rest of the current chunk, chunks are not copied into another buffer.
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        while self.chunk.as_ref().map_or(true, |chunk| self.offset >= chunk.as_ref().len()) {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.chunk = Some(chunk);
+                    self.offset = 0;
+                }
+                None => return Ok(&[]),
+            }
+        }
+        Ok(&self.chunk.as_ref().unwrap().as_ref()[self.offset..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.offset += amt;
+    }
+}
+
+impl<I> Decoder<ChunkReader<I>>
+    where I: Iterator,
+          I::Item: AsRef<[u8]>
+{
+    /// Creates a Decoder instance reading from an iterator of byte chunks,
+    /// values may span chunk boundaries.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Decoder, Value};
+    /// let chunks = vec!["*2\r\n$3\r\nfo", "o\r\n", ":1\r", "\n"];
+    /// let mut decoder = Decoder::from_chunks(chunks);
+    /// assert_eq!(decoder.decode().unwrap(),
+    ///            Value::Array(vec![Value::Bulk("foo".to_string()), Value::Integer(1)]));
+    /// ```
+    pub fn from_chunks<T>(chunks: T) -> Self
+        where T: IntoIterator<IntoIter = I, Item = I::Item>
+    {
+        Decoder::new(ChunkReader {
+                         chunks: chunks.into_iter(),
+                         chunk: None,
+                         offset: 0,
+                     })
+    }
+}
+
+/// Relays the next value from the decoder to the writer, returns the number of bytes written.
+/// The value is relayed byte for byte by `relay_frame`: each header is copied as it is read and
+/// each bulk payload, with its trailing CRLF, is copied through a small fixed buffer, so relaying
+/// a command with a large argument costs no more memory than relaying a small one.
+/// The bytes are written in blocks of 8KB: a value that fits in a block is checked completely
+/// before any of it is written, a longer one is written as it arrives and may be cut short by
+/// an error.
+/// # Examples
+/// ```
+/// # use self::resp::{Decoder, Value, relay_bulk};
+///
+/// let buf = Value::Bulk("Hello".to_string()).encode();
+/// let mut decoder = Decoder::new(buf.as_slice());
+/// let mut output: Vec<u8> = Vec::new();
+/// assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap(), 11);
+/// assert_eq!(output, buf);
+/// ```
+pub fn relay_bulk<R: BufRead, W: Write>(decoder: &mut Decoder<R>, writer: &mut W) -> Result<usize> {
+    let mut buffered = io::BufWriter::with_capacity(RELAY_BUF_SIZE, writer);
+    match relay_frame(decoder, &mut buffered) {
+        Ok(len) => {
+            buffered.flush()?;
+            Ok(len)
+        }
+        Err(err) => {
+            // the bytes of the invalid value not written yet are dropped.
+            let _ = buffered.into_parts();
+            Err(err)
+        }
+    }
+}
+
+/// Relays the next value from the decoder to the writer byte for byte, returns the number of
+/// bytes written. Only the framing of the value is checked, as `check_complete` does, so the
+/// value is never decoded: lines are written as they are read and bulk payloads are copied
+/// through a small fixed buffer. The limits of the decoder apply.
+/// # Examples
+/// ```
+/// # use self::resp::{Decoder, relay_frame};
+/// let buf = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n:1\r\n";
+/// let mut decoder = Decoder::new(&buf[..]);
+/// let mut output: Vec<u8> = Vec::new();
+/// assert_eq!(relay_frame(&mut decoder, &mut output).unwrap(), 22);
+/// assert_eq!(output, &buf[..22]);
+/// ```
+pub fn relay_frame<R: BufRead, W: Write>(decoder: &mut Decoder<R>,
+                                         writer: &mut W)
+                                         -> Result<usize> {
+    decoder.check_blocking("relay_frame")?;
+    decoder.frame_len = 0;
+    let mut written = 0;
+    let mut stack: Vec<(Aggregate, Option<usize>, usize)> = Vec::new();
+    loop {
+        let line = decoder.read_line()?;
+        let streamed = matches!(stack.last(), Some(&(_, None, _)));
+        let header = if streamed && line == STREAMED_END_BYTES {
+            let (aggregate, _, values) = stack.pop().unwrap();
+            aggregate.check_streamed(values)?;
+            writer.write_all(&line)?;
+            None
+        } else {
+            let framing = parse_frame_line(&line, &decoder.options)?;
+            writer.write_all(&line)?;
+            match framing {
+                None | Some(Line::Value(_)) | Some(Line::Unknown(None)) => None,
+                Some(Line::Bulk(int)) | Some(Line::Unknown(Some(int))) => {
+                    relay_payload(decoder, writer, int)?;
+                    written += int + 2;
+                    None
+                }
+                Some(Line::StreamedBulk) => {
+                    let mut total = 0;
+                    loop {
+                        let chunk = decoder.read_line()?;
+                        let int = parse_chunk_header(&chunk, &decoder.options)?;
+                        writer.write_all(&chunk)?;
+                        written += chunk.len();
+                        if int == 0 {
+                            break;
+                        }
+                        check_streamed_len(total, int, &decoder.options)?;
+                        relay_payload(decoder, writer, int)?;
+                        written += int + 2;
+                        total += int;
+                    }
+                    None
+                }
+                Some(Line::Aggregate(aggregate, int)) => Some((aggregate, Some(int))),
+                Some(Line::StreamedAggregate(aggregate)) => Some((aggregate, None)),
+            }
+        };
+        written += line.len();
+        decoder.line = line;
+        if let Some((aggregate, len)) = header {
+            aggregate.check_len(len, 0, &decoder.options)?;
+            if len.map(|len| aggregate.values_len(len)) != Some(0) {
+                stack.push((aggregate, len, 0));
+                continue;
+            }
+        }
+
+        loop {
+            match stack.last_mut() {
+                None => return Ok(written),
+                Some(&mut (aggregate, len, ref mut values)) => {
+                    *values += 1;
+                    match len {
+                        Some(len) if *values >= aggregate.values_len(len) => {}
+                        Some(_) => break,
+                        None => {
+                            aggregate.check_len(None, *values, &decoder.options)?;
+                            break;
+                        }
+                    }
+                }
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// Relays a payload of `int` bytes and its trailing CRLF through a fixed buffer.
+fn relay_payload<R: BufRead, W: Write>(decoder: &mut Decoder<R>,
+                                       writer: &mut W,
+                                       int: usize)
+                                       -> Result<()> {
+    decoder.consume(int + 2)?;
+    let mut remaining = int;
+    let mut buf = [0u8; RELAY_BUF_SIZE];
+    while remaining > 0 {
+        let n = std::cmp::min(remaining, RELAY_BUF_SIZE);
+        decoder.reader.read_exact(&mut buf[..n])?;
+        writer.write_all(&buf[..n])?;
+        remaining -= n;
+    }
+    // the trailing CRLF is checked before it is relayed.
+    decoder.reader.read_exact(&mut buf[..2])?;
+    if !is_crlf(buf[0], buf[1]) {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid CRLF: {:?}", &buf[..2])));
+    }
+    writer.write_all(CRLF_BYTES)
+}
+
+/// Appends the content of a single line value, CR and LF are replaced with spaces as Redis does
+/// for error replies, so the content can't end the line early and inject a value.
+pub(crate) fn extend_line(buf: &mut Vec<u8>, val: &str) {
+    let start = buf.len();
+    buf.extend_from_slice(val.as_bytes());
+    for byte in &mut buf[start..] {
+        if *byte == b'\r' || *byte == b'\n' {
+            *byte = b' ';
+        }
+    }
+}
+
+/// Appends a length header to the buffer, e.g. `$5\r\n` for a bulk string of 5 bytes.
+/// # Examples
+/// ```
+/// # use self::resp::encode_len_header;
+/// let mut buf: Vec<u8> = Vec::new();
+/// encode_len_header(b'*', 2, &mut buf);
+/// assert_eq!(buf, b"*2\r\n");
+/// ```
+#[inline]
+pub fn encode_len_header(prefix: u8, len: usize, buf: &mut Vec<u8>) {
+    buf.push(prefix);
+    extend_decimal(buf, len as u64);
+    buf.extend_from_slice(CRLF_BYTES);
+}
+
+/// Returns `true` if the bytes are `\r` and `\n`.
+#[inline]
+pub fn is_crlf(a: u8, b: u8) -> bool {
+    a == b'\r' && b == b'\n'
+}
+
+/// Returns the line without its trailing CRLF, fails with `InvalidInput` if it has none.
+/// # Examples
+/// ```
+/// # use self::resp::strip_crlf;
+/// assert_eq!(strip_crlf(b"+OK\r\n").unwrap(), b"+OK");
+/// assert!(strip_crlf(b"+OK\n").is_err());
+/// ```
+#[inline]
+pub fn strip_crlf(line: &[u8]) -> Result<&[u8]> {
+    let len = line.len();
+    if len < 2 || !is_crlf(line[len - 2], line[len - 1]) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", line)));
+    }
+    Ok(&line[..len - 2])
+}
+
+/// Parses UTF-8 bytes to a String, fails with `InvalidData`.
+#[inline]
+pub fn parse_string(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Parses decimal bytes to an integer, fails with `InvalidData`.
+#[inline]
+pub fn parse_integer(bytes: &[u8]) -> Result<i64> {
+    let str_integer = str::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+    (str_integer.parse::<i64>()).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Parses decimal bytes to a double, `inf`, `-inf` and `nan` included, fails with `InvalidData`.
+/// # Examples
+/// ```
+/// # use self::resp::parse_double;
+/// assert_eq!(parse_double(b"3.25").unwrap(), 3.25);
+/// assert_eq!(parse_double(b"-inf").unwrap(), f64::NEG_INFINITY);
+/// assert!(parse_double(b"nan").unwrap().is_nan());
+/// assert!(parse_double(b"1,5").is_err());
+/// ```
+#[inline]
+pub fn parse_double(bytes: &[u8]) -> Result<f64> {
+    let str_double = parse_string(bytes)?;
+    (str_double.parse::<f64>()).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Parses the decimal bytes of a big number, an optional `-` followed by digits,
+/// fails with `InvalidData`.
+/// # Examples
+/// ```
+/// # use self::resp::parse_big_number;
+/// assert_eq!(parse_big_number(b"-3492890328409238509324850943850943825024385").unwrap(),
+///            "-3492890328409238509324850943850943825024385");
+/// assert!(parse_big_number(b"1.5").is_err());
+/// ```
+pub fn parse_big_number(bytes: &[u8]) -> Result<String> {
+    let digits = if bytes.first() == Some(&b'-') {
+        &bytes[1..]
+    } else {
+        bytes
+    };
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              format!("invalid big number: {:?}",
+                                      String::from_utf8_lossy(bytes))));
+    }
+    parse_string(bytes)
+}
+
+/// Formats a double as in a RESP3 double reply: `inf`, `-inf`, `nan`, or the shortest decimal
+/// representation that parses back to the same value, in exponent notation when it is shorter,
+/// e.g. `1e300`.
+pub(crate) fn format_double(val: f64) -> String {
+    if !val.is_finite() {
+        return format_non_finite_double(val);
+    }
+    let decimal = val.to_string();
+    let exponent = format!("{:e}", val);
+    if exponent.len() < decimal.len() {
+        exponent
+    } else {
+        decimal
+    }
+}
+
+/// Formats a double with a fixed number of decimals, `inf`, `-inf` and `nan` are kept.
+fn format_double_fixed(val: f64, precision: usize) -> String {
+    if !val.is_finite() {
+        return format_non_finite_double(val);
+    }
+    format!("{:.*}", precision, val)
+}
+
+fn format_non_finite_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else if val > 0.0 {
+        "inf".to_string()
+    } else {
+        "-inf".to_string()
+    }
+}
+
+/// Parses a line holding an integer after its type prefix, e.g. `:1000\r\n` or `*2\r\n`.
+/// The prefix is not checked.
+/// # Examples
+/// ```
+/// # use self::resp::parse_integer_line;
+/// assert_eq!(parse_integer_line(b":-1000\r\n").unwrap(), -1000);
+/// assert!(parse_integer_line(b":1000").is_err());
+/// ```
+#[inline]
+pub fn parse_integer_line(line: &[u8]) -> Result<i64> {
+    let bytes = strip_crlf(line)?;
+    if bytes.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("too short: {}", line.len())));
+    }
+    parse_integer(&bytes[1..])
+}
+
+/// Parses a bulk string header line, returns the bulk length, or `None` for a null bulk.
+/// # Examples
+/// ```
+/// # use self::resp::parse_bulk_header;
+/// assert_eq!(parse_bulk_header(b"$5\r\n").unwrap(), Some(5));
+/// assert_eq!(parse_bulk_header(b"$-1\r\n").unwrap(), None);
+/// assert!(parse_bulk_header(b"*5\r\n").is_err());
+/// ```
+#[inline]
+pub fn parse_bulk_header(line: &[u8]) -> Result<Option<usize>> {
+    if line.first() != Some(&b'$') {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid bulk header: {:?}", line)));
+    }
+    let int = parse_bulk_len(strip_crlf(line).map(|bytes| &bytes[1..])?)?;
+    if int == -1 {
+        return Ok(None);
+    }
+    Ok(Some(int as usize))
+}
+
+/// Parses a chunk header of a streamed bulk string, e.g. `;4\r\n`, `0` ends the string.
+pub(crate) fn parse_chunk_header(line: &[u8], options: &Options) -> Result<usize> {
+    if line.first() != Some(&b';') {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid chunk header: {:?}", line)));
+    }
+    let bytes = strip_crlf(line).map(|bytes| &bytes[1..])?;
+    if options.strict_lengths {
+        check_length_literal(bytes)?;
+    }
+    let int = parse_integer(bytes)?;
+    if !(0..RESP_MAX_SIZE).contains(&int) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid chunk length: {}", int)));
+    }
+    Ok(int as usize)
+}
+
+/// Checks the length of a streamed bulk string after a chunk of `len` bytes.
+pub(crate) fn check_streamed_len(total: usize, len: usize, options: &Options) -> Result<()> {
+    if total + len >= RESP_MAX_SIZE as usize {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("invalid bulk length: {}", total + len)));
+    }
+    check_max_bulk_len(total + len, options)
+}
+
+/// Checks the number of bytes of a top-level value against the `max_frame_len` option.
+pub(crate) fn check_max_frame_len(len: usize, options: &Options) -> Result<()> {
+    match options.max_frame_len {
+        Some(max) if len > max => {
+            Err(Error::new(ErrorKind::InvalidInput,
+                           format!("frame length {} exceeds max frame length {}", len, max)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks the length of a line against the `max_line_len` option, the line may not be complete
+/// yet. Its trailing CRLF, or the CR of an incomplete one, is not counted.
+pub(crate) fn check_max_line_len(line: &[u8], options: &Options) -> Result<()> {
+    let max = match options.max_line_len {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.len() > max {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("line length {} exceeds max line length {}",
+                                      line.len(),
+                                      max)));
+    }
+    Ok(())
+}
+
+/// Checks the length of a bulk string against the `max_bulk_len` option.
+pub(crate) fn check_max_bulk_len(len: usize, options: &Options) -> Result<()> {
+    match options.max_bulk_len {
+        Some(max) if len > max => {
+            Err(Error::new(ErrorKind::InvalidInput,
+                           format!("bulk length {} exceeds max bulk length {}", len, max)))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[inline]
+pub(crate) fn parse_bulk_len(bytes: &[u8]) -> Result<i64> {
+    let int = parse_integer(bytes)?;
+    if !(-1..RESP_MAX_SIZE).contains(&int) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid bulk length: {}", int)));
+    }
+    Ok(int)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use super::*;
+    use super::super::Value;
+
+    struct Case {
+        data: Vec<u8>,
+        want: Value,
+    }
+
+    #[test]
+    fn fn_encode_slice() {
+        let array = ["SET", "a", "1"];
+        assert_eq!(String::from_utf8(encode_slice(&array)).unwrap(),
+                   "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+
+        let array = vec!["SET", "a", "1"];
+        assert_eq!(String::from_utf8(encode_slice(&array)).unwrap(),
+                   "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+
+        let expected = encode_slice(&["SET", "a", "1"]);
+        let array = vec!["SET".to_string(), "a".to_string(), "1".to_string()];
+        assert_eq!(encode_slice(&array), expected);
+        assert_eq!(encode_slice(array.iter().map(String::as_str)), expected);
+        assert_eq!(encode_slice(array), expected);
+        let array: Vec<Vec<u8>> = vec![b"SET".to_vec(), b"a".to_vec(), b"1".to_vec()];
+        assert_eq!(encode_slice(&array[..]), expected);
+        assert_eq!(encode_slice(Vec::<&str>::new()), b"*0\r\n");
+    }
+
+    #[test]
+    fn fn_encoded_len() {
+        let values = vec![Value::Null,
+                          Value::NullArray,
+                          Value::String("OK".to_string()),
+                          Value::Error("ERR".to_string()),
+                          Value::Integer(0),
+                          Value::Integer(9),
+                          Value::Integer(10),
+                          Value::Integer(-10),
+                          Value::Integer(i64::MAX),
+                          Value::Integer(i64::MIN),
+                          Value::Boolean(false),
+                          Value::Double(-1.5e300),
+                          Value::Double(1.0 / 0.0),
+                          Value::BigNumber("-123".to_string()),
+                          Value::Bulk("中".to_string()),
+                          Value::BufBulk(vec![0; 1000]),
+                          Value::Array(vec![]),
+                          Value::Set(vec![Value::Null; 10]),
+                          Value::Push(vec![Value::Bulk("a".to_string())]),
+                          Value::Map(vec![(Value::Integer(1), Value::Integer(2))]),
+                          Value::Attribute(vec![(Value::Integer(1), Value::Integer(2))],
+                                           Box::new(Value::Integer(3))),
+                          Value::Unknown(b"=1\r\n".to_vec())];
+        for value in &values {
+            assert_eq!(encoded_len(value), buf_encoded(value).len(), "{:?}", value);
+            assert_eq!(encode(value).capacity(), encoded_len(value));
+        }
+        let array = Value::Array(values);
+        assert_eq!(array.encoded_len(), buf_encoded(&array).len());
+    }
+
+    fn buf_encoded(value: &Value) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        buf_encode(value, &mut buf);
+        buf
+    }
+
+    /// A writer that writes at most `max` bytes per write.
+    struct ShortWriter {
+        output: Vec<u8>,
+        max: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let n = min(buf.len(), self.max);
+            self.output.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fn_encode_vectored() {
+        let large: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        let value = Value::Array(vec![Value::Bulk("SET".to_string()),
+                                      Value::Bulk("a".repeat(VECTORED_MIN_LEN)),
+                                      Value::Map(vec![(Value::Integer(1),
+                                                       Value::BufBulk(large.clone()))]),
+                                      Value::Attribute(vec![],
+                                                       Box::new(Value::BufBulk(large.clone()))),
+                                      Value::Bulk("a".repeat(VECTORED_MIN_LEN - 1)),
+                                      Value::Null]);
+        let encoding = encode_vectored(&value);
+        assert_eq!(encoding.len(), value.encoded_len());
+        assert!(!encoding.is_empty());
+
+        // the large payloads are referenced, the buffer parts are between them.
+        let slices = encoding.io_slices();
+        assert_eq!(slices.len(), 7);
+        let Value::Array(ref values) = value else { unreachable!() };
+        let Value::Attribute(_, ref attributed) = values[3] else { unreachable!() };
+        let Value::BufBulk(ref payload) = **attributed else { unreachable!() };
+        assert_eq!(slices[5].as_ptr(), payload.as_ptr());
+
+        for max in &[1, 7, 4096, usize::MAX] {
+            let mut writer = ShortWriter {
+                output: Vec::new(),
+                max: *max,
+            };
+            assert_eq!(encoding.write_to(&mut writer).unwrap(), encoding.len());
+            assert_eq!(writer.output, value.encode());
+        }
+        let mut writer = ShortWriter {
+            output: Vec::new(),
+            max: 0,
+        };
+        assert_eq!(encoding.write_to(&mut writer).unwrap_err().kind(), ErrorKind::WriteZero);
+
+        let raw = Value::Raw(Value::BufBulk(large.clone()).encode());
+        let encoding = encode_vectored(&raw);
+        assert_eq!(encoding.io_slices().len(), 1);
+        let Value::Raw(ref raw_bytes) = raw else { unreachable!() };
+        assert_eq!(encoding.io_slices()[0].as_ptr(), raw_bytes.as_ptr());
+
+        let shared = Value::SharedBulk(::std::sync::Arc::from(large.as_slice()));
+        let encoding = encode_vectored(&shared);
+        assert_eq!(encoding.io_slices().len(), 3);
+        assert_eq!(encoding.io_slices()[1].as_ptr(), shared.as_bytes().unwrap().as_ptr());
+
+        let bulk = Value::BufBulk(large);
+        assert_eq!(encode_vectored(&bulk).io_slices().len(), 3);
+        assert_eq!(encode_vectored(&Value::Integer(1)).io_slices().len(), 1);
+    }
+
+    #[test]
+    fn fn_encode_bulk_from_reader() {
+        let payload: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        let mut output: Vec<u8> = Vec::new();
+        let len = encode_bulk_from_reader(&mut payload.as_slice(), payload.len(), &mut output);
+        assert_eq!(len.unwrap(), output.len());
+        assert_eq!(output, Value::BufBulk(payload.clone()).encode());
+
+        // only `len` bytes are read.
+        let mut reader = &b"foobar"[..];
+        let mut output: Vec<u8> = Vec::new();
+        encode_bulk_from_reader(&mut reader, 3, &mut output).unwrap();
+        encode_bulk_from_reader(&mut reader, 0, &mut output).unwrap();
+        assert_eq!(output, b"$3\r\nfoo\r\n$0\r\n\r\n");
+        assert_eq!(reader, b"bar");
+
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(encode_bulk_from_reader(&mut reader, 4, &mut output).unwrap_err().kind(),
+                   ErrorKind::UnexpectedEof);
+        assert_eq!(encode_bulk_from_reader(&mut io::empty(), 1 << 29, &mut output)
+                       .unwrap_err()
+                       .kind(),
+                   ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn fn_encode_to_fmt() {
+        let value = Value::Array(vec![Value::String("OK正".to_string()),
+                                      Value::BufBulk("中文".as_bytes().to_vec()),
+                                      Value::Map(vec![(Value::Double(1.5), Value::Null)]),
+                                      Value::Raw(b"#t\r\n".to_vec()),
+                                      Value::Error("ERR a\r\nb".to_string()),
+                                      Value::SmallBulk(SmallBulk::new(b"ab").unwrap()),
+                                      Value::Push(vec![Value::Integer(-1), Value::Boolean(true),
+                                                       Value::NullArray])]);
+        let mut output = String::new();
+        assert_eq!(encode_to_fmt(&value, &mut output).unwrap(), value.encoded_len());
+        assert_eq!(output.as_bytes(), &value.encode()[..]);
+
+        let invalid = vec![Value::BufBulk(vec![255]),
+                           Value::Unknown(vec![b'=', 255, b'\r', b'\n']),
+                           Value::Array(vec![Value::Raw(vec![255])])];
+        for value in &invalid {
+            let mut output = String::new();
+            assert_eq!(encode_to_fmt(value, &mut output).unwrap_err().kind(),
+                       ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn fn_encode_into() {
+        let values = vec![Value::String("OK".to_string()),
+                          Value::Array(vec![Value::Bulk("GET".to_string()), Value::Null]),
+                          Value::Map(vec![(Value::Integer(1), Value::Double(0.5))])];
+        let mut buf: Vec<u8> = b"+PONG\r\n".to_vec();
+        let mut expected = buf.clone();
+        for value in &values {
+            encode_into(value, &mut buf);
+            expected.extend_from_slice(&encode(value));
+        }
+        assert_eq!(buf, expected);
+
+        // the buffer is reused once its bytes are sent.
+        let capacity = buf.capacity();
+        buf.clear();
+        encode_into(&values[1], &mut buf);
+        assert_eq!(buf, encode(&values[1]));
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn fn_encode_to_writer() {
+        let values = vec![Value::Null,
+                          Value::NullArray,
+                          Value::String("OK".to_string()),
+                          Value::Integer(-1),
+                          Value::Double(1.5),
+                          Value::Bulk("中".to_string()),
+                          Value::BufBulk(vec![0xff; 100_000]),
+                          Value::Array(vec![Value::Bulk("a".to_string()), Value::Array(vec![])]),
+                          Value::Set(vec![Value::Boolean(true)]),
+                          Value::Push(vec![Value::BigNumber("1".to_string())]),
+                          Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1))]),
+                          Value::Attribute(vec![(Value::String("ttl".to_string()),
+                                                 Value::Integer(1))],
+                                           Box::new(Value::Bulk("b".to_string()))),
+                          Value::Unknown(b"=1\r\n".to_vec())];
+        let mut output: Vec<u8> = Vec::new();
+        for value in &values {
+            output.clear();
+            assert_eq!(encode_to_writer(value, &mut output).unwrap(), output.len());
+            assert_eq!(output, encode(value), "{:?}", value);
+        }
+        let mut output: Vec<u8> = Vec::new();
+        let array = Value::Array(values);
+        assert_eq!(encode_to_writer(&array, &mut output).unwrap(), output.len());
+        assert_eq!(output, encode(&array));
+
+        let mut short = [0u8; 8];
+        assert_eq!(encode_to_writer(&array, &mut &mut short[..]).unwrap_err().kind(),
+                   ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn fn_encode_bytes_slice() {
+        let array: Vec<&[u8]> = vec![b"SET", b"a", b"1"];
+        assert_eq!(encode_bytes_slice(&array), encode_slice(&["SET", "a", "1"]));
+
+        let array: [&[u8]; 3] = [b"SET", "中".as_bytes(), &[0, 0xff, b'\r', b'\n']];
+        let buf = encode_bytes_slice(&array);
+        let mut decoder = Decoder::with_buf_bulk(BufReader::new(&buf[..]));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(array.iter().map(|arg| Value::BufBulk(arg.to_vec())).collect()));
+        assert_eq!(encode_bytes_slice(&[]), b"*0\r\n");
+    }
+
+    #[test]
+    fn struct_pipeline_encoder() {
+        let mut pipeline = PipelineEncoder::with_capacity(64);
+        assert!(pipeline.is_empty());
+        let mut expected: Vec<u8> = Vec::new();
+        for i in 0..10 {
+            let value = Value::Integer(i);
+            pipeline.push(&value);
+            pipeline.push_command(vec!["INCRBY".to_string(), "a".to_string(), i.to_string()]);
+            expected.extend_from_slice(&value.encode());
+            expected.extend_from_slice(&encode_slice(&["INCRBY", "a", &i.to_string()]));
+        }
+        assert_eq!(pipeline.len(), 20);
+        assert_eq!(pipeline.as_bytes(), &expected[..]);
+
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(pipeline.flush_to(&mut output).unwrap(), expected.len());
+        assert_eq!(output, expected);
+        assert!(pipeline.is_empty());
+        assert!(pipeline.as_bytes().is_empty());
+
+        // the capacity is kept across flushes.
+        let capacity = pipeline.buf.capacity();
+        pipeline.push_command(&["PING"]);
+        let mut output: Vec<u8> = Vec::new();
+        pipeline.flush_to(&mut output).unwrap();
+        assert_eq!(output, b"*1\r\n$4\r\nPING\r\n");
+        assert_eq!(pipeline.buf.capacity(), capacity);
+
+        // a failed write keeps the batch.
+        pipeline.push_command(&["PING"]);
+        let mut short = [0u8; 4];
+        assert!(pipeline.flush_to(&mut &mut short[..]).is_err());
+        assert_eq!(pipeline.len(), 1);
+        pipeline.clear();
+        assert!(pipeline.is_empty());
+    }
+
+    #[test]
+    fn fn_encode_raw() {
+        let raw = b"%1\r\n+a\r\n#t\r\n";
+        let value = Value::Array(vec![Value::Bulk("x".to_string()), Value::Raw(raw.to_vec())]);
+        let expected = b"*2\r\n$1\r\nx\r\n%1\r\n+a\r\n#t\r\n";
+        assert_eq!(encode(&value), expected.to_vec());
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(encode_to_writer(&value, &mut output).unwrap(), expected.len());
+        assert_eq!(output, expected.to_vec());
+        // the raw bytes are not converted to RESP2.
+        assert_eq!(Encoder::new(Version::Resp2).encode(&value), expected.to_vec());
+
+        let mut pipeline = PipelineEncoder::new();
+        pipeline.push(&Value::Raw(b"+OK\r\n".to_vec()));
+        assert_eq!(pipeline.as_bytes(), b"+OK\r\n");
+    }
+
+    #[test]
+    fn fn_encode_line_sanitized() {
+        let cases = vec![(Value::String("OK\r\n+INJECTED".to_string()), "+OK  +INJECTED\r\n"),
+                         (Value::Error("ERR\nbad\r".to_string()), "-ERR bad \r\n"),
+                         (Value::BigNumber("1\r\n".to_string()), "(1  \r\n")];
+        for (value, expected) in cases {
+            assert_eq!(encode(&value), expected.as_bytes());
+            assert_eq!(encoded_len(&value), expected.len());
+            let mut output: Vec<u8> = Vec::new();
+            encode_to_writer(&value, &mut output).unwrap();
+            assert_eq!(output, expected.as_bytes());
+            assert_eq!(Encoder::new(Version::Resp3).encode(&value), expected.as_bytes());
+        }
+
+        // the value is decoded as a single value.
+        let buf = encode(&Value::Array(vec![Value::Error("ERR\r\n:1".to_string())]));
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Error("ERR  :1".to_string())]));
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn fn_encode_canonical() {
+        let bulk = Value::Array(vec![Value::Bulk("OK".to_string()),
+                                     Value::Array(vec![Value::Bulk("中".to_string())])]);
+        let buf_bulk = Value::Array(vec![Value::BufBulk(vec![79, 75]),
+                                         Value::Array(vec![Value::BufBulk(vec![228, 184, 173])])]);
+        assert_eq!(encode_canonical(&bulk), encode_canonical(&buf_bulk));
+        assert_eq!(encode_canonical(&bulk), encode(&bulk));
+
+        assert!(encode_canonical(&Value::Null) != encode_canonical(&Value::NullArray));
+
+        let map = Value::Map(vec![(Value::Bulk("b".to_string()), Value::Integer(1)),
+                                  (Value::Bulk("a".to_string()), Value::Integer(2))]);
+        let sorted = Value::Map(vec![(Value::BufBulk(b"a".to_vec()), Value::Integer(2)),
+                                     (Value::BufBulk(b"b".to_vec()), Value::Integer(1))]);
+        assert_eq!(encode_canonical(&map), encode_canonical(&sorted));
+        assert_eq!(encode_canonical(&map), encode(&sorted));
+
+        let set = Value::Set(vec![Value::Integer(2), Value::Integer(1)]);
+        assert_eq!(encode_canonical(&set), b"~2\r\n:1\r\n:2\r\n".to_vec());
+        assert_eq!(encode_canonical(&Value::Double(-0.0)), b",0\r\n".to_vec());
+        assert!(encode_canonical(&set) != encode_canonical(&Value::Array(vec![Value::Integer(1),
+                                                                              Value::Integer(2)])));
+        assert!(encode_canonical(&Value::String("OK".to_string())) !=
+                encode_canonical(&Value::Bulk("OK".to_string())));
+    }
+
+    struct Xor(u8);
+
+    impl BulkHook for Xor {
+        fn encoded_len(&self, len: usize) -> Option<usize> {
+            Some(len + 1)
+        }
+
+        fn encode(&mut self, len: usize, input: &mut dyn Read, output: &mut dyn Write)
+                  -> Result<()> {
+            // a toy compression: payloads longer than 4 bytes are prefixed and xored.
+            let flag = if len > 4 { b'x' } else { b'-' };
+            output.write_all(&[flag])?;
+            let mut buf = [0u8; 2];
+            loop {
+                let n = input.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(());
+                }
+                if flag == b'x' {
+                    for b in &mut buf[..n] {
+                        *b ^= self.0;
+                    }
+                }
+                output.write_all(&buf[..n])?;
+            }
+        }
+
+        fn decode(&mut self,
+                  _: Option<usize>,
+                  input: &mut dyn Read,
+                  output: &mut dyn Write)
+                  -> Result<()> {
+            let mut flag = [0u8; 1];
+            input.read_exact(&mut flag)?;
+            let mut buf = [0u8; 2];
+            loop {
+                let n = input.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(());
+                }
+                if flag[0] == b'x' {
+                    for b in &mut buf[..n] {
+                        *b ^= self.0;
+                    }
+                }
+                output.write_all(&buf[..n])?;
+            }
+        }
+    }
+
+    #[test]
+    fn fn_encode_with_hook() {
+        let value = Value::Array(vec![Value::Bulk("Hello".to_string()),
+                                      Value::BufBulk(vec![1, 2]),
+                                      Value::Integer(1),
+                                      Value::Null]);
+        let buf = encode_with_hook(&value, &mut Xor(1)).unwrap();
+        assert_eq!(buf, b"*4\r\n$6\r\nxIdmmn\r\n$3\r\n-\x01\x02\r\n:1\r\n$-1\r\n".to_vec());
+
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Bulk("Hello".to_string()),
+                                     Value::Bulk("\u{1}\u{2}".to_string()),
+                                     Value::Integer(1),
+                                     Value::Null]));
+        assert!(decoder.decode().is_err());
+
+        let mut decoder = Decoder::with_buf_bulk(BufReader::new(buf.as_slice()));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::BufBulk(b"Hello".to_vec()),
+                                     Value::BufBulk(vec![1, 2]),
+                                     Value::Integer(1),
+                                     Value::Null]));
+
+        let buf = b"$6\r\nxIdmmn\r\r".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"$6\r\nxIdm".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert!(decoder.decode().is_err());
+
+        let buf = encode_with_hook(&value, &mut Xor(1)).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(encode_to_writer_with_hook(&value, &mut output, &mut Xor(1)).unwrap(),
+                   buf.len());
+        assert_eq!(output, buf);
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(encode_to_writer_with_hook(&value, &mut output, &mut Buffered).unwrap(),
+                   buf.len());
+        assert_eq!(output, buf);
+        assert_eq!(encode_with_hook(&Value::Bulk("a".to_string()), &mut Short).unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+
+        // a hook must read the whole payload, and restores it within the limits.
+        let mut decoder = Decoder::new(&b"$6\r\nxIdmmn\r\n"[..]);
+        decoder.set_bulk_hook(Box::new(Short));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidData);
+        let mut decoder = Decoder::new(&b"$1\r\nx\r\n"[..]);
+        decoder.set_bulk_hook(Box::new(Bomb));
+        decoder.set_max_bulk_len(1024);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"$1\r\nx\r\n"[..]);
+        decoder.set_bulk_hook(Box::new(Bomb));
+        decoder.set_max_frame_len(1024);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    /// A hook that doesn't know the length of its output up front.
+    struct Buffered;
+
+    impl BulkHook for Buffered {
+        fn encode(&mut self, len: usize, input: &mut dyn Read, output: &mut dyn Write)
+                  -> Result<()> {
+            Xor(1).encode(len, input, output)
+        }
+
+        fn decode(&mut self,
+                  len: Option<usize>,
+                  input: &mut dyn Read,
+                  output: &mut dyn Write)
+                  -> Result<()> {
+            Xor(1).decode(len, input, output)
+        }
+    }
+
+    /// A hook that writes one byte short and reads one byte.
+    struct Short;
+
+    impl BulkHook for Short {
+        fn encoded_len(&self, len: usize) -> Option<usize> {
+            Some(len + 1)
+        }
+
+        fn encode(&mut self, len: usize, _: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+            output.write_all(&vec![0; len])
+        }
+
+        fn decode(&mut self, _: Option<usize>, input: &mut dyn Read, _: &mut dyn Write)
+                  -> Result<()> {
+            input.read_exact(&mut [0u8; 1])
+        }
+    }
+
+    /// A hook that expands any payload without end.
+    struct Bomb;
+
+    impl BulkHook for Bomb {
+        fn encode(&mut self, _: usize, _: &mut dyn Read, _: &mut dyn Write) -> Result<()> {
+            Ok(())
+        }
+
+        fn decode(&mut self, _: Option<usize>, input: &mut dyn Read, output: &mut dyn Write)
+                  -> Result<()> {
+            io::copy(input, &mut io::sink())?;
+            loop {
+                output.write_all(&[0; 256])?;
+            }
+        }
+    }
+
+    #[test]
+    fn struct_decoder_from_chunks() {
+        let value = Value::Array(vec![Value::Bulk("foo".to_string()),
+                                      Value::Integer(123),
+                                      Value::Null]);
+        let buf = value.encode();
+        let chunks: Vec<Vec<u8>> = buf.chunks(3).map(|chunk| chunk.to_vec()).collect();
+        let mut decoder = Decoder::from_chunks(chunks);
+        assert_eq!(decoder.decode().unwrap(), value);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let chunks: Vec<&[u8]> = vec![b"", b"+O", b"", b"K\r\n+OK\r\n", b"$3\r\nfo"];
+        let mut decoder = Decoder::from_chunks(chunks);
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn fn_parse_primitives() {
+        let mut buf: Vec<u8> = Vec::new();
+        encode_len_header(b'$', 0, &mut buf);
+        encode_len_header(b'*', 12, &mut buf);
+        assert_eq!(buf, b"$0\r\n*12\r\n");
+
+        assert!(is_crlf(b'\r', b'\n'));
+        assert!(!is_crlf(b'\n', b'\r'));
+        assert_eq!(strip_crlf(b"\r\n").unwrap(), b"");
+        assert!(strip_crlf(b"\n").is_err());
+        assert!(strip_crlf(b"").is_err());
+
+        assert_eq!(parse_integer_line(b"*0\r\n").unwrap(), 0);
+        assert_eq!(parse_integer_line(b"\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(parse_integer_line(b":x\r\n").unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+
+        assert_eq!(parse_bulk_header(b"$0\r\n").unwrap(), Some(0));
+        assert!(parse_bulk_header(b"$-2\r\n").is_err());
+        assert!(parse_bulk_header(b"$536870912\r\n").is_err());
+        assert!(parse_bulk_header(b"$1").is_err());
+        assert!(parse_bulk_header(b"").is_err());
+    }
+
+    #[test]
+    fn struct_decoder_map() {
+        let value = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+                                    (Value::Integer(2),
+                                     Value::Map(vec![(Value::Null, Value::Array(vec![]))])),
+                                    (Value::String("c".to_string()), Value::Map(vec![]))]);
+        let buf = value.encode();
+        assert_eq!(buf,
+                   b"%3\r\n$1\r\na\r\n:1\r\n:2\r\n%1\r\n$-1\r\n*0\r\n+c\r\n%0\r\n".to_vec());
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), value);
+        assert!(decoder.decode().is_err());
+
+        let buf = b"%-1\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"%1\r\n:1\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn struct_decoder_set() {
+        let value = Value::Set(vec![Value::Bulk("a".to_string()),
+                                    Value::Set(vec![]),
+                                    Value::Map(vec![(Value::Integer(1),
+                                                     Value::Set(vec![Value::Null]))])]);
+        let buf = value.encode();
+        assert_eq!(buf,
+                   b"~3\r\n$1\r\na\r\n~0\r\n%1\r\n:1\r\n~1\r\n$-1\r\n".to_vec());
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), value);
+        assert!(decoder.decode().is_err());
+
+        let buf = b"~-1\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_decoder_streamed_bulk() {
+        let buf = b"$?\r\n;4\r\nHell\r\n;6\r\no worl\r\n;1\r\nd\r\n;0\r\n\
+                    *2\r\n$?\r\n;0\r\n:1\r\n$?\r\n;2\r\nabc\r\n"
+            .to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello world".to_string()));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Bulk("".to_string()), Value::Integer(1)]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"$?\r\n;2\r\n\xff\x00\r\n;0\r\n$?\r\n:2\r\n$?\r\n;-1\r\n".to_vec();
+        let mut decoder = Decoder::with_buf_bulk(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::BufBulk(vec![255, 0]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let mut writer = StreamedBulkWriter::new(Vec::new()).unwrap();
+        writer.write_all(b"").unwrap();
+        let buf = writer.finish().unwrap();
+        assert_eq!(buf, b"$?\r\n;0\r\n".to_vec());
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("".to_string()));
+
+        let mut payload: Vec<u8> = Vec::new();
+        Xor(1).encode(5, &mut &b"Hello"[..], &mut payload).unwrap();
+        let mut writer = StreamedBulkWriter::new(Vec::new()).unwrap();
+        writer.write_all(&payload[..2]).unwrap();
+        writer.write_all(&payload[2..]).unwrap();
+        let buf = writer.finish().unwrap();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello".to_string()));
+        let mut decoder = Decoder::new(BufReader::new(&buf[..buf.len() - 4]));
+        decoder.set_bulk_hook(Box::new(Xor(1)));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let buf = b"$?\r\n;2\r\nol\r\n;3\r\nleH\r\n;0\r\n".to_vec();
+        let mut output: Vec<u8> = Vec::new();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap(), buf.len());
+        assert_eq!(output, buf);
+    }
+
+    #[test]
+    fn struct_decoder_streamed_aggregate() {
+        let buf = b"*?\r\n:1\r\n*?\r\n.\r\n~?\r\n$?\r\n;1\r\na\r\n;0\r\n.\r\n.\r\n\
+                    %?\r\n+a\r\n:1\r\n+b\r\n*1\r\n:2\r\n.\r\n%?\r\n+a\r\n.\r\n"
+            .to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Integer(1),
+                                     Value::Array(vec![]),
+                                     Value::Set(vec![Value::Bulk("a".to_string())])]));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Map(vec![(Value::String("a".to_string()), Value::Integer(1)),
+                                   (Value::String("b".to_string()),
+                                    Value::Array(vec![Value::Integer(2)]))]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b".\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"*?\r\n:1\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn struct_decoder_attribute() {
+        let buf = b"*2\r\n|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.1923\r\n:2039123\r\n\
+                    :9543892\r\n|0\r\n|1\r\n:1\r\n:2\r\n+OK\r\n"
+            .to_vec();
+        let attribute = vec![(Value::String("key-popularity".to_string()),
+                              Value::Map(vec![(Value::Bulk("a".to_string()),
+                                               Value::Double(0.1923))]))];
+        let value = Value::Array(vec![Value::Attribute(attribute,
+                                                       Box::new(Value::Integer(2039123))),
+                                      Value::Integer(9543892)]);
+        let ok = Value::Attribute(vec![(Value::Integer(1), Value::Integer(2))],
+                                  Box::new(Value::String("OK".to_string())));
+        let nested = Value::Attribute(vec![], Box::new(ok));
+
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Integer(2039123), Value::Integer(9543892)]));
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        assert!(decoder.decode().is_err());
+
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_keep_attributes(true);
+        assert_eq!(decoder.decode().unwrap(), value);
+        assert_eq!(decoder.decode().unwrap(), nested);
+        let mut output = value.encode();
+        output.extend_from_slice(&nested.encode());
+        assert_eq!(output, buf);
+
+        let buf = b"|1\r\n:1\r\n:2\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert!(decoder.decode().is_err());
+    }
+
+    #[test]
+    fn struct_decoder_push() {
+        let value = Value::Push(vec![Value::Bulk("message".to_string()),
+                                     Value::Bulk("channel".to_string()),
+                                     Value::Bulk("hello".to_string())]);
+        let buf = value.encode();
+        assert_eq!(buf,
+                   b">3\r\n$7\r\nmessage\r\n$7\r\nchannel\r\n$5\r\nhello\r\n".to_vec());
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let push = decoder.decode().unwrap();
+        assert_eq!(push, value);
+        assert!(push != Value::Array(vec![Value::Bulk("message".to_string()),
+                                          Value::Bulk("channel".to_string()),
+                                          Value::Bulk("hello".to_string())]));
+
+        let buf = b">-1\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_decoder_double() {
+        let buf = b",3.25\r\n,-1\r\n,inf\r\n,-inf\r\n,nan\r\n,1e3\r\n,\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::Double(3.25));
+        assert_eq!(decoder.decode().unwrap(), Value::Double(-1.0));
+        assert_eq!(decoder.decode().unwrap(), Value::Double(f64::INFINITY));
+        assert_eq!(decoder.decode().unwrap(), Value::Double(f64::NEG_INFINITY));
+        match decoder.decode().unwrap() {
+            Value::Double(val) => assert!(val.is_nan()),
+            value => panic!("unexpected {:?}", value),
+        }
+        assert_eq!(decoder.decode().unwrap(), Value::Double(1000.0));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        for val in &[0.1, -2.5e-300, 1.7976931348623157e308, 123456789.0] {
+            let buf = Value::Double(*val).encode();
+            let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+            assert_eq!(decoder.decode().unwrap(), Value::Double(*val));
+        }
+    }
+
+    #[test]
+    fn struct_decoder_big_number() {
+        let big = "3492890328409238509324850943850943825024385";
+        let buf = format!("({}\r\n(-{}\r\n(0\r\n(\r\n(-\r\n(1e3\r\n", big, big);
+        let mut decoder = Decoder::new(BufReader::new(buf.as_bytes()));
+        assert_eq!(decoder.decode().unwrap(), Value::BigNumber(big.to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::BigNumber(format!("-{}", big)));
+        assert_eq!(decoder.decode().unwrap(), Value::BigNumber("0".to_string()));
+        for _ in 0..3 {
+            assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+
+        let value = Value::Array(vec![Value::BigNumber(big.to_string())]);
+        let buf = value.encode();
+        assert_eq!(buf, format!("*1\r\n({}\r\n", big).into_bytes());
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), value);
+    }
+
+    #[test]
+    fn struct_decoder_passthrough_unknown() {
+        let frames: Vec<&[u8]> = vec![b"@t\r\n",
+                                      b"@-1.5\r\n",
+                                      b"=16\r\ntxt:Some\r\nstring\r\n",
+                                      b"!0\r\n\r\n"];
+        let mut all: Vec<u8> = Vec::new();
+        for frame in &frames {
+            all.extend_from_slice(frame);
+        }
+        all.extend_from_slice(b"*2\r\n@f\r\n:1\r\n");
+
+        let mut decoder = Decoder::new(BufReader::new(all.as_slice()));
+        decoder.set_passthrough_unknown(true);
+        let mut output: Vec<u8> = Vec::new();
+        for frame in &frames {
+            let value = decoder.decode().unwrap();
+            assert_eq!(value, Value::Unknown(frame.to_vec()));
+            output.extend_from_slice(&value.encode());
+        }
+        let value = decoder.decode().unwrap();
+        assert_eq!(value,
+                   Value::Array(vec![Value::Unknown(b"@f\r\n".to_vec()), Value::Integer(1)]));
+        output.extend_from_slice(&value.encode());
+        assert_eq!(output, all);
+        assert!(decoder.decode().is_err());
+
+        let buf = b"=15\r\ntxt:Some\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        decoder.set_passthrough_unknown(true);
+        assert!(decoder.decode().is_err());
+
+        let buf = b"@t\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_decoder_resp3_null() {
+        let buf = b"_\r\n*2\r\n_\r\n:1\r\n%1\r\n_\r\n_\r\n_x\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::Null);
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Null, Value::Integer(1)]));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Map(vec![(Value::Null, Value::Null)]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
 
-/// up to 512 MB in length
-const RESP_MAX_SIZE: i64 = 512 * 1024 * 1024;
-const CRLF_BYTES: &'static [u8] = b"\r\n";
-const NULL_BYTES: &'static [u8] = b"$-1\r\n";
-const NULL_ARRAY_BYTES: &'static [u8] = b"*-1\r\n";
+    #[test]
+    fn struct_decoder_boolean() {
+        let buf = b"#t\r\n*2\r\n#f\r\n#t\r\n#x\r\n".to_vec();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::Boolean(true));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Boolean(false), Value::Boolean(true)]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
 
-/// Encodes RESP value to RESP binary buffer.
-/// # Examples
-/// ```
-/// # use self::resp::{Value, encode};
-/// let val = Value::String("OK".to_string());
-/// assert_eq!(encode(&val), vec![43, 79, 75, 13, 10]);
-/// ```
-pub fn encode(value: &Value) -> Vec<u8> {
-    let mut res: Vec<u8> = Vec::new();
-    buf_encode(value, &mut res);
-    res
-}
+    #[test]
+    fn struct_decoder_deeply_nested() {
+        let depth = 100_000;
+        let mut buf: Vec<u8> = Vec::new();
+        for _ in 0..depth {
+            buf.extend_from_slice(b"*1\r\n");
+        }
+        buf.extend_from_slice(b"*?\r\n:1\r\n.\r\n");
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let mut value = decoder.decode().unwrap();
+        // unwrap the levels one by one, dropping the value recursively would overflow the stack.
+        for _ in 0..depth {
+            value = match value {
+                Value::Array(mut array) => {
+                    assert_eq!(array.len(), 1);
+                    array.pop().unwrap()
+                }
+                value => panic!("unexpected {:?}", value),
+            };
+        }
+        assert_eq!(value, Value::Array(vec![Value::Integer(1)]));
+        assert!(decoder.decode().is_err());
 
-/// Encodes a slice of string to RESP binary buffer.
-/// It is use to create a request command on redis client.
-/// # Examples
-/// ```
-/// # use self::resp::encode_slice;
-/// let array = ["SET", "a", "1"];
-/// assert_eq!(encode_slice(&array),
-///            "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n".to_string().into_bytes());
-/// ```
-pub fn encode_slice(slice: &[&str]) -> Vec<u8> {
-    let array: Vec<Value> = slice.iter().map(|string| Value::Bulk(string.to_string())).collect();
-    let mut res: Vec<u8> = Vec::new();
-    buf_encode(&Value::Array(array), &mut res);
-    res
-}
+        // a huge declared length doesn't allocate up front.
+        let buf = b"*536870911\r\n:1\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
 
-#[inline]
-fn buf_encode(value: &Value, buf: &mut Vec<u8>) {
-    match *value {
-        Value::Null => {
-            buf.extend_from_slice(NULL_BYTES);
+    #[test]
+    fn struct_decoder_version() {
+        let resp2 = encode_slice(&["SET", "a", "1"]);
+        let frames: Vec<&[u8]> = vec![b"_\r\n",
+                                      b",1.5\r\n",
+                                      b"#t\r\n",
+                                      b"(1\r\n",
+                                      b"=7\r\ntxt:abc\r\n",
+                                      b"!3\r\nERR\r\n",
+                                      b"%0\r\n",
+                                      b"~0\r\n",
+                                      b">0\r\n",
+                                      b"|0\r\n:1\r\n",
+                                      b"$?\r\n;0\r\n",
+                                      b"*?\r\n.\r\n"];
+        for frame in &frames {
+            let mut buf = resp2.clone();
+            buf.extend_from_slice(frame);
+            let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+            decoder.set_version(Version::Resp2);
+            decoder.set_passthrough_unknown(true);
+            assert!(decoder.decode().is_ok());
+            assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+            let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+            decoder.set_version(Version::Resp3);
+            decoder.set_passthrough_unknown(true);
+            assert!(decoder.decode().is_ok());
+            assert!(decoder.decode().is_ok());
         }
-        Value::NullArray => {
-            buf.extend_from_slice(NULL_ARRAY_BYTES);
+    }
+
+    #[test]
+    fn struct_decoder_buf_read() {
+        let buf = encode_slice(&["SET", "a", "1"]);
+        let mut decoder = Decoder::new(buf.as_slice());
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Bulk("SET".to_string()),
+                                     Value::Bulk("a".to_string()),
+                                     Value::Bulk("1".to_string())]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut decoder = Decoder::with_buf_bulk(io::Cursor::new(buf.clone()));
+        assert!(decoder.decode().is_ok());
+
+        // a small buffer splits the lines and payloads.
+        let mut decoder = Decoder::new(BufReader::with_capacity(3, buf.as_slice()));
+        assert!(decoder.decode().is_ok());
+
+        let chunks = buf.chunks(2).collect::<Vec<&[u8]>>();
+        let mut decoder = Decoder::from_chunks(chunks.clone());
+        assert!(decoder.decode().is_ok());
+        let mut reader = Decoder::from_chunks(chunks).reader;
+        let mut res: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut res).unwrap();
+        assert_eq!(res, buf);
+    }
+
+    #[test]
+    fn struct_decoder_into_inner() {
+        let buf = b"+OK\r\n:1\r\n";
+        let mut decoder = Decoder::new(io::Cursor::new(&buf[..]));
+        assert_eq!(decoder.get_ref().position(), 0);
+        assert!(decoder.decode().is_ok());
+        assert_eq!(decoder.get_ref().position(), 5);
+        decoder.get_mut().set_position(0);
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        let cursor = decoder.into_inner();
+        assert_eq!(cursor.position(), 5);
+        let mut decoder = Decoder::new(cursor);
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+    }
+
+    #[test]
+    fn struct_decoder_line_buffer() {
+        let buf = b"*2\r\n$3\r\nGET\r\n$?\r\n;1\r\na\r\n;0\r\n+OK\r\n:100\r\n";
+        let mut decoder = Decoder::new(&buf[..]);
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Bulk("GET".to_string()),
+                                     Value::Bulk("a".to_string())]));
+        let line = decoder.line.as_ptr();
+        assert!(decoder.line.capacity() > 0);
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(100));
+        assert_eq!(decoder.line.as_ptr(), line);
+    }
+
+    #[test]
+    fn struct_decoder_decode_all() {
+        let values = vec![Value::Array(vec![Value::Bulk("GET".to_string()),
+                                            Value::Bulk("a".to_string())]),
+                          Value::Null,
+                          Value::Integer(1)];
+        let mut buf: Vec<u8> = Vec::new();
+        for value in &values {
+            buf.extend_from_slice(&value.encode());
         }
-        Value::String(ref val) => {
-            buf.push(b'+');
-            buf.extend_from_slice(val.as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
+        let mut decoder = Decoder::from_chunks(buf.chunks(3));
+        assert_eq!(decoder.decode_all().unwrap(), values);
+
+        buf.extend_from_slice(b"*2\r\n:1\r\n");
+        let mut decoder = Decoder::new(buf.as_slice());
+        assert_eq!(decoder.decode_all().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let mut decoder = Decoder::new(&b""[..]);
+        assert_eq!(decoder.decode_all().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn struct_decoder_large_bulk() {
+        let bulk: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+        let buf = Value::BufBulk(bulk.clone()).encode();
+        let mut decoder = Decoder::with_buf_bulk(BufReader::new(buf.as_slice()));
+        assert_eq!(decoder.decode().unwrap(), Value::BufBulk(bulk));
+        let mut decoder = Decoder::from_chunks(buf[..buf.len() - 1].chunks(4096));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn struct_decoder_lenient_line_endings() {
+        let buf = b"+OK\n-ERR bad\r\n$?\n;3\nfoo\r\n;0\n$3\nbar\n+OK\n";
+        let mut decoder = Decoder::new(&buf[..]);
+        decoder.set_lenient_line_endings(true);
+        assert_eq!(decoder.decode().unwrap(), Value::String("OK".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::Error("ERR bad".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("foo".to_string()));
+        // the trailing CRLF of a payload is strict.
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let mut decoder = Decoder::new(&b"\n"[..]);
+        decoder.set_lenient_line_endings(true);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"+OK\n"[..]);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_decoder_strict_lengths() {
+        let valid: &[&[u8]] = &[b"$0\r\n\r\n",
+                                b"$-1\r\n",
+                                b"$10\r\n0123456789\r\n",
+                                b"*-1\r\n",
+                                b"*1\r\n:+1\r\n",
+                                b"%0\r\n",
+                                b"$?\r\n;1\r\na\r\n;0\r\n",
+                                b"*?\r\n.\r\n"];
+        for case in valid {
+            let mut decoder = Decoder::new(*case);
+            decoder.set_strict_lengths(true);
+            assert!(decoder.decode().is_ok(), "{:?}", case);
         }
-        Value::Error(ref val) => {
-            buf.push(b'-');
-            buf.extend_from_slice(val.as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
+
+        let invalid: &[&[u8]] = &[b"$03\r\nfoo\r\n",
+                                  b"$+3\r\nfoo\r\n",
+                                  b"$ 3\r\nfoo\r\n",
+                                  b"$-0\r\n",
+                                  b"$-01\r\n",
+                                  b"$\r\n",
+                                  b"*01\r\n:1\r\n",
+                                  b"~+0\r\n",
+                                  b"|00\r\n",
+                                  b"$?\r\n;01\r\na\r\n;0\r\n"];
+        for case in invalid {
+            let mut decoder = Decoder::new(*case);
+            decoder.set_strict_lengths(true);
+            assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput, "{:?}", case);
+            let mut decoder = Decoder::new(*case);
+            decoder.set_strict_lengths(true);
+            let mut output: Vec<u8> = Vec::new();
+            assert!(relay_bulk(&mut decoder, &mut output).is_err(), "{:?}", case);
         }
-        Value::Integer(ref val) => {
-            buf.push(b':');
-            buf.extend_from_slice(val.to_string().as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
+    }
+
+    #[test]
+    fn fn_split_inline() {
+        let cases: &[(&[u8], &[&[u8]])] =
+            &[(b"\r\n", &[]),
+              (b"  \t \n", &[]),
+              (b"PING\r\n", &[b"PING"]),
+              (b"PING\n", &[b"PING"]),
+              (b"SET  foo\tbar \r\n", &[b"SET", b"foo", b"bar"]),
+              (b"SET foo \"a b\\\"\\n\\x41\\x4g\"\r\n", &[b"SET", b"foo", b"a b\"\nAx4g"]),
+              (b"SET k \"\\x41BC\"\r\n", &[b"SET", b"k", b"ABC"]),
+              (b"SET k \"AB\\x43\"\r\n", &[b"SET", b"k", b"ABC"]),
+              (b"SET k \"a\\x00\\x7fz\"\r\n", &[b"SET", b"k", b"a\x00\x7fz"]),
+              (b"SET foo 'it\\'s \\n'\r\n", &[b"SET", b"foo", b"it's \\n"]),
+              (b"SET foo \"\" ''\r\n", &[b"SET", b"foo", b"", b""]),
+              (b"SET f\"o o\" bar\r\n", &[b"SET", b"fo o", b"bar"])];
+        for &(line, args) in cases {
+            assert_eq!(split_inline(line).unwrap(), args, "{:?}", line);
         }
-        Value::Bulk(ref val) => {
-            buf.push(b'$');
-            buf.extend_from_slice(val.len().to_string().as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
-            buf.extend_from_slice(val.as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
+
+        let invalid: &[&[u8]] = &[b"SET \"foo\r\n", b"SET 'foo\r\n", b"SET \"foo\"bar\r\n"];
+        for line in invalid {
+            assert_eq!(split_inline(line).unwrap_err().kind(), ErrorKind::InvalidInput);
         }
-        Value::BufBulk(ref val) => {
-            buf.push(b'$');
-            buf.extend_from_slice(val.len().to_string().as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
-            buf.extend_from_slice(val);
-            buf.extend_from_slice(CRLF_BYTES);
+    }
+
+    #[test]
+    fn struct_decoder_inline_commands() {
+        let buf = b"PING\r\n\r\n*1\r\n$4\r\nPING\r\n+OK\nGET \"foo";
+        let mut decoder = Decoder::with_buf_bulk(&buf[..]);
+        decoder.set_inline_commands(true);
+        assert_eq!(decoder.decode().unwrap(), Value::Array(vec![Value::BufBulk(b"PING".to_vec())]));
+        assert_eq!(decoder.decode().unwrap(), Value::Array(vec![Value::BufBulk(b"PING".to_vec())]));
+        assert_eq!(decoder.decode().unwrap(), Value::Array(vec![Value::BufBulk(b"+OK".to_vec())]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let mut decoder = Decoder::new(&b"GET \"foo\r\n"[..]);
+        decoder.set_inline_commands(true);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"PING\r\n"[..]);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn enum_frame_type() {
+        for prefix in 0..=255u8 {
+            assert_eq!(FrameType::from_prefix(prefix).prefix(), prefix);
         }
-        Value::Array(ref val) => {
-            buf.push(b'*');
-            buf.extend_from_slice(val.len().to_string().as_bytes());
-            buf.extend_from_slice(CRLF_BYTES);
-            for item in val {
-                buf_encode(item, buf);
-            }
+        let values = vec![Value::String("OK".to_string()),
+                          Value::Error("ERR".to_string()),
+                          Value::Integer(1),
+                          Value::Boolean(true),
+                          Value::Double(1.5),
+                          Value::BigNumber("1".to_string()),
+                          Value::Bulk("a".to_string()),
+                          Value::Null,
+                          Value::Array(vec![]),
+                          Value::Map(vec![]),
+                          Value::Set(vec![]),
+                          Value::Push(vec![])];
+        let types = vec![FrameType::String,
+                         FrameType::Error,
+                         FrameType::Integer,
+                         FrameType::Boolean,
+                         FrameType::Double,
+                         FrameType::BigNumber,
+                         FrameType::Bulk,
+                         FrameType::Bulk,
+                         FrameType::Array,
+                         FrameType::Map,
+                         FrameType::Set,
+                         FrameType::Push];
+        let mut buf: Vec<u8> = Vec::new();
+        for value in &values {
+            buf.extend_from_slice(&value.encode());
+        }
+        let mut decoder = Decoder::from_chunks(buf.chunks(1));
+        for (value, frame_type) in values.iter().zip(types) {
+            assert_eq!(decoder.peek_type().unwrap(), frame_type);
+            assert_eq!(decoder.peek_type().unwrap(), frame_type);
+            assert_eq!(&decoder.decode().unwrap(), value);
         }
+        assert_eq!(decoder.peek_type().unwrap_err().kind(), ErrorKind::UnexpectedEof);
     }
-}
 
-/// A streaming RESP Decoder.
-#[derive(Debug)]
-pub struct Decoder<R> {
-    buf_bulk: bool,
-    reader: BufReader<R>,
-}
+    #[test]
+    fn fn_relay_frame() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut frames: Vec<Vec<u8>> = Vec::new();
+        let values = vec![Value::Array(vec![Value::Bulk("SET".to_string()),
+                                            Value::BufBulk(vec![0xff; 100_000])]),
+                          Value::Map(vec![(Value::Integer(1), Value::Set(vec![]))]),
+                          Value::Null,
+                          Value::NullArray,
+                          Value::String("OK".to_string())];
+        for value in &values {
+            frames.push(value.encode());
+        }
+        frames.push(b"|1\r\n+a\r\n:1\r\n*?\r\n$?\r\n;1\r\na\r\n;0\r\n%?\r\n.\r\n.\r\n".to_vec());
+        frames.push(b":not checked\r\n".to_vec());
+        for frame in &frames {
+            buf.extend_from_slice(frame);
+        }
+        let mut decoder = Decoder::from_chunks(buf.chunks(1000));
+        for frame in &frames {
+            let mut output: Vec<u8> = Vec::new();
+            assert_eq!(relay_frame(&mut decoder, &mut output).unwrap(), frame.len());
+            assert_eq!(&output, frame);
+        }
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_frame(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::UnexpectedEof);
 
-impl<R: Read> Decoder<R> {
-    /// Creates a Decoder instance with given BufReader for decoding the RESP buffers.
-    /// # Examples
-    /// ```
-    /// # use std::io::BufReader;
-    /// # use self::resp::{Decoder, Value};
-    ///
-    /// let value = Value::Bulk("Hello".to_string());
-    /// let buf = value.encode();
-    /// let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
-    /// assert_eq!(decoder.decode().unwrap(), Value::Bulk("Hello".to_string()));
-    /// ```
-    pub fn new(reader: BufReader<R>) -> Self {
-        Decoder {
-            buf_bulk: false,
-            reader: reader,
+        let invalid: &[&[u8]] = &[b"$3\r\nfoo\r\r", b"*1\r\n!\r\n", b"%?\r\n+a\r\n.\r\n", b".\r\n"];
+        for case in invalid {
+            let mut decoder = Decoder::new(*case);
+            assert_eq!(relay_frame(&mut decoder, &mut output).unwrap_err().kind(),
+                       ErrorKind::InvalidInput,
+                       "{:?}",
+                       case);
         }
+        let mut decoder = Decoder::new(&b"*3\r\n:1\r\n:2\r\n:3\r\n"[..]);
+        decoder.set_max_array_len(2);
+        assert!(relay_frame(&mut decoder, &mut output).is_err());
     }
 
-    /// Creates a Decoder instance with given BufReader for decoding the RESP buffers.
-    /// The instance will decode bulk value to buffer bulk.
-    /// # Examples
-    /// ```
-    /// # use std::io::BufReader;
-    /// # use self::resp::{Decoder, Value};
-    ///
-    /// let value = Value::Bulk("Hello".to_string());
-    /// let buf = value.encode();
-    /// let mut decoder = Decoder::with_buf_bulk(BufReader::new(buf.as_slice()));
-    /// // Always decode "$" buffers to Value::BufBulk even if feed Value::Bulk buffers
-    /// assert_eq!(decoder.decode().unwrap(), Value::BufBulk("Hello".to_string().into_bytes()));
-    /// ```
-    pub fn with_buf_bulk(reader: BufReader<R>) -> Self {
-        Decoder {
-            buf_bulk: true,
-            reader: reader,
-        }
+    #[test]
+    fn struct_decoder_stats() {
+        let mut buf = b"*2\r\n$3\r\nGET\r\n$?\r\n;1\r\na\r\n;0\r\n".to_vec();
+        buf.extend_from_slice(b":1\r\n$1\r\na\r\nPING\r\n+OK\r\n-ERR\r\n");
+        let mut decoder = Decoder::new(&buf[..]);
+        decoder.decode().unwrap();
+        assert_eq!(decoder.bytes_consumed(), 28);
+        decoder.skip_value().unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        relay_bulk(&mut decoder, &mut output).unwrap();
+        assert_eq!(decoder.bytes_consumed(), 39);
+        assert_eq!(decoder.frames_decoded(), 1);
+
+        decoder.set_inline_commands(true);
+        decoder.decode().unwrap();
+        decoder.set_inline_commands(false);
+        assert_eq!(decoder.decode_all().unwrap().len(), 2);
+        assert_eq!(decoder.bytes_consumed(), buf.len() as u64);
+        assert_eq!(decoder.frames_decoded(), 4);
+        assert_eq!(decoder.type_count(FrameType::Array), 2);
+        assert_eq!(decoder.type_count(FrameType::String), 1);
+        assert_eq!(decoder.type_count(FrameType::Error), 1);
+        assert_eq!(decoder.type_count(FrameType::Bulk), 0);
     }
 
-    /// It will read buffers from the inner BufReader, decode it to a Value.
-    pub fn decode(&mut self) -> Result<Value> {
-        let mut res: Vec<u8> = Vec::new();
-        self.reader.read_until(b'\n', &mut res)?;
+    /// A reader that fails with `WouldBlock` before every chunk.
+    struct WouldBlock<'a> {
+        chunks: Vec<&'a [u8]>,
+        blocked: bool,
+    }
 
-        let len = res.len();
-        if len == 0 {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "unexpected EOF"));
-        }
-        if len < 3 {
-            return Err(Error::new(ErrorKind::InvalidInput, format!("too short: {}", len)));
+    impl<'a> Read for WouldBlock<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(Error::new(ErrorKind::WouldBlock, "would block"));
+            }
+            let chunk = self.chunks.remove(0);
+            let n = min(buf.len(), chunk.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.chunks.insert(0, &chunk[n..]);
+            }
+            Ok(n)
         }
-        if !is_crlf(res[len - 2], res[len - 1]) {
-            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid CRLF: {:?}", res)));
+    }
+
+    #[test]
+    fn struct_decoder_nonblocking() {
+        let values = vec![Value::Array(vec![Value::Bulk("SET".to_string()),
+                                            Value::Bulk("a".repeat(100)),
+                                            Value::Map(vec![(Value::Integer(1),
+                                                             Value::Null)])]),
+                          Value::String("OK".to_string()),
+                          Value::Bulk("streamed".to_string())];
+        let mut buf: Vec<u8> = Vec::new();
+        for value in &values[..2] {
+            buf.extend_from_slice(&value.encode());
         }
+        buf.extend_from_slice(b"$?\r\n;3\r\nstr\r\n;5\r\neamed\r\n;0\r\n");
 
-        let bytes = res[1..len - 2].as_ref();
-        match res[0] {
-            // Value::String
-            b'+' => parse_string(bytes).map(Value::String),
-            // Value::Error
-            b'-' => parse_string(bytes).map(Value::Error),
-            // Value::Integer
-            b':' => parse_integer(bytes).map(Value::Integer),
-            // Value::Bulk
-            b'$' => {
-                let int = parse_integer(bytes)?;
-                if int == -1 {
-                    // Null bulk
-                    return Ok(Value::Null);
-                }
-                if int < -1 || int >= RESP_MAX_SIZE {
-                    return Err(Error::new(ErrorKind::InvalidInput,
-                                          format!("invalid bulk length: {}", int)));
+        for size in &[1, 2, 7, 64] {
+            let reader = WouldBlock {
+                chunks: buf.chunks(*size).collect(),
+                blocked: false,
+            };
+            let mut decoder = Decoder::new(BufReader::with_capacity(16, reader));
+            decoder.set_nonblocking(true);
+            let mut res: Vec<Value> = Vec::new();
+            let mut blocked = 0;
+            while res.len() < values.len() {
+                match decoder.decode() {
+                    Ok(value) => res.push(value),
+                    Err(err) => {
+                        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                        blocked += 1;
+                    }
                 }
+            }
+            assert_eq!(res, values);
+            assert!(blocked >= buf.len() / size);
+            assert_eq!(decoder.bytes_consumed(), buf.len() as u64);
+            assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
 
-                let mut buf: Vec<u8> = Vec::new();
-                let int = int as usize;
-                buf.resize(int + 2, 0);
-                self.reader.read_exact(buf.as_mut_slice())?;
-                if !is_crlf(buf[int], buf[int + 1]) {
-                    return Err(Error::new(ErrorKind::InvalidInput,
-                                          format!("invalid CRLF: {:?}", buf)));
-                }
-                buf.truncate(int);
-                if self.buf_bulk {
-                    return Ok(Value::BufBulk(buf));
-                }
-                parse_string(buf.as_slice()).map(Value::Bulk)
+        // the other entry points resume too.
+        for size in &[1, 3, 64] {
+            let reader = WouldBlock {
+                chunks: buf.chunks(*size).collect(),
+                blocked: false,
+            };
+            let mut decoder = Decoder::new(BufReader::with_capacity(16, reader));
+            decoder.set_nonblocking(true);
+            while let Err(err) = decoder.skip_value() {
+                assert_eq!(err.kind(), ErrorKind::WouldBlock);
             }
-            // Value::Array
-            b'*' => {
-                let int = parse_integer(bytes)?;
-                if int == -1 {
-                    // Null array
-                    return Ok(Value::NullArray);
+            assert!(decoder.reader.journal.is_empty());
+            let mut res: Vec<Value> = Vec::new();
+            while res.is_empty() {
+                match decoder.decode_all() {
+                    Ok(values) => res.extend(values),
+                    Err(err) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
                 }
-                if int < -1 || int >= RESP_MAX_SIZE {
-                    return Err(Error::new(ErrorKind::InvalidInput,
-                                          format!("invalid array length: {}", int)));
+            }
+            assert_eq!(res[0], values[1]);
+            let mut payload: Vec<u8> = Vec::new();
+            let mut reader = loop {
+                match decoder.read_bulk() {
+                    Ok(reader) => break reader.unwrap(),
+                    Err(err) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
                 }
-
-                let mut array: Vec<Value> = Vec::with_capacity(int as usize);
-                for _ in 0..int {
-                    let val = self.decode()?;
-                    array.push(val);
+            };
+            let mut chunk = [0u8; 4];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => payload.extend_from_slice(&chunk[..n]),
+                    Err(err) => assert_eq!(err.kind(), ErrorKind::WouldBlock),
                 }
-                Ok(Value::Array(array))
             }
-            prefix => {
-                Err(Error::new(ErrorKind::InvalidInput,
-                               format!("invalid RESP type: {:?}", prefix)))
+            assert_eq!(payload, b"streamed");
+            assert_eq!(decoder.bytes_consumed(), buf.len() as u64);
+            assert!(decoder.reader.journal.is_empty());
+        }
+
+        let mut decoder = Decoder::new(&buf[..]);
+        decoder.set_nonblocking(true);
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_frame(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(decoder.decode().unwrap(), values[0]);
+
+        // without the nonblocking mode the partial value is lost.
+        let reader = WouldBlock {
+            chunks: buf.chunks(8).collect(),
+            blocked: false,
+        };
+        let mut decoder = Decoder::new(BufReader::with_capacity(16, reader));
+        let mut res: Vec<Value> = Vec::new();
+        loop {
+            match decoder.decode() {
+                Ok(value) => res.push(value),
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(_) => {}
             }
         }
+        assert_ne!(res, values);
     }
-}
 
-#[inline]
-fn is_crlf(a: u8, b: u8) -> bool {
-    a == b'\r' && b == b'\n'
-}
+    #[test]
+    fn struct_decoder_skip_value() {
+        let values = vec![Value::Array(vec![Value::Bulk("GET".to_string()),
+                                            Value::Map(vec![(Value::Integer(1),
+                                                             Value::Set(vec![]))]),
+                                            Value::Push(vec![Value::NullArray, Value::Null])]),
+                          Value::BufBulk(vec![0xff; 10_000]),
+                          Value::Array(vec![]),
+                          Value::Error("ERR".to_string()),
+                          Value::Double(1.5)];
+        let mut buf: Vec<u8> = Vec::new();
+        for value in &values {
+            buf.extend_from_slice(&value.encode());
+            buf.extend_from_slice(b":1\r\n");
+        }
+        buf.extend_from_slice(b"|1\r\n+a\r\n+b\r\n*?\r\n$?\r\n;1\r\na\r\n;0\r\n");
+        buf.extend_from_slice(b"%?\r\n.\r\n.\r\n:1\r\n");
+        let mut decoder = Decoder::from_chunks(buf.chunks(7));
+        for _ in 0..values.len() + 1 {
+            decoder.skip_value().unwrap();
+            assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+        }
+        assert_eq!(decoder.skip_value().unwrap_err().kind(), ErrorKind::UnexpectedEof);
 
-#[inline]
-fn parse_string(bytes: &[u8]) -> Result<String> {
-    String::from_utf8(bytes.to_vec()).map_err(|err| Error::new(ErrorKind::InvalidData, err))
-}
+        let invalid: &[&[u8]] = &[b"$3\r\nfoo\r\r",
+                                  b"*1\r\n!\r\n",
+                                  b"%?\r\n+a\r\n.\r\n",
+                                  b".\r\n",
+                                  b"#x\r\n"];
+        for case in invalid {
+            let mut decoder = Decoder::new(*case);
+            assert_eq!(decoder.skip_value().unwrap_err().kind(),
+                       ErrorKind::InvalidInput,
+                       "{:?}",
+                       case);
+        }
+        let mut decoder = Decoder::new(&b"*3\r\n:1\r\n:2\r\n:3\r\n"[..]);
+        decoder.set_max_array_len(2);
+        assert_eq!(decoder.skip_value().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"*1\r\n$3\r\nfoo\r\n"[..]);
+        decoder.set_max_frame_len(8);
+        assert_eq!(decoder.skip_value().unwrap_err().kind(), ErrorKind::InvalidInput);
+        let mut decoder = Decoder::new(&b"PING\r\n:1\r\n"[..]);
+        decoder.set_inline_commands(true);
+        decoder.skip_value().unwrap();
+        assert_eq!(decoder.decode().unwrap(), Value::Array(vec![Value::Bulk(":1".to_string())]));
+    }
 
-#[inline]
-fn parse_integer(bytes: &[u8]) -> Result<i64> {
-    let str_integer = parse_string(bytes)?;
-    (str_integer.parse::<i64>()).map_err(|err| Error::new(ErrorKind::InvalidData, err))
-}
+    #[test]
+    fn struct_decoder_decode_bulk_to() {
+        let payload: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        let mut buf = Value::BufBulk(payload.clone()).encode();
+        buf.extend_from_slice(b"$?\r\n;3\r\nstr\r\n;5\r\neamed\r\n;0\r\n$0\r\n\r\n:1\r\n");
+        let mut decoder = Decoder::from_chunks(buf.chunks(1000));
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap(), Some(payload.len()));
+        assert_eq!(output, payload);
+        output.clear();
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap(), Some(8));
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap(), Some(0));
+        assert_eq!(output, b"streamed");
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+        assert_eq!(decoder.decode_bulk_to(&mut output).unwrap_err().kind(),
+                   ErrorKind::UnexpectedEof);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use super::super::Value;
+        let invalid: &[&[u8]] = &[b"$3\r\nfoo\r\r", b"$-2\r\n", b"$?\r\n;1\r\na\r\n.\r\n"];
+        for case in invalid {
+            let mut decoder = Decoder::new(*case);
+            assert_eq!(decoder.decode_bulk_to(&mut io::sink()).unwrap_err().kind(),
+                       ErrorKind::InvalidInput,
+                       "{:?}",
+                       case);
+        }
+        let mut decoder = Decoder::new(&b"$3\r\nfoo\r\n"[..]);
+        decoder.set_max_bulk_len(2);
+        assert_eq!(decoder.decode_bulk_to(&mut io::sink()).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
 
-    struct Case {
-        data: Vec<u8>,
-        want: Value,
+    #[test]
+    fn struct_decoder_eof() {
+        let cases: &[&[u8]] = &[b"",
+                                b"+",
+                                b"+OK",
+                                b"+OK\r",
+                                b"$5\r\nhel",
+                                b"$5\r\nhello",
+                                b"$5\r\nhello\r",
+                                b"*2\r\n:1\r\n",
+                                b"*?\r\n:1\r\n",
+                                b"$?\r\n;1\r\na\r\n"];
+        for case in cases {
+            let mut decoder = Decoder::new(BufReader::new(*case));
+            assert_eq!(decoder.decode().unwrap_err().kind(),
+                       ErrorKind::UnexpectedEof,
+                       "{:?}",
+                       case);
+        }
+
+        let mut decoder = Decoder::new(BufReader::new(&b"+OK\r\n"[..]));
+        assert!(decoder.decode().is_ok());
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        let mut decoder = Decoder::new(BufReader::new(&b"+OK\n"[..]));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
     }
 
     #[test]
-    fn fn_encode_slice() {
-        let array = ["SET", "a", "1"];
-        assert_eq!(String::from_utf8(encode_slice(&array)).unwrap(),
-                   "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+    fn struct_decoder_max_bulk_len() {
+        let buf = b"$5\r\nhello\r\n$-1\r\n*2\r\n$5\r\nhello\r\n$6\r\nhello!\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_bulk_len(5);
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("hello".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Value::Null);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
 
-        let array = vec!["SET", "a", "1"];
-        assert_eq!(String::from_utf8(encode_slice(&array)).unwrap(),
-                   "*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+        // the length is checked before the payload is read.
+        let buf = b"$1073741823\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_bulk_len(1024);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"$?\r\n;3\r\nhel\r\n;2\r\nlo\r\n;0\r\n$?\r\n;3\r\nhel\r\n;3\r\nlo!\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_bulk_len(5);
+        assert_eq!(decoder.decode().unwrap(), Value::Bulk("hello".to_string()));
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "bulk length 6 exceeds max bulk length 5");
+
+        let buf = b"!4\r\nERR!\r\n!20\r\n01234567890123456789\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_passthrough_unknown(true);
+        decoder.set_max_bulk_len(4);
+        assert_eq!(decoder.decode().unwrap(), Value::Unknown(b"!4\r\nERR!\r\n".to_vec()));
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"$6\r\nhello!\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_bulk_len(5);
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn struct_decoder_max_array_len() {
+        let buf = b"*2\r\n:1\r\n*2\r\n:1\r\n:2\r\n*3\r\n%1\r\n:1\r\n:2\r\n%2\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_array_len(2);
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Integer(1),
+                                     Value::Array(vec![Value::Integer(1), Value::Integer(2)])]));
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "array length 3 exceeds max array length 2");
+        decoder.set_max_array_len(1);
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Map(vec![(Value::Integer(1), Value::Integer(2))]));
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "map length 2 exceeds max array length 1");
+
+        let buf = b"*?\r\n:1\r\n:2\r\n.\r\n%?\r\n:1\r\n:2\r\n:3\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_array_len(2);
+        assert!(decoder.decode().is_ok());
+        decoder.set_max_array_len(1);
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "map length 2 exceeds max array length 1");
+
+        let buf = b"*?\r\n:1\r\n:2\r\n:3\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_array_len(2);
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "array length 3 exceeds max array length 2");
+    }
+
+    #[test]
+    fn struct_decoder_max_line_len() {
+        let buf = b"+ABCD\r\n*1\r\n$5\r\nABCDE\r\n-ERROR\r\n";
+        let mut decoder = Decoder::from_chunks(buf.chunks(3));
+        decoder.set_max_line_len(5);
+        assert_eq!(decoder.decode().unwrap(), Value::String("ABCD".to_string()));
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::Array(vec![Value::Bulk("ABCDE".to_string())]));
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "line length 6 exceeds max line length 5");
+
+        // an endless line fails once the max is read.
+        let mut decoder = Decoder::new(BufReader::new(io::repeat(b'a')));
+        decoder.set_max_line_len(64 * 1024);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let mut decoder = Decoder::new(&b"PING\r\nECHO hello\r\n"[..]);
+        decoder.set_inline_commands(true);
+        decoder.set_max_line_len(8);
+        assert!(decoder.decode().is_ok());
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_decoder_max_frame_len() {
+        // 4 + 9 + 9 bytes, then 4 + 4 + 4 + 4 + 4 + 3 bytes
+        let buf = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*2\r\n*1\r\n:1\r\n*?\r\n:2\r\n.\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_frame_len(22);
+        assert!(decoder.decode().is_ok());
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "frame length 23 exceeds max frame length 22");
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_frame_len(23);
+        assert!(decoder.decode().is_ok());
+        assert!(decoder.decode().is_ok());
+
+        // a bulk string fails before its payload is read.
+        let buf = b"*2\r\n:1\r\n$1000000\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_frame_len(1024);
+        assert_eq!(decoder.decode().unwrap_err().to_string(),
+                   "frame length 1000020 exceeds max frame length 1024");
+
+        let buf = b"$?\r\n;3\r\nfoo\r\n;3\r\nbar\r\n;0\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_frame_len(20);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let buf = b"$6\r\nfoobar\r\n";
+        let mut decoder = Decoder::new(BufReader::new(&buf[..]));
+        decoder.set_max_frame_len(11);
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn struct_encoder_downgrade() {
+        let value = Value::Push(vec![Value::Bulk("message".to_string()),
+                                     Value::Map(vec![(Value::Boolean(false),
+                                                      Value::Set(vec![Value::Double(f64::NAN)])),
+                                                     (Value::BigNumber("1".to_string()),
+                                                      Value::Null)]),
+                                     Value::Attribute(vec![(Value::Null, Value::Null)],
+                                                      Box::new(Value::Integer(7))),
+                                     Value::NullArray]);
+        assert_eq!(Encoder::new(Version::Resp2).encode(&value),
+                   b"*4\r\n$7\r\nmessage\r\n*4\r\n:0\r\n*1\r\n$3\r\nnan\r\n$1\r\n1\r\n\
+                     $-1\r\n:7\r\n*-1\r\n"
+                       .to_vec());
+        assert_eq!(Encoder::new(Version::Resp3).encode(&value),
+                   b">4\r\n$7\r\nmessage\r\n%2\r\n#f\r\n~1\r\n,nan\r\n(1\r\n_\r\n\
+                     |1\r\n_\r\n_\r\n:7\r\n_\r\n"
+                       .to_vec());
+    }
+
+    #[test]
+    fn struct_encoder() {
+        let value = Value::Map(vec![(Value::Null,
+                                     Value::Push(vec![Value::NullArray,
+                                                      Value::Set(vec![Value::Null])]))]);
+        assert_eq!(Encoder::default().version(), Version::Resp2);
+        assert_eq!(Encoder::new(Version::Resp2).encode(&value),
+                   b"*2\r\n$-1\r\n*2\r\n*-1\r\n*1\r\n$-1\r\n".to_vec());
+        assert_eq!(Encoder::new(Version::Resp3).encode(&value),
+                   b"%1\r\n_\r\n>2\r\n_\r\n~1\r\n_\r\n".to_vec());
+
+        let value = Value::Array(vec![Value::Bulk("a".to_string()), Value::Integer(1)]);
+        assert_eq!(Encoder::new(Version::Resp2).encode(&value), encode(&value));
+        assert_eq!(Encoder::new(Version::Resp3).encode(&value), encode(&value));
+    }
+
+    #[test]
+    fn struct_encoder_double_precision() {
+        let value = Value::Array(vec![Value::Double(1.0 / 3.0),
+                                      Value::Double(2.5),
+                                      Value::Double(f64::NAN)]);
+        let mut encoder = Encoder::new(Version::Resp3);
+        assert_eq!(encoder.double_precision(), None);
+        assert_eq!(encoder.encode(&value), encode(&value));
+        encoder.set_double_precision(Some(2));
+        assert_eq!(encoder.double_precision(), Some(2));
+        assert_eq!(encoder.encode(&value),
+                   b"*3\r\n,0.33\r\n,2.50\r\n,nan\r\n".to_vec());
+        encoder.set_double_precision(Some(0));
+        assert_eq!(encoder.encode(&Value::Double(2.5)), b",2\r\n".to_vec());
+
+        let mut encoder = Encoder::new(Version::Resp2);
+        encoder.set_double_precision(Some(1));
+        assert_eq!(encoder.encode(&value),
+                   b"*3\r\n$3\r\n0.3\r\n$3\r\n2.5\r\n$3\r\nnan\r\n".to_vec());
+    }
+
+    #[test]
+    fn fn_format_double() {
+        let cases: &[(f64, &str)] = &[(0.0, "0"),
+                                      (-0.0, "-0"),
+                                      (1.5, "1.5"),
+                                      (0.1, "0.1"),
+                                      (100.0, "100"),
+                                      (1e21, "1e21"),
+                                      (1e300, "1e300"),
+                                      (-1.5e-7, "-1.5e-7"),
+                                      (f64::MAX, "1.7976931348623157e308"),
+                                      (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+                                      (f64::INFINITY, "inf"),
+                                      (f64::NEG_INFINITY, "-inf"),
+                                      (f64::NAN, "nan")];
+        for &(val, want) in cases {
+            let got = format_double(val);
+            assert_eq!(got, want);
+            let parsed = parse_double(got.as_bytes()).unwrap();
+            assert!(parsed == val || val.is_nan() && parsed.is_nan());
+            assert_eq!(parsed.is_sign_negative(), val.is_sign_negative());
+        }
     }
 
     #[test]
@@ -497,6 +5172,12 @@ mod tests {
         let mut decoder = Decoder::new(BufReader::new(buf));
         let rt = decoder.decode();
         assert!(rt.is_err());
+        assert_eq!(rt.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let buf: &[u8] = &[1, 2, 10];
+        let mut decoder = Decoder::new(BufReader::new(buf));
+        let rt = decoder.decode();
+        assert!(rt.is_err());
         assert_eq!(rt.unwrap_err().kind(), ErrorKind::InvalidInput);
 
 
@@ -547,4 +5228,86 @@ mod tests {
         let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
         assert!(decoder.decode().is_err());
     }
+
+    #[test]
+    fn fn_relay_bulk() {
+        let mut payload: Vec<u8> = Vec::new();
+        for i in 0..(RELAY_BUF_SIZE * 2 + 3) {
+            payload.push((i % 256) as u8);
+        }
+        let values = vec![Value::BufBulk(payload),
+                          Value::BufBulk(vec![]),
+                          Value::Null,
+                          Value::Integer(123),
+                          Value::Array(vec![Value::Bulk("foo".to_string()), Value::Null])];
+        let mut all: Vec<u8> = Vec::new();
+        for value in &values {
+            all.extend_from_slice(&value.encode());
+        }
+
+        let mut decoder = Decoder::new(BufReader::new(all.as_slice()));
+        let mut output: Vec<u8> = Vec::new();
+        for value in &values {
+            let n = relay_bulk(&mut decoder, &mut output).unwrap();
+            assert_eq!(n, value.encode().len());
+        }
+        assert_eq!(output, all);
+        assert!(relay_bulk(&mut decoder, &mut output).is_err());
+
+        let buf = "$3\r\nfoo\r\n:1\r\n".to_string().into_bytes();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap(), 9);
+        assert_eq!(decoder.decode().unwrap(), Value::Integer(1));
+
+        let buf = "$3\r\nfoobar\r\n".to_string().into_bytes();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let mut output: Vec<u8> = Vec::new();
+        let rt = relay_bulk(&mut decoder, &mut output);
+        assert_eq!(rt.unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+
+        let buf = "$3\r\nfo".to_string().into_bytes();
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let mut output: Vec<u8> = Vec::new();
+        assert!(relay_bulk(&mut decoder, &mut output).is_err());
+
+        // nested payloads are streamed, not decoded, so they needn't be valid UTF-8, and every
+        // header is relayed as it was read.
+        let command = Value::Array(vec![Value::Bulk("SET".to_string()),
+                                        Value::Bulk("key".to_string()),
+                                        Value::BufBulk(vec![0xff; RELAY_BUF_SIZE * 2])]);
+        let mut buf = command.encode();
+        buf.extend_from_slice(b"%1\r\n$1\r\na\r\n*0\r\n*03\r\n:1\r\n|1\r\n+t\r\n:1\r\n_\r\n:2\r\n");
+        buf.extend_from_slice(b"*1\r\n$?\r\n;1\r\nb\r\n;0\r\n");
+        let mut decoder = Decoder::new(BufReader::new(buf.as_slice()));
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap(), command.encode().len());
+        assert_eq!(output, command.encode());
+        let mut output: Vec<u8> = Vec::new();
+        relay_bulk(&mut decoder, &mut output).unwrap();
+        relay_bulk(&mut decoder, &mut output).unwrap();
+        relay_bulk(&mut decoder, &mut output).unwrap();
+        assert_eq!(output, &buf[command.encode().len()..]);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        let cases = [&b"_\r\n"[..],
+                     b",1.50\r\n",
+                     b"|1\r\n+a\r\n:1\r\n:2\r\n",
+                     b"*1\r\n$03\r\nfoo\r\n"];
+        for case in &cases {
+            let mut decoder = Decoder::new(*case);
+            let mut output: Vec<u8> = Vec::new();
+            assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap(), case.len());
+            assert_eq!(&output[..], *case);
+        }
+
+        // an invalid value shorter than the buffer is not written.
+        let buf = b"*2\r\n$3\r\nGET\r\n$3\r\nkeyX\r\n";
+        let mut decoder = Decoder::new(&buf[..]);
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(relay_bulk(&mut decoder, &mut output).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+    }
 }
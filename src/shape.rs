@@ -0,0 +1,302 @@
+//! RESP reply shape
+
+use std::vec::Vec;
+use std::boxed::Box;
+use std::io::{Result, Error, ErrorKind};
+
+use super::Value;
+
+/// Describes the expected shape of a RESP value, see `Value::matches`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Shape {
+    /// Matches any value.
+    Any,
+    /// Matches `Null` and `NullArray`.
+    Null,
+    /// Matches a `String`.
+    String,
+    /// Matches an `Error`.
+    Error,
+    /// Matches an `Integer`.
+    Int,
+    /// Matches a `Bulk` or a `BufBulk`.
+    Bulk,
+    /// Matches a RESP3 `Boolean`.
+    Boolean,
+    /// Matches a RESP3 `Double`.
+    Double,
+    /// Matches a RESP3 `BigNumber`.
+    BigNumber,
+    /// Matches an `Array` with exactly these elements.
+    Array(Vec<Shape>),
+    /// Matches an `Array` of any length whose elements all match the shape.
+    ArrayOf(Box<Shape>),
+    /// Matches a RESP3 `Map` with exactly these entries, in order.
+    Map(Vec<(Shape, Shape)>),
+    /// Matches a RESP3 `Map` of any length whose keys and values all match the shapes.
+    MapOf(Box<Shape>, Box<Shape>),
+    /// Matches a RESP3 `Set` whose elements all match the shape.
+    Set(Box<Shape>),
+    /// Matches a RESP3 `Push` with exactly these elements.
+    Push(Vec<Shape>),
+    /// Matches a value with RESP3 attributes, the attributes match the first shape, a `Map` or
+    /// `MapOf` shape, and the value the second one.
+    Attribute(Box<Shape>, Box<Shape>),
+    /// Matches a null value or the inner shape.
+    Optional(Box<Shape>),
+    /// Matches a value encoded like the given value, see `Value::eq_encoded`, so a `Bulk`
+    /// matches a `BufBulk` of the same bytes.
+    Verbatim(Value),
+}
+
+/// Checks the value against the shape, `path` holds the indexes locating the value, it is only
+/// formatted into the error of a mismatch. The key of the `i`th entry of a map is at index
+/// `2 * i` and its value at `2 * i + 1`, the attributes of a value are at index 0 and the value
+/// at index 1.
+pub fn check(value: &Value, shape: &Shape, path: &mut Vec<usize>) -> Result<()> {
+    let ok = match (shape, value) {
+        (Shape::Any, _) => true,
+        (Shape::Null, _) => value.is_null(),
+        (Shape::String, Value::String(_)) => true,
+        (Shape::Error, Value::Error(_)) => true,
+        (Shape::Int, Value::Integer(_)) => true,
+        (Shape::Boolean, Value::Boolean(_)) => true,
+        (Shape::Double, Value::Double(_)) => true,
+        (Shape::BigNumber, Value::BigNumber(_)) => true,
+        (Shape::Bulk, Value::Bulk(_)) |
+        (Shape::Bulk, Value::BufBulk(_)) |
+        (Shape::Bulk, Value::SharedBulk(_)) |
+        (Shape::Bulk, Value::SmallBulk(_)) => true,
+        (Shape::Array(shapes), Value::Array(values)) => {
+            check_values(values, shapes, "array", path)?;
+            true
+        }
+        (Shape::ArrayOf(shape), Value::Array(values)) |
+        (Shape::Set(shape), Value::Set(values)) => {
+            for (i, value) in values.iter().enumerate() {
+                path.push(i);
+                check(value, shape, path)?;
+                path.pop();
+            }
+            true
+        }
+        (Shape::Push(shapes), Value::Push(values)) => {
+            check_values(values, shapes, "push", path)?;
+            true
+        }
+        (Shape::Map(_), Value::Map(pairs)) |
+        (Shape::MapOf(_, _), Value::Map(pairs)) => {
+            check_pairs(pairs, shape, "map", path)?;
+            true
+        }
+        (Shape::Attribute(attributes_shape, shape), Value::Attribute(attributes, value)) => {
+            path.push(0);
+            check_pairs(attributes, attributes_shape, "attributes", path)?;
+            path.pop();
+            path.push(1);
+            check(value, shape, path)?;
+            path.pop();
+            true
+        }
+        (Shape::Optional(shape), _) => {
+            if value.is_null() {
+                return Ok(());
+            }
+            return check(value, shape, path);
+        }
+        (Shape::Verbatim(expected), _) => {
+            if !expected.eq_encoded(value) {
+                return Err(mismatch(path, &format!("{:?}", expected), &format!("{:?}", value)));
+            }
+            true
+        }
+        _ => false,
+    };
+
+    if ok {
+        Ok(())
+    } else {
+        Err(mismatch(path, shape_name(shape), value.type_name()))
+    }
+}
+
+/// Checks the elements of an array or a push against the shapes of its exact elements.
+fn check_values(values: &[Value],
+                shapes: &[Shape],
+                name: &str,
+                path: &mut Vec<usize>)
+                -> Result<()> {
+    if shapes.len() != values.len() {
+        return Err(mismatch(path,
+                            &format!("{} of {} elements", name, shapes.len()),
+                            &format!("{} of {} elements", name, values.len())));
+    }
+    for (i, (value, shape)) in values.iter().zip(shapes).enumerate() {
+        path.push(i);
+        check(value, shape, path)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Checks the entries of a map or of attributes against a `Map` or a `MapOf` shape, `name`
+/// names the entries in the error of any other shape.
+fn check_pairs(pairs: &[(Value, Value)],
+               shape: &Shape,
+               name: &str,
+               path: &mut Vec<usize>)
+               -> Result<()> {
+    match *shape {
+        Shape::Any => Ok(()),
+        Shape::Map(ref shapes) => {
+            if shapes.len() != pairs.len() {
+                return Err(mismatch(path,
+                                    &format!("map of {} entries", shapes.len()),
+                                    &format!("map of {} entries", pairs.len())));
+            }
+            for (i, (pair, shapes)) in pairs.iter().zip(shapes).enumerate() {
+                check_pair(i, pair, &shapes.0, &shapes.1, path)?;
+            }
+            Ok(())
+        }
+        Shape::MapOf(ref key_shape, ref value_shape) => {
+            for (i, pair) in pairs.iter().enumerate() {
+                check_pair(i, pair, key_shape, value_shape, path)?;
+            }
+            Ok(())
+        }
+        _ => Err(mismatch(path, shape_name(shape), name)),
+    }
+}
+
+fn check_pair(i: usize,
+              pair: &(Value, Value),
+              key_shape: &Shape,
+              value_shape: &Shape,
+              path: &mut Vec<usize>)
+              -> Result<()> {
+    path.push(2 * i);
+    check(&pair.0, key_shape, path)?;
+    path.pop();
+    path.push(2 * i + 1);
+    check(&pair.1, value_shape, path)?;
+    path.pop();
+    Ok(())
+}
+
+fn mismatch(path: &[usize], expected: &str, found: &str) -> Error {
+    let mut location = "value".to_string();
+    for i in path {
+        location.push_str(&format!("[{}]", i));
+    }
+    Error::new(ErrorKind::InvalidData,
+               format!("{}: expected {}, found {}", location, expected, found))
+}
+
+fn shape_name(shape: &Shape) -> &'static str {
+    match *shape {
+        Shape::Any => "any",
+        Shape::Null => "null",
+        Shape::String => "string",
+        Shape::Error => "error",
+        Shape::Int => "integer",
+        Shape::Bulk => "bulk",
+        Shape::Boolean => "boolean",
+        Shape::Double => "double",
+        Shape::BigNumber => "big number",
+        Shape::Array(_) | Shape::ArrayOf(_) => "array",
+        Shape::Map(_) | Shape::MapOf(_, _) => "map",
+        Shape::Set(_) => "set",
+        Shape::Push(_) => "push",
+        Shape::Attribute(_, _) => "attribute",
+        Shape::Optional(_) => "optional",
+        Shape::Verbatim(_) => "verbatim",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fn_check() {
+        let value = Value::Array(vec![Value::Bulk("0".to_string()),
+                                      Value::Array(vec![Value::BufBulk(vec![79, 75]),
+                                                        Value::Null])]);
+        assert!(value.matches(&Shape::Any).is_ok());
+        assert!(value.matches(&Shape::Array(vec![Shape::Bulk, Shape::Any])).is_ok());
+        assert!(value.matches(&Shape::Array(vec![Shape::Verbatim(Value::Bulk("0".to_string())),
+                                                 Shape::ArrayOf(Box::new(Shape::Optional(
+                                                     Box::new(Shape::Bulk))))]))
+                     .is_ok());
+
+        let err = value.matches(&Shape::Array(vec![Shape::Bulk,
+                                                   Shape::ArrayOf(Box::new(Shape::Bulk))]))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "value[1][1]: expected bulk, found null");
+
+        let err = value.matches(&Shape::Array(vec![Shape::Bulk])).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "value: expected array of 1 elements, found array of 2 elements");
+
+        let err = value.matches(&Shape::Array(vec![Shape::Int, Shape::Any])).unwrap_err();
+        assert_eq!(err.to_string(), "value[0]: expected integer, found bulk");
+
+        let verbatim = Shape::Verbatim(Value::Bulk("OK".to_string()));
+        assert!(Value::BufBulk(b"OK".to_vec()).matches(&verbatim).is_ok());
+        assert!(Value::String("OK".to_string()).matches(&verbatim).is_err());
+
+        let err = Value::Integer(1).matches(&Shape::Verbatim(Value::Integer(2))).unwrap_err();
+        assert_eq!(err.to_string(), "value: expected Integer(2), found Integer(1)");
+
+        assert!(Value::NullArray.matches(&Shape::Null).is_ok());
+        assert!(Value::NullArray.matches(&Shape::Optional(Box::new(Shape::Int))).is_ok());
+        assert!(Value::Integer(1).matches(&Shape::Optional(Box::new(Shape::Int))).is_ok());
+        assert!(Value::String("OK".to_string()).matches(&Shape::String).is_ok());
+        assert!(Value::Error("ERR".to_string()).matches(&Shape::Error).is_ok());
+        assert!(Value::Error("ERR".to_string()).matches(&Shape::String).is_err());
+    }
+
+    #[test]
+    fn fn_check_resp3() {
+        let value = Value::Map(vec![(Value::Bulk("ok".to_string()), Value::Boolean(true)),
+                                    (Value::Bulk("ratio".to_string()), Value::Double(0.5))]);
+        assert!(value.matches(&Shape::Map(vec![(Shape::Bulk, Shape::Boolean),
+                                               (Shape::Bulk, Shape::Double)]))
+                     .is_ok());
+        assert!(value.matches(&Shape::MapOf(Box::new(Shape::Bulk), Box::new(Shape::Any))).is_ok());
+        let err = value.matches(&Shape::MapOf(Box::new(Shape::Bulk), Box::new(Shape::Boolean)))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "value[3]: expected boolean, found double");
+        let err = value.matches(&Shape::Map(vec![])).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "value: expected map of 0 entries, found map of 2 entries");
+        assert!(value.matches(&Shape::ArrayOf(Box::new(Shape::Any))).is_err());
+
+        let set = Value::Set(vec![Value::BigNumber("1".to_string())]);
+        assert!(set.matches(&Shape::Set(Box::new(Shape::BigNumber))).is_ok());
+        assert!(set.matches(&Shape::ArrayOf(Box::new(Shape::BigNumber))).is_err());
+
+        let push = Value::Push(vec![Value::Bulk("message".to_string()), Value::Integer(1)]);
+        assert!(push.matches(&Shape::Push(vec![Shape::Bulk, Shape::Int])).is_ok());
+        assert!(push.matches(&Shape::Array(vec![Shape::Bulk, Shape::Int])).is_err());
+        let err = push.matches(&Shape::Push(vec![Shape::Bulk])).unwrap_err();
+        assert_eq!(err.to_string(),
+                   "value: expected push of 1 elements, found push of 2 elements");
+
+        let attributed = Value::Attribute(vec![(Value::Bulk("ttl".to_string()),
+                                                Value::Integer(1))],
+                                          Box::new(Value::Bulk("v".to_string())));
+        let shape = Shape::Attribute(Box::new(Shape::MapOf(Box::new(Shape::Bulk),
+                                                           Box::new(Shape::Int))),
+                                     Box::new(Shape::Bulk));
+        assert!(attributed.matches(&shape).is_ok());
+        let shape = Shape::Attribute(Box::new(Shape::Any), Box::new(Shape::Int));
+        let err = attributed.matches(&shape).unwrap_err();
+        assert_eq!(err.to_string(), "value[1]: expected integer, found bulk");
+        let shape = Shape::Attribute(Box::new(Shape::Int), Box::new(Shape::Any));
+        let err = attributed.matches(&shape).unwrap_err();
+        assert_eq!(err.to_string(), "value[0]: expected integer, found attributes");
+    }
+}
@@ -0,0 +1,138 @@
+//! Inline storage for short bulk strings
+
+use std::fmt;
+use std::ops::Deref;
+
+/// The max length of a `SmallBulk`, with its length byte it takes the 24 bytes of a `String`, so
+/// `Value` doesn't grow.
+pub const SMALL_BULK_CAPACITY: usize = 23;
+
+/// The payload of a short bulk string stored inline, without a heap allocation, see
+/// `Decoder::set_small_bulks`.
+#[derive(Clone, Copy)]
+pub struct SmallBulk {
+    len: u8,
+    bytes: [u8; SMALL_BULK_CAPACITY],
+}
+
+impl SmallBulk {
+    /// Copies the bytes inline. Returns `None` if they are longer than `SMALL_BULK_CAPACITY`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{SmallBulk, SMALL_BULK_CAPACITY};
+    /// let bulk = SmallBulk::new(b"key").unwrap();
+    /// assert_eq!(&bulk[..], b"key");
+    /// assert!(SmallBulk::new(&[0; SMALL_BULK_CAPACITY + 1]).is_none());
+    /// ```
+    pub fn new(bytes: &[u8]) -> Option<SmallBulk> {
+        if bytes.len() > SMALL_BULK_CAPACITY {
+            return None;
+        }
+        let mut bulk = SmallBulk {
+            len: bytes.len() as u8,
+            bytes: [0; SMALL_BULK_CAPACITY],
+        };
+        bulk.bytes[..bytes.len()].copy_from_slice(bytes);
+        Some(bulk)
+    }
+
+    /// Returns the bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl Deref for SmallBulk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PartialEq for SmallBulk {
+    fn eq(&self, other: &SmallBulk) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for SmallBulk {}
+
+impl fmt::Debug for SmallBulk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SmallBulk({:?})", String::from_utf8_lossy(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Value, Decoder, FeedDecoder};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn struct_small_bulk() {
+        assert_eq!(::std::mem::size_of::<SmallBulk>(), ::std::mem::size_of::<String>());
+        assert_eq!(::std::mem::size_of::<Value>(), 32);
+
+        let bulk = SmallBulk::new(&[b'a'; SMALL_BULK_CAPACITY]).unwrap();
+        assert_eq!(bulk.len(), SMALL_BULK_CAPACITY);
+        assert_eq!(SmallBulk::new(b"").unwrap().as_bytes(), b"");
+        assert_eq!(SmallBulk::new(b"ab").unwrap(), SmallBulk::new(b"ab").unwrap());
+        assert!(SmallBulk::new(b"ab").unwrap() != SmallBulk::new(b"a").unwrap());
+        assert_eq!(format!("{:?}", SmallBulk::new(b"ab").unwrap()), "SmallBulk(\"ab\")");
+
+        let value = Value::from(bulk);
+        assert_eq!(value.encode(), Value::BufBulk(bulk.to_vec()).encode());
+        assert_eq!(value.encoded_len(), SMALL_BULK_CAPACITY + 7);
+        assert_eq!(value.mem_size(), ::std::mem::size_of::<Value>());
+        assert_eq!(value.as_str(), Some(&"a".repeat(SMALL_BULK_CAPACITY)[..]));
+        assert!(value.is_bulk());
+    }
+
+    #[test]
+    fn struct_small_bulk_decode() {
+        let long = "b".repeat(SMALL_BULK_CAPACITY + 1);
+        let value = Value::Array(vec![Value::Bulk("a".repeat(SMALL_BULK_CAPACITY)),
+                                      Value::Bulk(String::new()),
+                                      Value::Bulk(long.clone())]);
+        let expected = Value::Array(vec![Value::SmallBulk(SmallBulk::new(&[b'a';
+                                                                            SMALL_BULK_CAPACITY])
+                                                              .unwrap()),
+                                         Value::SmallBulk(SmallBulk::new(b"").unwrap()),
+                                         Value::Bulk(long)]);
+        let buf = value.encode();
+
+        let mut decoder = Decoder::new(buf.as_slice());
+        decoder.set_small_bulks(true);
+        assert_eq!(decoder.decode().unwrap(), expected);
+
+        let mut decoder = FeedDecoder::new();
+        decoder.set_small_bulks(true);
+        decoder.feed(&buf).unwrap();
+        assert_eq!(decoder.read().unwrap(), expected);
+
+        let mut decoder = FeedDecoder::new();
+        decoder.set_small_bulks(true);
+        decoder = FeedDecoder::restore(&decoder.snapshot()).unwrap();
+        decoder.feed(b"$3\r\nabc\r\n").unwrap();
+        assert_eq!(decoder.read(), Some(Value::SmallBulk(SmallBulk::new(b"abc").unwrap())));
+
+        // not valid UTF-8
+        let buf = b"$2\r\n\xff\xfe\r\n";
+        let mut decoder = Decoder::new(&buf[..]);
+        decoder.set_small_bulks(true);
+        assert_eq!(decoder.decode().unwrap_err().kind(), ErrorKind::InvalidData);
+        let mut decoder = Decoder::new(&buf[..]);
+        decoder.set_small_bulks(true);
+        decoder.set_buf_bulk_fallback(true);
+        assert_eq!(decoder.decode().unwrap(),
+                   Value::SmallBulk(SmallBulk::new(b"\xff\xfe").unwrap()));
+
+        for buf in &[&b"$2\r\nab\r"[..], &b"$2\r\nabc\r\n"[..]] {
+            let mut decoder = Decoder::new(&buf[..]);
+            decoder.set_small_bulks(true);
+            assert!(decoder.decode().is_err());
+        }
+    }
+}
@@ -3,31 +3,145 @@
 use std::vec::Vec;
 use std::string::String;
 use std::marker::{Send, Sync};
-use std::io::{Result, Error, ErrorKind};
-use super::serialize::encode;
+use std::hash::Hasher;
+use std::str::{self, FromStr};
+use std::ops::Index;
+use std::fmt::{self, Write};
+use std::iter::FromIterator;
+use std::sync::{Arc, LazyLock};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Result, Error, ErrorKind};
+use super::serialize::{encode, encode_canonical, encode_to_fmt, encoded_len, format_double};
+use super::shape::{self, Shape};
+use super::de::decode;
+use super::small::SmallBulk;
+#[cfg(feature = "bignum")]
+use num_bigint::BigInt;
 
 /// Represents a RESP value, see [Redis Protocol specification](http://redis.io/topics/protocol).
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Value {
-    /// Null bulk reply, `$-1\r\n`
+    /// Null bulk reply, `$-1\r\n`, or RESP3 null reply, `_\r\n`, the default value.
+    #[default]
     Null,
     /// Null array reply, `*-1\r\n`
     NullArray,
-    /// For Simple Strings the first byte of the reply is "+".
+    /// For Simple Strings the first byte of the reply is "+", CR and LF are encoded as spaces.
     String(String),
-    /// For Errors the first byte of the reply is "-".
+    /// For Errors the first byte of the reply is "-", CR and LF are encoded as spaces.
     Error(String),
     /// For Integers the first byte of the reply is ":".
     Integer(i64),
+    /// For RESP3 Booleans the first byte of the reply is "#", `#t\r\n` or `#f\r\n`.
+    Boolean(bool),
+    /// For RESP3 Doubles the first byte of the reply is ",", e.g. `,3.25\r\n` or `,inf\r\n`.
+    /// Doubles are equal if their bits are, so `Value` is `Eq`: a NaN equals itself and `0.0`
+    /// differs from `-0.0`.
+    Double(f64),
+    /// For RESP3 Big Numbers the first byte of the reply is "(", holding the decimal digits
+    /// with an optional `-` sign, e.g. `(3492890328409238509324850943850943825024385\r\n`.
+    BigNumber(String),
     /// For Bulk Strings the first byte of the reply is "$".
     Bulk(String),
     /// For Bulk <binary> Strings the first byte of the reply is "$".
     BufBulk(Vec<u8>),
     /// For Arrays the first byte of the reply is "*".
     Array(Vec<Value>),
+    /// For RESP3 Maps the first byte of the reply is "%", the entries keep the order of the reply.
+    Map(Vec<(Value, Value)>),
+    /// For RESP3 Sets the first byte of the reply is "~", the elements keep the order of the reply.
+    Set(Vec<Value>),
+    /// For RESP3 Pushes the first byte of the reply is ">", they are out of band data like
+    /// pub/sub messages and client side caching invalidations.
+    Push(Vec<Value>),
+    /// For RESP3 Attributes the first byte of the reply is "|", the attributes are followed by
+    /// the value they describe, see `Decoder::set_keep_attributes`.
+    Attribute(Vec<(Value, Value)>, Box<Value>),
+    /// For values of a type unknown to this crate, holding the raw bytes of the value,
+    /// see `Decoder::set_passthrough_unknown`.
+    Unknown(Vec<u8>),
+    /// Already encoded RESP bytes, e.g. a hot reply cached by a proxy, the encoders write them
+    /// verbatim. The bytes should hold complete RESP values, they are not validated.
+    Raw(Vec<u8>),
+    /// A bulk string sharing its payload, cloning the value only bumps a reference count, e.g.
+    /// for a proxy sending the same payload to many clients. It is encoded and treated like a
+    /// `BufBulk`, the decoders never produce it.
+    SharedBulk(Arc<[u8]>),
+    /// A short bulk string stored inline, without a heap allocation, see
+    /// `Decoder::set_small_bulks`. It is encoded and treated like a `BufBulk`.
+    SmallBulk(SmallBulk),
 }
 
+/// The `+OK` reply shared by its users, borrowing it doesn't allocate, unlike `Value::okay`.
+/// # Examples
+/// ```
+/// # use self::resp::{encode, Value, OK_BYTES, OK_REPLY};
+/// assert_eq!(*OK_REPLY, Value::okay());
+/// assert_eq!(encode(&OK_REPLY), OK_BYTES);
+/// ```
+pub static OK_REPLY: LazyLock<Value> = LazyLock::new(Value::okay);
+/// The `+PONG` reply shared by its users, see `OK_REPLY`.
+pub static PONG_REPLY: LazyLock<Value> = LazyLock::new(Value::pong);
+/// The `+QUEUED` reply shared by its users, see `OK_REPLY`.
+pub static QUEUED_REPLY: LazyLock<Value> = LazyLock::new(Value::queued);
+
 impl Value {
+    /// Returns the `+OK` reply, its `String` is allocated by every call. Hot paths can borrow
+    /// `OK_REPLY` instead, or write `OK_BYTES` to save encoding it too.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, OK_BYTES};
+    /// assert_eq!(Value::okay(), Value::String("OK".to_string()));
+    /// assert_eq!(Value::okay().encode(), OK_BYTES);
+    /// ```
+    pub fn okay() -> Value {
+        Value::String("OK".to_string())
+    }
+
+    /// Returns the `+PONG` reply to `PING`, its `String` is allocated by every call. Hot paths
+    /// can borrow `PONG_REPLY` instead, or write `PONG_BYTES`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, PONG_BYTES};
+    /// assert_eq!(Value::pong().encode(), PONG_BYTES);
+    /// ```
+    pub fn pong() -> Value {
+        Value::String("PONG".to_string())
+    }
+
+    /// Returns the `+QUEUED` reply to a command inside `MULTI`, its `String` is allocated by every
+    /// call. Hot paths can borrow `QUEUED_REPLY` instead, or write `QUEUED_BYTES`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, QUEUED_BYTES};
+    /// assert_eq!(Value::queued().encode(), QUEUED_BYTES);
+    /// ```
+    pub fn queued() -> Value {
+        Value::String("QUEUED".to_string())
+    }
+
+    /// Returns an `Error` reply of an error code and a message, e.g. `-WRONGTYPE Operation
+    /// against a key holding the wrong kind of value`. Fails with `InvalidInput` if the code is
+    /// empty or holds whitespace, or if either holds CR or LF.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let value = Value::error_with_code("WRONGTYPE", "Operation against a key").unwrap();
+    /// assert_eq!(value, Value::Error("WRONGTYPE Operation against a key".to_string()));
+    /// assert!(Value::error_with_code("ERR", "bad\r\n+OK").is_err());
+    /// ```
+    pub fn error_with_code(code: &str, message: &str) -> Result<Value> {
+        if code.is_empty() || code.contains(char::is_whitespace) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("invalid error code {:?}", code)));
+        }
+        if message.contains(['\r', '\n']) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("error message {:?} contains CR or LF", message)));
+        }
+        Ok(Value::Error(format!("{} {}", code, message)))
+    }
+
     /// Returns `true` if the value is a `Null` or `NullArray`. Returns `false` otherwise.
     /// # Examples
     /// ```
@@ -36,6 +150,7 @@ impl Value {
     /// assert_eq!(Value::NullArray.is_null(), true);
     /// assert_eq!(Value::Integer(123).is_null(), false);
     /// ```
+    #[allow(clippy::match_like_matches_macro)]
     pub fn is_null(&self) -> bool {
         match *self {
             Value::Null | Value::NullArray => true,
@@ -50,6 +165,7 @@ impl Value {
     /// assert_eq!(Value::Null.is_error(), false);
     /// assert_eq!(Value::Error("".to_string()).is_error(), true);
     /// ```
+    #[allow(clippy::match_like_matches_macro)]
     pub fn is_error(&self) -> bool {
         match *self {
             Value::Error(_) => true,
@@ -57,6 +173,482 @@ impl Value {
         }
     }
 
+    /// Returns `true` if the value is a `String`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::String("OK".to_string()).is_string(), true);
+    /// assert_eq!(Value::Bulk("OK".to_string()).is_string(), false);
+    /// ```
+    pub fn is_string(&self) -> bool {
+        matches!(*self, Value::String(_))
+    }
+
+    /// Returns `true` if the value is an `Integer`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Integer(1).is_integer(), true);
+    /// assert_eq!(Value::Bulk("1".to_string()).is_integer(), false);
+    /// ```
+    pub fn is_integer(&self) -> bool {
+        matches!(*self, Value::Integer(_))
+    }
+
+    /// Returns `true` if the value is a `Boolean`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Boolean(false).is_boolean(), true);
+    /// assert_eq!(Value::Integer(0).is_boolean(), false);
+    /// ```
+    pub fn is_boolean(&self) -> bool {
+        matches!(*self, Value::Boolean(_))
+    }
+
+    /// Returns `true` if the value is a `Double`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Double(1.5).is_double(), true);
+    /// assert_eq!(Value::Integer(1).is_double(), false);
+    /// ```
+    pub fn is_double(&self) -> bool {
+        matches!(*self, Value::Double(_))
+    }
+
+    /// Returns `true` if the value is a `BigNumber`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::BigNumber("1".to_string()).is_big_number(), true);
+    /// assert_eq!(Value::Integer(1).is_big_number(), false);
+    /// ```
+    pub fn is_big_number(&self) -> bool {
+        matches!(*self, Value::BigNumber(_))
+    }
+
+    /// Returns `true` if the value is a `Bulk` or `BufBulk`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::BufBulk(vec![]).is_bulk(), true);
+    /// assert_eq!(Value::String("OK".to_string()).is_bulk(), false);
+    /// ```
+    pub fn is_bulk(&self) -> bool {
+        matches!(*self,
+                 Value::Bulk(_) | Value::BufBulk(_) | Value::SharedBulk(_) | Value::SmallBulk(_))
+    }
+
+    /// Returns `true` if the value is an `Array`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Array(vec![]).is_array(), true);
+    /// assert_eq!(Value::NullArray.is_array(), false);
+    /// ```
+    pub fn is_array(&self) -> bool {
+        matches!(*self, Value::Array(_))
+    }
+
+    /// Returns `true` if the value is a `Map`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Map(vec![]).is_map(), true);
+    /// assert_eq!(Value::Array(vec![]).is_map(), false);
+    /// ```
+    pub fn is_map(&self) -> bool {
+        matches!(*self, Value::Map(_))
+    }
+
+    /// Returns `true` if the value is a `Set`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Set(vec![]).is_set(), true);
+    /// assert_eq!(Value::Array(vec![]).is_set(), false);
+    /// ```
+    pub fn is_set(&self) -> bool {
+        matches!(*self, Value::Set(_))
+    }
+
+    /// Returns `true` if the value is a `Push`. Returns `false` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Push(vec![]).is_push(), true);
+    /// assert_eq!(Value::Array(vec![]).is_push(), false);
+    /// ```
+    pub fn is_push(&self) -> bool {
+        matches!(*self, Value::Push(_))
+    }
+
+    /// Returns the element at a `usize` index if the value is an `Array`, `Set` or `Push`, or
+    /// the value of the first entry with a string key equal to a `&str` index if the value is a
+    /// `Map`. Returns `None` otherwise, or if the index is out of bounds.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let val = Value::Map(vec![(Value::Bulk("maxmemory".to_string()), Value::Integer(0))]);
+    /// assert_eq!(val.get("maxmemory"), Some(&Value::Integer(0)));
+    /// assert_eq!(val.get("timeout"), None);
+    /// assert_eq!(Value::Null.get("maxmemory"), None);
+    ///
+    /// let val = Value::Array(vec![Value::String("OK".to_string()), Value::Integer(1)]);
+    /// assert_eq!(val.get(1), Some(&Value::Integer(1)));
+    /// assert_eq!(val.get(2), None);
+    /// assert_eq!(val.get("OK"), None);
+    /// ```
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&Value> {
+        index.index_into(self)
+    }
+
+    /// Descends through nested values, indexing each level with `get`. Returns `None` if a level
+    /// has no such element. A path mixing array indexes and map keys is a slice of
+    /// `&dyn ValueIndex`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, ValueIndex};
+    /// let reply = Value::Array(vec![Value::Null,
+    ///                               Value::Array(vec![Value::Array(vec![Value::Integer(1),
+    ///                                                                   Value::Integer(2)])])]);
+    /// assert_eq!(reply.get_path(&[1, 0, 1]), Some(&Value::Integer(2)));
+    /// assert_eq!(reply.get_path(&[1, 1, 0]), None);
+    ///
+    /// let info = Value::Map(vec![(Value::Bulk("entries".to_string()), reply)]);
+    /// let path: &[&dyn ValueIndex] = &[&"entries", &1, &0, &0];
+    /// assert_eq!(info.get_path(path), Some(&Value::Integer(1)));
+    /// ```
+    pub fn get_path<I: ValueIndex>(&self, path: &[I]) -> Option<&Value> {
+        path.iter().try_fold(self, |value, index| index.index_into(value))
+    }
+
+    /// Inserts an entry into a `Map`, keeping the order of the entries. If an entry with an
+    /// equal key exists, its value is replaced and the old value returned.
+    /// A `Null` or `NullArray` value becomes an empty `Map` first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not a `Map` or a null.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let mut val = Value::Null;
+    /// assert_eq!(val.insert(Value::Bulk("a".to_string()), Value::Integer(1)), None);
+    /// assert_eq!(val.insert(Value::Bulk("b".to_string()), Value::Integer(2)), None);
+    /// assert_eq!(val.insert(Value::Bulk("a".to_string()), Value::Integer(3)),
+    ///            Some(Value::Integer(1)));
+    /// assert_eq!(val.encode(), b"%2\r\n$1\r\na\r\n:3\r\n$1\r\nb\r\n:2\r\n");
+    /// ```
+    pub fn insert(&mut self, key: Value, value: Value) -> Option<Value> {
+        if self.is_null() {
+            *self = Value::Map(Vec::new());
+        }
+        match *self {
+            Value::Map(ref mut map) => {
+                if let Some(entry) = map.iter_mut().find(|entry| entry.0 == key) {
+                    return Some(::std::mem::replace(&mut entry.1, value));
+                }
+                map.push((key, value));
+                None
+            }
+            _ => panic!("insert into {} value", self.type_name()),
+        }
+    }
+
+    /// Takes the value out, leaving `Null` in its place.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// struct Pending {
+    ///     reply: Value,
+    /// }
+    /// let mut pending = Pending { reply: Value::String("OK".to_string()) };
+    /// assert_eq!(pending.reply.take(), Value::String("OK".to_string()));
+    /// assert_eq!(pending.reply, Value::Null);
+    /// ```
+    pub fn take(&mut self) -> Value {
+        ::std::mem::take(self)
+    }
+
+    /// Returns an iterator over the entries if the value is a `Map`, in order. Returns an empty
+    /// iterator otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let val = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+    ///                           (Value::Bulk("b".to_string()), Value::Integer(2))]);
+    /// let sum: i64 = val.pairs()
+    ///     .map(|(_, value)| match *value {
+    ///              Value::Integer(val) => val,
+    ///              _ => 0,
+    ///          })
+    ///     .sum();
+    /// assert_eq!(sum, 3);
+    /// assert_eq!(Value::Integer(1).pairs().count(), 0);
+    /// ```
+    pub fn pairs(&self) -> Pairs<'_> {
+        let map: &[(Value, Value)] = match *self {
+            Value::Map(ref map) => map,
+            _ => &[],
+        };
+        Pairs { inner: map.iter() }
+    }
+
+    /// Returns the entries of a `Map`, or of a RESP2 flat `Array` of alternating keys and
+    /// values, e.g. the reply of `HGETALL` or `CONFIG GET`. Fails with `InvalidData` if the
+    /// array has an odd number of elements, or if the value is of another type.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Bulk("maxmemory".to_string()),
+    ///                               Value::Bulk("0".to_string())]);
+    /// let pairs = reply.as_pairs().unwrap();
+    /// assert_eq!(pairs[0].0, &Value::Bulk("maxmemory".to_string()));
+    /// assert_eq!(pairs[0].1, &Value::Bulk("0".to_string()));
+    ///
+    /// let reply = Value::Array(vec![Value::Bulk("maxmemory".to_string())]);
+    /// assert!(reply.as_pairs().is_err());
+    /// ```
+    pub fn as_pairs(&self) -> Result<Vec<(&Value, &Value)>> {
+        match *self {
+            Value::Map(_) => Ok(self.pairs().collect()),
+            Value::Array(ref val) if val.len() % 2 == 0 => {
+                Ok(val.chunks(2).map(|pair| (&pair[0], &pair[1])).collect())
+            }
+            Value::Array(ref val) => {
+                Err(Error::new(ErrorKind::InvalidData,
+                               format!("expected array of key and value pairs, found array of \
+                                        {} elements",
+                                       val.len())))
+            }
+            _ => {
+                Err(Error::new(ErrorKind::InvalidData,
+                               format!("expected array or map, found {}", self.type_name())))
+            }
+        }
+    }
+
+    /// Collects the entries of a `Map`, or of a flat `Array`, see `as_pairs`, into a `HashMap`,
+    /// a later entry replacing an earlier one with the same key. Fails with `InvalidData` if a
+    /// key is not a string, see `as_str`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Bulk("a".to_string()), Value::Integer(1),
+    ///                               Value::Bulk("b".to_string()), Value::Integer(2)]);
+    /// let map = reply.to_map().unwrap();
+    /// assert_eq!(map["b"], Value::Integer(2));
+    /// ```
+    pub fn to_map(&self) -> Result<HashMap<String, Value>> {
+        let pairs = self.as_pairs()?;
+        let mut map = HashMap::with_capacity(pairs.len());
+        for (key, value) in pairs {
+            let key = key.as_str().ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData,
+                           format!("expected string key, found {}", key.type_name()))
+            })?;
+            map.insert(key.to_string(), value.clone());
+        }
+        Ok(map)
+    }
+
+    /// Returns an iterator over the elements if the value is an `Array`, `Set` or `Push`.
+    /// Returns an empty iterator otherwise. `&Value` and `Value` implement `IntoIterator` the
+    /// same way, the latter moves the elements out.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Bulk("a".to_string()), Value::Null]);
+    /// let keys: Vec<&str> = reply.iter().filter_map(Value::as_str).collect();
+    /// assert_eq!(keys, vec!["a"]);
+    /// let values: Vec<Value> = reply.into_iter().collect();
+    /// assert_eq!(values, vec![Value::Bulk("a".to_string()), Value::Null]);
+    /// assert_eq!(Value::Integer(1).iter().count(), 0);
+    /// ```
+    pub fn iter(&self) -> ::std::slice::Iter<'_, Value> {
+        self.as_array().unwrap_or(&[]).iter()
+    }
+
+    /// Returns the text if the value is a `String` or `Bulk`, or a `BufBulk` of valid UTF-8.
+    /// Returns `None` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::String("OK".to_string()).as_str(), Some("OK"));
+    /// assert_eq!(Value::BufBulk(vec![79, 75]).as_str(), Some("OK"));
+    /// assert_eq!(Value::BufBulk(vec![255]).as_str(), None);
+    /// assert_eq!(Value::Error("ERR".to_string()).as_str(), None);
+    /// ```
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref val) | Value::Bulk(ref val) => Some(val),
+            Value::BufBulk(ref val) => ::std::str::from_utf8(val).ok(),
+            Value::SharedBulk(ref val) => ::std::str::from_utf8(val).ok(),
+            Value::SmallBulk(ref val) => ::std::str::from_utf8(val).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes if the value is a `String`, `Bulk` or `BufBulk`. Returns `None`
+    /// otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Bulk("OK".to_string()).as_bytes(), Some(&b"OK"[..]));
+    /// assert_eq!(Value::BufBulk(vec![255]).as_bytes(), Some(&[255][..]));
+    /// assert_eq!(Value::Integer(1).as_bytes(), None);
+    /// ```
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::String(ref val) | Value::Bulk(ref val) => Some(val.as_bytes()),
+            Value::BufBulk(ref val) => Some(val),
+            Value::SharedBulk(ref val) => Some(val),
+            Value::SmallBulk(ref val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if the value is an `Integer`. Returns `None` otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Integer(-1).as_i64(), Some(-1));
+    /// assert_eq!(Value::Bulk("1".to_string()).as_i64(), None);
+    /// ```
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements if the value is an `Array`, `Set` or `Push`. Returns `None`
+    /// otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let val = Value::Array(vec![Value::Integer(1), Value::Null]);
+    /// assert_eq!(val.as_array(), Some(&[Value::Integer(1), Value::Null][..]));
+    /// assert_eq!(Value::NullArray.as_array(), None);
+    /// ```
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Moves the text out if the value is a `String` or `Bulk`, or a `BufBulk` of valid UTF-8.
+    /// Returns the value back as the error otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Bulk("OK".to_string()).into_string(), Ok("OK".to_string()));
+    /// assert_eq!(Value::BufBulk(vec![255]).into_string(), Err(Value::BufBulk(vec![255])));
+    /// ```
+    pub fn into_string(self) -> ::std::result::Result<String, Value> {
+        match self {
+            Value::String(val) | Value::Bulk(val) => Ok(val),
+            Value::BufBulk(val) => {
+                String::from_utf8(val).map_err(|err| Value::BufBulk(err.into_bytes()))
+            }
+            Value::SharedBulk(val) => {
+                match ::std::str::from_utf8(&val) {
+                    Ok(string) => Ok(string.to_string()),
+                    Err(_) => Err(Value::SharedBulk(val)),
+                }
+            }
+            Value::SmallBulk(val) => {
+                match ::std::str::from_utf8(&val) {
+                    Ok(string) => Ok(string.to_string()),
+                    Err(_) => Err(Value::SmallBulk(val)),
+                }
+            }
+            value => Err(value),
+        }
+    }
+
+    /// Moves the bytes out if the value is a `String`, `Bulk` or `BufBulk`. Returns the value
+    /// back as the error otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::String("OK".to_string()).into_bytes(), Ok(b"OK".to_vec()));
+    /// assert_eq!(Value::Integer(1).into_bytes(), Err(Value::Integer(1)));
+    /// ```
+    pub fn into_bytes(self) -> ::std::result::Result<Vec<u8>, Value> {
+        match self {
+            Value::String(val) | Value::Bulk(val) => Ok(val.into_bytes()),
+            Value::BufBulk(val) => Ok(val),
+            Value::SharedBulk(val) => Ok(val.to_vec()),
+            Value::SmallBulk(val) => Ok(val.to_vec()),
+            value => Err(value),
+        }
+    }
+
+    /// Moves the elements out if the value is an `Array`, `Set` or `Push`. Returns the value
+    /// back as the error otherwise.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let val = Value::Array(vec![Value::Integer(1)]);
+    /// assert_eq!(val.into_array(), Ok(vec![Value::Integer(1)]));
+    /// assert_eq!(Value::NullArray.into_array(), Err(Value::NullArray));
+    /// ```
+    pub fn into_array(self) -> ::std::result::Result<Vec<Value>, Value> {
+        match self {
+            Value::Array(val) | Value::Set(val) | Value::Push(val) => Ok(val),
+            value => Err(value),
+        }
+    }
+
+    /// Returns the name of the value type, e.g. `"bulk"` for `Bulk` and `BufBulk`, as used in
+    /// error messages and logs.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::BufBulk(vec![]).type_name(), "bulk");
+    /// assert_eq!(Value::NullArray.type_name(), "null array");
+    /// assert_eq!(Value::BigNumber("1".to_string()).type_name(), "big number");
+    /// ```
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::NullArray => "null array",
+            Value::String(_) => "string",
+            Value::Error(_) => "error",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Double(_) => "double",
+            Value::BigNumber(_) => "big number",
+            Value::Bulk(_) | Value::BufBulk(_) | Value::SharedBulk(_) | Value::SmallBulk(_) => {
+                "bulk"
+            }
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Set(_) => "set",
+            Value::Push(_) => "push",
+            Value::Attribute(..) => "attribute",
+            Value::Unknown(_) => "unknown",
+            Value::Raw(_) => "raw",
+        }
+    }
+
+    /// Decodes the bytes of a `Raw` value when they hold a single complete value.
+    pub(crate) fn decode_raw(&self) -> Option<Value> {
+        match *self {
+            Value::Raw(ref val) => {
+                match decode(val) {
+                    Ok((value, len)) if len == val.len() => Some(value),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     /// Encode the value to RESP binary buffer.
     /// # Examples
     /// ```
@@ -68,6 +660,175 @@ impl Value {
         encode(self)
     }
 
+    /// Returns the number of bytes of the encoded value, without encoding it. `encode` allocates
+    /// this many bytes up front.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let value = Value::Array(vec![Value::Bulk("GET".to_string()), Value::Integer(-1)]);
+    /// assert_eq!(value.encoded_len(), 18);
+    /// assert_eq!(value.encoded_len(), value.encode().len());
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        encoded_len(self)
+    }
+
+    /// Returns the approximate memory footprint of the value in bytes, the size of the value
+    /// itself plus the capacity of the strings and vectors it owns, nested values included. The
+    /// payload of a `SharedBulk` is counted in full, even if other values share it.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// use std::mem::size_of;
+    ///
+    /// assert_eq!(Value::Integer(1).mem_size(), size_of::<Value>());
+    /// let mut payload = Vec::with_capacity(1024);
+    /// payload.push(0);
+    /// let value = Value::Array(vec![Value::BufBulk(payload)]);
+    /// assert_eq!(value.mem_size(), 2 * size_of::<Value>() + 1024);
+    /// ```
+    pub fn mem_size(&self) -> usize {
+        ::std::mem::size_of::<Value>() + self.heap_size()
+    }
+
+    /// Returns the capacity of the heap allocations owned by the value.
+    fn heap_size(&self) -> usize {
+        let value_size = ::std::mem::size_of::<Value>();
+        match *self {
+            Value::String(ref val) | Value::Error(ref val) | Value::BigNumber(ref val) |
+            Value::Bulk(ref val) => val.capacity(),
+            Value::BufBulk(ref val) | Value::Unknown(ref val) | Value::Raw(ref val) => {
+                val.capacity()
+            }
+            // the reference counts are stored with the payload.
+            Value::SharedBulk(ref val) => 2 * ::std::mem::size_of::<usize>() + val.len(),
+            Value::SmallBulk(_) => 0,
+            Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                val.capacity() * value_size + val.iter().map(Value::heap_size).sum::<usize>()
+            }
+            Value::Map(ref val) => pairs_heap_size(val),
+            Value::Attribute(ref attributes, ref val) => {
+                pairs_heap_size(attributes) + val.mem_size()
+            }
+            Value::Null | Value::NullArray | Value::Integer(_) | Value::Boolean(_) |
+            Value::Double(_) => 0,
+        }
+    }
+
+    /// Encode the value to canonical RESP binary buffer, see `encode_canonical`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::Bulk("OK".to_string()).encode_canonical(),
+    ///            Value::BufBulk(vec![79, 75]).encode_canonical());
+    /// ```
+    pub fn encode_canonical(&self) -> Vec<u8> {
+        encode_canonical(self)
+    }
+
+    /// Returns `true` if the values have the same encoding, e.g. a `Bulk` and a `BufBulk` of the
+    /// same bytes, which compare unequal with `==`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let value = Value::Array(vec![Value::Bulk("OK".to_string())]);
+    /// assert!(value.eq_encoded(&Value::Array(vec![Value::BufBulk(vec![79, 75])])));
+    /// assert!(!value.eq_encoded(&Value::Array(vec![Value::String("OK".to_string())])));
+    /// ```
+    pub fn eq_encoded(&self, other: &Value) -> bool {
+        self.encoded_len() == other.encoded_len() && self.encode() == other.encode()
+    }
+
+    /// Converts the value to its canonical form in place, so values equal in content compare
+    /// equal with `==`. The canonical value encodes to the `encode_canonical` encoding: `Bulk`
+    /// becomes `BufBulk`, `Map` and `Attribute` entries and `Set` elements are sorted, and a
+    /// `Double` zero loses its sign.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let mut value = Value::Set(vec![Value::Bulk("b".to_string()), Value::BufBulk(vec![97])]);
+    /// value.canonicalize();
+    /// assert_eq!(value, Value::Set(vec![Value::BufBulk(vec![97]), Value::BufBulk(vec![98])]));
+    /// ```
+    pub fn canonicalize(&mut self) {
+        match *self {
+            Value::Bulk(ref mut val) => {
+                let val = ::std::mem::take(val).into_bytes();
+                *self = Value::BufBulk(val);
+            }
+            Value::SharedBulk(ref val) => *self = Value::BufBulk(val.to_vec()),
+            Value::SmallBulk(ref val) => *self = Value::BufBulk(val.to_vec()),
+            // matches -0.0 too.
+            Value::Double(ref mut val) if *val == 0.0 => *val = 0.0,
+            Value::Array(ref mut val) | Value::Push(ref mut val) => {
+                val.iter_mut().for_each(Value::canonicalize);
+            }
+            Value::Set(ref mut val) => {
+                val.iter_mut().for_each(Value::canonicalize);
+                val.sort_by_cached_key(Value::encode);
+            }
+            Value::Map(ref mut val) => canonicalize_pairs(val),
+            Value::Attribute(ref mut attributes, ref mut val) => {
+                canonicalize_pairs(attributes);
+                val.canonicalize();
+            }
+            _ => {}
+        }
+    }
+
+    /// Hashes the canonical encoding of the value, values equal in content have the same digest.
+    /// The digest is only as stable as the algorithm of the hasher: `DefaultHasher` may change
+    /// between Rust releases, so a digest stored or compared across builds, e.g. between a
+    /// primary and its replicas, needs a hasher with a specified algorithm like FNV-1a below.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// use std::hash::Hasher;
+    ///
+    /// // 64-bit FNV-1a.
+    /// struct Fnv(u64);
+    ///
+    /// impl Default for Fnv {
+    ///     fn default() -> Fnv {
+    ///         Fnv(0xcbf29ce484222325)
+    ///     }
+    /// }
+    ///
+    /// impl Hasher for Fnv {
+    ///     fn write(&mut self, bytes: &[u8]) {
+    ///         for byte in bytes {
+    ///             self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100000001b3);
+    ///         }
+    ///     }
+    ///
+    ///     fn finish(&self) -> u64 {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(Value::Bulk("OK".to_string()).digest::<Fnv>(),
+    ///            Value::BufBulk(vec![79, 75]).digest::<Fnv>());
+    /// assert_eq!(Value::Integer(1).digest::<Fnv>(), 0xfa15374d45321e03);
+    /// ```
+    pub fn digest<H: Hasher + Default>(&self) -> u64 {
+        let mut hasher = H::default();
+        hasher.write(&self.encode_canonical());
+        hasher.finish()
+    }
+
+    /// Writes the canonical encoding of the value to `writer`, for digests that are not a
+    /// `Hasher`: cryptographic hashers such as `sha2::Sha256` implement `io::Write`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let mut output = Vec::new();
+    /// Value::Set(vec![Value::Integer(2), Value::Integer(1)]).digest_into(&mut output).unwrap();
+    /// assert_eq!(output, b"~2\r\n:1\r\n:2\r\n");
+    /// ```
+    pub fn digest_into<W: io::Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.encode_canonical())
+    }
+
     /// Encode the value to RESP string.
     /// # Examples
     /// ```
@@ -76,11 +837,14 @@ impl Value {
     /// assert_eq!(val.to_encoded_string().unwrap(), "+OK正\r\n");
     /// ```
     pub fn to_encoded_string(&self) -> Result<String> {
-        let bytes = self.encode();
-        String::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+        let mut string = String::with_capacity(self.encoded_len());
+        encode_to_fmt(self, &mut string)?;
+        Ok(string)
     }
 
-    /// Encode the value to beautify formated string.
+    /// Encode the value to beautify formated string, the same as formatting it with `Display`.
+    /// Bulk strings are quoted, with quotes, backslashes and control characters escaped like
+    /// redis-cli does.
     /// # Examples
     /// ```
     /// # use self::resp::{Value};
@@ -89,10 +853,20 @@ impl Value {
     /// assert_eq!(Value::String("OK".to_string()).to_string_pretty(), "OK");
     /// assert_eq!(Value::Error("Err".to_string()).to_string_pretty(), "(Error) Err");
     /// assert_eq!(Value::Integer(123).to_string_pretty(), "(Integer) 123");
+    /// assert_eq!(Value::Boolean(true).to_string_pretty(), "(Boolean) true");
+    /// assert_eq!(Value::Double(1.5).to_string_pretty(), "(Double) 1.5");
     /// assert_eq!(Value::Bulk("Bulk String".to_string()).to_string_pretty(), "\"Bulk String\"");
+    /// assert_eq!(Value::Bulk("a\"\0\n".to_string()).to_string_pretty(),
+    ///            r#""a\"\x00\n""#);
     /// assert_eq!(Value::BufBulk(vec![]).to_string_pretty(), "(Empty Buffer)");
     /// assert_eq!(Value::BufBulk(vec![0, 100]).to_string_pretty(), "(Buffer) 00 64");
     /// assert_eq!(Value::Array(vec![]).to_string_pretty(), "(Empty Array)");
+    /// assert_eq!(Value::Map(vec![]).to_string_pretty(), "(Empty Map)");
+    /// assert_eq!(Value::Set(vec![]).to_string_pretty(), "(Empty Set)");
+    /// assert_eq!(Value::Push(vec![]).to_string_pretty(), "(Empty Push)");
+    /// assert_eq!(Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1))])
+    ///                .to_string_pretty(),
+    ///            "1# \"a\" => (Integer) 1");
     /// ```
     ///
     /// A full formated example:
@@ -146,71 +920,765 @@ impl Value {
     ///    11) (Null)
     /// 13) (Null)
     /// ```
-    pub fn to_string_pretty(&self) -> String {
-        match *self {
-            Value::Null => "(Null)".to_string(),
-            Value::NullArray => "(Null Array)".to_string(),
-            Value::String(ref val) => val.to_string(),
-            Value::Error(ref val) => format!("(Error) {}", val),
-            Value::Integer(ref val) => format!("(Integer) {}", val.to_string()),
-            Value::Bulk(ref val) => format!("\"{}\"", val),
-            Value::BufBulk(ref val) => {
-                if val.is_empty() {
-                    return "(Empty Buffer)".to_string();
-                }
-                let mut string = String::new();
-                for u in val.iter().take(16) {
-                    string.push_str(&format_to_hex_str(u));
+    pub fn to_string_pretty(&self) -> String {
+        self.to_string()
+    }
+
+    /// Encode the value to JSON text.
+    ///
+    /// `Null` and `NullArray` become `null`, `Integer` becomes a number, `String` and `Bulk`
+    /// become JSON strings and `Array`, `Set` and `Push` become JSON arrays. `Error` becomes an
+    /// object `{"error":"..."}`. `BufBulk` becomes a JSON string when it is valid UTF-8,
+    /// otherwise an object `{"base64":"..."}` holding the standard base64 (with padding) of the
+    /// bytes.
+    /// `Unknown` becomes an object `{"unknown":"..."}` holding the base64 of the raw bytes.
+    /// `Boolean` becomes `true` or `false`. `BigNumber` becomes a number. `Double` becomes a
+    /// number, or the string `"inf"`, `"-inf"` or `"nan"`.
+    /// `Attribute` becomes an object `{"attributes":{...},"value":...}`.
+    /// `Map` becomes a JSON object, a `String`, `Bulk` or UTF-8 `BufBulk` key is used as is and
+    /// any other key is replaced by its own JSON text.
+    ///
+    /// Strings escape `"`, `\` and control characters (`\n`, `\r`, `\t`, otherwise
+    /// `\u00XX`), other characters are written as UTF-8.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let val = Value::Array(vec![Value::Null,
+    ///                             Value::Integer(1),
+    ///                             Value::Bulk("a\"b\n".to_string()),
+    ///                             Value::Error("ERR".to_string()),
+    ///                             Value::BufBulk(vec![255, 0])]);
+    /// assert_eq!(val.to_json_string(),
+    ///            r#"[null,1,"a\"b\n",{"error":"ERR"},{"base64":"/wA="}]"#);
+    /// ```
+    pub fn to_json_string(&self) -> String {
+        let mut string = String::new();
+        write_json(self, &mut string);
+        string
+    }
+
+    /// Checks the value against a shape, the error describes where the first mismatch is.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value, Shape};
+    /// let val = Value::Array(vec![Value::Bulk("0".to_string()), Value::Integer(1)]);
+    /// assert!(val.matches(&Shape::Array(vec![Shape::Bulk, Shape::Int])).is_ok());
+    /// assert_eq!(val.matches(&Shape::Array(vec![Shape::Bulk, Shape::Bulk]))
+    ///               .unwrap_err()
+    ///               .to_string(),
+    ///            "value[1]: expected bulk, found integer");
+    /// ```
+    pub fn matches(&self, shape: &Shape) -> Result<()> {
+        shape::check(self, shape, &mut Vec::new())
+    }
+
+    /// Encode the value to beautify formated string like `to_string_pretty`, colored with ANSI
+    /// escape codes for terminals: errors are red, integers yellow and the indexes of nested
+    /// values dim. Requires the `color` feature.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let value = Value::Array(vec![Value::Integer(1)]);
+    /// assert_eq!(value.to_string_colored(), "\x1b[2m1)\x1b[0m \x1b[33m(Integer) 1\x1b[0m");
+    /// ```
+    #[cfg(feature = "color")]
+    pub fn to_string_colored(&self) -> String {
+        Colored(self).to_string()
+    }
+
+    /// [DEPRECATED] Alias of to_string_pretty.
+    pub fn to_beautify_string(&self) -> String {
+        self.to_string_pretty()
+    }
+}
+
+/// An iterator over the entries of a `Map` value, see `Value::pairs`.
+#[derive(Clone, Debug)]
+pub struct Pairs<'a> {
+    inner: ::std::slice::Iter<'a, (Value, Value)>,
+}
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a Value, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Pairs<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, value)| (key, value))
+    }
+}
+
+impl<'a> ExactSizeIterator for Pairs<'a> {}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) | (Value::NullArray, Value::NullArray) => true,
+            (Value::String(a), Value::String(b)) |
+            (Value::Error(a), Value::Error(b)) |
+            (Value::BigNumber(a), Value::BigNumber(b)) |
+            (Value::Bulk(a), Value::Bulk(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (Value::BufBulk(a), Value::BufBulk(b)) |
+            (Value::Unknown(a), Value::Unknown(b)) |
+            (Value::Raw(a), Value::Raw(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) |
+            (Value::Set(a), Value::Set(b)) |
+            (Value::Push(a), Value::Push(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::Attribute(a, x), Value::Attribute(b, y)) => a == b && x == y,
+            (Value::SharedBulk(a), Value::SharedBulk(b)) => a == b,
+            (Value::SmallBulk(a), Value::SmallBulk(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl fmt::Display for Value {
+    /// Formats the value like `redis-cli`, see `Value::to_string_pretty`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Bulk("a".to_string()), Value::Integer(1)]);
+    /// assert_eq!(format!("reply: {}", reply), "reply: 1) \"a\"\n2) (Integer) 1");
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_pretty(self,
+                     &mut PrettyWriter {
+                              inner: f,
+                              column: 0,
+                              color: false,
+                          })
+    }
+}
+
+/// Formats a value like `Display`, colored with ANSI escape codes, see `Value::to_string_colored`.
+#[cfg(feature = "color")]
+struct Colored<'a>(&'a Value);
+
+#[cfg(feature = "color")]
+impl<'a> fmt::Display for Colored<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_pretty(self.0,
+                     &mut PrettyWriter {
+                              inner: f,
+                              column: 0,
+                              color: true,
+                          })
+    }
+}
+
+impl IntoIterator for Value {
+    type Item = Value;
+    type IntoIter = ::std::vec::IntoIter<Value>;
+
+    /// Moves the elements out if the value is an `Array`, `Set` or `Push`, see `Value::iter`.
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_array().unwrap_or_default().into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Value {
+    type Item = &'a Value;
+    type IntoIter = ::std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Into<Value>> FromIterator<T> for Value {
+    /// Converts the items and collects them into an `Array`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let keys = vec!["a", "b"];
+    /// let value: Value = keys.iter().map(|key| format!("{}:{}", "user", key)).collect();
+    /// assert_eq!(value,
+    ///            Value::Array(vec![Value::Bulk("user:a".to_string()),
+    ///                              Value::Bulk("user:b".to_string())]));
+    /// ```
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Value {
+        Value::Array(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> Extend<T> for Value {
+    /// Converts the items and appends them to an `Array`, `Set` or `Push`. A `Null` or
+    /// `NullArray` value becomes an empty `Array` first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not an `Array`, `Set`, `Push` or a null.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let mut command = Value::from(vec!["DEL"]);
+    /// command.extend(vec!["a", "b"]);
+    /// assert_eq!(command.encode(), b"*3\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nb\r\n");
+    /// ```
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        if self.is_null() {
+            *self = Value::Array(Vec::new());
+        }
+        match *self {
+            Value::Array(ref mut val) | Value::Set(ref mut val) | Value::Push(ref mut val) => {
+                val.extend(iter.into_iter().map(Into::into));
+            }
+            _ => panic!("extend {} value", self.type_name()),
+        }
+    }
+}
+
+/// An index into a `Value`, see `Value::get`. Implemented for `usize`, to index into an
+/// `Array`, `Set` or `Push`, and for strings, to look up an entry of a `Map`.
+pub trait ValueIndex {
+    /// Returns the indexed value, or `None` if there is none.
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value>;
+}
+
+impl ValueIndex for usize {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        value.as_array().and_then(|values| values.get(*self))
+    }
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        value.pairs()
+            .find(|&(k, _)| key_bytes(k) == Some(self.as_bytes()))
+            .map(|(_, value)| value)
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        self.as_str().index_into(value)
+    }
+}
+
+impl<T: ValueIndex + ?Sized> ValueIndex for &T {
+    fn index_into<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        (**self).index_into(value)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    /// Returns the element at the index of an `Array`, `Set` or `Push`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not an `Array`, `Set` or `Push`, or if the index is out of bounds.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::String("OK".to_string()), Value::Integer(1)]);
+    /// assert_eq!(reply[1], Value::Integer(1));
+    /// ```
+    fn index(&self, index: usize) -> &Value {
+        match self.as_array() {
+            Some(values) => &values[index],
+            None => panic!("index into {} value", self.type_name()),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    /// Converts the string into a `Bulk`.
+    fn from(val: &str) -> Value {
+        Value::Bulk(val.to_string())
+    }
+}
+
+impl From<String> for Value {
+    /// Converts the string into a `Bulk`.
+    fn from(val: String) -> Value {
+        Value::Bulk(val)
+    }
+}
+
+impl From<&[u8]> for Value {
+    /// Converts the bytes into a `BufBulk`.
+    fn from(val: &[u8]) -> Value {
+        Value::BufBulk(val.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    /// Converts the bytes into a `BufBulk`.
+    fn from(val: Vec<u8>) -> Value {
+        Value::BufBulk(val)
+    }
+}
+
+impl From<Arc<[u8]>> for Value {
+    /// Converts the shared bytes into a `SharedBulk`.
+    fn from(val: Arc<[u8]>) -> Value {
+        Value::SharedBulk(val)
+    }
+}
+
+impl From<SmallBulk> for Value {
+    /// Converts the inline bytes into a `SmallBulk`.
+    fn from(val: SmallBulk) -> Value {
+        Value::SmallBulk(val)
+    }
+}
+
+macro_rules! impl_from_integer_for_value {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Value {
+                /// Converts the integer into an `Integer`.
+                fn from(val: $ty) -> Value {
+                    Value::Integer(i64::from(val))
+                }
+            }
+        )*
+    };
+}
+
+// `u8` is left out so that `Vec<u8>` converts into a `BufBulk`, not an `Array`.
+impl_from_integer_for_value!(i64, i32, i16, i8, u32, u16);
+
+impl From<bool> for Value {
+    /// Converts the bool into a RESP3 `Boolean`.
+    fn from(val: bool) -> Value {
+        Value::Boolean(val)
+    }
+}
+
+impl From<f64> for Value {
+    /// Converts the float into a RESP3 `Double`.
+    fn from(val: f64) -> Value {
+        Value::Double(val)
+    }
+}
+
+impl From<f32> for Value {
+    /// Converts the float into a RESP3 `Double`.
+    fn from(val: f32) -> Value {
+        Value::Double(f64::from(val))
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    /// Converts `None` into `Null` and `Some` into the converted value.
+    fn from(val: Option<T>) -> Value {
+        val.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    /// Converts the elements and collects them into an `Array`.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// assert_eq!(Value::from(vec![1, 2]),
+    ///            Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+    /// assert_eq!(Value::from(vec![Some("a"), None]),
+    ///            Value::Array(vec![Value::Bulk("a".to_string()), Value::Null]));
+    /// ```
+    fn from(val: Vec<T>) -> Value {
+        Value::Array(val.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    /// Converts the map into a `Map` with bulk string keys, in the iteration order of the map.
+    fn from(map: HashMap<String, Value>) -> Value {
+        Value::Map(map.into_iter().map(|(key, value)| (Value::Bulk(key), value)).collect())
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    /// Converts the map into a `Map` with bulk string keys, sorted by key.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// # use std::collections::BTreeMap;
+    /// let mut config = BTreeMap::new();
+    /// config.insert("timeout".to_string(), Value::Bulk("0".to_string()));
+    /// config.insert("maxmemory".to_string(), Value::Bulk("0".to_string()));
+    /// let val = Value::from(config);
+    /// assert_eq!(val.pairs().next().unwrap().0, &Value::Bulk("maxmemory".to_string()));
+    /// ```
+    fn from(map: BTreeMap<String, Value>) -> Value {
+        Value::Map(map.into_iter().map(|(key, value)| (Value::Bulk(key), value)).collect())
+    }
+}
+
+impl FromStr for Value {
+    type Err = Error;
+
+    /// Decodes a value from its RESP encoding, see `decode`. Fails with `InvalidInput` if bytes
+    /// follow the value.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let value: Value = "*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n".parse().unwrap();
+    /// assert_eq!(value,
+    ///            Value::Array(vec![Value::Bulk("ECHO".to_string()),
+    ///                              Value::Bulk("hey".to_string())]));
+    /// assert!("+OK\r\n+OK\r\n".parse::<Value>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Value> {
+        let (value, len) = decode(s.as_bytes())?;
+        if len < s.len() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("trailing bytes: {:?}", &s[len..])));
+        }
+        Ok(value)
+    }
+}
+
+fn pairs_heap_size(pairs: &Vec<(Value, Value)>) -> usize {
+    pairs.capacity() * ::std::mem::size_of::<(Value, Value)>() +
+    pairs.iter().map(|(key, value)| key.heap_size() + value.heap_size()).sum::<usize>()
+}
+
+/// Canonicalizes the entries and sorts them by the encoding of their keys, then values.
+fn canonicalize_pairs(pairs: &mut [(Value, Value)]) {
+    for (key, value) in pairs.iter_mut() {
+        key.canonicalize();
+        value.canonicalize();
+    }
+    pairs.sort_by_cached_key(|(key, value)| (key.encode(), value.encode()));
+}
+
+/// Returns the bytes of a string key.
+fn key_bytes(key: &Value) -> Option<&[u8]> {
+    match *key {
+        Value::String(ref key) | Value::Bulk(ref key) => Some(key.as_bytes()),
+        Value::BufBulk(ref key) => Some(key),
+        Value::SharedBulk(ref key) => Some(key),
+        Value::SmallBulk(ref key) => Some(key),
+        _ => None,
+    }
+}
+
+unsafe impl Sync for Value {}
+unsafe impl Send for Value {}
+
+#[cfg(feature = "bignum")]
+impl From<BigInt> for Value {
+    /// Converts the integer into a RESP3 big number, requires the `bignum` feature.
+    /// # Examples
+    /// ```
+    /// # extern crate num_bigint;
+    /// # extern crate resp;
+    /// # use num_bigint::BigInt;
+    /// # use resp::Value;
+    /// # fn main() {
+    /// let big: BigInt = "-3492890328409238509324850943850943825024385".parse().unwrap();
+    /// assert_eq!(Value::from(big).encode(),
+    ///            b"(-3492890328409238509324850943850943825024385\r\n".to_vec());
+    /// # }
+    /// ```
+    fn from(val: BigInt) -> Value {
+        Value::BigNumber(val.to_string())
+    }
+}
+
+fn write_json(value: &Value, string: &mut String) {
+    match *value {
+        Value::Null | Value::NullArray => string.push_str("null"),
+        Value::String(ref val) | Value::Bulk(ref val) => write_json_str(val, string),
+        Value::Error(ref val) => {
+            string.push_str("{\"error\":");
+            write_json_str(val, string);
+            string.push('}');
+        }
+        Value::Integer(ref val) => string.push_str(&val.to_string()),
+        Value::BigNumber(ref val) => string.push_str(val),
+        Value::Boolean(val) => string.push_str(if val { "true" } else { "false" }),
+        Value::Double(val) if val.is_finite() => string.push_str(&format_double(val)),
+        Value::Double(val) => write_json_str(&format_double(val), string),
+        Value::BufBulk(ref val) => write_json_bytes(val, string),
+        Value::SharedBulk(ref val) => write_json_bytes(val, string),
+        Value::SmallBulk(ref val) => write_json_bytes(val, string),
+        Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+            string.push('[');
+            for (i, item) in val.iter().enumerate() {
+                if i > 0 {
+                    string.push(',');
                 }
-                if val.len() > 16 {
-                    string.push_str(" ...");
+                write_json(item, string);
+            }
+            string.push(']');
+        }
+        Value::Map(ref val) => write_json_map(val, string),
+        Value::Attribute(ref attributes, ref val) => {
+            string.push_str("{\"attributes\":");
+            write_json_map(attributes, string);
+            string.push_str(",\"value\":");
+            write_json(val, string);
+            string.push('}');
+        }
+        Value::Unknown(ref val) => {
+            string.push_str("{\"unknown\":\"");
+            write_base64(val, string);
+            string.push_str("\"}");
+        }
+        Value::Raw(ref val) => {
+            match value.decode_raw() {
+                Some(decoded) => write_json(&decoded, string),
+                None => {
+                    string.push_str("{\"raw\":\"");
+                    write_base64(val, string);
+                    string.push_str("\"}");
                 }
-                format!("(Buffer) {}", &string[1..])
             }
-            Value::Array(ref val) => format_array_to_str(val, 0),
         }
     }
-    /// [DEPRECATED] Alias of to_string_pretty.
-    pub fn to_beautify_string(&self) -> String {
-        self.to_string_pretty()
+}
+
+fn write_json_map(map: &[(Value, Value)], string: &mut String) {
+    string.push('{');
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 {
+            string.push(',');
+        }
+        match key.as_str() {
+            Some(key) => write_json_str(key, string),
+            None => write_json_str(&key.to_json_string(), string),
+        }
+        string.push(':');
+        write_json(value, string);
     }
+    string.push('}');
 }
 
-unsafe impl Sync for Value {}
-unsafe impl Send for Value {}
+fn write_json_bytes(val: &[u8], string: &mut String) {
+    match ::std::str::from_utf8(val) {
+        Ok(val) => write_json_str(val, string),
+        Err(_) => {
+            string.push_str("{\"base64\":\"");
+            write_base64(val, string);
+            string.push_str("\"}");
+        }
+    }
+}
+
+fn write_json_str(val: &str, string: &mut String) {
+    string.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                string.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+}
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn write_base64(bytes: &[u8], string: &mut String) {
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0],
+                 if chunk.len() > 1 { chunk[1] } else { 0 },
+                 if chunk.len() > 2 { chunk[2] } else { 0 }];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        string.push(BASE64_CHARS[n >> 18 & 63] as char);
+        string.push(BASE64_CHARS[n >> 12 & 63] as char);
+        if chunk.len() > 1 {
+            string.push(BASE64_CHARS[n >> 6 & 63] as char);
+        } else {
+            string.push('=');
+        }
+        if chunk.len() > 2 {
+            string.push(BASE64_CHARS[n & 63] as char);
+        } else {
+            string.push('=');
+        }
+    }
+}
+
+/// Tracks the column of the output, a nested map value is aligned after the last line of
+/// its key.
+struct PrettyWriter<'a, 'b: 'a> {
+    inner: &'a mut fmt::Formatter<'b>,
+    column: usize,
+    /// whether to color the output with ANSI escape codes.
+    color: bool,
+}
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+impl<'a, 'b> PrettyWriter<'a, 'b> {
+    /// Writes an ANSI escape code if coloring, it doesn't move the column.
+    fn paint(&mut self, code: &str) -> fmt::Result {
+        if self.color {
+            self.inner.write_str(code)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> fmt::Write for PrettyWriter<'a, 'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match s.rfind('\n') {
+            Some(i) => self.column = s[i + 1..].chars().count(),
+            None => self.column += s.chars().count(),
+        }
+        self.inner.write_str(s)
+    }
+}
+
+fn write_pretty(value: &Value, w: &mut PrettyWriter) -> fmt::Result {
+    match *value {
+        Value::Null => w.write_str("(Null)"),
+        Value::NullArray => w.write_str("(Null Array)"),
+        Value::String(ref val) => w.write_str(val),
+        Value::Error(ref val) => {
+            w.paint(RED)?;
+            write!(w, "(Error) {}", val)?;
+            w.paint(RESET)
+        }
+        Value::Integer(ref val) => {
+            w.paint(YELLOW)?;
+            write!(w, "(Integer) {}", val)?;
+            w.paint(RESET)
+        }
+        Value::Boolean(val) => write!(w, "(Boolean) {}", val),
+        Value::Double(val) => write!(w, "(Double) {}", format_double(val)),
+        Value::BigNumber(ref val) => write!(w, "(Big Number) {}", val),
+        Value::Bulk(ref val) => write_quoted(val, w),
+        Value::BufBulk(ref val) => write_buffer(val, w),
+        Value::SharedBulk(ref val) => write_buffer(val, w),
+        Value::SmallBulk(ref val) => write_buffer(val, w),
+        Value::Array(ref val) => write_pretty_array(val, 0, w),
+        Value::Map(ref val) => write_pretty_map(val, 0, w),
+        Value::Set(ref val) if val.is_empty() => w.write_str("(Empty Set)"),
+        Value::Set(ref val) => write_pretty_array(val, 0, w),
+        Value::Push(ref val) if val.is_empty() => w.write_str("(Empty Push)"),
+        Value::Push(ref val) => write_pretty_array(val, 0, w),
+        Value::Attribute(ref attributes, ref val) => {
+            w.write_str("(Attribute) ")?;
+            write_pretty_map(attributes, 13, w)?;
+            w.write_char('\n')?;
+            write_pretty(val, w)
+        }
+        Value::Unknown(ref val) => {
+            w.write_str("(Unknown) ")?;
+            write_quoted_bytes(val, w)
+        }
+        Value::Raw(ref val) => {
+            match value.decode_raw() {
+                Some(decoded) => write_pretty(&decoded, w),
+                None => {
+                    w.write_str("(Raw) ")?;
+                    write_quoted_bytes(val, w)
+                }
+            }
+        }
+    }
+}
 
-fn format_to_hex_str(u: &u8) -> String {
-    if *u >= 16 {
-        format!(" {:x}", u)
-    } else {
-        format!(" 0{:x}", u)
+/// Writes the first 16 bytes in hex.
+fn write_buffer(val: &[u8], w: &mut PrettyWriter) -> fmt::Result {
+    if val.is_empty() {
+        return w.write_str("(Empty Buffer)");
+    }
+    w.write_str("(Buffer)")?;
+    for u in val.iter().take(16) {
+        write!(w, " {:02x}", u)?;
     }
+    if val.len() > 16 {
+        w.write_str(" ...")?;
+    }
+    Ok(())
 }
 
-fn format_index_str(index: usize, num_len: usize) -> String {
-    let mut string = index.to_string();
-    let len = string.len();
+/// Writes the string quoted and escaped like redis-cli does: `\\`, `\"`, `\n`, `\r`, `\t`,
+/// `\a` and `\b`, and the bytes of other control characters as `\xHH`.
+fn write_quoted(val: &str, w: &mut PrettyWriter) -> fmt::Result {
+    w.write_char('"')?;
+    write_escaped(val, w)?;
+    w.write_char('"')
+}
 
-    if num_len > len {
-        let mut len = len;
-        string.reserve(num_len - len);
-        loop {
-            string.insert(0, ' ');
-            len += 1;
-            if num_len == len {
+/// Writes the bytes quoted and escaped like `write_quoted`, the bytes that are not UTF-8 as
+/// `\xHH`.
+fn write_quoted_bytes(mut val: &[u8], w: &mut PrettyWriter) -> fmt::Result {
+    w.write_char('"')?;
+    loop {
+        match str::from_utf8(val) {
+            Ok(text) => {
+                write_escaped(text, w)?;
                 break;
             }
+            Err(err) => {
+                let (text, rest) = val.split_at(err.valid_up_to());
+                write_escaped(str::from_utf8(text).unwrap(), w)?;
+                let invalid = err.error_len().unwrap_or(rest.len());
+                for byte in &rest[..invalid] {
+                    write!(w, "\\x{:02x}", byte)?;
+                }
+                val = &rest[invalid..];
+            }
+        }
+    }
+    w.write_char('"')
+}
+
+fn write_escaped(val: &str, w: &mut PrettyWriter) -> fmt::Result {
+    for c in val.chars() {
+        match c {
+            '\\' => w.write_str("\\\\")?,
+            '"' => w.write_str("\\\"")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            '\u{7}' => w.write_str("\\a")?,
+            '\u{8}' => w.write_str("\\b")?,
+            c if c.is_control() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    write!(w, "\\x{:02x}", byte)?;
+                }
+            }
+            c => w.write_char(c)?,
         }
     }
-    format!("{}) ", string)
+    Ok(())
 }
 
-fn format_array_to_str(array: &[Value], min_index_len: usize) -> String {
+fn write_index(index: usize, num_len: usize, delimiter: char, w: &mut PrettyWriter) -> fmt::Result {
+    w.paint(DIM)?;
+    write!(w, "{:>width$}{}", index, delimiter, width = num_len)?;
+    w.paint(RESET)?;
+    w.write_char(' ')
+}
+
+fn write_pretty_array(array: &[Value], min_index_len: usize, w: &mut PrettyWriter) -> fmt::Result {
     if array.is_empty() {
-        return "(Empty Array)".to_string();
+        return w.write_str("(Empty Array)");
     }
 
-    let mut string = String::new();
     let mut index_len = min_index_len;
     let len = array.len();
     let num_len = len.to_string().len();
@@ -224,16 +1692,58 @@ fn format_array_to_str(array: &[Value], min_index_len: usize) -> String {
         } else {
             index_len
         };
-        string.push_str(&format_index_str(i + 1, num_len));
-        match *value {
-            Value::Array(ref sub) => string.push_str(&format_array_to_str(sub, index_len + 3)),
-            _ => string.push_str(&value.to_string_pretty()),
+        write_index(i + 1, num_len, ')', w)?;
+        write_pretty_nested(value, index_len + 3, w)?;
+        if i + 1 < len {
+            w.write_char('\n')?;
+        }
+    }
+    Ok(())
+}
+
+fn write_pretty_map(map: &[(Value, Value)],
+                    min_index_len: usize,
+                    w: &mut PrettyWriter)
+                    -> fmt::Result {
+    if map.is_empty() {
+        return w.write_str("(Empty Map)");
+    }
+
+    let mut index_len = min_index_len;
+    let len = map.len();
+    let num_len = len.to_string().len();
+    if num_len > index_len {
+        index_len = num_len;
+    }
+    for (i, (key, value)) in map.iter().enumerate() {
+        // first element don't need indent.
+        let num_len = if i == 0 {
+            index_len - min_index_len
+        } else {
+            index_len
         };
+        write_index(i + 1, num_len, '#', w)?;
+        write_pretty_nested(key, index_len + 3, w)?;
+        w.write_str(" => ")?;
+        // a nested value is aligned after the last line of the key.
+        let column = w.column;
+        write_pretty_nested(value, column + 1, w)?;
         if i + 1 < len {
-            string.push('\n');
+            w.write_char('\n')?;
+        }
+    }
+    Ok(())
+}
+
+fn write_pretty_nested(value: &Value, min_index_len: usize, w: &mut PrettyWriter) -> fmt::Result {
+    match *value {
+        Value::Array(ref sub) => write_pretty_array(sub, min_index_len, w),
+        Value::Set(ref sub) | Value::Push(ref sub) if !sub.is_empty() => {
+            write_pretty_array(sub, min_index_len, w)
         }
+        Value::Map(ref sub) => write_pretty_map(sub, min_index_len, w),
+        _ => write_pretty(value, w),
     }
-    string
 }
 
 #[cfg(test)]
@@ -241,6 +1751,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn enum_is_null() {
         assert_eq!(Value::Null.is_null(), true);
         assert_eq!(Value::NullArray.is_null(), true);
@@ -254,6 +1765,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn enum_is_error() {
         assert_eq!(Value::Null.is_error(), false);
         assert_eq!(Value::NullArray.is_error(), false);
@@ -267,6 +1779,154 @@ mod tests {
                    false);
     }
 
+    #[test]
+    fn enum_as_accessors() {
+        let values = vec![Value::String("OK".to_string()),
+                          Value::Bulk("中文".to_string()),
+                          Value::BufBulk("中文".as_bytes().to_vec())];
+        for value in &values {
+            assert!(value.as_str().is_some());
+            assert_eq!(value.as_bytes().unwrap(), value.as_str().unwrap().as_bytes());
+            assert_eq!(value.as_i64(), None);
+            assert_eq!(value.as_array(), None);
+        }
+
+        let others = vec![Value::Null,
+                          Value::Error("ERR".to_string()),
+                          Value::BigNumber("1".to_string()),
+                          Value::Double(1.0),
+                          Value::Map(vec![])];
+        for value in &others {
+            assert_eq!(value.as_str(), None);
+            assert_eq!(value.as_bytes(), None);
+            assert_eq!(value.as_i64(), None);
+            assert_eq!(value.as_array(), None);
+        }
+
+        assert_eq!(Value::Integer(i64::MIN).as_i64(), Some(i64::MIN));
+        assert_eq!(Value::Set(vec![Value::Integer(1)]).as_array(), Some(&[Value::Integer(1)][..]));
+        assert_eq!(Value::Push(vec![]).as_array(), Some(&[][..]));
+    }
+
+    #[test]
+    fn enum_into_accessors() {
+        let bulk = "a".repeat(64);
+        let ptr = bulk.as_ptr();
+        // the payload is moved, not cloned.
+        let string = Value::Bulk(bulk).into_string().unwrap();
+        assert_eq!(string.as_ptr(), ptr);
+        let bytes = Value::String(string).into_bytes().unwrap();
+        assert_eq!(bytes.as_ptr(), ptr);
+        let string = Value::BufBulk(bytes).into_string().unwrap();
+        assert_eq!(string.as_ptr(), ptr);
+
+        let values = vec![Value::Integer(1), Value::Null];
+        let ptr = values.as_ptr();
+        let values = Value::Array(values).into_array().unwrap();
+        assert_eq!(values.as_ptr(), ptr);
+        assert_eq!(Value::Set(vec![]).into_array(), Ok(vec![]));
+        assert_eq!(Value::Push(vec![Value::Null]).into_array(), Ok(vec![Value::Null]));
+
+        let others = vec![Value::Null,
+                          Value::Error("ERR".to_string()),
+                          Value::Integer(1),
+                          Value::Map(vec![])];
+        for value in others {
+            assert_eq!(value.clone().into_string(), Err(value.clone()));
+            assert_eq!(value.clone().into_bytes(), Err(value.clone()));
+            assert_eq!(value.clone().into_array(), Err(value));
+        }
+        assert_eq!(Value::Bulk("a".to_string()).into_array(),
+                   Err(Value::Bulk("a".to_string())));
+        assert_eq!(Value::BufBulk(vec![255]).into_string(), Err(Value::BufBulk(vec![255])));
+    }
+
+    #[test]
+    fn enum_default() {
+        assert_eq!(Value::default(), Value::Null);
+        let mut reply = Value::Integer(1);
+        assert_eq!(::std::mem::take(&mut reply), Value::Integer(1));
+        assert_eq!(reply, Value::Null);
+    }
+
+    #[test]
+    fn enum_take() {
+        let mut reply = Value::Array(vec![Value::Bulk("a".repeat(64))]);
+        let ptr = reply.as_array().unwrap().as_ptr();
+        let taken = reply.take();
+        assert_eq!(taken.as_array().unwrap().as_ptr(), ptr);
+        assert_eq!(reply, Value::Null);
+        assert_eq!(reply.take(), Value::Null);
+    }
+
+    #[test]
+    fn enum_is_type() {
+        let values = vec![Value::Null,
+                          Value::String("OK".to_string()),
+                          Value::Integer(1),
+                          Value::Boolean(true),
+                          Value::Double(1.0),
+                          Value::BigNumber("1".to_string()),
+                          Value::Bulk("a".to_string()),
+                          Value::BufBulk(vec![0]),
+                          Value::Array(vec![]),
+                          Value::Map(vec![]),
+                          Value::Set(vec![]),
+                          Value::Push(vec![])];
+        for value in &values {
+            let checks = [("string", value.is_string()),
+                          ("integer", value.is_integer()),
+                          ("boolean", value.is_boolean()),
+                          ("double", value.is_double()),
+                          ("big number", value.is_big_number()),
+                          ("bulk", value.is_bulk()),
+                          ("array", value.is_array()),
+                          ("map", value.is_map()),
+                          ("set", value.is_set()),
+                          ("push", value.is_push())];
+            for &(name, is) in &checks {
+                assert_eq!(is, value.type_name() == name, "{:?} is {}", value, name);
+            }
+        }
+        assert_eq!(Value::Raw(b"+OK\r\n".to_vec()).type_name(), "raw");
+        assert!(!Value::Raw(b"+OK\r\n".to_vec()).is_string());
+    }
+
+    #[test]
+    fn enum_replies() {
+        use super::super::{OK_BYTES, PONG_BYTES, QUEUED_BYTES};
+
+        let replies = [(Value::okay(), &*OK_REPLY, OK_BYTES, "OK"),
+                       (Value::pong(), &*PONG_REPLY, PONG_BYTES, "PONG"),
+                       (Value::queued(), &*QUEUED_REPLY, QUEUED_BYTES, "QUEUED")];
+        for &(ref value, shared, bytes, text) in &replies {
+            assert_eq!(value, shared);
+            assert_eq!(value.encode(), bytes);
+            assert_eq!(value.as_str(), Some(text));
+            assert_eq!(decode(bytes).unwrap(), (value.clone(), bytes.len()));
+        }
+    }
+
+    #[test]
+    fn enum_error_with_code() {
+        let value = Value::error_with_code("ERR", "unknown command 'FOO'").unwrap();
+        assert_eq!(value.encode(), b"-ERR unknown command 'FOO'\r\n");
+        assert_eq!(Value::error_with_code("NOSCRIPT", "").unwrap(),
+                   Value::Error("NOSCRIPT ".to_string()));
+
+        let invalid = [("", "message"),
+                       ("WRONG TYPE", "message"),
+                       ("ERR\r\n", "message"),
+                       ("ERR", "a\nb"),
+                       ("ERR", "a\r")];
+        for &(code, message) in &invalid {
+            let err = Value::error_with_code(code, message).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        let err = Value::error_with_code("ERR", "a\nb").unwrap_err();
+        assert_eq!(err.to_string(), "error message \"a\\nb\" contains CR or LF");
+    }
+
     #[test]
     fn enum_encode_null() {
         let val = Value::Null;
@@ -300,6 +1960,47 @@ mod tests {
         assert_eq!(val.to_encoded_string().unwrap(), ":-123456789\r\n");
     }
 
+    #[test]
+    fn enum_encode_boolean() {
+        assert_eq!(Value::Boolean(true).to_encoded_string().unwrap(), "#t\r\n");
+        assert_eq!(Value::Boolean(false).to_encoded_string().unwrap(), "#f\r\n");
+        assert_eq!(Value::Array(vec![Value::Boolean(true), Value::Boolean(false)])
+                       .to_json_string(),
+                   "[true,false]");
+    }
+
+    #[test]
+    fn enum_encode_double() {
+        assert_eq!(Value::Double(3.25).to_encoded_string().unwrap(), ",3.25\r\n");
+        assert_eq!(Value::Double(-10.0).to_encoded_string().unwrap(), ",-10\r\n");
+        assert_eq!(Value::Double(f64::INFINITY).to_encoded_string().unwrap(), ",inf\r\n");
+        assert_eq!(Value::Double(f64::NEG_INFINITY).to_encoded_string().unwrap(),
+                   ",-inf\r\n");
+        assert_eq!(Value::Double(f64::NAN).to_encoded_string().unwrap(), ",nan\r\n");
+    }
+
+    #[test]
+    fn enum_eq_double() {
+        fn assert_eq_trait<T: Eq>() {}
+        assert_eq_trait::<Value>();
+
+        assert_eq!(Value::Double(1.5), Value::Double(1.5));
+        assert_eq!(Value::Double(f64::NAN), Value::Double(f64::NAN));
+        assert!(Value::Double(0.0) != Value::Double(-0.0));
+        assert!(Value::Double(1.0) != Value::Integer(1));
+        assert_eq!(Value::Array(vec![Value::Double(f64::NAN)]),
+                   Value::Array(vec![Value::Double(f64::NAN)]));
+    }
+
+    #[test]
+    fn enum_encode_big_number() {
+        let val = Value::BigNumber("-3492890328409238509324850943850943825024385".to_string());
+        assert_eq!(val.to_encoded_string().unwrap(),
+                   "(-3492890328409238509324850943850943825024385\r\n");
+        assert_eq!(val.to_string_pretty(),
+                   "(Big Number) -3492890328409238509324850943850943825024385");
+    }
+
     #[test]
     fn enum_encode_bulk() {
         let val = Value::Bulk("OK正".to_string());
@@ -313,6 +2014,20 @@ mod tests {
     }
 
     #[test]
+    fn enum_encode_unknown() {
+        let val = Value::Unknown(b"=3\r\nabc\r\n".to_vec());
+        assert_eq!(val.to_encoded_string().unwrap(), "=3\r\nabc\r\n");
+    }
+
+    #[test]
+    fn enum_encode_raw() {
+        let val = Value::Array(vec![Value::Raw(b"+OK\r\n:1\r\n".to_vec()), Value::Raw(vec![])]);
+        assert_eq!(val.to_encoded_string().unwrap(), "*2\r\n+OK\r\n:1\r\n");
+        assert_eq!(val.encoded_len(), 13);
+    }
+
+    #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn enum_encode_array() {
         let val = Value::Array(Vec::new());
         assert_eq!(val.to_encoded_string().unwrap(), "*0\r\n");
@@ -331,6 +2046,452 @@ mod tests {
                    $2\r\nOK\r\n");
     }
 
+    #[test]
+    fn enum_map_entries() {
+        let mut val = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+                                      (Value::Integer(2), Value::Integer(2)),
+                                      (Value::BufBulk(b"c".to_vec()), Value::Integer(3))]);
+        assert_eq!(val.get("a"), Some(&Value::Integer(1)));
+        assert_eq!(val.get("c"), Some(&Value::Integer(3)));
+        assert_eq!(val.get("2"), None);
+        assert_eq!(val.insert(Value::Integer(2), Value::Null), Some(Value::Integer(2)));
+        assert_eq!(val.insert(Value::String("d".to_string()), Value::Integer(4)), None);
+        let pairs: Vec<(&Value, &Value)> = val.pairs().rev().take(2).collect();
+        assert_eq!(pairs,
+                   vec![(&Value::String("d".to_string()), &Value::Integer(4)),
+                        (&Value::BufBulk(b"c".to_vec()), &Value::Integer(3))]);
+        assert_eq!(val.pairs().len(), 4);
+        assert_eq!(val.pairs().nth(1), Some((&Value::Integer(2), &Value::Null)));
+
+        let array = Value::Array(vec![Value::Bulk("a".to_string()), Value::Integer(1)]);
+        assert_eq!(array.get("a"), None);
+        assert_eq!(array.pairs().len(), 0);
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), Value::Integer(1));
+        map.insert("b".to_string(), Value::Integer(2));
+        let val = Value::from(map);
+        assert_eq!(val.pairs().len(), 2);
+        assert_eq!(val.get("b"), Some(&Value::Integer(2)));
+
+        let mut map = BTreeMap::new();
+        map.insert("b".to_string(), Value::Integer(2));
+        map.insert("a".to_string(), Value::Integer(1));
+        assert_eq!(Value::from(map),
+                   Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+                                   (Value::Bulk("b".to_string()), Value::Integer(2))]));
+    }
+
+    #[test]
+    #[should_panic(expected = "insert into array value")]
+    fn enum_map_insert_panics() {
+        Value::Array(vec![]).insert(Value::Null, Value::Null);
+    }
+
+    #[test]
+    fn enum_as_pairs() {
+        let bulk = |val: &str| Value::Bulk(val.to_string());
+        let reply = Value::Array(vec![bulk("a"), Value::Integer(1), bulk("a"), Value::Integer(2)]);
+        assert_eq!(reply.as_pairs().unwrap(),
+                   vec![(&bulk("a"), &Value::Integer(1)), (&bulk("a"), &Value::Integer(2))]);
+        let map = reply.to_map().unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["a"], Value::Integer(2));
+
+        let reply = Value::Map(vec![(Value::BufBulk(b"x".to_vec()), Value::Null)]);
+        assert_eq!(reply.as_pairs().unwrap(), vec![(&Value::BufBulk(b"x".to_vec()), &Value::Null)]);
+        assert_eq!(reply.to_map().unwrap()["x"], Value::Null);
+        assert_eq!(Value::Array(vec![]).to_map().unwrap().len(), 0);
+
+        let err = Value::Array(vec![bulk("a")]).as_pairs().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(),
+                   "expected array of key and value pairs, found array of 1 elements");
+        let err = Value::NullArray.to_map().unwrap_err();
+        assert_eq!(err.to_string(), "expected array or map, found null array");
+        let err = Value::Array(vec![Value::Integer(1), bulk("a")]).to_map().unwrap_err();
+        assert_eq!(err.to_string(), "expected string key, found integer");
+    }
+
+    #[test]
+    fn enum_iter() {
+        let reply = Value::Array(vec![Value::Integer(1), Value::Bulk("2".to_string())]);
+        let sum: i64 = reply.iter().filter_map(Value::as_i64).sum();
+        assert_eq!(sum, 1);
+        assert_eq!(reply.iter().len(), 2);
+        let mut count = 0;
+        for value in &reply {
+            assert!(!value.is_null());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        let strings: Vec<String> = reply.into_iter()
+            .filter_map(|val| val.into_string().ok())
+            .collect();
+        assert_eq!(strings, vec!["2".to_string()]);
+
+        assert_eq!(Value::Set(vec![Value::Null]).iter().count(), 1);
+        assert_eq!(Value::Push(vec![Value::Null]).into_iter().count(), 1);
+        let others = vec![Value::NullArray,
+                          Value::Bulk("a".to_string()),
+                          Value::Map(vec![(Value::Null, Value::Null)])];
+        for value in others {
+            assert_eq!(value.iter().count(), 0);
+            assert_eq!(value.into_iter().count(), 0);
+        }
+    }
+
+    #[test]
+    fn enum_from_iter_extend() {
+        let value: Value = (1..4).collect();
+        assert_eq!(value, Value::from(vec![1, 2, 3]));
+        let value: Value = Vec::<Value>::new().into_iter().collect();
+        assert_eq!(value, Value::Array(vec![]));
+
+        let mut command: Value = vec!["MGET"].into_iter().collect();
+        command.extend(vec!["a".to_string(), "b".to_string()]);
+        command.extend(Some(Value::Integer(1)));
+        assert_eq!(command,
+                   Value::Array(vec![Value::Bulk("MGET".to_string()),
+                                     Value::Bulk("a".to_string()),
+                                     Value::Bulk("b".to_string()),
+                                     Value::Integer(1)]));
+
+        let mut value = Value::NullArray;
+        value.extend(vec![true]);
+        assert_eq!(value, Value::Array(vec![Value::Boolean(true)]));
+        let mut value = Value::Set(vec![]);
+        value.extend(vec![1]);
+        assert_eq!(value, Value::Set(vec![Value::Integer(1)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "extend map value")]
+    fn enum_extend_panics() {
+        Value::Map(vec![]).extend(vec![1]);
+    }
+
+    #[test]
+    fn enum_index() {
+        // the reply of EXEC.
+        let reply = Value::Array(vec![Value::String("OK".to_string()),
+                                      Value::Array(vec![Value::Integer(1), Value::Null])]);
+        assert_eq!(reply[0], Value::String("OK".to_string()));
+        assert_eq!(reply[1][1], Value::Null);
+        assert_eq!(reply.get(1).and_then(|val| val.get(0)), Some(&Value::Integer(1)));
+        assert_eq!(reply.get(2), None);
+        assert_eq!(Value::Set(vec![Value::Null]).get(0), Some(&Value::Null));
+        assert_eq!(Value::Push(vec![Value::Null])[0], Value::Null);
+        assert_eq!(Value::Null.get(0), None);
+
+        let map = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1))]);
+        assert_eq!(map.get(0), None);
+        assert_eq!(map.get("a".to_string()), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn enum_get_path() {
+        let consumers = Value::Array(vec![Value::Map(vec![(Value::Bulk("name".to_string()),
+                                                           Value::Bulk("c1".to_string()))])]);
+        let group = Value::Map(vec![(Value::Bulk("consumers".to_string()), consumers)]);
+        let info = Value::Map(vec![(Value::Bulk("groups".to_string()), Value::Array(vec![group]))]);
+
+        let path: &[&dyn ValueIndex] = &[&"groups", &0, &"consumers", &0, &"name"];
+        assert_eq!(info.get_path(path), Some(&Value::Bulk("c1".to_string())));
+        let path: &[&dyn ValueIndex] = &[&"groups", &1];
+        assert_eq!(info.get_path(path), None);
+        assert_eq!(info.get_path(&["groups", "consumers"]), None);
+        assert_eq!(info.get_path::<usize>(&[]), Some(&info));
+        assert_eq!(Value::Integer(1).get_path(&[0]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "index into map value")]
+    fn enum_index_panics() {
+        let _ = &Value::Map(vec![])[0];
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn enum_index_out_of_bounds_panics() {
+        let _ = &Value::Array(vec![])[0];
+    }
+
+    #[test]
+    fn trait_from_str() {
+        assert_eq!("+OK\r\n".parse::<Value>().unwrap(), Value::String("OK".to_string()));
+        assert_eq!("%1\r\n$1\r\na\r\n,1.5\r\n".parse::<Value>().unwrap(),
+                   Value::Map(vec![(Value::Bulk("a".to_string()), Value::Double(1.5))]));
+        assert_eq!("+OK".parse::<Value>().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!("+OK\r\n:1".parse::<Value>().unwrap_err().to_string(),
+                   "trailing bytes: \":1\"");
+        assert_eq!("".parse::<Value>().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn enum_encode_map() {
+        let val = Value::Map(Vec::new());
+        assert_eq!(val.to_encoded_string().unwrap(), "%0\r\n");
+
+        let val = Value::Map(vec![(Value::String("OK".to_string()), Value::Integer(1)),
+                                  (Value::Integer(2), Value::Array(vec![Value::Null]))]);
+        assert_eq!(val.to_encoded_string().unwrap(),
+                   "%2\r\n+OK\r\n:1\r\n:2\r\n*1\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn enum_encode_set() {
+        let val = Value::Set(Vec::new());
+        assert_eq!(val.to_encoded_string().unwrap(), "~0\r\n");
+
+        let val = Value::Set(vec![Value::Integer(1), Value::Bulk("a".to_string())]);
+        assert_eq!(val.to_encoded_string().unwrap(), "~2\r\n:1\r\n$1\r\na\r\n");
+    }
+
+    #[test]
+    fn enum_encode_push() {
+        let val = Value::Push(Vec::new());
+        assert_eq!(val.to_encoded_string().unwrap(), ">0\r\n");
+
+        let val = Value::Push(vec![Value::Bulk("invalidate".to_string()),
+                                   Value::Array(vec![Value::Bulk("a".to_string())])]);
+        assert_eq!(val.to_encoded_string().unwrap(),
+                   ">2\r\n$10\r\ninvalidate\r\n*1\r\n$1\r\na\r\n");
+        assert_eq!(val.to_json_string(), r#"["invalidate",["a"]]"#);
+    }
+
+    #[test]
+    fn enum_encode_attribute() {
+        let val = Value::Attribute(vec![(Value::String("ttl".to_string()), Value::Integer(3600))],
+                                   Box::new(Value::Bulk("a".to_string())));
+        assert_eq!(val.to_encoded_string().unwrap(),
+                   "|1\r\n+ttl\r\n:3600\r\n$1\r\na\r\n");
+        assert_eq!(val.to_json_string(), r#"{"attributes":{"ttl":3600},"value":"a"}"#);
+        assert_eq!(val.to_string_pretty(), "(Attribute) 1# ttl => (Integer) 3600\n\"a\"");
+
+        let val = Value::Attribute(vec![(Value::Integer(1), Value::Null),
+                                        (Value::Integer(2), Value::Null)],
+                                   Box::new(Value::Null));
+        assert_eq!(val.to_string_pretty(),
+                   "(Attribute) 1# (Integer) 1 => (Null)\n            2# (Integer) 2 => (Null)\n\
+                    (Null)");
+    }
+
+    #[test]
+    fn enum_from() {
+        assert_eq!(Value::from("OK"), Value::Bulk("OK".to_string()));
+        assert_eq!(Value::from("OK".to_string()), Value::Bulk("OK".to_string()));
+        assert_eq!(Value::from(&b"\xff"[..]), Value::BufBulk(vec![255]));
+        assert_eq!(Value::from(vec![0u8, 1]), Value::BufBulk(vec![0, 1]));
+        assert_eq!(Value::from(-1i64), Value::Integer(-1));
+        assert_eq!(Value::from(u32::MAX), Value::Integer(4294967295));
+        assert_eq!(Value::from(-1i8), Value::Integer(-1));
+        assert_eq!(Value::from(true), Value::Boolean(true));
+        assert_eq!(Value::from(1.5f32), Value::Double(1.5));
+        assert_eq!(Value::from(None::<i64>), Value::Null);
+        assert_eq!(Value::from(Some(1)), Value::Integer(1));
+
+        let value = Value::from(vec![Value::from(vec!["SET", "key"]),
+                                     Value::from(vec![1, 2]),
+                                     Value::from(Vec::<Value>::new())]);
+        assert_eq!(value,
+                   Value::Array(vec![Value::Array(vec![Value::Bulk("SET".to_string()),
+                                                       Value::Bulk("key".to_string())]),
+                                     Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                                     Value::Array(vec![])]));
+    }
+
+    #[test]
+    fn enum_digest() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let val = Value::Array(vec![Value::Bulk("foo".to_string()), Value::Integer(1)]);
+        let buf_val = Value::Array(vec![Value::BufBulk(b"foo".to_vec()), Value::Integer(1)]);
+        assert_eq!(val.digest::<DefaultHasher>(), buf_val.digest::<DefaultHasher>());
+        assert!(val.digest::<DefaultHasher>() !=
+                Value::Array(vec![Value::Integer(1)]).digest::<DefaultHasher>());
+        assert!(Value::Null.digest::<DefaultHasher>() != Value::NullArray.digest::<DefaultHasher>());
+
+        let mut output = Vec::new();
+        val.digest_into(&mut output).unwrap();
+        assert_eq!(output, buf_val.encode_canonical());
+        let map = Value::Map(vec![(Value::Integer(2), Value::Null),
+                                  (Value::Integer(1), Value::Null)]);
+        let mut output = Vec::new();
+        map.digest_into(&mut output).unwrap();
+        assert_eq!(output, b"%2\r\n:1\r\n$-1\r\n:2\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn enum_shared_bulk() {
+        let payload: Arc<[u8]> = Arc::from(vec![b'a'; 1024]);
+        let value = Value::from(payload.clone());
+        let cloned = value.clone();
+        match (&value, &cloned) {
+            (Value::SharedBulk(a), Value::SharedBulk(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => unreachable!(),
+        }
+        assert_eq!(Arc::strong_count(&payload), 3);
+
+        let buf_bulk = Value::BufBulk(payload.to_vec());
+        assert_eq!(value.encode(), buf_bulk.encode());
+        assert_eq!(value.encoded_len(), buf_bulk.encoded_len());
+        assert!(value.eq_encoded(&buf_bulk));
+        assert_eq!(value.to_string(), buf_bulk.to_string());
+        assert_eq!(value.to_json_string(), buf_bulk.to_json_string());
+        assert_eq!(value.as_bytes(), Some(&payload[..]));
+        assert_eq!(value.as_str().map(str::len), Some(1024));
+        assert!(value.is_bulk());
+        assert_eq!(value.type_name(), "bulk");
+        assert_eq!(value.clone().into_bytes(), Ok(payload.to_vec()));
+        let invalid = Value::SharedBulk(Arc::from(vec![255]));
+        assert_eq!(invalid.clone().into_string(), Err(invalid));
+
+        let mut canonical = cloned;
+        canonical.canonicalize();
+        assert_eq!(canonical, buf_bulk);
+    }
+
+    #[test]
+    fn enum_mem_size() {
+        use std::mem::size_of;
+
+        let value_size = size_of::<Value>();
+        assert_eq!(Value::Null.mem_size(), value_size);
+        assert_eq!(Value::Bulk(String::with_capacity(100)).mem_size(), value_size + 100);
+        assert_eq!(Value::Raw(vec![0; 10]).mem_size(), value_size + 10);
+
+        let mut array = Vec::with_capacity(4);
+        array.push(Value::String("OK".to_string()));
+        let array = Value::Array(array);
+        assert_eq!(array.mem_size(), value_size + 4 * value_size + 2);
+
+        let array_size = array.mem_size();
+        let map = Value::Map(vec![(Value::Bulk("a".to_string()), array)]);
+        assert_eq!(map.mem_size(), value_size + 2 * value_size + 1 + array_size - value_size);
+        let map_size = map.mem_size();
+        let attribute = Value::Attribute(vec![], Box::new(map));
+        assert_eq!(attribute.mem_size(), value_size + map_size);
+    }
+
+    #[test]
+    fn enum_eq_encoded() {
+        let val = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Double(f64::NAN))]);
+        let buf_val = Value::Map(vec![(Value::BufBulk(b"a".to_vec()), Value::Double(f64::NAN))]);
+        assert!(val != buf_val);
+        assert!(val.eq_encoded(&buf_val));
+        assert!(val.eq_encoded(&Value::Raw(val.encode())));
+        assert!(!Value::Null.eq_encoded(&Value::NullArray));
+        assert!(!Value::Integer(1).eq_encoded(&Value::Integer(2)));
+    }
+
+    #[test]
+    fn enum_canonicalize() {
+        let map = Value::Map(vec![(Value::Integer(2), Value::Bulk("x".to_string())),
+                                  (Value::Integer(1),
+                                   Value::Set(vec![Value::Integer(3), Value::Integer(1)]))]);
+        let attributes = vec![(Value::Bulk("b".to_string()), Value::Null),
+                              (Value::Bulk("a".to_string()), Value::Double(-0.0))];
+        let mut val = Value::Attribute(attributes,
+                                       Box::new(Value::Push(vec![map, Value::okay()])));
+        let canonical = val.encode_canonical();
+        val.canonicalize();
+        assert_eq!(val.encode(), canonical);
+
+        let map = Value::Map(vec![(Value::Integer(1),
+                                   Value::Set(vec![Value::Integer(1), Value::Integer(3)])),
+                                  (Value::Integer(2), Value::BufBulk(b"x".to_vec()))]);
+        let attributes = vec![(Value::BufBulk(b"a".to_vec()), Value::Double(0.0)),
+                              (Value::BufBulk(b"b".to_vec()), Value::Null)];
+        assert_eq!(val,
+                   Value::Attribute(attributes,
+                                    Box::new(Value::Push(vec![map, Value::okay()]))));
+        assert!(val.clone().eq_encoded(&val));
+
+        // canonical values equal in content compare equal.
+        let mut a = Value::Set(vec![Value::Bulk("x".to_string()), Value::BufBulk(vec![0])]);
+        let mut b = Value::Set(vec![Value::BufBulk(vec![0]), Value::BufBulk(b"x".to_vec())]);
+        assert!(a != b);
+        a.canonicalize();
+        b.canonicalize();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn enum_to_json_string() {
+        assert_eq!(Value::Null.to_json_string(), "null");
+        assert_eq!(Value::NullArray.to_json_string(), "null");
+        assert_eq!(Value::String("OK".to_string()).to_json_string(), "\"OK\"");
+        assert_eq!(Value::Error("ERR x".to_string()).to_json_string(),
+                   "{\"error\":\"ERR x\"}");
+        assert_eq!(Value::Integer(-123).to_json_string(), "-123");
+        assert_eq!(Value::Bulk("中文\"\\\r\n\t\u{1}\u{7f}".to_string()).to_json_string(),
+                   "\"中文\\\"\\\\\\r\\n\\t\\u0001\\u007f\"");
+        assert_eq!(Value::BufBulk(vec![79, 75]).to_json_string(), "\"OK\"");
+        assert_eq!(Value::BufBulk(vec![255]).to_json_string(), "{\"base64\":\"/w==\"}");
+        assert_eq!(Value::BufBulk(vec![255, 254, 253]).to_json_string(),
+                   "{\"base64\":\"//79\"}");
+        assert_eq!(Value::BufBulk(vec![255, 0, 1, 2]).to_json_string(),
+                   "{\"base64\":\"/wABAg==\"}");
+        assert_eq!(Value::Unknown(b"#t\r\n".to_vec()).to_json_string(),
+                   "{\"unknown\":\"I3QNCg==\"}");
+        assert_eq!(Value::Raw(b"*1\r\n:1\r\n".to_vec()).to_json_string(), "[1]");
+        assert_eq!(Value::Raw(b":1\r\n:2\r\n".to_vec()).to_json_string(),
+                   "{\"raw\":\"OjENCjoyDQo=\"}");
+        assert_eq!(Value::Array(vec![]).to_json_string(), "[]");
+        assert_eq!(Value::Set(vec![Value::Integer(1)]).to_json_string(), "[1]");
+        assert_eq!(Value::Array(vec![Value::Double(-0.5),
+                                     Value::Double(f64::INFINITY),
+                                     Value::Double(f64::NAN)])
+                           .to_json_string(),
+                   r#"[-0.5,"inf","nan"]"#);
+        assert_eq!(Value::BigNumber("-12345678901234567890".to_string()).to_json_string(),
+                   "-12345678901234567890");
+        assert_eq!(Value::Array(vec![Value::Array(vec![Value::Integer(1)]), Value::Null])
+                       .to_json_string(),
+                   "[[1],null]");
+        let map = Value::Map(vec![(Value::Bulk("a".to_string()), Value::Integer(1)),
+                                  (Value::Integer(2), Value::Map(vec![])),
+                                  (Value::BufBulk(vec![255]), Value::Null)]);
+        assert_eq!(map.to_json_string(), r#"{"a":1,"2":{},"{\"base64\":\"/w==\"}":null}"#);
+    }
+
+    #[test]
+    fn enum_display() {
+        let map = Value::Map(vec![(Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                                   Value::Map(vec![(Value::Bulk("a".to_string()),
+                                                    Value::BufBulk(vec![0; 20]))])),
+                                  (Value::Bulk("b".to_string()), Value::Set(vec![]))]);
+        let value = Value::Attribute(vec![(Value::Bulk("ttl".to_string()), Value::Integer(1))],
+                                     Box::new(Value::Array(vec![map, Value::Null])));
+        let expected = "(Attribute) 1# \"ttl\" => (Integer) 1\n\
+                        1) 1# 1) (Integer) 1\n\
+                        \x20     2) (Integer) 2 => 1# \"a\" => (Buffer) 00 00 00 00 00 00 00 \
+                        00 00 00 00 00 00 00 00 00 ...\n\
+                        \x20  2# \"b\" => (Empty Set)\n\
+                        2) (Null)";
+        assert_eq!(format!("{}", value), expected);
+        assert_eq!(value.to_string_pretty(), expected);
+        assert_eq!(Value::Raw(b"#t\r\n".to_vec()).to_string(), "(Boolean) true");
+    }
+
+    #[test]
+    #[cfg(feature = "color")]
+    fn enum_to_string_colored() {
+        let value = Value::Map(vec![(Value::Array(vec![Value::Null, Value::Null]),
+                                     Value::Map(vec![(Value::Integer(1),
+                                                      Value::Error("ERR".to_string()))]))]);
+        let colored = value.to_string_colored();
+        assert_eq!(colored,
+                   "\x1b[2m1#\x1b[0m \x1b[2m1)\x1b[0m (Null)\n\x1b[2m   2)\x1b[0m (Null) => \
+                    \x1b[2m1#\x1b[0m \x1b[33m(Integer) 1\x1b[0m => \x1b[31m(Error) ERR\x1b[0m");
+        // the escape codes don't move the alignment.
+        let plain = [DIM, RED, YELLOW, RESET]
+            .iter()
+            .fold(colored, |text, code| text.replace(code, ""));
+        assert_eq!(plain, value.to_string_pretty());
+    }
+
     #[test]
     fn enum_to_string_pretty() {
         // test the alias of to_string_pretty.
@@ -344,6 +2505,9 @@ mod tests {
         assert_eq!(Value::Integer(123).to_string_pretty(), "(Integer) 123");
         assert_eq!(Value::Bulk("Bulk String".to_string()).to_string_pretty(),
                    "\"Bulk String\"");
+        assert_eq!(Value::Bulk("\\\"\r\n\t\x07\x08\x00\x1b[31m\x7f\u{85}中文".to_string())
+                       .to_string_pretty(),
+                   r#""\\\"\r\n\t\a\b\x00\x1b[31m\x7f\xc2\x85中文""#);
         assert_eq!(Value::BufBulk(vec![]).to_string_pretty(), "(Empty Buffer)");
         assert_eq!(Value::BufBulk(vec![0, 100]).to_string_pretty(),
                    "(Buffer) 00 64");
@@ -352,8 +2516,31 @@ mod tests {
                            .to_string_pretty(),
                    "(Buffer) 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f ...");
         assert_eq!(Value::Array(vec![]).to_string_pretty(), "(Empty Array)");
+        assert_eq!(Value::Unknown(b"=3\r\nabc\r\n".to_vec()).to_string_pretty(),
+                   r#"(Unknown) "=3\r\nabc\r\n""#);
+        assert_eq!(Value::Unknown(b"!3\r\n\x1b[2\xff\r\n".to_vec()).to_string_pretty(),
+                   r#"(Unknown) "!3\r\n\x1b[2\xff\r\n""#);
+        assert_eq!(Value::Raw(b":1\r\n".to_vec()).to_string_pretty(), "(Integer) 1");
+        assert_eq!(Value::Raw(b":1".to_vec()).to_string_pretty(), r#"(Raw) ":1""#);
         assert_eq!(Value::Array(vec![Value::Null, Value::Integer(123)]).to_string_pretty(),
                    "1) (Null)\n2) (Integer) 123");
+        assert_eq!(Value::Map(vec![]).to_string_pretty(), "(Empty Map)");
+        assert_eq!(Value::Set(vec![]).to_string_pretty(), "(Empty Set)");
+        assert_eq!(Value::Array(vec![Value::Set(vec![]),
+                                     Value::Set(vec![Value::Null, Value::Integer(1)])])
+                           .to_string_pretty(),
+                   "1) (Empty Set)\n2) 1) (Null)\n   2) (Integer) 1");
+        let map = Value::Map(vec![(Value::Bulk("a".to_string()),
+                                   Value::Array(vec![Value::Integer(1), Value::Integer(2)])),
+                                  (Value::Integer(2),
+                                   Value::Map(vec![(Value::Null, Value::Integer(3)),
+                                                   (Value::Null, Value::Integer(4))]))]);
+        assert_eq!(map.to_string_pretty(),
+                   ["1# \"a\" => 1) (Integer) 1",
+                    "          2) (Integer) 2",
+                    "2# (Integer) 2 => 1# (Null) => (Integer) 3",
+                    "                  2# (Null) => (Integer) 4"]
+                           .join("\n"));
 
         let _values = vec![Value::Null,
                            Value::NullArray,
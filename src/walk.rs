@@ -0,0 +1,151 @@
+//! RESP value traversal
+
+use std::vec::Vec;
+
+use super::Value;
+
+/// Visits the values of a tree, see `Value::walk`. Closures `FnMut(&Value, usize) -> bool`
+/// implement it as `enter`.
+pub trait Visitor {
+    /// Called for each value before its children, `depth` is 0 for the root. Returns whether
+    /// to visit the children of the value, `false` skips them and their `leave` call.
+    fn enter(&mut self, value: &Value, depth: usize) -> bool;
+
+    /// Called after the children of an aggregate value have been visited.
+    fn leave(&mut self, _value: &Value, _depth: usize) {}
+}
+
+impl<F: FnMut(&Value, usize) -> bool> Visitor for F {
+    fn enter(&mut self, value: &Value, depth: usize) -> bool {
+        self(value, depth)
+    }
+}
+
+enum Step<'a> {
+    Enter(&'a Value, usize),
+    Leave(&'a Value, usize),
+}
+
+impl Value {
+    /// Walks the value tree depth first, in encoding order: the elements of an `Array`, `Set`
+    /// or `Push`, the keys and values of a `Map`, and the attributes then the value of an
+    /// `Attribute`. The walk keeps its own stack, so deeply nested values don't overflow the
+    /// call stack.
+    /// # Examples
+    /// ```
+    /// # use self::resp::{Value};
+    /// let reply = Value::Array(vec![Value::Integer(1),
+    ///                               Value::Array(vec![Value::Integer(2)]),
+    ///                               Value::Map(vec![(Value::Bulk("a".to_string()),
+    ///                                                Value::Integer(3))])]);
+    /// let mut sum = 0;
+    /// reply.walk(&mut |value: &Value, _depth: usize| {
+    ///     sum += value.as_i64().unwrap_or(0);
+    ///     true
+    /// });
+    /// assert_eq!(sum, 6);
+    /// ```
+    pub fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        let mut stack = vec![Step::Enter(self, 0)];
+        while let Some(step) = stack.pop() {
+            let (value, depth) = match step {
+                Step::Enter(value, depth) => (value, depth),
+                Step::Leave(value, depth) => {
+                    visitor.leave(value, depth);
+                    continue;
+                }
+            };
+            if !visitor.enter(value, depth) {
+                continue;
+            }
+            let child = depth + 1;
+            match *value {
+                Value::Array(ref val) | Value::Set(ref val) | Value::Push(ref val) => {
+                    stack.push(Step::Leave(value, depth));
+                    stack.extend(val.iter().rev().map(|item| Step::Enter(item, child)));
+                }
+                Value::Map(ref val) => {
+                    stack.push(Step::Leave(value, depth));
+                    push_pairs(&mut stack, val, child);
+                }
+                Value::Attribute(ref attributes, ref val) => {
+                    stack.push(Step::Leave(value, depth));
+                    stack.push(Step::Enter(val, child));
+                    push_pairs(&mut stack, attributes, child);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Pushes the entries so that they are popped in order, each key before its value.
+fn push_pairs<'a>(stack: &mut Vec<Step<'a>>, pairs: &'a [(Value, Value)], depth: usize) {
+    for (key, value) in pairs.iter().rev() {
+        stack.push(Step::Enter(value, depth));
+        stack.push(Step::Enter(key, depth));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        events: Vec<String>,
+        skip: &'static str,
+    }
+
+    impl Visitor for Recorder {
+        fn enter(&mut self, value: &Value, depth: usize) -> bool {
+            self.events.push(format!("{}{}", " ".repeat(depth), value.type_name()));
+            value.type_name() != self.skip
+        }
+
+        fn leave(&mut self, value: &Value, depth: usize) {
+            self.events.push(format!("{}/{}", " ".repeat(depth), value.type_name()));
+        }
+    }
+
+    #[test]
+    fn trait_visitor() {
+        let value = Value::Attribute(vec![(Value::Bulk("ttl".to_string()), Value::Integer(1))],
+                                     Box::new(Value::Array(vec![Value::Map(vec![]),
+                                                                Value::Set(vec![Value::Null]),
+                                                                Value::Boolean(true)])));
+        let mut recorder = Recorder {
+            events: Vec::new(),
+            skip: "set",
+        };
+        value.walk(&mut recorder);
+        assert_eq!(recorder.events,
+                   vec!["attribute", " bulk", " integer", " array", "  map", "  /map", "  set",
+                        "  boolean", " /array", "/attribute"]);
+
+        let mut recorder = Recorder {
+            events: Vec::new(),
+            skip: "attribute",
+        };
+        value.walk(&mut recorder);
+        assert_eq!(recorder.events, vec!["attribute"]);
+    }
+
+    #[test]
+    fn trait_visitor_deep() {
+        let mut value = Value::Null;
+        for _ in 0..100_000 {
+            value = Value::Array(vec![value]);
+        }
+        let mut max_depth = 0;
+        value.walk(&mut |_: &Value, depth: usize| {
+                            max_depth = depth;
+                            true
+                        });
+        assert_eq!(max_depth, 100_000);
+
+        // drop the value without recursing.
+        while let Value::Array(mut values) = value {
+            value = values.pop().unwrap();
+        }
+    }
+}
@@ -38,6 +38,7 @@ impl Read for FakeNetIO {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn enum_is_null() {
     assert_eq!(Value::Null.is_null(), true);
     assert_eq!(Value::NullArray.is_null(), true);
@@ -45,6 +46,7 @@ fn enum_is_null() {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn enum_is_error() {
     assert_eq!(Value::Null.is_error(), false);
     assert_eq!(Value::NullArray.is_error(), false);